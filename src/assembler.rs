@@ -1,6 +1,15 @@
 pub mod cli;
+pub mod diagnostics;
+pub mod disassembler;
 pub mod evaluator;
+pub mod formatter;
+mod include;
+mod json;
 mod lexer;
+mod listing;
+pub mod lsp;
+pub mod optimizer;
 mod parser;
 mod sections;
+pub mod tester;
 mod tokens;
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
 #[derive(Debug, Parser)]
 #[command(name = "Vondel Assembler")]
@@ -8,7 +8,6 @@ use clap::Parser;
 #[command(
     help_template = "{author-with-newline} {about-section}Version: {version} \n\n {usage-heading} {usage} \n {all-args} {tab}"
 )]
-
 pub struct AssemblerCli {
     /// The name of the file to assemble
     #[arg(short, long)]
@@ -17,4 +16,101 @@ pub struct AssemblerCli {
     /// The name of the output
     #[arg(short, long, default_value = "a")]
     pub output: Option<String>,
+
+    /// Also write a `.lst` listing with the microcode generated for each line
+    #[arg(short, long)]
+    pub listing: bool,
+
+    /// Run the peephole optimizer over the generated microcode
+    #[arg(short = 'O', long)]
+    pub optimize: bool,
+
+    /// How errors and warnings are reported
+    #[arg(long, value_enum, default_value_t = MessageFormat::Human)]
+    pub message_format: MessageFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum MessageFormat {
+    /// Source snippets with the problems underlined, on stderr
+    Human,
+    /// A JSON array of diagnostics, on stdout
+    Json,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "Vondel Disassembler")]
+#[command(version = "1.0")]
+#[command(about = "Turns a Vondel control store image back into assembly")]
+#[command(author, long_about = None)]
+#[command(
+    help_template = "{author-with-newline} {about-section}Version: {version} \n\n {usage-heading} {usage} \n {all-args} {tab}"
+)]
+pub struct DisassemblerCli {
+    /// The `.rom` file to disassemble
+    #[arg(short, long)]
+    pub input: String,
+
+    /// A `.ram` dump to write back as the `.data` section
+    #[arg(long)]
+    pub ram: Option<String>,
+
+    /// Where to write the assembly, instead of the standard output
+    #[arg(short, long)]
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "Vondel Tester")]
+#[command(version = "1.0")]
+#[command(about = "Runs assembly programs and checks the expectations they declare")]
+#[command(author, long_about = None)]
+#[command(
+    help_template = "{author-with-newline} {about-section}Version: {version} \n\n {usage-heading} {usage} \n {all-args} {tab}"
+)]
+pub struct TesterCli {
+    /// The `.asm` files to run
+    #[arg(required = true)]
+    pub inputs: Vec<String>,
+
+    /// Cycles after which a program that didn't halt fails
+    #[arg(long, default_value_t = 1_000_000)]
+    pub max_cycles: u32,
+
+    /// Run the peephole optimizer over the generated microcode
+    #[arg(short = 'O', long)]
+    pub optimize: bool,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "Vondel Language Server")]
+#[command(version = "1.0")]
+#[command(about = "A language server for Vondel assembly")]
+#[command(author, long_about = None)]
+#[command(
+    help_template = "{author-with-newline} {about-section}Version: {version} \n\n {usage-heading} {usage} \n {all-args} {tab}"
+)]
+pub struct LspCli {
+    /// Talk over the standard input and output, the only transport there is. Accepted
+    /// since editors pass it
+    #[arg(long)]
+    pub stdio: bool,
+}
+
+#[derive(Debug, Parser)]
+#[command(name = "Vondel Formatter")]
+#[command(version = "1.0")]
+#[command(about = "Rewrites assembly sources in the canonical layout")]
+#[command(author, long_about = None)]
+#[command(
+    help_template = "{author-with-newline} {about-section}Version: {version} \n\n {usage-heading} {usage} \n {all-args} {tab}"
+)]
+pub struct FormatterCli {
+    /// The `.asm` files to format in place
+    #[arg(required = true)]
+    pub inputs: Vec<String>,
+
+    /// Don't write anything, only list the files that aren't formatted and fail if any
+    #[arg(short, long)]
+    pub check: bool,
 }
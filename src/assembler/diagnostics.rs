@@ -0,0 +1,348 @@
+use std::{collections::HashMap, fmt, fmt::Write, rc::Rc};
+
+use anyhow::Error;
+
+use crate::assembler::{
+    evaluator::EvaluatorError, include::IncludeError, json::quote, parser::ParserError,
+    sections::ExprError,
+};
+
+/// Position of a token in the sources, as produced by the lexer.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(file: &str, line: usize, column: usize) -> Self {
+        Self {
+            file: file.to_string(),
+            line,
+            column,
+        }
+    }
+}
+
+/// Where the symbols of a program are defined and used, recorded by the parser so
+/// later passes can point back at the sources.
+#[derive(Debug, Default, Clone)]
+pub struct SourceSpans {
+    /// Definitions of the labels of the `.text` section.
+    pub labels: HashMap<Rc<str>, Span>,
+    /// Definitions of data labels and `.equ` constants.
+    pub symbols: HashMap<Rc<str>, Span>,
+    /// Labels used as branch or jump targets.
+    pub label_refs: Vec<(Rc<str>, Span)>,
+    /// Symbols used in constant expressions.
+    pub symbol_refs: Vec<(Rc<str>, Span)>,
+    /// Start of each instruction, in the order they were parsed.
+    pub instructions: Vec<Span>,
+    /// Start of each data directive and `.equ`, in the order they were parsed.
+    pub data: Vec<Span>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Note {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: String, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message,
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning(code: &'static str, message: String, span: Option<Span>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code,
+            message,
+            span,
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_note(mut self, message: String, span: Option<Span>) -> Self {
+        self.notes.push(Note { message, span });
+        self
+    }
+
+    /// Builds a diagnostic out of any error raised while assembling.
+    pub fn from_error(err: &Error) -> Self {
+        // The location is part of the diagnostic, so only the headline is kept
+        let message = err.to_string().lines().next().unwrap_or("").to_string();
+
+        if let Some(e) = err.downcast_ref::<ParserError>() {
+            let diag = Diagnostic::error(e.code(), message, Some(e.span()));
+            return match e {
+                ParserError::DuplicateLabel {
+                    first_line,
+                    first_column,
+                    first_file,
+                    ..
+                } => diag.with_note(
+                    "first defined here".to_string(),
+                    Some(Span::new(first_file, *first_line, *first_column)),
+                ),
+                _ => diag,
+            };
+        }
+        if let Some(e) = err.downcast_ref::<IncludeError>() {
+            return Diagnostic::error(e.code(), message, Some(e.span()));
+        }
+        if let Some(e) = err.downcast_ref::<EvaluatorError>() {
+            return Diagnostic::error(e.code(), message, None);
+        }
+        if let Some(e) = err.downcast_ref::<ExprError>() {
+            return Diagnostic::error(e.code(), message, None);
+        }
+        Diagnostic::error("E0000", format!("{:#}", err), None)
+    }
+
+    /// Builds a diagnostic out of an error raised while evaluating the statement at
+    /// `span`, which locates the errors that don't carry a position of their own.
+    pub fn from_error_at(err: &Error, span: Option<&Span>) -> Self {
+        let mut diag = Self::from_error(err);
+        if diag.span.is_none() {
+            diag.span = span.cloned();
+        }
+        diag
+    }
+
+    /// Renders the diagnostic with the offending source lines underlined.
+    pub fn render(&self, sources: &HashMap<String, String>) -> String {
+        let mut out = format!("{}[{}]: {}\n", self.severity, self.code, self.message);
+        if let Some(span) = &self.span {
+            render_snippet(&mut out, span, sources);
+        }
+        for note in &self.notes {
+            match &note.span {
+                Some(span) => {
+                    let _ = writeln!(out, "note: {}", note.message);
+                    render_snippet(&mut out, span, sources);
+                }
+                None => {
+                    let _ = writeln!(out, "  = note: {}", note.message);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let notes: Vec<String> = self
+            .notes
+            .iter()
+            .map(|n| {
+                format!(
+                    "{{\"message\":{},\"span\":{}}}",
+                    quote(&n.message),
+                    span_json(&n.span)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"severity\":\"{}\",\"code\":\"{}\",\"message\":{},\"span\":{},\"notes\":[{}]}}",
+            self.severity,
+            self.code,
+            quote(&self.message),
+            span_json(&self.span),
+            notes.join(",")
+        )
+    }
+}
+
+fn render_snippet(out: &mut String, span: &Span, sources: &HashMap<String, String>) {
+    let width = span.line.to_string().len();
+    let _ = writeln!(
+        out,
+        "{:width$}--> {}:{}:{}",
+        "", span.file, span.line, span.column
+    );
+
+    let line = sources
+        .get(&span.file)
+        .and_then(|src| src.lines().nth(span.line.saturating_sub(1)));
+    if let Some(line) = line {
+        // The lexer counts a tab as four columns
+        let line = line.replace('\t', "    ");
+        let start = span.column.saturating_sub(1);
+        let len = token_len(&line.chars().skip(start).collect::<String>());
+        let _ = writeln!(out, "{:width$} |", "");
+        let _ = writeln!(out, "{} | {}", span.line, line);
+        let _ = writeln!(
+            out,
+            "{:width$} | {}{}",
+            "",
+            " ".repeat(start),
+            "^".repeat(len)
+        );
+    }
+}
+
+/// Length of the token at the start of `rest`, to be underlined.
+fn token_len(rest: &str) -> usize {
+    let mut chars = rest.chars();
+    match chars.next() {
+        Some('"') => chars.position(|c| c == '"').map_or(rest.len(), |p| p + 2),
+        Some('<') if rest.starts_with("<-") => 2,
+        Some(c) if c.is_alphanumeric() || matches!(c, '_' | '.' | '\'') => {
+            1 + chars
+                .take_while(|c| c.is_alphanumeric() || matches!(c, '_' | '\''))
+                .count()
+        }
+        _ => 1,
+    }
+}
+
+fn span_json(span: &Option<Span>) -> String {
+    match span {
+        Some(s) => format!(
+            "{{\"file\":{},\"line\":{},\"column\":{}}}",
+            quote(&s.file),
+            s.line,
+            s.column
+        ),
+        None => "null".to_string(),
+    }
+}
+
+/// Every diagnostic produced while assembling a program, along with the sources
+/// needed to render them.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub items: Vec<Diagnostic>,
+    pub sources: HashMap<String, String>,
+}
+
+impl Diagnostics {
+    pub fn has_errors(&self) -> bool {
+        self.items.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn to_json(&self) -> String {
+        let items: Vec<String> = self.items.iter().map(Diagnostic::to_json).collect();
+        format!("[{}]", items.join(","))
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diag in &self.items {
+            writeln!(f, "{}", diag.render(&self.sources))?;
+        }
+        let errors = self
+            .items
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .count();
+        if errors > 0 {
+            write!(
+                f,
+                "error: could not assemble the program due to {errors} error(s)"
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_snippet_with_note() {
+        let sources = HashMap::from([(
+            "prog.asm".to_string(),
+            ".text\nmain:\n\tbeq a0, a1, loop\nmain:\n".to_string(),
+        )]);
+        let diag = Diagnostic::error(
+            "E0012",
+            "Label 'main' is already defined".to_string(),
+            Some(Span::new("prog.asm", 4, 1)),
+        )
+        .with_note(
+            "first defined here".to_string(),
+            Some(Span::new("prog.asm", 2, 1)),
+        )
+        .with_note("labels must be unique".to_string(), None);
+
+        let expected = "\
+error[E0012]: Label 'main' is already defined
+ --> prog.asm:4:1
+  |
+4 | main:
+  | ^^^^
+note: first defined here
+ --> prog.asm:2:1
+  |
+2 | main:
+  | ^^^^
+  = note: labels must be unique
+";
+        assert_eq!(diag.render(&sources), expected);
+
+        let diag = Diagnostic::error(
+            "E0016",
+            "Undefined label 'loop'".to_string(),
+            Some(Span::new("prog.asm", 3, 17)),
+        );
+        assert!(diag
+            .render(&sources)
+            .ends_with("3 |     beq a0, a1, loop\n  |                 ^^^^\n"));
+    }
+
+    #[test]
+    fn json_output() {
+        let diags = Diagnostics {
+            items: vec![
+                Diagnostic::error(
+                    "E0002",
+                    "Expected token: \"Comma\"".to_string(),
+                    Some(Span::new("a.asm", 1, 5)),
+                ),
+                Diagnostic::warning("W0001", "Unused".to_string(), None)
+                    .with_note("here\t".to_string(), None),
+            ],
+            sources: HashMap::new(),
+        };
+        assert_eq!(
+            diags.to_json(),
+            r#"[{"severity":"error","code":"E0002","message":"Expected token: \"Comma\"","span":{"file":"a.asm","line":1,"column":5},"notes":[]},{"severity":"warning","code":"W0001","message":"Unused","span":null,"notes":[{"message":"here\t","span":null}]}]"#
+        );
+    }
+}
@@ -0,0 +1,1348 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fmt::Write,
+};
+
+use crate::assembler::{
+    evaluator::{AsmEvaluator, BranchTo, Microinstruction, Operand},
+    sections::{BranchOp, DoubleOperandOpcode},
+};
+
+/// Registers by their A bus code. Code 8 is the immediate.
+const A_REGS: [&str; 25] = [
+    "mdr", "pc", "mbr", "mbru", "mbr2", "mbr2u", "lv", "cpp", "", "ra", "t0", "t1", "t2", "t3",
+    "s0", "s1", "s2", "s3", "s4", "s5", "s6", "a0", "a1", "a2", "a3",
+];
+/// Registers by their B bus code. Code 3 is the immediate.
+const B_REGS: [&str; 20] = [
+    "mdr", "lv", "cpp", "", "ra", "t0", "t1", "t2", "t3", "s0", "s1", "s2", "s3", "s4", "s5", "s6",
+    "a0", "a1", "a2", "a3",
+];
+/// Registers of the C bus, from the most significant bit down. CPP is the bit kept
+/// out of the C bus field.
+const C_REGS: [&str; 21] = [
+    "cpp", "mdr", "mar", "pc", "lv", "ra", "t0", "t1", "t2", "t3", "s0", "s1", "s2", "s3", "s4",
+    "s5", "s6", "a0", "a1", "a2", "a3",
+];
+const TEMPS: [&str; 4] = ["t0", "t1", "t2", "t3"];
+
+const ALU_PASS: u16 = 0b000011000;
+const ALU_NOT: u16 = 0b000011010;
+const ALU_OR: u16 = 0b000011100;
+const ALU_ADD: u16 = 0b000111100;
+const ALU_SUB: u16 = 0b000111111;
+const ALU_DEC: u16 = 0b000110110;
+const ALU_INC: u16 = 0b000110101;
+const ALU_AND: u16 = 0b000001100;
+const ALU_AND_NOT: u16 = 0b000001110;
+const ALU_SLL8: u16 = 0b0100011000;
+const ALU_SRL1: u16 = 0b1000011000;
+const ALU_SRA8: u16 = 0b1010011000;
+const ALU_SRL8: u16 = 0b1100011000;
+const ALU_SHIFT: u16 = 0b100000000;
+
+const JAM_BIT: u16 = 0b100000000;
+
+/// The shifts by an immediate and by a register, with their names.
+const SHIFTS: [(DoubleOperandOpcode, &str, &str); 3] = [
+    (DoubleOperandOpcode::Slli, "slli", "sllv"),
+    (DoubleOperandOpcode::Srli, "srli", "srlv"),
+    (DoubleOperandOpcode::Srai, "srai", "srav"),
+];
+
+/// An operand of a branch, as read from a bus.
+#[derive(Clone, Copy)]
+enum BranchOperand {
+    Reg(&'static str),
+    Imm(u8),
+}
+
+/// A run of words recognized as one instruction.
+struct Item {
+    addr: u16,
+    len: u16,
+    /// JAM targets and trampolines written by the instruction.
+    side_words: Vec<u16>,
+    /// The instruction, or `None` to dump its words with `.micro`.
+    text: Option<String>,
+    /// Address the instruction jumps to, written as a label after `text`.
+    target: Option<u16>,
+}
+
+impl Item {
+    fn new(addr: u16, len: u16, text: String) -> Self {
+        Self {
+            addr,
+            len,
+            side_words: Vec::new(),
+            text: Some(text),
+            target: None,
+        }
+    }
+
+    fn raw(addr: u16) -> Self {
+        Self {
+            addr,
+            len: 1,
+            side_words: Vec::new(),
+            text: None,
+            target: None,
+        }
+    }
+
+    fn contains(&self, addr: u16) -> bool {
+        (self.addr..self.addr + self.len).contains(&addr)
+    }
+}
+
+/// Turns a control store image back into Vondel assembly.
+///
+/// The words are matched against the sequences [`AsmEvaluator`] generates for each
+/// instruction, following JAM branches to their targets and trampolines. Words that
+/// match no sequence are written back verbatim with `.micro`, so assembling the
+/// output gives the same image.
+pub struct Disassembler<'a> {
+    rom: &'a [u64],
+    /// End of the sequential code, which grows from address 0.
+    end: u16,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(rom: &'a [u64]) -> Self {
+        let mut dis = Self { rom, end: 0 };
+
+        // A JAM branch that does not fall through to the next word goes through a
+        // trampoline, which is never part of the sequential code. JMPC and JMPR
+        // jumps don't use the upper half
+        let trampolines: HashSet<u16> = (0..JAM_BIT)
+            .filter(|&addr| !matches!(dis.word(addr), 0 | Microinstruction::HALT))
+            .map(|addr| (addr, dis.mi(addr)))
+            .filter(|(addr, mi)| {
+                matches!(mi.jam, 0b001..=0b011) && mi.next != addr + 1 && mi.next < JAM_BIT
+            })
+            .map(|(_, mi)| mi.next)
+            .collect();
+        dis.end = (0..JAM_BIT)
+            .find(|&addr| dis.word(addr) == 0 || trampolines.contains(&addr))
+            .unwrap_or(JAM_BIT);
+        dis
+    }
+
+    /// Disassembles the image, with `ram` as the initial contents of the `.data`
+    /// section.
+    pub fn disassemble(&self, ram: Option<&[u32]>) -> String {
+        let mut out = String::from("# Disassembled from a Vondel control store image\n");
+
+        if let Some(ram) = ram.filter(|r| !r.is_empty()) {
+            let _ = writeln!(out, ".data");
+            for chunk in ram.chunks(8) {
+                let words: Vec<String> = chunk.iter().map(|w| format!("{:#010x}", w)).collect();
+                let _ = writeln!(out, "    .word {}", words.join(", "));
+            }
+            out.push('\n');
+        }
+
+        let items = self.items();
+        let mut labels: BTreeSet<u16> = items.iter().filter_map(|i| i.target).collect();
+        labels.insert(0);
+
+        let mut claimed = HashSet::new();
+        for item in &items {
+            claimed.extend(item.addr..item.addr + item.len);
+            claimed.extend(item.side_words.iter().copied());
+        }
+
+        let _ = writeln!(out, ".text");
+        let _ = writeln!(out, "{}:", label(0));
+        // Words out of the sequential code that no instruction accounts for are taken
+        // first, so the instructions can't be laid out over them
+        for addr in 0..self.rom.len().min(2 * JAM_BIT as usize) as u16 {
+            if self.word(addr) != 0 && !claimed.contains(&addr) {
+                let _ = writeln!(out, "    .micro {:#05x}, {:#018x}", addr, self.word(addr));
+            }
+        }
+
+        for item in &items {
+            if item.addr != 0 && labels.contains(&item.addr) {
+                let _ = writeln!(out, "{}:", label(item.addr));
+            }
+            match &item.text {
+                Some(text) => {
+                    let target = item.target.map(label).unwrap_or_default();
+                    let _ = writeln!(out, "    {}{}", text, target);
+                }
+                None => {
+                    for addr in item.addr..item.addr + item.len {
+                        let _ = writeln!(out, "    .micro {:#018x}", self.word(addr));
+                    }
+                    for &addr in &item.side_words {
+                        let _ =
+                            writeln!(out, "    .micro {:#05x}, {:#018x}", addr, self.word(addr));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Splits the sequential code into instructions, leaving as raw words the ones
+    /// whose jumps can't be written with a label.
+    fn items(&self) -> Vec<Item> {
+        let mut items = Vec::new();
+        let mut pc = 0;
+        while pc < self.end {
+            let item = self.decode(pc).unwrap_or_else(|| Item::raw(pc));
+            pc += item.len;
+            items.push(item);
+        }
+
+        // A jump into the middle of an instruction needs its words one by one
+        let targets: Vec<u16> = items.iter().filter_map(|i| i.target).collect();
+        let mut split = Vec::new();
+        for item in items {
+            if targets.iter().any(|&t| t != item.addr && item.contains(t)) {
+                split.extend((item.addr..item.addr + item.len).map(Item::raw));
+                let last = split.len() - 1;
+                split[last].side_words = item.side_words;
+            } else {
+                split.push(item);
+            }
+        }
+
+        let starts: HashSet<u16> = split.iter().map(|i| i.addr).collect();
+        for item in &mut split {
+            if item.target.is_some_and(|t| !starts.contains(&t)) {
+                item.text = None;
+                item.target = None;
+            }
+        }
+        split
+    }
+
+    fn decode(&self, pc: u16) -> Option<Item> {
+        if self.word(pc) == Microinstruction::HALT {
+            return Some(Item::new(pc, 1, "halt".to_string()));
+        }
+        self.decode_read(pc)
+            .or_else(|| self.decode_write(pc))
+            .or_else(|| self.decode_push(pc))
+            .or_else(|| self.decode_pop(pc))
+            .or_else(|| self.decode_read_indexed(pc))
+            .or_else(|| self.decode_write_indexed(pc))
+            .or_else(|| self.decode_read_subword(pc))
+            .or_else(|| self.decode_write_subword(pc))
+            .or_else(|| self.decode_mul(pc))
+            .or_else(|| self.decode_shift_reg(pc))
+            .or_else(|| self.decode_branch(pc))
+            .or_else(|| self.decode_jalr(pc))
+            .or_else(|| self.decode_call(pc))
+            .or_else(|| self.decode_li(pc))
+            .or_else(|| self.decode_jal(pc))
+            .or_else(|| self.decode_shift(pc))
+            .or_else(|| self.decode_single(pc))
+    }
+
+    fn decode_read(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 2)?;
+        let addr = self.mi(pc).immediate;
+        let rds = self.mi(pc + 1).c_bus;
+
+        let mut read = Microinstruction::new(pc + 1);
+        read.c_bus = c_code(&["mar"]);
+        read.alu = ALU_PASS;
+        read.mem = 0b010;
+        read.a = Microinstruction::IMM_A;
+        read.immediate = addr;
+
+        let mut w_reg = Microinstruction::new(pc + 2);
+        w_reg.c_bus = rds;
+        w_reg.alu = ALU_PASS;
+        w_reg.a = a_code("mdr")?;
+
+        (rds != 0 && self.is(pc, &read) && self.is(pc + 1, &w_reg)).then(|| {
+            Item::new(
+                pc,
+                2,
+                format!("read {} <- {}", c_regs(rds).join(", "), addr),
+            )
+        })
+    }
+
+    fn decode_write(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 2)?;
+        let rd = a_reg(self.mi(pc).a)?;
+        let addr = self.mi(pc + 1).immediate;
+
+        let mut mdr = Microinstruction::new(pc + 1);
+        mdr.c_bus = c_code(&["mdr"]);
+        mdr.alu = ALU_PASS;
+        mdr.a = a_code(rd)?;
+
+        let mut mar = Microinstruction::new(pc + 2);
+        mar.c_bus = c_code(&["mar"]);
+        mar.alu = ALU_PASS;
+        mar.mem = 0b100;
+        mar.a = Microinstruction::IMM_A;
+        mar.immediate = addr;
+
+        (self.is(pc, &mdr) && self.is(pc + 1, &mar))
+            .then(|| Item::new(pc, 2, format!("write {} <- {}", addr, rd)))
+    }
+
+    fn decode_push(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 2)?;
+        let rs = a_reg(self.mi(pc).a)?;
+
+        let mut mdr = Microinstruction::new(pc + 1);
+        mdr.c_bus = c_code(&["mdr"]);
+        mdr.alu = ALU_PASS;
+        mdr.a = a_code(rs)?;
+
+        let mut mar = Microinstruction::new(pc + 2);
+        mar.c_bus = c_code(&["mar", "cpp"]);
+        mar.alu = ALU_DEC;
+        mar.mem = 0b100;
+        mar.b = b_code("cpp")?;
+
+        (self.is(pc, &mdr) && self.is(pc + 1, &mar))
+            .then(|| Item::new(pc, 2, format!("push {}", rs)))
+    }
+
+    fn decode_pop(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 3)?;
+        let rds = self.mi(pc + 1).c_bus;
+
+        let mut read = Microinstruction::new(pc + 1);
+        read.c_bus = c_code(&["mar"]);
+        read.alu = ALU_PASS;
+        read.mem = 0b010;
+        read.a = a_code("cpp")?;
+
+        let mut w_reg = Microinstruction::new(pc + 2);
+        w_reg.c_bus = rds;
+        w_reg.alu = ALU_PASS;
+        w_reg.a = a_code("mdr")?;
+
+        let mut inc = Microinstruction::new(pc + 3);
+        inc.c_bus = c_code(&["cpp"]);
+        inc.alu = ALU_INC;
+        inc.b = b_code("cpp")?;
+
+        (rds != 0 && self.is(pc, &read) && self.is(pc + 1, &w_reg) && self.is(pc + 2, &inc))
+            .then(|| Item::new(pc, 3, format!("pop {}", c_regs(rds).join(", "))))
+    }
+
+    fn decode_read_indexed(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 2)?;
+        let (addr, mut read) = indexed_word(&self.mi(pc), "mar")?;
+        read.next = pc + 1;
+        read.mem = 0b010;
+        let rds = self.mi(pc + 1).c_bus;
+
+        let mut w_reg = Microinstruction::new(pc + 2);
+        w_reg.c_bus = rds;
+        w_reg.alu = ALU_PASS;
+        w_reg.a = a_code("mdr")?;
+
+        (rds != 0 && self.is(pc, &read) && self.is(pc + 1, &w_reg)).then(|| {
+            Item::new(
+                pc,
+                2,
+                format!("read {} <- {}", c_regs(rds).join(", "), addr),
+            )
+        })
+    }
+
+    fn decode_write_indexed(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 2)?;
+        let (addr, mut mar) = indexed_word(&self.mi(pc), "mar")?;
+        mar.next = pc + 1;
+        let rs = a_reg(self.mi(pc + 1).a)?;
+
+        let mut mdr = Microinstruction::new(pc + 2);
+        mdr.c_bus = c_code(&["mdr"]);
+        mdr.alu = ALU_PASS;
+        mdr.mem = 0b100;
+        mdr.a = a_code(rs)?;
+
+        (self.is(pc, &mar) && self.is(pc + 1, &mdr))
+            .then(|| Item::new(pc, 2, format!("write {} <- {}", addr, rs)))
+    }
+
+    /// Matches the words that start a byte or halfword access, returning the
+    /// address, the register holding it and where the words that follow start.
+    fn decode_subword_read(&self, pc: u16) -> Option<(String, &'static str, u16)> {
+        self.fits(pc, 3)?;
+        let (addr, byte_addr, pc) = match indexed_word(&self.mi(pc), "t0") {
+            Some((addr, mut mi)) if self.mi(pc).c_bus == c_code(&["t0"]) => {
+                mi.next = pc + 1;
+                self.is(pc, &mi).then_some((addr, "t0", pc + 1))?
+            }
+            _ => {
+                let base = a_reg(self.mi(pc).a)?;
+                (format!("[{}]", base), base, pc)
+            }
+        };
+        self.fits(pc, 2)?;
+
+        let mut t1 = Microinstruction::new(pc + 1);
+        t1.c_bus = c_code(&["t1"]);
+        t1.alu = ALU_SRL1;
+        t1.a = a_code(byte_addr)?;
+
+        let mut mar = Microinstruction::new(pc + 2);
+        mar.c_bus = c_code(&["mar"]);
+        mar.alu = ALU_SRL1;
+        mar.mem = 0b010;
+        mar.a = a_code("t1")?;
+
+        (self.is(pc, &t1) && self.is(pc + 1, &mar)).then_some((addr, byte_addr, pc + 2))
+    }
+
+    fn decode_read_subword(&self, pc: u16) -> Option<Item> {
+        let (addr, byte_addr, start) = self.decode_subword_read(pc)?;
+        let count = self.mi(start);
+        let mask = count.immediate;
+        if !matches!(mask, 2 | 3) {
+            return None;
+        }
+        self.fits(start, 4 + mask as u16)?;
+        let tail = start + 4;
+        let rd = self.mi(start + 1).c_bus;
+        let rd0 = a_reg(self.mi(start + 3).a).filter(|r| c_regs(rd).contains(r))?;
+        let (zero, mut side_words) = self.jam_slot(&count, start + 1)?;
+        let dec = self.mi(start + 2);
+        let (exit, exit_words) = self.jam_slot(&dec, start + 3)?;
+        side_words.extend(exit_words);
+        let sr8 = self.mi(tail).alu;
+        let name = match (mask, sr8) {
+            (3, ALU_SRA8) => "readb",
+            (3, ALU_SRL8) => "readbu",
+            (2, ALU_SRA8) => "readh",
+            (2, ALU_SRL8) => "readhu",
+            _ => return None,
+        };
+
+        let word = |next: u16, alu: u16, a: &str| -> Option<Microinstruction> {
+            let mut mi = Microinstruction::new(next);
+            mi.alu = alu;
+            mi.c_bus = rd;
+            mi.a = a_code(a)?;
+            Some(mi)
+        };
+
+        let mut cnt = Microinstruction::new(count.next);
+        cnt.jam = 0b001;
+        cnt.alu = ALU_AND_NOT;
+        cnt.c_bus = c_code(&["t0"]);
+        cnt.a = a_code(byte_addr)?;
+        cnt.b = Microinstruction::IMM_B;
+        cnt.immediate = mask;
+
+        let mut expected = vec![
+            (start, cnt),
+            (start + 1, word(start + 2, ALU_SLL8, "mdr")?),
+            (start + 2, dec_t0(dec.next)?),
+            (start + 3, word(start + 2, ALU_SLL8, rd0)?),
+            (zero, word(tail, ALU_PASS, "mdr")?),
+            (exit, Microinstruction::new(tail)),
+        ];
+        for addr in tail..tail + mask as u16 {
+            expected.push((addr, word(addr + 1, sr8, rd0)?));
+        }
+        if !expected.iter().all(|(addr, mi)| self.is(*addr, mi)) {
+            return None;
+        }
+
+        let mut rds = c_regs(rd);
+        let pos = rds.iter().position(|r| *r == rd0)?;
+        rds.remove(pos);
+        rds.insert(0, rd0);
+        let end = tail + mask as u16;
+        Some(Item {
+            side_words,
+            ..Item::new(
+                pc,
+                end - pc,
+                format!("{} {} <- {}", name, rds.join(", "), addr),
+            )
+        })
+    }
+
+    fn decode_write_subword(&self, pc: u16) -> Option<Item> {
+        let (addr, byte_addr, start) = self.decode_subword_read(pc)?;
+        self.fits(start, 2)?;
+        let t1 = c_code(&["t1"]);
+        let t2 = c_code(&["t2"]);
+        let word = |next: u16, alu: u16, c_bus: u32, a: u8, b: u8, imm: u8| {
+            let mut mi = Microinstruction::new(next);
+            (mi.alu, mi.c_bus, mi.a, mi.b, mi.immediate) = (alu, c_bus, a, b, imm);
+            mi
+        };
+        let imm_a = Microinstruction::IMM_A;
+        let imm_b = Microinstruction::IMM_B;
+        let no_b = Microinstruction::NO_B;
+
+        let (name, mask, rs, mut expected) = if self.mi(start).c_bus == t1 {
+            let rs = a_reg(self.mi(start).a)?;
+            let value = vec![
+                (
+                    start,
+                    word(start + 1, ALU_AND, t1, a_code(rs)?, imm_b, 0xFF),
+                ),
+                (start + 1, word(start + 2, ALU_PASS, t2, imm_a, no_b, 0xFF)),
+            ];
+            ("writeb", 3, rs, value)
+        } else {
+            self.fits(start, 3)?;
+            let rs = a_reg(self.mi(start + 2).a)?;
+            let value = vec![
+                (start, word(start + 1, ALU_SLL8, t2, imm_a, no_b, 0xFF)),
+                (
+                    start + 1,
+                    word(start + 2, ALU_OR, t2, a_code("t2")?, imm_b, 0xFF),
+                ),
+                (
+                    start + 2,
+                    word(start + 3, ALU_AND, t1, a_code(rs)?, b_code("t2")?, 0),
+                ),
+            ];
+            ("writeh", 2, rs, value)
+        };
+        let count_addr = start + expected.len() as u16;
+        self.fits(count_addr, 8)?;
+        let count = self.mi(count_addr);
+        let (zero, mut side_words) = self.jam_slot(&count, count_addr + 1)?;
+        let dec = self.mi(count_addr + 3);
+        let (exit, exit_words) = self.jam_slot(&dec, count_addr + 4)?;
+        side_words.extend(exit_words);
+
+        let mut cnt = word(
+            count.next,
+            ALU_AND,
+            c_code(&["t0"]),
+            a_code(byte_addr)?,
+            imm_b,
+            mask,
+        );
+        cnt.jam = 0b001;
+        let shift = |next: u16, reg: &str| {
+            word(
+                next,
+                ALU_SLL8,
+                c_code(&[reg]),
+                a_code(reg).unwrap(),
+                no_b,
+                0,
+            )
+        };
+        let combine = count_addr + 6;
+        let mut write = word(
+            combine + 2,
+            ALU_OR,
+            c_code(&["mdr"]),
+            a_code("t1")?,
+            b_code("mdr")?,
+            0,
+        );
+        write.mem = 0b100;
+        expected.extend([
+            (count_addr, cnt),
+            (count_addr + 1, shift(count_addr + 2, "t1")),
+            (count_addr + 2, shift(count_addr + 3, "t2")),
+            (count_addr + 3, dec_t0(dec.next)?),
+            (count_addr + 4, shift(count_addr + 5, "t1")),
+            (count_addr + 5, shift(count_addr + 3, "t2")),
+            (zero, Microinstruction::new(combine)),
+            (exit, Microinstruction::new(combine)),
+            (
+                combine,
+                word(
+                    combine + 1,
+                    ALU_AND_NOT,
+                    c_code(&["mdr"]),
+                    a_code("t2")?,
+                    b_code("mdr")?,
+                    0,
+                ),
+            ),
+            (combine + 1, write),
+        ]);
+        if !expected.iter().all(|(addr, mi)| self.is(*addr, mi)) {
+            return None;
+        }
+        Some(Item {
+            side_words,
+            ..Item::new(pc, combine + 2 - pc, format!("{} {} <- {}", name, addr, rs))
+        })
+    }
+
+    fn decode_mul(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 6)?;
+        let first = self.mi(pc);
+        let rs1 = a_reg(first.a)?;
+        let rs2 = b_reg(first.b)?;
+        if TEMPS.contains(&rs1) || TEMPS.contains(&rs2) {
+            return None;
+        }
+        let rd = self.mi(pc + 4).c_bus & !c_code(&["t1"]);
+        let mut rds = c_regs(rd);
+        // The first destination is the one checked against the temporaries
+        rds.sort_by_key(|r| TEMPS.contains(r));
+        if rds.first().is_none_or(|r| TEMPS.contains(r)) {
+            return None;
+        }
+        let (jumped, mut side_words) = self.jam_slot(&first, pc + 1)?;
+        let dec = self.mi(pc + 3);
+        let (exit, exit_words) = self.jam_slot(&dec, pc + 4)?;
+        side_words.extend(exit_words);
+
+        let loop_addr = pc + 3;
+        let move_to = |next: u16, c_bus: u32, a: &str| -> Option<Microinstruction> {
+            let mut mi = Microinstruction::new(next);
+            mi.alu = ALU_PASS;
+            mi.c_bus = c_bus;
+            mi.a = a_code(a)?;
+            Some(mi)
+        };
+
+        let mut cmp = Microinstruction::new(first.next);
+        cmp.jam = 0b010;
+        cmp.alu = ALU_SUB;
+        cmp.a = first.a;
+        cmp.b = first.b;
+
+        let mut dec_t0 = Microinstruction::new(dec.next);
+        dec_t0.jam = 0b001;
+        dec_t0.alu = ALU_DEC;
+        dec_t0.c_bus = c_code(&["t0"]);
+        dec_t0.b = b_code("t0")?;
+
+        let mut add = Microinstruction::new(loop_addr);
+        add.c_bus = c_code(&["t1"]) | rd;
+        add.alu = ALU_ADD;
+        add.a = a_code("t1")?;
+        add.b = b_code("t2")?;
+
+        let t1_t2 = c_code(&["t1", "t2"]) | rd;
+        let expected = [
+            (pc, cmp),
+            (pc + 1, move_to(pc + 2, c_code(&["t0"]), rs1)?),
+            (pc + 2, move_to(loop_addr, t1_t2, rs2)?),
+            (loop_addr, dec_t0),
+            (pc + 4, add),
+            (jumped, move_to(pc + 5, c_code(&["t0"]), rs2)?),
+            (pc + 5, move_to(loop_addr, t1_t2, rs1)?),
+            (exit, Microinstruction::new(pc + 6)),
+        ];
+        expected
+            .iter()
+            .all(|(addr, mi)| self.is(*addr, mi))
+            .then(|| Item {
+                side_words,
+                ..Item::new(pc, 6, format!("mul {} <- {}, {}", rds.join(", "), rs1, rs2))
+            })
+    }
+
+    fn decode_branch(&self, pc: u16) -> Option<Item> {
+        // The XOR the ordered comparisons start with keeps the operands in their
+        // order, so each one gives different words
+        [
+            BranchOp::Bne,
+            BranchOp::Bltu,
+            BranchOp::Bgtu,
+            BranchOp::Bgeu,
+            BranchOp::Bleu,
+            BranchOp::Beq,
+            BranchOp::Bgt,
+            BranchOp::Bge,
+            BranchOp::Blt,
+            BranchOp::Ble,
+        ]
+        .into_iter()
+        .find_map(|op| self.decode_branch_op(pc, op))
+    }
+
+    /// Matches the words [`AsmEvaluator::branch_steps`] generates for `op`, with
+    /// the operands read from the buses of the first one.
+    fn decode_branch_op(&self, pc: u16, op: BranchOp) -> Option<Item> {
+        let steps = AsmEvaluator::branch_steps(op);
+        self.fits(pc, steps.len() as u16)?;
+        let first = self.mi(pc);
+        let a_operand = match first.a {
+            Microinstruction::IMM_A => BranchOperand::Imm(first.immediate),
+            code => BranchOperand::Reg(a_reg(code)?),
+        };
+        let b_operand = match first.b {
+            Microinstruction::IMM_B => BranchOperand::Imm(first.immediate),
+            code => BranchOperand::Reg(b_reg(code)?),
+        };
+        let operand = |o: Operand| match (steps[0].a == Some(o), steps[0].b == Some(o)) {
+            (true, _) => Some(a_operand),
+            (_, true) => Some(b_operand),
+            _ => None,
+        };
+        let BranchOperand::Reg(rs1) = operand(Operand::Rs1)? else {
+            return None;
+        };
+        let rs2 = operand(Operand::Rs2)?;
+
+        let mut target = None;
+        let mut side_words = Vec::new();
+        for (i, step) in steps.iter().enumerate() {
+            let addr = pc + i as u16;
+            let word = self.mi(addr);
+            let mut mi = Microinstruction::new(addr + 1);
+            mi.alu = step.alu;
+            for (bus, o) in [(true, step.a), (false, step.b)] {
+                let Some(o) = o else { continue };
+                let code = match (operand(o)?, bus) {
+                    (BranchOperand::Reg(r), true) => a_code(r)?,
+                    (BranchOperand::Reg(r), false) => b_code(r)?,
+                    (BranchOperand::Imm(imm), true) => {
+                        mi.immediate = imm;
+                        Microinstruction::IMM_A
+                    }
+                    (BranchOperand::Imm(imm), false) => {
+                        mi.immediate = imm;
+                        Microinstruction::IMM_B
+                    }
+                };
+                match bus {
+                    true => mi.a = code,
+                    false => mi.b = code,
+                }
+            }
+
+            let to = match step.to {
+                BranchTo::Step(k) => Some(pc + k),
+                BranchTo::Label => None,
+            };
+            if step.jam == 0 {
+                mi.next = to?;
+                if !self.is(addr, &mi) {
+                    return None;
+                }
+                continue;
+            }
+
+            let (slot, words) = self.jam_slot(&word, addr + 1)?;
+            mi.jam = step.jam;
+            mi.next = word.next;
+            let jump = self.mi(slot).next;
+            if !self.is(addr, &mi) || !self.is(slot, &Microinstruction::new(jump)) {
+                return None;
+            }
+            match to {
+                Some(to) if to != jump => return None,
+                Some(_) => {}
+                None if *target.get_or_insert(jump) != jump => return None,
+                None => {}
+            }
+            side_words.extend(words);
+        }
+
+        let name = format!("{:?}", op).to_lowercase();
+        let text = match rs2 {
+            BranchOperand::Imm(0) if !name.ends_with('u') => format!("{}z {}, ", name, rs1),
+            BranchOperand::Imm(imm) => format!("{} {}, {}, ", name, rs1, imm),
+            BranchOperand::Reg(rs2) => format!("{} {}, {}, ", name, rs1, rs2),
+        };
+        Some(Item {
+            side_words,
+            target: Some(target?),
+            ..Item::new(pc, steps.len() as u16, text)
+        })
+    }
+
+    fn decode_li(&self, pc: u16) -> Option<Item> {
+        let first = self.mi(pc);
+        if first.a != Microinstruction::IMM_A || first.b != Microinstruction::NO_B {
+            return None;
+        }
+        // A single unshifted load is a `lui`
+        if !matches!(first.alu & !ALU_SHIFT, ALU_PASS | ALU_NOT) || first.alu == ALU_PASS {
+            return None;
+        }
+        // A constant ending in a zero byte is one step shorter, so the longest
+        // sequence of steps that rebuilds its constant is taken
+        (1..=4).rev().find_map(|len| self.decode_li_steps(pc, len))
+    }
+
+    fn decode_li_steps(&self, pc: u16, len: u16) -> Option<Item> {
+        self.fits(pc, len)?;
+        let first = self.mi(pc);
+        let steps: Vec<(u8, u16)> = (pc..pc + len)
+            .map(|addr| (self.mi(addr).immediate, self.mi(addr).alu))
+            .collect();
+
+        let mut value = 0u32;
+        for (i, &(imm, alu)) in steps.iter().enumerate() {
+            value = match i {
+                0 if alu & !ALU_SHIFT == ALU_NOT => !(imm as u32),
+                0 => imm as u32,
+                _ => value | imm as u32,
+            };
+            if alu & ALU_SHIFT != 0 {
+                value <<= 8;
+            }
+        }
+        if AsmEvaluator::li_steps(value) != steps {
+            return None;
+        }
+
+        // The first destination accumulates the constant through the A bus
+        let mut rds = c_regs(first.c_bus);
+        let rd0 = match len {
+            1 => *rds.iter().find(|r| a_code(r).is_some())?,
+            _ => a_reg(self.mi(pc + 1).a)?,
+        };
+        let pos = rds.iter().position(|r| *r == rd0)?;
+        rds.remove(pos);
+        rds.insert(0, rd0);
+
+        for (i, &(imm, alu)) in steps.iter().enumerate() {
+            let addr = pc + i as u16;
+            let mut mi = Microinstruction::new(addr + 1);
+            mi.c_bus = first.c_bus;
+            mi.alu = alu;
+            mi.immediate = imm;
+            if i == 0 {
+                mi.a = Microinstruction::IMM_A;
+            } else {
+                mi.a = a_code(rd0)?;
+                mi.b = Microinstruction::IMM_B;
+            }
+            if !self.is(addr, &mi) {
+                return None;
+            }
+        }
+
+        let value = if (value as i32) < 0 {
+            (value as i32).to_string()
+        } else {
+            value.to_string()
+        };
+        Some(Item::new(
+            pc,
+            len,
+            format!("li {} <- {}", rds.join(", "), value),
+        ))
+    }
+
+    fn decode_shift(&self, pc: u16) -> Option<Item> {
+        let shift = |addr: u16| -> Option<Microinstruction> {
+            let mi = self.mi(addr);
+            let mut plain = Microinstruction::new(addr + 1);
+            (plain.alu, plain.c_bus, plain.a) = (mi.alu, mi.c_bus, mi.a);
+            (mi.c_bus != 0 && mi.a != Microinstruction::IMM_A && self.is(addr, &plain))
+                .then_some(mi)
+        };
+
+        self.fits(pc, 1)?;
+        let first = shift(pc)?;
+        let rs = a_reg(first.a)?;
+        let (op, name, _) = SHIFTS.iter().find(|(op, ..)| {
+            let (by_1, by_8) = (
+                AsmEvaluator::shift_steps(op, 1)[0],
+                AsmEvaluator::shift_steps(op, 8)[0],
+            );
+            first.alu == by_1 || first.alu == by_8
+        })?;
+        let by_8 = AsmEvaluator::shift_steps(op, 8)[0];
+
+        // The first destination accumulates the shifts through the A bus
+        let mut rds = c_regs(first.c_bus);
+        let rd0 = match pc + 1 < self.end {
+            true => a_reg(self.mi(pc + 1).a).filter(|r| rds.contains(r)),
+            false => None,
+        };
+        let mut alus = vec![first.alu];
+        if let Some(rd0) = rd0 {
+            let mut addr = pc + 1;
+            while addr < self.end {
+                let Some(mi) = shift(addr) else { break };
+                if mi.c_bus != first.c_bus || mi.a != a_code(rd0)? {
+                    break;
+                }
+                alus.push(mi.alu);
+                addr += 1;
+            }
+        }
+
+        // The longest run of words that is the whole sequence of a shift
+        let (len, n) = (1..=alus.len()).rev().find_map(|len| {
+            let n: u32 = alus[..len]
+                .iter()
+                .map(|&alu| if alu == by_8 { 8 } else { 1 })
+                .sum();
+            let n = u8::try_from(n).ok().filter(|&n| n <= 31)?;
+            (AsmEvaluator::shift_steps(op, n) == alus[..len]).then_some((len, n))
+        })?;
+        // A single shift by 1, or to the left by 8, has its own instruction
+        if len == 1 && (first.alu != by_8 || *op == DoubleOperandOpcode::Slli) {
+            return None;
+        }
+        if let Some(rd0) = rd0.filter(|_| len > 1) {
+            let pos = rds.iter().position(|r| *r == rd0)?;
+            rds.remove(pos);
+            rds.insert(0, rd0);
+        }
+        Some(Item::new(
+            pc,
+            len as u16,
+            format!("{} {} <- {}, {}", name, rds.join(", "), rs, n),
+        ))
+    }
+
+    fn decode_shift_reg(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 4)?;
+        let first = self.mi(pc);
+        let rt = a_reg(first.a)?;
+        let step = self.mi(pc + 1);
+        let rs = a_reg(step.a)?;
+        let rd = step.c_bus;
+        let mut rds = c_regs(rd);
+        let rd0 = a_reg(self.mi(pc + 3).a).filter(|r| rds.contains(r))?;
+        let (op, _, name) = SHIFTS
+            .iter()
+            .find(|(op, ..)| AsmEvaluator::shift_steps(op, 1)[0] == step.alu)?;
+        if [rs, rt].contains(&"t0") || rds.contains(&"t0") {
+            return None;
+        }
+        let by_1 = AsmEvaluator::shift_steps(op, 1)[0];
+
+        let (zero, mut side_words) = self.jam_slot(&first, pc + 1)?;
+        let dec = self.mi(pc + 2);
+        let (exit, exit_words) = self.jam_slot(&dec, pc + 3)?;
+        side_words.extend(exit_words);
+
+        let shift = |next: u16, alu: u16, a: &str| -> Option<Microinstruction> {
+            let mut mi = Microinstruction::new(next);
+            mi.alu = alu;
+            mi.c_bus = rd;
+            mi.a = a_code(a)?;
+            Some(mi)
+        };
+
+        let mut mask = Microinstruction::new(first.next);
+        mask.jam = 0b001;
+        mask.alu = ALU_AND;
+        mask.c_bus = c_code(&["t0"]);
+        mask.a = a_code(rt)?;
+        mask.b = Microinstruction::IMM_B;
+        mask.immediate = 31;
+
+        let mut dec_t0 = Microinstruction::new(dec.next);
+        dec_t0.jam = 0b001;
+        dec_t0.alu = ALU_DEC;
+        dec_t0.c_bus = c_code(&["t0"]);
+        dec_t0.b = b_code("t0")?;
+
+        let expected = [
+            (pc, mask),
+            (pc + 1, shift(pc + 2, by_1, rs)?),
+            (pc + 2, dec_t0),
+            (pc + 3, shift(pc + 2, by_1, rd0)?),
+            (zero, shift(pc + 4, ALU_PASS, rs)?),
+            (exit, Microinstruction::new(pc + 4)),
+        ];
+        if !expected.iter().all(|(addr, mi)| self.is(*addr, mi)) {
+            return None;
+        }
+        let pos = rds.iter().position(|r| *r == rd0)?;
+        rds.remove(pos);
+        rds.insert(0, rd0);
+        Some(Item {
+            side_words,
+            ..Item::new(
+                pc,
+                4,
+                format!("{} {} <- {}, {}", name, rds.join(", "), rs, rt),
+            )
+        })
+    }
+
+    fn decode_jalr(&self, pc: u16) -> Option<Item> {
+        let jump = |addr: u16, rs: &str| -> Option<Microinstruction> {
+            let mut mi = Microinstruction::new(0);
+            mi.jam = Microinstruction::JMPR;
+            mi.alu = ALU_PASS;
+            mi.a = a_code(rs)?;
+            self.is(addr, &mi).then_some(mi)
+        };
+
+        self.fits(pc, 1)?;
+        let rs = a_reg(self.mi(pc).a);
+        if rs == Some("ra") && jump(pc, "ra").is_some() {
+            return Some(Item::new(pc, 1, "ret".to_string()));
+        }
+
+        self.fits(pc, 2)?;
+        let rs = a_reg(self.mi(pc + 1).a)?;
+        jump(pc + 1, rs)?;
+        let link = self.mi(pc);
+        let rd = match c_regs(link.c_bus)[..] {
+            [rd] if rd != rs => rd,
+            _ => return None,
+        };
+        let mut mi = Microinstruction::new(pc + 1);
+        mi.alu = ALU_PASS;
+        mi.a = Microinstruction::IMM_A;
+        mi.immediate = u8::try_from(pc + 2).ok()?;
+        mi.c_bus = link.c_bus;
+        self.is(pc, &mi)
+            .then(|| Item::new(pc, 2, format!("jalr {}, {}", rd, rs)))
+    }
+
+    fn decode_call(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 1)?;
+        let next = self.mi(pc).next;
+        let mut mi = Microinstruction::new(next);
+        mi.alu = ALU_PASS;
+        mi.a = Microinstruction::IMM_A;
+        mi.immediate = u8::try_from(pc + 1).ok()?;
+        mi.c_bus = c_code(&["ra"]);
+        // Calling the next word is the same as loading the return address
+        if next == pc + 1 || !self.is(pc, &mi) {
+            return None;
+        }
+        Some(Item {
+            target: Some(next),
+            ..Item::new(pc, 1, "call ".to_string())
+        })
+    }
+
+    fn decode_jal(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 1)?;
+        let next = self.mi(pc).next;
+        if !self.is(pc, &Microinstruction::new(next)) {
+            return None;
+        }
+        if next == pc + 1 {
+            return Some(Item::new(pc, 1, "nop".to_string()));
+        }
+        Some(Item {
+            target: Some(next),
+            ..Item::new(pc, 1, "jal ".to_string())
+        })
+    }
+
+    fn decode_single(&self, pc: u16) -> Option<Item> {
+        self.fits(pc, 1)?;
+        let mi = self.mi(pc);
+        if mi.next != pc + 1 || mi.jam != 0 || mi.mem != 0 || mi.c_bus == 0 {
+            return None;
+        }
+        let rds = c_regs(mi.c_bus).join(", ");
+        let imm_a = mi.a == Microinstruction::IMM_A;
+        let imm_b = mi.b == Microinstruction::IMM_B;
+        // The immediate is left zeroed when no bus reads it
+        if !imm_a && !imm_b && mi.immediate != 0 {
+            return None;
+        }
+
+        let text = if mi.b == Microinstruction::NO_B {
+            let op = match mi.alu {
+                ALU_PASS if imm_a => {
+                    return Some(Item::new(pc, 1, format!("lui {} <- {}", rds, mi.immediate)))
+                }
+                ALU_PASS => "mov",
+                ALU_NOT => "not",
+                0b100011000 => "sll",
+                0b010011000 => "sra",
+                0b110011000 => "sla",
+                0b1000011000 => "srl",
+                0b1110011000 => "rol",
+                _ => return None,
+            };
+            format!("{} {} <- {}", op, rds, a_reg(mi.a)?)
+        } else if mi.alu == ALU_SUB {
+            // sub takes its first operand from the B bus
+            let rs1 = b_reg(mi.b)?;
+            match imm_a {
+                true => format!("subi {} <- {}, {}", rds, rs1, mi.immediate),
+                false => format!("sub {} <- {}, {}", rds, rs1, a_reg(mi.a)?),
+            }
+        } else {
+            let (op, op_imm) = match mi.alu {
+                ALU_ADD => ("add", "addi"),
+                ALU_PASS => ("and", "andi"),
+                ALU_OR => ("or", "ori"),
+                0b001001100 => ("xor", "xori"),
+                0b001011100 => ("mul2", "muli"),
+                0b001101100 => ("div", "divi"),
+                0b001111100 => ("mod", "modi"),
+                _ => return None,
+            };
+            let rs1 = a_reg(mi.a)?;
+            match imm_b {
+                true => format!("{} {} <- {}, {}", op_imm, rds, rs1, mi.immediate),
+                false => format!("{} {} <- {}, {}", op, rds, rs1, b_reg(mi.b)?),
+            }
+        };
+        Some(Item::new(pc, 1, text))
+    }
+
+    /// Finds the word a JAM branch from `mi`, falling through to `next`, lands on,
+    /// along with the words it takes outside the sequential code.
+    fn jam_slot(&self, mi: &Microinstruction, next: u16) -> Option<(u16, Vec<u16>)> {
+        if mi.next == next {
+            return Some((next | JAM_BIT, vec![next | JAM_BIT]));
+        }
+        let trampoline = mi.next;
+        (trampoline < JAM_BIT && self.is(trampoline, &Microinstruction::new(next)))
+            .then(|| (trampoline | JAM_BIT, vec![trampoline, trampoline | JAM_BIT]))
+    }
+
+    /// Checks that `len` words from `pc` are part of the sequential code.
+    fn fits(&self, pc: u16, len: u16) -> Option<()> {
+        (pc + len <= self.end).then_some(())
+    }
+
+    fn is(&self, addr: u16, mi: &Microinstruction) -> bool {
+        self.word(addr) == mi.get()
+    }
+
+    fn mi(&self, addr: u16) -> Microinstruction {
+        Microinstruction::decode(self.word(addr))
+    }
+
+    fn word(&self, addr: u16) -> u64 {
+        self.rom.get(addr as usize).copied().unwrap_or(0)
+    }
+}
+
+/// Reads the indexed address a word loads `rd` with, returning it along with the
+/// word that loads it, without its memory operation.
+fn indexed_word(mi: &Microinstruction, rd: &str) -> Option<(String, Microinstruction)> {
+    let mut mar = Microinstruction::new(0);
+    mar.c_bus = c_code(&[rd]);
+    mar.alu = mi.alu;
+    let addr = match mi.alu {
+        ALU_PASS if mi.b == Microinstruction::NO_B => {
+            mar.a = mi.a;
+            format!("[{}]", a_reg(mi.a)?)
+        }
+        ALU_ADD if mi.b == Microinstruction::IMM_B && mi.immediate != 0 => {
+            (mar.a, mar.b, mar.immediate) = (mi.a, mi.b, mi.immediate);
+            format!("[{} + {}]", a_reg(mi.a)?, mi.immediate)
+        }
+        ALU_SUB if mi.a == Microinstruction::IMM_A && mi.immediate != 0 => {
+            (mar.a, mar.b, mar.immediate) = (mi.a, mi.b, mi.immediate);
+            format!("[{} - {}]", b_reg(mi.b)?, mi.immediate)
+        }
+        _ => return None,
+    };
+    Some((addr, mar))
+}
+
+/// T0 <- T0 - 1, jumping when it reaches zero.
+fn dec_t0(next: u16) -> Option<Microinstruction> {
+    let mut mi = Microinstruction::new(next);
+    mi.jam = 0b001;
+    mi.alu = ALU_DEC;
+    mi.c_bus = c_code(&["t0"]);
+    mi.b = b_code("t0")?;
+    Some(mi)
+}
+
+fn label(addr: u16) -> String {
+    format!("L{:03x}", addr)
+}
+
+fn a_reg(code: u8) -> Option<&'static str> {
+    A_REGS.get(code as usize).copied().filter(|r| !r.is_empty())
+}
+
+fn b_reg(code: u8) -> Option<&'static str> {
+    B_REGS.get(code as usize).copied().filter(|r| !r.is_empty())
+}
+
+fn a_code(reg: &str) -> Option<u8> {
+    A_REGS.iter().position(|r| *r == reg).map(|c| c as u8)
+}
+
+fn b_code(reg: &str) -> Option<u8> {
+    B_REGS.iter().position(|r| *r == reg).map(|c| c as u8)
+}
+
+fn c_regs(c_bus: u32) -> Vec<&'static str> {
+    C_REGS
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| c_bus & (1 << (20 - i)) != 0)
+        .map(|(_, r)| *r)
+        .collect()
+}
+
+fn c_code(regs: &[&str]) -> u32 {
+    C_REGS
+        .iter()
+        .enumerate()
+        .filter(|(_, r)| regs.contains(r))
+        .fold(0, |c, (i, _)| c | 1 << (20 - i))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(src: &str) -> Vec<u64> {
+        let mut evaluator = AsmEvaluator::new();
+        let (cs, _) = evaluator.evaluate_buffer(src).unwrap();
+        cs.firmware().to_vec()
+    }
+
+    fn round_trip(src: &str) -> String {
+        let rom = assemble(src);
+        let asm = Disassembler::new(&rom).disassemble(None);
+        assert_eq!(assemble(&asm), rom, "{}", asm);
+        asm
+    }
+
+    #[test]
+    fn round_trip_programs() {
+        for src in [
+            include_str!("../../programs/power.asm"),
+            include_str!("../../programs/factorial.asm"),
+            include_str!("../../programs/div.asm"),
+            include_str!("../../programs/csw.asm"),
+        ] {
+            round_trip(src);
+        }
+    }
+
+    #[test]
+    fn disassemble_every_instruction() {
+        let asm = round_trip(
+            "
+            .text
+            main:
+                add a0, s1 <- a1, t2
+                addi a0 <- a1, 200
+                sub a0 <- a1, a2
+                subi a0 <- mdr, 3
+                and t0 <- t1, lv
+                ori ra <- s6, 0xF0
+                xor a3 <- a2, a1
+                mul2 s0 <- s1, s2
+                divi s0 <- s1, 2
+                mod s0 <- s1, cpp
+                lui a0, mar <- 7
+                mov s3 <- mbr2u
+                not pc <- a0
+                sll a0 <- a0
+                srl a1 <- a0
+                rol a1 <- a0
+                slli s3, s2 <- a0, 10
+                srai s2 <- a0, 8
+                srav s4 <- a0, a1
+                li a1, a2 <- 0x12345678
+                li a1 <- -2
+                li a1 <- 0x1200
+                read a0, a1 <- 12
+                write 13 <- a2
+                read a0 <- [s0]
+                read a0, a1 <- [s0 + 4]
+                write [mdr - 255] <- a2
+                readb a0 <- [s0 + 3]
+                readhu a1, a0 <- [s1]
+                writeb [s0 - 1] <- a1
+                writeh [s1] <- a2
+                push lv
+                pop s1, s0
+                mov cpp <- a0
+                mul s0, t2 <- a0, a1
+                bne a0, a1, main
+                blt a0, a1, done
+                bge a0, 7, main
+                blez a2, main
+                bltu a0, a1, main
+                bgeu a3, 9, main
+                call done
+                jalr t0, s6
+                ret
+                nop
+            done:
+                jal main
+                halt
+            ",
+        );
+        let expected = "\
+# Disassembled from a Vondel control store image
+.text
+L000:
+    add s1, a0 <- a1, t2
+    addi a0 <- a1, 200
+    sub a0 <- a1, a2
+    subi a0 <- mdr, 3
+    and t0 <- t1, lv
+    ori ra <- s6, 240
+    xor a3 <- a2, a1
+    mul2 s0 <- s1, s2
+    divi s0 <- s1, 2
+    mod s0 <- s1, cpp
+    lui mar, a0 <- 7
+    mov s3 <- mbr2u
+    not pc <- a0
+    sll a0 <- a0
+    srl a1 <- a0
+    rol a1 <- a0
+    slli s3, s2 <- a0, 10
+    srai s2 <- a0, 8
+    srav s4 <- a0, a1
+    li a1, a2 <- 305419896
+    li a1 <- -2
+    li a1 <- 4608
+    read a0, a1 <- 12
+    write 13 <- a2
+    read a0 <- [s0]
+    read a0, a1 <- [s0 + 4]
+    write [mdr - 255] <- a2
+    readb a0 <- [s0 + 3]
+    readhu a1, a0 <- [s1]
+    writeb [s0 - 1] <- a1
+    writeh [s1] <- a2
+    push lv
+    pop s0, s1
+    mov cpp <- a0
+    mul s0, t2 <- a0, a1
+    bne a0, a1, L000
+    blt a0, a1, L07b
+    bge a0, 7, L000
+    blez a2, L000
+    bltu a0, a1, L000
+    bgeu a3, 9, L000
+    call L07b
+    jalr t0, s6
+    ret
+    nop
+L07b:
+    jal L000
+    halt
+";
+        assert_eq!(asm, expected);
+    }
+
+    #[test]
+    fn raw_words() {
+        // A jump into the middle of a `read` and a collision that forces a trampoline
+        let asm = round_trip(
+            "
+            .text
+            main:
+                .micro 0x102, 0x123
+                nop
+                beq a0, a1, main
+                read a0 <- 1
+                .micro 0x006000000003ff00
+                .micro 0x3
+            ",
+        );
+        assert!(asm.contains("    .micro 0x102, 0x0000000000000123\n"));
+        assert!(asm.contains("    beq a0, a1, L000\n"));
+        assert!(asm.contains("    jal L003\n"));
+        assert!(asm.contains("L003:\n    .micro"));
+    }
+}
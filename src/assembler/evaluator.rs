@@ -1,53 +1,235 @@
-use anyhow::{bail, Result};
-use std::{collections::HashMap, rc::Rc};
+use anyhow::{bail, Error, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    rc::Rc,
+};
+use thiserror::Error;
 
 use crate::{
     assembler::{
-        lexer::Lexer,
-        parser::{Parser, Program},
+        diagnostics::{Diagnostic, Diagnostics, SourceSpans, Span},
+        include::Includer,
+        listing::{DataEntry, Listing, SymbolKind, TextEntry},
+        optimizer::{self, OptStats},
+        parser::{
+            Parser, Program, ADDRESS_RANGE, BYTE_RANGE, COMPARE_RANGE, IMMEDIATE_RANGE,
+            OFFSET_RANGE, SHIFT_RANGE, WORD_RANGE,
+        },
         sections::{
-            BranchOp, DoubleOperandOpcode, ImmediateOrLabel, Instruction, NoOperandOpcode,
-            Sections, SingleOperandOpcode, TextSegment, Value,
+            BranchOp, ConstExpr, DoubleOperandOpcode, ExprError, ImmediateOrLabel, IndexedAddress,
+            Instruction, LoadOp, NoOperandOpcode, Sections, SingleOperandOpcode, StoreOp,
+            TextSegment, Value,
         },
         tokens::Register,
     },
-    uarch::mem::{CtrlStore, CtrlStoreBuilder},
+    uarch::mem::{CtrlStore, CtrlStoreBuilder, RAM_ADDRS},
 };
 
 use super::sections::{BranchInstruction, DataKind, DataWrited};
 
+#[derive(Debug, PartialEq, Error)]
+pub enum EvaluatorError {
+    #[error("Value {value} of '{expr}' does not fit in range {min}..={max}")]
+    ValueOutOfRange {
+        expr: String,
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+
+    #[error("Alignment must be a power of two, found: {found}")]
+    InvalidAlignment { found: i64 },
+
+    #[error("Data segment needs {size} bytes but the RAM only has {max}")]
+    DataTooLarge { size: usize, max: usize },
+
+    #[error("Program does not fit in the control store: microinstruction at {addr:#05x} is past the {limit} words available for code")]
+    ControlStoreOverflow { addr: u16, limit: u16 },
+
+    #[error("Control store word {addr:#05x} is already in use")]
+    ControlStoreCollision { addr: u16 },
+
+    #[error("Undefined label '{label}'")]
+    UndefinedLabel { label: String },
+}
+
+impl EvaluatorError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvaluatorError::UndefinedLabel { .. } => "E0016",
+            EvaluatorError::ValueOutOfRange { .. } => "E0018",
+            EvaluatorError::InvalidAlignment { .. } => "E0019",
+            EvaluatorError::DataTooLarge { .. } => "E0020",
+            EvaluatorError::ControlStoreOverflow { .. } => "E0021",
+            EvaluatorError::ControlStoreCollision { .. } => "E0022",
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct AsmEvaluator {
-    values: HashMap<Rc<str>, u8>,
-    addr: HashMap<Rc<str>, u8>,
+    consts: HashMap<Rc<str>, i64>,
+    /// Word address of each data label.
+    values: HashMap<Rc<str>, u32>,
+    addr: HashMap<Rc<str>, u16>,
     ram: Vec<u32>,
+    /// Size in bytes of the data placed in `ram`.
+    data_len: usize,
     unreachable: Vec<(Rc<str>, u16, Microinstruction)>,
+    /// Contents of the assembled files, to render diagnostics.
+    sources: HashMap<String, String>,
+    warnings: Vec<Diagnostic>,
+    /// Errors found evaluating the statements, which don't stop the evaluation.
+    errors: Vec<Diagnostic>,
+    spans: SourceSpans,
+    listing: Listing,
+    optimize: bool,
+    /// What the optimizer saved on the last program, when it ran.
+    optimizations: Option<OptStats>,
 }
 
 impl AsmEvaluator {
     pub fn new() -> Self {
         Self {
+            consts: HashMap::new(),
             values: HashMap::new(),
             addr: HashMap::new(),
             ram: Vec::new(),
+            data_len: 0,
             unreachable: Vec::new(),
+            sources: HashMap::new(),
+            warnings: Vec::new(),
+            errors: Vec::new(),
+            spans: SourceSpans::default(),
+            listing: Listing::default(),
+            optimize: false,
+            optimizations: None,
         }
     }
 
+    /// Runs the peephole optimizer over the generated microcode.
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    /// What the optimizer saved on the last evaluated program.
+    pub fn optimizations(&self) -> Option<OptStats> {
+        self.optimizations
+    }
+
+    /// Includes in an in-memory buffer are resolved from the current directory.
     pub fn evaluate_buffer(&mut self, buf: &str) -> Result<(CtrlStore, &[u32])> {
-        let toks = Lexer::new(buf).get_deez_toks_w_ctx();
-        let program = Parser::new(toks.into()).get_deez_program();
+        let mut includer = Includer::default();
+        let toks = includer.expand_buffer(buf, Rc::from("<input>"), Path::new("."));
+        self.sources = includer.into_sources();
+        let program = Parser::new(toks?.into()).get_deez_program();
 
         self.eval_program(program)
     }
 
-    pub fn eval_program(&mut self, prog: Program) -> Result<(CtrlStore, &[u32])> {
-        if !prog.errors.is_empty() {
-            eprintln!("Errors found while parsing the program.");
-            for err in prog.errors {
-                eprintln!("{}", err);
+    pub fn evaluate_file(&mut self, path: &Path) -> Result<(CtrlStore, &[u32])> {
+        let mut includer = Includer::default();
+        let toks = includer.expand_file(path);
+        self.sources = includer.into_sources();
+        let program = Parser::new(toks?.into()).get_deez_program();
+
+        self.eval_program(program)
+    }
+
+    /// Word address of a data label of the last evaluated program.
+    pub fn data_label(&self, name: &str) -> Option<u32> {
+        self.values.get(name).copied()
+    }
+
+    /// Warnings found in the last evaluated program.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    /// Collects the warnings and the error that stopped the evaluation, if any,
+    /// into diagnostics that can be rendered against the sources.
+    pub fn diagnostics(&self, err: Option<&Error>) -> Diagnostics {
+        let mut items = Vec::new();
+        match err.map(|e| e.downcast_ref::<Diagnostics>().ok_or(e)) {
+            // Already includes the warnings
+            Some(Ok(diags)) => items.extend(diags.items.iter().cloned()),
+            Some(Err(e)) => {
+                items.extend(self.warnings.iter().cloned());
+                items.push(Diagnostic::from_error(e));
+            }
+            None => items.extend(self.warnings.iter().cloned()),
+        }
+        Diagnostics {
+            items,
+            sources: self.sources.clone(),
+        }
+    }
+
+    /// Checks that every label and symbol used is defined, warning about data
+    /// that is never used.
+    fn check_symbols(&mut self, spans: &SourceSpans) -> Vec<Diagnostic> {
+        let mut errors = Vec::new();
+        let mut reported = HashSet::new();
+
+        for (label, span) in spans.label_refs.iter() {
+            if !spans.labels.contains_key(label) && reported.insert(label) {
+                errors.push(Diagnostic::error(
+                    EvaluatorError::UndefinedLabel {
+                        label: label.to_string(),
+                    }
+                    .code(),
+                    format!("Undefined label '{}'", label),
+                    Some(span.clone()),
+                ));
             }
-            bail!("Errors found while parsing the program.");
+        }
+        for (name, span) in spans.symbol_refs.iter() {
+            if !spans.symbols.contains_key(name) && reported.insert(name) {
+                let err = ExprError::UndefinedSymbol {
+                    name: name.to_string(),
+                };
+                let mut diag = Diagnostic::error(err.code(), err.to_string(), Some(span.clone()));
+                if let Some(def) = spans.labels.get(name) {
+                    diag = diag.with_note(
+                        format!("'{}' is a text label, which has no value", name),
+                        Some(def.clone()),
+                    );
+                }
+                errors.push(diag);
+            }
+        }
+
+        let mut unused: Vec<_> = spans
+            .symbols
+            .iter()
+            .filter(|(name, _)| !spans.symbol_refs.iter().any(|(r, _)| r == *name))
+            .collect();
+        unused.sort_by_key(|(_, span)| (span.file.clone(), span.line, span.column));
+        self.warnings = unused
+            .into_iter()
+            .map(|(name, span)| {
+                Diagnostic::warning(
+                    "W0001",
+                    format!("Symbol '{}' is never used", name),
+                    Some(span.clone()),
+                )
+            })
+            .collect();
+
+        errors
+    }
+
+    pub fn eval_program(&mut self, prog: Program) -> Result<(CtrlStore, &[u32])> {
+        let mut errors: Vec<_> = prog.errors.iter().map(Diagnostic::from_error).collect();
+        errors.extend(self.check_symbols(&prog.spans));
+        if !errors.is_empty() {
+            let mut items = self.warnings.clone();
+            items.extend(errors);
+            bail!(Diagnostics {
+                items,
+                sources: self.sources.clone(),
+            })
         }
 
         let mut data = Vec::new();
@@ -63,15 +245,103 @@ impl AsmEvaluator {
         }
 
         let mut cs = CsState::new();
-
-        data.iter().for_each(|d| self.eval_data_seg(d));
-        text.iter().for_each(|t| self.eval_txt_seg(t, &mut cs));
-        self.resolve_unreachable(&mut cs);
+        self.spans = prog.spans;
+        self.errors.clear();
+
+        for (i, d) in data.iter().enumerate() {
+            let span = self.spans.data.get(i).cloned();
+            match self.eval_data_seg(d) {
+                // Constants take no RAM, so they are only listed as symbols
+                Ok(_) if matches!(d.kind, DataKind::Equ(_)) => {}
+                Ok(start) => self.listing.data.push(DataEntry {
+                    bytes: start..self.data_len,
+                    span,
+                }),
+                Err(e) => self.report(&e, span.as_ref()),
+            }
+        }
+        for t in text.iter() {
+            self.eval_txt_seg(t, &mut cs);
+        }
+        if !self.errors.is_empty() {
+            let mut items = self.warnings.clone();
+            items.append(&mut self.errors);
+            bail!(Diagnostics {
+                items,
+                sources: self.sources.clone(),
+            })
+        }
+        self.resolve_unreachable(&mut cs)?;
+        if self.optimize {
+            self.optimize_cs(&mut cs);
+        }
+        self.list_symbols();
 
         Ok((cs.build_cs(), &self.ram))
     }
 
-    pub fn eval(&mut self, secs: &Sections) -> CtrlStore {
+    /// Optimizes the laid out program, moving the labels and the listing along with
+    /// the code.
+    fn optimize_cs(&mut self, cs: &mut CsState) {
+        let mut words = cs.words();
+        let labels: Vec<u16> = self.addr.values().copied().collect();
+        let Some((stats, reloc)) = optimizer::optimize(&mut words, cs.addr(), &labels) else {
+            self.optimizations = Some(OptStats::default());
+            return;
+        };
+        cs.load_words(&words, reloc.end);
+
+        for addr in self.addr.values_mut() {
+            *addr = reloc.entry(*addr);
+        }
+        for entry in self.listing.text.iter_mut() {
+            match entry {
+                TextEntry::Label { addr, .. } => *addr = reloc.entry(*addr),
+                TextEntry::Instruction {
+                    addrs, side_words, ..
+                } => {
+                    let placed: Vec<u16> = addrs.clone().filter_map(|a| reloc.place(a)).collect();
+                    *addrs = match (placed.iter().min(), placed.iter().max()) {
+                        (Some(&first), Some(&last)) => first..last + 1,
+                        _ => reloc.entry(addrs.start)..reloc.entry(addrs.start),
+                    };
+                    *side_words = side_words.iter().filter_map(|&a| reloc.place(a)).collect();
+                }
+            }
+        }
+        self.optimizations = Some(stats);
+    }
+
+    /// What was generated for each line of the last evaluated program.
+    pub(crate) fn listing_entries(&self) -> &Listing {
+        &self.listing
+    }
+
+    /// Renders the listing of the last evaluated program against its control store.
+    pub fn listing(&self, cs: &CtrlStore) -> String {
+        self.listing.render(&cs.firmware()[..], &self.sources)
+    }
+
+    fn list_symbols(&mut self) {
+        let text = self
+            .addr
+            .iter()
+            .map(|(k, v)| (k, SymbolKind::Text, *v as i64));
+        let data = self
+            .values
+            .iter()
+            .map(|(k, v)| (k, SymbolKind::Data, *v as i64));
+        let consts = self.consts.iter().map(|(k, v)| (k, SymbolKind::Equ, *v));
+        let mut symbols: Vec<_> = text
+            .chain(data)
+            .chain(consts)
+            .map(|(k, kind, v)| (Rc::clone(k), kind, v))
+            .collect();
+        symbols.sort_by(|a, b| (a.1, a.2, &a.0).cmp(&(b.1, b.2, &b.0)));
+        self.listing.symbols = symbols;
+    }
+
+    pub fn eval(&mut self, secs: &Sections) -> Result<CtrlStore> {
         let mut cs_state = CsState::new();
 
         match secs {
@@ -79,30 +349,163 @@ impl AsmEvaluator {
                 for seg in txt_segs {
                     self.eval_txt_seg(seg, &mut cs_state);
                 }
+                if !self.errors.is_empty() {
+                    bail!(Diagnostics {
+                        items: std::mem::take(&mut self.errors),
+                        sources: self.sources.clone(),
+                    })
+                }
             }
             Sections::DataSection(data) => {
                 for seg in data {
-                    self.eval_data_seg(seg);
+                    self.eval_data_seg(seg)?;
                 }
             }
         }
 
-        cs_state.build_cs()
+        Ok(cs_state.build_cs())
     }
 
-    fn eval_data_seg(&mut self, data: &DataWrited) {
-        let label = Rc::clone(&data.label);
+    /// Records an error found evaluating the statement at `span`, so the evaluation
+    /// goes on with the next one.
+    fn report(&mut self, err: &Error, span: Option<&Span>) {
+        let diag = Diagnostic::from_error_at(err, span);
+        // Once the data or the code doesn't fit, neither does anything after it
+        let overflow = matches!(
+            err.downcast_ref::<EvaluatorError>(),
+            Some(EvaluatorError::DataTooLarge { .. } | EvaluatorError::ControlStoreOverflow { .. })
+        );
+        if overflow && self.errors.iter().any(|d| d.code == diag.code) {
+            return;
+        }
+        self.errors.push(diag);
+    }
+
+    /// Places bytes at the end of the data segment, packed little-endian into words.
+    fn push_data(&mut self, bytes: &[u8]) -> Result<()> {
+        let size = self.data_len + bytes.len();
+        if size > RAM_ADDRS * 4 {
+            bail!(EvaluatorError::DataTooLarge {
+                size,
+                max: RAM_ADDRS * 4,
+            })
+        }
+        self.ram.resize(size.div_ceil(4), 0);
+        for b in bytes {
+            self.ram[self.data_len / 4] |= (*b as u32) << (8 * (self.data_len % 4));
+            self.data_len += 1;
+        }
+        Ok(())
+    }
+
+    fn align_data(&mut self, align: usize) -> Result<()> {
+        let padding = self.data_len.next_multiple_of(align) - self.data_len;
+        self.push_data(&vec![0; padding])
+    }
+
+    /// Places the data in the RAM, returning the byte offset where it starts.
+    fn eval_data_seg(&mut self, data: &DataWrited) -> Result<usize> {
+        if let (DataKind::Equ(ref e), Some(label)) = (&data.kind, &data.label) {
+            let v = self.eval_const_expr(e, (i64::MIN, i64::MAX));
+            // A constant that can't be computed is still defined, so its uses don't report it again
+            self.consts
+                .insert(Rc::clone(label), *v.as_ref().unwrap_or(&0));
+            return v.map(|_| self.data_len);
+        }
+
+        // Labels are word addresses, so labeled data always starts on a word
+        let is_word = matches!(
+            data.kind,
+            DataKind::Word(_) | DataKind::WordExpr(_) | DataKind::WordList(_)
+        );
+        if data.label.is_some() || is_word {
+            self.align_data(4)?;
+        }
+        if let Some(label) = &data.label {
+            self.values
+                .insert(Rc::clone(label), (self.data_len / 4) as u32);
+        }
+        let start = self.data_len;
+
         match data.kind {
-            DataKind::Byte(b) => {
-                self.values.insert(label, b);
+            DataKind::Byte(b) => self.push_data(&[b])?,
+            DataKind::ByteExpr(ref e) => {
+                let b = self.eval_const_expr(e, BYTE_RANGE)?;
+                self.push_data(&[b as u8])?;
+            }
+            DataKind::ByteList(ref list) => {
+                for e in list {
+                    let b = self.eval_const_expr(e, BYTE_RANGE)?;
+                    self.push_data(&[b as u8])?;
+                }
             }
-            DataKind::Word(w) => {
-                self.values.insert(label, self.ram.len() as u8);
-                self.ram.push(w as u32);
+            DataKind::Word(w) => self.push_data(&w.to_le_bytes())?,
+            DataKind::WordExpr(ref e) => {
+                let w = self.eval_const_expr(e, WORD_RANGE)?;
+                self.push_data(&(w as u32).to_le_bytes())?;
             }
+            DataKind::WordList(ref list) => {
+                for e in list {
+                    let w = self.eval_const_expr(e, WORD_RANGE)?;
+                    self.push_data(&(w as u32).to_le_bytes())?;
+                }
+            }
+            DataKind::Space(ref e) => {
+                let size = self.eval_const_expr(e, (0, (RAM_ADDRS * 4) as i64))?;
+                self.push_data(&vec![0; size as usize])?;
+            }
+            DataKind::Ascii(ref text) => self.push_data(text.as_bytes())?,
+            DataKind::Asciz(ref text) => {
+                self.push_data(text.as_bytes())?;
+                self.push_data(&[0])?;
+            }
+            DataKind::Align(ref e) => {
+                let align = self.eval_const_expr(e, (1, (RAM_ADDRS * 4) as i64))?;
+                if !(align as usize).is_power_of_two() {
+                    bail!(EvaluatorError::InvalidAlignment { found: align })
+                }
+                self.align_data(align as usize)?;
+            }
+            DataKind::Equ(_) => unreachable!("Constants are always named"),
+        }
+        Ok(start)
+    }
+
+    /// Resolves a symbol used in an operand: `.equ` constants first, then data labels.
+    fn symbol(&self, name: &str) -> Option<i64> {
+        self.consts
+            .get(name)
+            .copied()
+            .or_else(|| self.values.get(name).map(|v| *v as i64))
+    }
+
+    fn eval_const_expr(&self, expr: &ConstExpr, (min, max): (i64, i64)) -> Result<i64> {
+        let value = expr.eval(&|name| self.symbol(name))?;
+        if value < min || value > max {
+            bail!(EvaluatorError::ValueOutOfRange {
+                expr: expr.to_string(),
+                value,
+                min,
+                max,
+            })
         }
+        Ok(value)
+    }
+
+    fn eval_address(&self, addr: &ImmediateOrLabel) -> Result<u8> {
+        let res = match addr {
+            ImmediateOrLabel::Immediate(imm) => *imm,
+            ImmediateOrLabel::Label(label) => {
+                let expr = ConstExpr::Symbol(Rc::clone(label));
+                self.eval_const_expr(&expr, ADDRESS_RANGE)? as u8
+            }
+            ImmediateOrLabel::Expr(expr) => self.eval_const_expr(expr, ADDRESS_RANGE)? as u8,
+        };
+        Ok(res)
     }
 
+    /// Lays out the instructions of a segment, recording the errors of the ones
+    /// that can't be generated.
     fn eval_txt_seg(&mut self, txt_seg: &TextSegment, state: &mut CsState) {
         match txt_seg {
             // ignoring labels for now
@@ -110,49 +513,161 @@ impl AsmEvaluator {
                 label,
                 instructions,
             } => {
-                self.addr.insert(Rc::clone(label), state.curr_addr as u8);
+                self.addr.insert(Rc::clone(label), state.curr_addr);
+                self.listing.text.push(TextEntry::Label {
+                    name: Rc::clone(label),
+                    addr: state.curr_addr,
+                    span: self.spans.labels.get(label).cloned(),
+                });
                 for inst in instructions {
-                    self.eval_inst(inst, state);
+                    let start = state.addr();
+                    let side_words = state.side_words.len();
+                    let res = self.eval_inst(inst, state);
+
+                    let span = self.spans.instructions.get(self.listing.instructions());
+                    let span = span.cloned();
+                    if let Err(e) = res {
+                        self.report(&e, span.as_ref());
+                    }
+                    self.listing.text.push(TextEntry::Instruction {
+                        addrs: start..state.addr(),
+                        side_words: state.side_words[side_words..].to_vec(),
+                        span,
+                    });
                 }
             }
-            TextSegment::GlobalSection { label: _ } => unimplemented!(),
+            // The whole program is assembled together, so there is nothing to export
+            TextSegment::GlobalSection { label: _ } => {}
         }
     }
 
-    fn resolve_unreachable(&mut self, state: &mut CsState) {
+    fn resolve_unreachable(&mut self, state: &mut CsState) -> Result<()> {
         for (label, cs_addr, mut micro) in self.unreachable.drain(..) {
-            let addr = *self
-                .addr
-                .get(label.as_ref())
-                .expect("Should be defined before");
+            let Some(addr) = self.addr.get(label.as_ref()) else {
+                bail!(EvaluatorError::UndefinedLabel {
+                    label: label.to_string()
+                })
+            };
 
-            micro.next = addr as u16;
+            micro.next = *addr;
             state.set_instr(cs_addr, micro.get());
         }
+        Ok(())
     }
 
-    fn eval_inst(&mut self, inst: &Instruction, state: &mut CsState) {
+    fn eval_inst(&mut self, inst: &Instruction, state: &mut CsState) -> Result<()> {
         match inst {
             Instruction::DoubleOperand(inst) => {
-                self.eval_double_op_inst(&inst.opcode, &inst.rd, &inst.rs1, &inst.rs2, state);
+                self.eval_double_op_inst(&inst.opcode, &inst.rd, &inst.rs1, &inst.rs2, state)?;
             }
             Instruction::SingleOperand(ins) => {
-                self.eval_single_op_inst(&ins.opcode, &ins.rd, &ins.rs1, state);
+                self.eval_single_op_inst(&ins.opcode, &ins.rd, &ins.rs1, state)?;
             }
-            Instruction::NoOperand(opcode) => self.eval_no_op_inst(opcode, state),
+            Instruction::NoOperand(opcode) => self.eval_no_op_inst(opcode, state)?,
             Instruction::Branch(ins) => {
-                self.eval_branch_inst(ins, state);
+                self.eval_branch_inst(ins, state)?;
             }
             Instruction::Jal(label) => {
-                self.eval_jal_inst(label, state);
+                self.eval_jal_inst(label, state)?;
+            }
+            Instruction::Jalr(rd, rs) => {
+                self.eval_jalr_inst(rd, rs, state)?;
+            }
+            Instruction::Call(label) => {
+                self.eval_call_inst(label, state)?;
+            }
+            Instruction::Write(addr, rd) => {
+                self.eval_write_inst(addr, rd, state)?;
+            }
+            Instruction::Read(addr, rd) => {
+                self.eval_read_inst(addr, rd, state)?;
+            }
+            Instruction::WriteIndexed(addr, rs) => {
+                self.eval_write_indexed_inst(addr, rs, state)?;
+            }
+            Instruction::ReadIndexed(addr, rds) => {
+                self.eval_read_indexed_inst(addr, rds, state)?;
+            }
+            Instruction::ReadSubword(op, addr, rds) => {
+                self.eval_read_subword_inst(*op, addr, rds, state)?;
             }
-            Instruction::WriteInstruction(addr, rd) => {
-                self.eval_write_inst(addr, rd, state);
+            Instruction::WriteSubword(op, addr, rs) => {
+                self.eval_write_subword_inst(*op, addr, rs, state)?;
             }
-            Instruction::ReadInstruction(addr, rd) => {
-                self.eval_read_inst(addr, rd, state);
+            Instruction::Push(rs) => {
+                self.eval_push_inst(rs, state)?;
             }
+            Instruction::Pop(rds) => {
+                self.eval_pop_inst(rds, state)?;
+            }
+            Instruction::Li(value, rd) => {
+                self.eval_li_inst(value, rd, state)?;
+            }
+            Instruction::Micro(None, word) => state.add_instr(*word)?,
+            Instruction::Micro(Some(addr), word) => state.place_instr(*addr, *word)?,
+        }
+        Ok(())
+    }
+
+    /// Splits a 32-bit constant into the (immediate, alu) steps that rebuild it.
+    ///
+    /// The first step loads the most significant byte that is needed, zero-extended,
+    /// or one-extended through INVA when the bytes above it are all ones. Each
+    /// following step ORs the accumulated value with the next byte and shifts by 8;
+    /// an OR with a zero low byte is skipped.
+    pub(crate) fn li_steps(value: u32) -> Vec<(u8, u16)> {
+        let bytes = value.to_be_bytes();
+        let len = (1..=4)
+            .find(|&n| {
+                let upper = &bytes[..4 - n];
+                upper.iter().all(|&b| b == 0x00) || upper.iter().all(|&b| b == 0xFF)
+            })
+            .unwrap_or(4);
+        let bytes = &bytes[4 - len..];
+        let shift = |last: bool| if last { 0 } else { 0b100000000 };
+
+        let mut steps = Vec::new();
+        if len < 4 && value >> 31 == 1 {
+            // rd <- ~(~byte)
+            steps.push((!bytes[0], 0b000011010 | shift(len == 1)));
+        } else {
+            // rd <- byte
+            steps.push((bytes[0], 0b000011000 | shift(len == 1)));
+        }
+        for (i, &byte) in bytes.iter().enumerate().skip(1) {
+            let last = i == len - 1;
+            if last && byte == 0 {
+                break;
+            }
+            // rd <- rd | byte
+            steps.push((byte, 0b000011100 | shift(last)));
         }
+        steps
+    }
+
+    fn eval_li_inst(
+        &mut self,
+        value: &ConstExpr,
+        rds: &Vec<Rc<Register>>,
+        state: &mut CsState,
+    ) -> Result<()> {
+        let value = self.eval_const_expr(value, WORD_RANGE)? as u32;
+        let c_code = self.get_c_code(rds);
+
+        for (i, (imm, alu)) in Self::li_steps(value).into_iter().enumerate() {
+            let mut mi = Microinstruction::new(state.next_addr());
+            mi.c_bus = c_code;
+            mi.alu = alu;
+            mi.immediate = imm;
+            if i == 0 {
+                mi.a = Microinstruction::IMM_A;
+            } else {
+                mi.a = self.reg_a_code(&rds[0]);
+                mi.b = Microinstruction::IMM_B;
+            }
+            state.add_instr(mi.get())?;
+        }
+        Ok(())
     }
 
     fn eval_read_inst(
@@ -160,127 +675,582 @@ impl AsmEvaluator {
         addr: &ImmediateOrLabel,
         rds: &Vec<Rc<Register>>,
         state: &mut CsState,
-    ) {
+    ) -> Result<()> {
         let mut read = Microinstruction::new(state.next_addr());
         read.c_bus = self.get_c_code(&vec![Rc::new(Register::Mar)]);
         read.alu = 0b000011000;
         read.mem = 0b010;
         read.a = Microinstruction::IMM_A;
-        read.immediate = match addr {
-            ImmediateOrLabel::Immediate(imm) => *imm,
-            ImmediateOrLabel::Label(label) => *self
-                .values
-                .get(label.as_ref())
-                .expect("Should be defined before"),
-        };
-        state.add_instr(read.get());
+        read.immediate = self.eval_address(addr)?;
+        state.add_instr(read.get())?;
 
         let mut w_reg = Microinstruction::new(state.next_addr());
         w_reg.c_bus = self.get_c_code(rds);
         w_reg.alu = 0b000011000;
         w_reg.a = self.reg_a_code(&Register::Mdr);
-        state.add_instr(w_reg.get());
+        state.add_instr(w_reg.get())?;
+        Ok(())
     }
 
-    fn eval_write_inst(&mut self, addr: &ImmediateOrLabel, rd: &Rc<Register>, state: &mut CsState) {
+    fn eval_write_inst(
+        &mut self,
+        addr: &ImmediateOrLabel,
+        rd: &Rc<Register>,
+        state: &mut CsState,
+    ) -> Result<()> {
         let mut mdr = Microinstruction::new(state.next_addr());
         mdr.c_bus = self.get_c_code(&vec![Rc::new(Register::Mdr)]);
         mdr.alu = 0b000011000;
         mdr.a = self.reg_a_code(rd);
-        state.add_instr(mdr.get());
+        state.add_instr(mdr.get())?;
 
         let mut mar = Microinstruction::new(state.next_addr());
         mar.c_bus = self.get_c_code(&vec![Rc::new(Register::Mar)]);
         mar.alu = 0b000011000;
         mar.mem = 0b100;
         mar.a = Microinstruction::IMM_A;
-        mar.immediate = match addr {
-            ImmediateOrLabel::Immediate(imm) => *imm,
-            ImmediateOrLabel::Label(label) => *self
-                .values
-                .get(label.as_ref())
-                .expect("Should be defined before"),
+        mar.immediate = self.eval_address(addr)?;
+        state.add_instr(mar.get())?;
+        Ok(())
+    }
+
+    fn eval_read_indexed_inst(
+        &mut self,
+        addr: &IndexedAddress,
+        rds: &Vec<Rc<Register>>,
+        state: &mut CsState,
+    ) -> Result<()> {
+        let offset = self.eval_const_expr(&addr.offset, OFFSET_RANGE)?;
+        let mut read = self.indexed_word(&addr.base, offset, Register::Mar, state.next_addr());
+        read.mem = 0b010;
+        state.add_instr(read.get())?;
+
+        let mut w_reg = Microinstruction::new(state.next_addr());
+        w_reg.c_bus = self.get_c_code(rds);
+        w_reg.alu = 0b000011000;
+        w_reg.a = self.reg_a_code(&Register::Mdr);
+        state.add_instr(w_reg.get())?;
+        Ok(())
+    }
+
+    /// MAR is loaded first, so the base can be MDR.
+    fn eval_write_indexed_inst(
+        &mut self,
+        addr: &IndexedAddress,
+        rs: &Rc<Register>,
+        state: &mut CsState,
+    ) -> Result<()> {
+        let offset = self.eval_const_expr(&addr.offset, OFFSET_RANGE)?;
+        let mar = self.indexed_word(&addr.base, offset, Register::Mar, state.next_addr());
+        state.add_instr(mar.get())?;
+
+        let mut mdr = Microinstruction::new(state.next_addr());
+        mdr.c_bus = self.get_c_code(&vec![Rc::new(Register::Mdr)]);
+        mdr.alu = 0b000011000;
+        mdr.mem = 0b100;
+        mdr.a = self.reg_a_code(rs);
+        state.add_instr(mdr.get())?;
+        Ok(())
+    }
+
+    /// Writes `base + offset` into `rd`. A negative offset is subtracted from the
+    /// base, which then goes through the B bus.
+    fn indexed_word(
+        &self,
+        base: &Register,
+        offset: i64,
+        rd: Register,
+        next: u16,
+    ) -> Microinstruction {
+        let mut mi = Microinstruction::new(next);
+        mi.c_bus = self.get_c_code(&vec![Rc::new(rd)]);
+        match offset {
+            0 => {
+                mi.alu = 0b000011000;
+                mi.a = self.reg_a_code(base);
+            }
+            1.. => {
+                mi.alu = 0b000111100;
+                mi.a = self.reg_a_code(base);
+                mi.b = Microinstruction::IMM_B;
+                mi.immediate = offset as u8;
+            }
+            _ => {
+                mi.alu = 0b000111111;
+                mi.b = self.reg_b_code(base);
+                mi.a = Microinstruction::IMM_A;
+                mi.immediate = -offset as u8;
+            }
+        }
+        mi
+    }
+
+    /// Starts reading the word holding the byte address of `addr`, returning the
+    /// register that holds that address.
+    ///
+    /// Temp registers usage:
+    /// - T0: the byte address, when it has an offset
+    /// - T1: the word address, on the way to MAR
+    fn eval_subword_read(
+        &mut self,
+        addr: &IndexedAddress,
+        state: &mut CsState,
+    ) -> Result<Register> {
+        let offset = self.eval_const_expr(&addr.offset, OFFSET_RANGE)?;
+        let byte_addr = match offset {
+            0 => addr.base.as_ref().clone(),
+            _ => {
+                let mi = self.indexed_word(&addr.base, offset, Register::T0, state.next_addr());
+                state.add_instr(mi.get())?;
+                Register::T0
+            }
+        };
+
+        // MAR <- byte_addr >> 2, in two logical shifts by 1
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T1)]);
+        mi.alu = 0b1000011000;
+        mi.a = self.reg_a_code(&byte_addr);
+        state.add_instr(mi.get())?;
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.c_bus = self.get_c_code(&vec![Rc::new(Register::Mar)]);
+        mi.alu = 0b1000011000;
+        mi.mem = 0b010;
+        mi.a = self.reg_a_code(&Register::T1);
+        state.add_instr(mi.get())?;
+        Ok(byte_addr)
+    }
+
+    /// T0 <- the byte address ANDed with `mask`, inverted first when `invert`,
+    /// skipping the loop that follows when it's zero. Returns the JAM slot.
+    fn eval_subword_count(
+        &mut self,
+        byte_addr: &Register,
+        mask: u8,
+        invert: bool,
+        state: &mut CsState,
+    ) -> Result<u16> {
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.jam = 0b001;
+        mi.alu = if invert { 0b000001110 } else { 0b000001100 };
+        mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T0)]);
+        mi.a = self.reg_a_code(byte_addr);
+        mi.b = Microinstruction::IMM_B;
+        mi.immediate = mask;
+        let slot = state.jam_slot(&mut mi)?;
+        state.add_instr(mi.get())?;
+        Ok(slot)
+    }
+
+    /// T0 <- T0 - 1, jumping out of the loop when it reaches zero. Returns the JAM
+    /// slot.
+    fn eval_subword_dec(&mut self, state: &mut CsState) -> Result<u16> {
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.jam = 0b001;
+        mi.alu = 0b000110110;
+        mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T0)]);
+        mi.b = self.reg_b_code(&Register::T0);
+        let slot = state.jam_slot(&mut mi)?;
+        state.add_instr(mi.get())?;
+        Ok(slot)
+    }
+
+    /// Reads the word holding the byte or halfword, then shifts it left until the
+    /// wanted bytes are on top and back right to extend them.
+    fn eval_read_subword_inst(
+        &mut self,
+        op: LoadOp,
+        addr: &IndexedAddress,
+        rds: &Vec<Rc<Register>>,
+        state: &mut CsState,
+    ) -> Result<()> {
+        let (mask, sr8) = match op {
+            LoadOp::Readb => (3, 0b1010011000),
+            LoadOp::Readbu => (3, 0b1100011000),
+            LoadOp::Readh => (2, 0b1010011000),
+            LoadOp::Readhu => (2, 0b1100011000),
         };
-        state.add_instr(mar.get());
+        let c_code = self.get_c_code(rds);
+        let byte_addr = self.eval_subword_read(addr, state)?;
+        // Bytes above the wanted ones, which are shifted out to the left
+        let zero_addr = self.eval_subword_count(&byte_addr, mask, true, state)?;
+
+        // rd <- MDR << 8
+        let loop_addr = state.next_addr();
+        let mut mi = Microinstruction::new(loop_addr);
+        mi.alu = 0b0100011000;
+        mi.c_bus = c_code;
+        mi.a = self.reg_a_code(&Register::Mdr);
+        state.add_instr(mi.get())?;
+        let exit_addr = self.eval_subword_dec(state)?;
+        // rd <- rd << 8
+        let mut mi = Microinstruction::new(loop_addr);
+        mi.alu = 0b0100011000;
+        mi.c_bus = c_code;
+        mi.a = self.reg_a_code(&rds[0]);
+        state.add_instr(mi.get())?;
+
+        let mut mi = Microinstruction::new(state.addr());
+        mi.alu = 0b000011000;
+        mi.c_bus = c_code;
+        mi.a = self.reg_a_code(&Register::Mdr);
+        state.set_instr(zero_addr, mi.get());
+        state.set_instr(exit_addr, Microinstruction::new(state.addr()).get());
+
+        // Extends the bytes on top down to the bottom
+        for _ in 0..mask {
+            let mut mi = Microinstruction::new(state.next_addr());
+            mi.alu = sr8;
+            mi.c_bus = c_code;
+            mi.a = self.reg_a_code(&rds[0]);
+            state.add_instr(mi.get())?;
+        }
+        Ok(())
     }
 
-    fn eval_jal_inst(&mut self, label: &Rc<str>, state: &mut CsState) {
+    /// Reads the word holding the byte or halfword, shifts the value and a mask of
+    /// its bytes into place and writes back the word with them replaced.
+    ///
+    /// Temp registers usage, after reading:
+    /// - T0: the bytes below the written ones, counted down
+    /// - T1: the value
+    /// - T2: the mask
+    fn eval_write_subword_inst(
+        &mut self,
+        op: StoreOp,
+        addr: &IndexedAddress,
+        rs: &Rc<Register>,
+        state: &mut CsState,
+    ) -> Result<()> {
+        let t1 = self.get_c_code(&vec![Rc::new(Register::T1)]);
+        let t2 = self.get_c_code(&vec![Rc::new(Register::T2)]);
+        let byte_addr = self.eval_subword_read(addr, state)?;
+
+        let mask = match op {
+            StoreOp::Writeb => {
+                // t1 <- rs & 255
+                let mut mi = Microinstruction::new(state.next_addr());
+                mi.alu = 0b000001100;
+                mi.c_bus = t1;
+                mi.a = self.reg_a_code(rs);
+                mi.b = Microinstruction::IMM_B;
+                mi.immediate = 0xFF;
+                state.add_instr(mi.get())?;
+                // t2 <- 255
+                let mut mi = Microinstruction::new(state.next_addr());
+                mi.alu = 0b000011000;
+                mi.c_bus = t2;
+                mi.a = Microinstruction::IMM_A;
+                mi.immediate = 0xFF;
+                state.add_instr(mi.get())?;
+                3
+            }
+            StoreOp::Writeh => {
+                // t2 <- 255 << 8
+                let mut mi = Microinstruction::new(state.next_addr());
+                mi.alu = 0b0100011000;
+                mi.c_bus = t2;
+                mi.a = Microinstruction::IMM_A;
+                mi.immediate = 0xFF;
+                state.add_instr(mi.get())?;
+                // t2 <- t2 | 255
+                let mut mi = Microinstruction::new(state.next_addr());
+                mi.alu = 0b000011100;
+                mi.c_bus = t2;
+                mi.a = self.reg_a_code(&Register::T2);
+                mi.b = Microinstruction::IMM_B;
+                mi.immediate = 0xFF;
+                state.add_instr(mi.get())?;
+                // t1 <- rs & t2
+                let mut mi = Microinstruction::new(state.next_addr());
+                mi.alu = 0b000001100;
+                mi.c_bus = t1;
+                mi.a = self.reg_a_code(rs);
+                mi.b = self.reg_b_code(&Register::T2);
+                state.add_instr(mi.get())?;
+                2
+            }
+        };
+        let zero_addr = self.eval_subword_count(&byte_addr, mask, false, state)?;
+
+        // t1 <- t1 << 8, t2 <- t2 << 8
+        let shift = |this: &Self, c_bus: u32, rs: Register, next: u16| {
+            let mut mi = Microinstruction::new(next);
+            mi.alu = 0b0100011000;
+            mi.c_bus = c_bus;
+            mi.a = this.reg_a_code(&rs);
+            mi
+        };
+        state.add_instr(shift(self, t1, Register::T1, state.next_addr()).get())?;
+        state.add_instr(shift(self, t2, Register::T2, state.next_addr()).get())?;
+        let loop_addr = state.addr();
+        let exit_addr = self.eval_subword_dec(state)?;
+        state.add_instr(shift(self, t1, Register::T1, state.next_addr()).get())?;
+        state.add_instr(shift(self, t2, Register::T2, loop_addr).get())?;
+
+        state.set_instr(zero_addr, Microinstruction::new(state.addr()).get());
+        state.set_instr(exit_addr, Microinstruction::new(state.addr()).get());
+
+        // MDR <- MDR & !t2
+        let mdr = self.get_c_code(&vec![Rc::new(Register::Mdr)]);
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.alu = 0b000001110;
+        mi.c_bus = mdr;
+        mi.a = self.reg_a_code(&Register::T2);
+        mi.b = self.reg_b_code(&Register::Mdr);
+        state.add_instr(mi.get())?;
+        // MDR <- MDR | t1; write
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.alu = 0b000011100;
+        mi.c_bus = mdr;
+        mi.mem = 0b100;
+        mi.a = self.reg_a_code(&Register::T1);
+        mi.b = self.reg_b_code(&Register::Mdr);
+        state.add_instr(mi.get())
+    }
+
+    /// The stack grows down and CPP points to its top word, so the first push
+    /// goes right below the address CPP starts at.
+    fn eval_push_inst(&mut self, rs: &Rc<Register>, state: &mut CsState) -> Result<()> {
+        let mut mdr = Microinstruction::new(state.next_addr());
+        mdr.c_bus = self.get_c_code(&vec![Rc::new(Register::Mdr)]);
+        mdr.alu = 0b000011000;
+        mdr.a = self.reg_a_code(rs);
+        state.add_instr(mdr.get())?;
+
+        // MAR, CPP <- CPP - 1; write
+        let mut mar = Microinstruction::new(state.next_addr());
+        mar.c_bus = self.get_c_code(&vec![Rc::new(Register::Mar), Rc::new(Register::Cpp)]);
+        mar.alu = 0b000110110;
+        mar.mem = 0b100;
+        mar.b = self.reg_b_code(&Register::Cpp);
+        state.add_instr(mar.get())
+    }
+
+    fn eval_pop_inst(&mut self, rds: &Vec<Rc<Register>>, state: &mut CsState) -> Result<()> {
+        let mut read = Microinstruction::new(state.next_addr());
+        read.c_bus = self.get_c_code(&vec![Rc::new(Register::Mar)]);
+        read.alu = 0b000011000;
+        read.mem = 0b010;
+        read.a = self.reg_a_code(&Register::Cpp);
+        state.add_instr(read.get())?;
+
+        let mut w_reg = Microinstruction::new(state.next_addr());
+        w_reg.c_bus = self.get_c_code(rds);
+        w_reg.alu = 0b000011000;
+        w_reg.a = self.reg_a_code(&Register::Mdr);
+        state.add_instr(w_reg.get())?;
+
+        // CPP <- CPP + 1
+        let mut inc = Microinstruction::new(state.next_addr());
+        inc.c_bus = self.get_c_code(&vec![Rc::new(Register::Cpp)]);
+        inc.alu = 0b000110101;
+        inc.b = self.reg_b_code(&Register::Cpp);
+        state.add_instr(inc.get())
+    }
+
+    fn eval_jal_inst(&mut self, label: &Rc<str>, state: &mut CsState) -> Result<()> {
         let mut mi = Microinstruction::new(state.next_addr());
         match self.addr.get(label) {
             Some(v) => {
-                mi.next = *v as u16;
+                mi.next = *v;
             }
             None => {
                 self.unreachable
                     .push((Rc::clone(label), state.curr_addr, mi.clone()));
             }
         }
-        state.add_instr(mi.get());
+        state.add_instr(mi.get())
     }
 
-    fn eval_branch_inst(&mut self, ins: &BranchInstruction, state: &mut CsState) {
-        let label = Rc::clone(&ins.label);
-
-        let mut first = Microinstruction::new(state.next_addr());
-        let mut second = None;
-        let branched_addr = state.next_addr() | 0b100000000;
-        first.a = self.reg_a_code(&ins.rs1);
-        first.b = self.reg_b_code(&ins.rs2);
-        first.alu = 0b000111111;
-
-        let mut branched = Microinstruction::new(branched_addr);
-        let mut second_branched = None;
+    /// rd <- return address, then MPC <- rs
+    fn eval_jalr_inst(
+        &mut self,
+        rd: &Rc<Register>,
+        rs: &Rc<Register>,
+        state: &mut CsState,
+    ) -> Result<()> {
+        let mut link = Microinstruction::new(state.next_addr());
+        link.alu = 0b000011000;
+        link.a = Microinstruction::IMM_A;
+        link.immediate = Self::return_addr(state.curr_addr + 2)?;
+        link.c_bus = self.get_c_code(&vec![Rc::clone(rd)]);
+        state.add_instr(link.get())?;
+        state.add_instr(self.jump_register(rs).get())
+    }
 
-        match self.addr.get(&label) {
+    /// ra <- return address, jumping to the label on the same microinstruction.
+    fn eval_call_inst(&mut self, label: &Rc<str>, state: &mut CsState) -> Result<()> {
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.alu = 0b000011000;
+        mi.a = Microinstruction::IMM_A;
+        mi.immediate = Self::return_addr(state.next_addr())?;
+        mi.c_bus = self.get_c_code(&vec![Rc::new(Register::Ra)]);
+        match self.addr.get(label) {
             Some(v) => {
-                branched.next = *v as u16;
+                mi.next = *v;
             }
             None => {
                 self.unreachable
-                    .push((label, branched_addr, branched.clone()));
+                    .push((Rc::clone(label), state.curr_addr, mi.clone()));
             }
         }
+        state.add_instr(mi.get())
+    }
 
-        match ins.opcode {
-            BranchOp::Beq => first.jam = 0b001,
-            BranchOp::Bne => {
-                first.jam = 0b010;
-                let mut mi = first.clone();
-                mi.next += 1;
-                mi.a = self.reg_a_code(&ins.rs2);
-                mi.b = self.reg_b_code(&ins.rs1);
-                second = Some(mi);
-                second_branched = Some(branched.clone());
+    /// MPC <- rs, through the JMPR bits of the JAM field.
+    fn jump_register(&self, rs: &Register) -> Microinstruction {
+        let mut mi = Microinstruction::new(0);
+        mi.jam = Microinstruction::JMPR;
+        mi.alu = 0b000011000;
+        mi.a = self.reg_a_code(rs);
+        mi
+    }
+
+    /// The immediate that holds the address execution returns to.
+    fn return_addr(addr: u16) -> Result<u8> {
+        match u8::try_from(addr) {
+            Ok(addr) => Ok(addr),
+            Err(_) => bail!(EvaluatorError::ControlStoreOverflow {
+                addr,
+                limit: CsState::JAM_BIT,
+            }),
+        }
+    }
+
+    fn eval_branch_inst(&mut self, ins: &BranchInstruction, state: &mut CsState) -> Result<()> {
+        let rs1 = Value::Reg(Rc::clone(&ins.rs1));
+        let rs2 = match &ins.rs2 {
+            Value::Label(l) => {
+                let expr = ConstExpr::Symbol(Rc::clone(l));
+                Value::Immediate(self.eval_const_expr(&expr, COMPARE_RANGE)? as u8)
+            }
+            Value::Expr(expr) => Value::Immediate(self.eval_const_expr(expr, COMPARE_RANGE)? as u8),
+            Value::Immediate(imm) => Value::Immediate(*imm),
+            Value::Reg(r) => Value::Reg(Rc::clone(r)),
+        };
+        let operand = |o: Operand| match o {
+            Operand::Rs1 => &rs1,
+            Operand::Rs2 => &rs2,
+        };
+
+        let base = state.curr_addr;
+        for step in Self::branch_steps(ins.opcode) {
+            let mut mi = Microinstruction::new(state.next_addr());
+            mi.alu = step.alu;
+            if let Some(a) = step.a {
+                (mi.a, mi.immediate) = self.val_a_code(operand(a))?;
+            }
+            if let Some(b) = step.b {
+                let (code, imm) = self.val_b_code(operand(b))?;
+                mi.b = code;
+                mi.immediate |= imm;
+            }
 
-                let label = Rc::clone(&ins.label);
-                if self.addr.get(&label).is_none() {
-                    self.unreachable
-                        .push((label, branched_addr + 1, branched.clone()));
+            if step.jam == 0 {
+                if let BranchTo::Step(k) = step.to {
+                    mi.next = base + k;
                 }
+                state.add_instr(mi.get())?;
+                continue;
             }
-            BranchOp::Blt => {
-                first.jam = 0b010;
-                first.a = self.reg_a_code(&ins.rs2);
-                first.b = self.reg_b_code(&ins.rs1);
+
+            mi.jam = step.jam;
+            let branched_addr = state.jam_slot(&mut mi)?;
+            state.add_instr(mi.get())?;
+            match step.to {
+                BranchTo::Label => self.eval_branch_target(&ins.label, branched_addr, state),
+                BranchTo::Step(k) => {
+                    state.set_instr(branched_addr, Microinstruction::new(base + k).get())
+                }
             }
-            BranchOp::Bgt => first.jam = 0b010,
         }
+        Ok(())
+    }
 
-        state.add_instr(first.get());
-        if let Some(mi) = second {
-            state.add_instr(mi.get());
+    /// The microinstructions a branch compares its operands with.
+    ///
+    /// The equality ones look at the flags of a subtraction, which the ALU does as
+    /// B - A. The others first check, with a XOR, if the most significant bits of
+    /// the operands differ: in that case the result only depends on which one has
+    /// it set, the negative one being the least when signed and the greatest when
+    /// unsigned, and otherwise the subtraction can't overflow.
+    pub(crate) fn branch_steps(op: BranchOp) -> Vec<BranchStep> {
+        use Operand::{Rs1, Rs2};
+        const Z: u8 = 0b001;
+        const N: u8 = 0b010;
+
+        // Jumps to the label on the flags of B - A
+        let sub = |a, b, jam| BranchStep {
+            alu: 0b000111111,
+            a: Some(a),
+            b: Some(b),
+            jam,
+            to: BranchTo::Label,
+        };
+        // Jumps to the label if the most significant bit of `decides` is set when
+        // the ones of the operands differ, and on `cmp` when they don't
+        let by_sign = |decides, cmp| {
+            vec![
+                BranchStep {
+                    alu: 0b001001100,
+                    a: Some(Rs1),
+                    b: Some(Rs2),
+                    jam: N,
+                    to: BranchTo::Step(3),
+                },
+                cmp,
+                BranchStep {
+                    alu: 0,
+                    a: None,
+                    b: None,
+                    jam: 0,
+                    to: BranchTo::Step(4),
+                },
+                BranchStep {
+                    alu: 0b000011000,
+                    a: Some(decides),
+                    b: None,
+                    jam: N,
+                    to: BranchTo::Label,
+                },
+            ]
+        };
+
+        match op {
+            BranchOp::Beq => vec![sub(Rs1, Rs2, Z)],
+            BranchOp::Bne => vec![sub(Rs1, Rs2, N), sub(Rs2, Rs1, N)],
+            BranchOp::Bgt => by_sign(Rs2, sub(Rs1, Rs2, N)),
+            BranchOp::Blt => by_sign(Rs1, sub(Rs2, Rs1, N)),
+            BranchOp::Bge => by_sign(Rs2, sub(Rs1, Rs2, N | Z)),
+            BranchOp::Ble => by_sign(Rs1, sub(Rs2, Rs1, N | Z)),
+            BranchOp::Bgtu => by_sign(Rs1, sub(Rs1, Rs2, N)),
+            BranchOp::Bltu => by_sign(Rs2, sub(Rs2, Rs1, N)),
+            BranchOp::Bgeu => by_sign(Rs1, sub(Rs1, Rs2, N | Z)),
+            BranchOp::Bleu => by_sign(Rs2, sub(Rs2, Rs1, N | Z)),
         }
+    }
 
-        state.set_instr(branched_addr, branched.get());
-        if let Some(mi) = second_branched {
-            state.set_instr(branched_addr + 1, mi.get());
+    /// Writes the jump to `label` taken by a JAM branch landing on `branched_addr`.
+    fn eval_branch_target(&mut self, label: &Rc<str>, branched_addr: u16, state: &mut CsState) {
+        let mut branched = Microinstruction::new(branched_addr);
+        match self.addr.get(label) {
+            Some(v) => {
+                branched.next = *v;
+            }
+            None => {
+                self.unreachable
+                    .push((Rc::clone(label), branched_addr, branched.clone()));
+            }
         }
+        state.set_instr(branched_addr, branched.get());
     }
 
-    fn eval_no_op_inst(&mut self, opcode: &NoOperandOpcode, state: &mut CsState) {
+    fn eval_no_op_inst(&mut self, opcode: &NoOperandOpcode, state: &mut CsState) -> Result<()> {
         match opcode {
             NoOperandOpcode::Halt => state.add_instr(Microinstruction::HALT),
             NoOperandOpcode::Nop => state.add_instr(Microinstruction::new(state.next_addr()).get()),
+            NoOperandOpcode::Ret => state.add_instr(self.jump_register(&Register::Ra).get()),
         }
     }
 
@@ -290,10 +1260,10 @@ impl AsmEvaluator {
         rd: &Vec<Rc<Register>>,
         rs1: &Value,
         cs_state: &mut CsState,
-    ) {
+    ) -> Result<()> {
         let c_code = self.get_c_code(rd);
         let mut mi = Microinstruction::new(cs_state.next_addr());
-        (mi.a, mi.immediate) = self.val_a_code(rs1);
+        (mi.a, mi.immediate) = self.val_a_code(rs1)?;
         mi.c_bus = c_code;
         mi.b = Microinstruction::NO_B;
 
@@ -303,9 +1273,12 @@ impl AsmEvaluator {
             SingleOperandOpcode::Sll => mi.alu = 0b100011000,
             SingleOperandOpcode::Sra => mi.alu = 0b010011000,
             SingleOperandOpcode::Sla => mi.alu = 0b110011000,
+            SingleOperandOpcode::Srl => mi.alu = 0b1000011000,
+            SingleOperandOpcode::Rol => mi.alu = 0b1110011000,
             SingleOperandOpcode::Mov => mi.alu = 0b000011000,
         }
-        cs_state.add_instr(mi.get());
+        cs_state.add_instr(mi.get())?;
+        Ok(())
     }
 
     fn eval_double_op_inst(
@@ -315,7 +1288,7 @@ impl AsmEvaluator {
         rs1: &Rc<Register>,
         rs2: &Value,
         cs_state: &mut CsState,
-    ) {
+    ) -> Result<()> {
         let c_code = self.get_c_code(rd);
         match opcode {
             DoubleOperandOpcode::Add | DoubleOperandOpcode::Addi => {
@@ -323,46 +1296,45 @@ impl AsmEvaluator {
                 mi.c_bus = c_code;
                 mi.alu = 0b000111100;
                 mi.a = self.reg_a_code(rs1.as_ref());
-                (mi.b, mi.immediate) = self.val_b_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.b, mi.immediate) = self.val_b_code(rs2)?;
+                cs_state.add_instr(mi.get())?;
             }
             DoubleOperandOpcode::Sub | DoubleOperandOpcode::Subi => {
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 mi.c_bus = c_code;
                 mi.alu = 0b000111111;
                 mi.b = self.reg_b_code(rs1.as_ref());
-                (mi.a, mi.immediate) = self.val_a_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.a, mi.immediate) = self.val_a_code(rs2)?;
+                cs_state.add_instr(mi.get())?;
             }
             DoubleOperandOpcode::And | DoubleOperandOpcode::Andi => {
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 mi.c_bus = c_code;
                 mi.alu = 0b000011000;
                 mi.a = self.reg_a_code(rs1.as_ref());
-                (mi.b, mi.immediate) = self.val_b_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.b, mi.immediate) = self.val_b_code(rs2)?;
+                cs_state.add_instr(mi.get())?;
             }
             DoubleOperandOpcode::Or | DoubleOperandOpcode::Ori => {
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 mi.c_bus = c_code;
                 mi.alu = 0b000011100;
                 mi.a = self.reg_a_code(rs1.as_ref());
-                (mi.b, mi.immediate) = self.val_b_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.b, mi.immediate) = self.val_b_code(rs2)?;
+                cs_state.add_instr(mi.get())?;
             }
             DoubleOperandOpcode::Mul => {
                 // Temp registers usage:
                 // - T0: gonna store the min(rs1, rs2)
                 // - T1: gonna store the max(rs1, rs2)
-                let branched_addr = cs_state.next_addr() | 0b100000000;
-
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 // JUMP if rs1 > rs2
                 mi.jam = 0b010;
                 mi.alu = 0b000111111;
                 mi.a = self.reg_a_code(rs1);
-                (mi.b, mi.immediate) = self.val_b_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.b, mi.immediate) = self.val_b_code(rs2)?;
+                let branched_addr = cs_state.jam_slot(&mut mi)?;
+                cs_state.add_instr(mi.get())?;
 
                 // HAS NOT JUMPED, therefore rs1 <= rs2
                 // mv t0 <- rs1
@@ -370,7 +1342,7 @@ impl AsmEvaluator {
                 mi.alu = 0b000011000;
                 mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T0)]);
                 mi.a = self.reg_a_code(rs1);
-                cs_state.add_instr(mi.get());
+                cs_state.add_instr(mi.get())?;
                 // mv t1, t2, rd <- rs2
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 mi.alu = 0b000011000;
@@ -382,7 +1354,7 @@ impl AsmEvaluator {
                     _ => unreachable!("Should't receive a label arg."),
                 };
                 let loop_addr = cs_state.next_addr();
-                cs_state.add_instr(mi.get());
+                cs_state.add_instr(mi.get())?;
 
                 // Intersection between the cases: t1 + .. + t1, t0-times
                 // t0 <- t0 - 1 (special case because we subtract 1 without ussing immediate)
@@ -391,18 +1363,19 @@ impl AsmEvaluator {
                 mi.alu = 0b000110110;
                 mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T0)]);
                 mi.b = self.reg_b_code(&Register::T0);
-                cs_state.add_instr(mi.get());
+                let exit_addr = cs_state.jam_slot(&mut mi)?;
+                cs_state.add_instr(mi.get())?;
                 // add t1, rd <- t1 + t2
                 let mut mi = Microinstruction::new(loop_addr);
                 mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T1)]) | c_code;
                 mi.alu = 0b000111100;
                 mi.a = self.reg_a_code(&Register::T1);
                 mi.b = self.reg_b_code(&Register::T2);
-                cs_state.add_instr(mi.get());
+                cs_state.add_instr(mi.get())?;
 
                 // HAS JUMPED, therefore rs1 > rs2
                 // mv t0 <- rs2
-                let mut mi = Microinstruction::new(cs_state.next_addr() - 1);
+                let mut mi = Microinstruction::new(cs_state.addr());
                 mi.alu = 0b000011000;
                 mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T0)]);
                 mi.a = match rs2 {
@@ -410,83 +1383,189 @@ impl AsmEvaluator {
                     Value::Immediate(_) => unreachable!("Should't receive a immediate arg."),
                     _ => unreachable!("Should't receive a label arg."),
                 };
-                cs_state.add_complex_instr(|cs, addr| {
-                    cs.set_word(branched_addr, mi.get());
-                    addr
-                });
+                cs_state.set_instr(branched_addr, mi.get());
                 // mv t1, t2, rd <- rs1
                 let mut mi = Microinstruction::new(loop_addr);
                 mi.alu = 0b000011000;
                 mi.c_bus =
                     self.get_c_code(&vec![Rc::new(Register::T1), Rc::new(Register::T2)]) | c_code;
                 mi.a = self.reg_a_code(rs1);
-                cs_state.add_instr(mi.get());
+                cs_state.add_instr(mi.get())?;
 
                 let jal = Microinstruction::new(cs_state.addr());
-                cs_state.set_instr((loop_addr + 1) | 0b100000000, jal.get())
+                cs_state.set_instr(exit_addr, jal.get());
             }
             DoubleOperandOpcode::Xor | DoubleOperandOpcode::Xori => {
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 mi.c_bus = c_code;
                 mi.alu = 0b001001100;
                 mi.a = self.reg_a_code(rs1.as_ref());
-                (mi.b, mi.immediate) = self.val_b_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.b, mi.immediate) = self.val_b_code(rs2)?;
+                cs_state.add_instr(mi.get())?;
             }
             DoubleOperandOpcode::Mul2 | DoubleOperandOpcode::Muli => {
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 mi.c_bus = c_code;
                 mi.alu = 0b001011100;
                 mi.a = self.reg_a_code(rs1.as_ref());
-                (mi.b, mi.immediate) = self.val_b_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.b, mi.immediate) = self.val_b_code(rs2)?;
+                cs_state.add_instr(mi.get())?;
             }
             DoubleOperandOpcode::Div | DoubleOperandOpcode::Divi => {
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 mi.c_bus = c_code;
                 mi.alu = 0b001101100;
                 mi.a = self.reg_a_code(rs1.as_ref());
-                (mi.b, mi.immediate) = self.val_b_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.b, mi.immediate) = self.val_b_code(rs2)?;
+                cs_state.add_instr(mi.get())?;
             }
             DoubleOperandOpcode::Mod | DoubleOperandOpcode::Modi => {
                 let mut mi = Microinstruction::new(cs_state.next_addr());
                 mi.c_bus = c_code;
                 mi.alu = 0b001111100;
                 mi.a = self.reg_a_code(rs1.as_ref());
-                (mi.b, mi.immediate) = self.val_b_code(rs2);
-                cs_state.add_instr(mi.get());
+                (mi.b, mi.immediate) = self.val_b_code(rs2)?;
+                cs_state.add_instr(mi.get())?;
+            }
+            DoubleOperandOpcode::Slli | DoubleOperandOpcode::Srli | DoubleOperandOpcode::Srai => {
+                let n = match rs2 {
+                    Value::Immediate(n) => *n,
+                    Value::Label(l) => {
+                        let expr = ConstExpr::Symbol(Rc::clone(l));
+                        self.eval_const_expr(&expr, SHIFT_RANGE)? as u8
+                    }
+                    Value::Expr(expr) => self.eval_const_expr(expr, SHIFT_RANGE)? as u8,
+                    Value::Reg(_) => unreachable!("Shouldn't receive a register amount."),
+                };
+                for (i, alu) in Self::shift_steps(opcode, n).into_iter().enumerate() {
+                    let mut mi = Microinstruction::new(cs_state.next_addr());
+                    mi.c_bus = c_code;
+                    mi.alu = alu;
+                    mi.a = match i {
+                        0 => self.reg_a_code(rs1),
+                        _ => self.reg_a_code(&rd[0]),
+                    };
+                    cs_state.add_instr(mi.get())?;
+                }
             }
+            DoubleOperandOpcode::Sllv | DoubleOperandOpcode::Srlv | DoubleOperandOpcode::Srav => {
+                let Value::Reg(rs2) = rs2 else {
+                    unreachable!("Shouldn't receive an immediate amount.")
+                };
+                self.eval_shift_reg_inst(opcode, rd, rs1, rs2, cs_state)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// ALU codes that pass A through the shifter, moving it by 1 and by 8 bits in
+    /// the direction of `op`.
+    fn shift_codes(op: &DoubleOperandOpcode) -> (u16, u16) {
+        match op {
+            DoubleOperandOpcode::Slli | DoubleOperandOpcode::Sllv => (0b0110011000, 0b0100011000),
+            DoubleOperandOpcode::Srli | DoubleOperandOpcode::Srlv => (0b1000011000, 0b1100011000),
+            DoubleOperandOpcode::Srai | DoubleOperandOpcode::Srav => (0b0010011000, 0b1010011000),
+            _ => unreachable!("{:?} isn't a shift", op),
+        }
+    }
+
+    /// The ALU code of each word of a shift by `n` bits: as many shifts by 8 as fit,
+    /// then shifts by 1. A shift by 0 is a single `mov`.
+    pub(crate) fn shift_steps(op: &DoubleOperandOpcode, n: u8) -> Vec<u16> {
+        let (by_1, by_8) = Self::shift_codes(op);
+        if n == 0 {
+            return vec![0b000011000];
         }
+        let mut steps = vec![by_8; n as usize / 8];
+        steps.extend(vec![by_1; n as usize % 8]);
+        steps
+    }
+
+    /// Shifts by the 5 low bits of `rs2`, one bit per iteration, counting them down
+    /// on T0.
+    fn eval_shift_reg_inst(
+        &mut self,
+        opcode: &DoubleOperandOpcode,
+        rd: &Vec<Rc<Register>>,
+        rs1: &Register,
+        rs2: &Register,
+        state: &mut CsState,
+    ) -> Result<()> {
+        let c_code = self.get_c_code(rd);
+        let (by_1, _) = Self::shift_codes(opcode);
+
+        // t0 <- rs2 & 31, jumping out when there's nothing to shift
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.jam = 0b001;
+        mi.alu = 0b000001100;
+        mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T0)]);
+        mi.a = self.reg_a_code(rs2);
+        mi.b = Microinstruction::IMM_B;
+        mi.immediate = 31;
+        let zero_addr = state.jam_slot(&mut mi)?;
+        state.add_instr(mi.get())?;
+
+        // rd <- rs1 shifted by 1
+        let loop_addr = state.next_addr();
+        let mut mi = Microinstruction::new(loop_addr);
+        mi.alu = by_1;
+        mi.c_bus = c_code;
+        mi.a = self.reg_a_code(rs1);
+        state.add_instr(mi.get())?;
+
+        // t0 <- t0 - 1, until it's zero
+        let mut mi = Microinstruction::new(state.next_addr());
+        mi.jam = 0b001;
+        mi.alu = 0b000110110;
+        mi.c_bus = self.get_c_code(&vec![Rc::new(Register::T0)]);
+        mi.b = self.reg_b_code(&Register::T0);
+        let exit_addr = state.jam_slot(&mut mi)?;
+        state.add_instr(mi.get())?;
+
+        // rd <- rd shifted by 1
+        let mut mi = Microinstruction::new(loop_addr);
+        mi.alu = by_1;
+        mi.c_bus = c_code;
+        mi.a = self.reg_a_code(&rd[0]);
+        state.add_instr(mi.get())?;
+
+        // A shift by 0 still copies rs1 into rd
+        let mut mi = Microinstruction::new(state.addr());
+        mi.alu = 0b000011000;
+        mi.c_bus = c_code;
+        mi.a = self.reg_a_code(rs1);
+        state.set_instr(zero_addr, mi.get());
+        state.set_instr(exit_addr, Microinstruction::new(state.addr()).get());
+        Ok(())
+    }
+
+    /// Returns the immediate field for a label or constant expression operand.
+    fn val_immediate(&self, val: &Value) -> Result<u8> {
+        let res = match val {
+            Value::Immediate(imm) => *imm,
+            Value::Label(l) => {
+                let expr = ConstExpr::Symbol(Rc::clone(l));
+                self.eval_const_expr(&expr, IMMEDIATE_RANGE)? as u8
+            }
+            Value::Expr(expr) => self.eval_const_expr(expr, IMMEDIATE_RANGE)? as u8,
+            Value::Reg(_) => 0,
+        };
+        Ok(res)
     }
 
     /// Returns a pair of (A bus code, Immediate).
-    fn val_a_code(&self, val: &Value) -> (u8, u8) {
+    fn val_a_code(&self, val: &Value) -> Result<(u8, u8)> {
         match val {
-            Value::Reg(r) => (self.reg_a_code(r), 0),
-            Value::Immediate(imm) => (Microinstruction::IMM_A, *imm),
-            Value::Label(l) => (
-                Microinstruction::IMM_A,
-                *self
-                    .values
-                    .get(l.as_ref())
-                    .expect("Should be defined before"),
-            ),
+            Value::Reg(r) => Ok((self.reg_a_code(r), 0)),
+            _ => Ok((Microinstruction::IMM_A, self.val_immediate(val)?)),
         }
     }
 
     /// Returns a pair of (B bus code, Immediate).
-    fn val_b_code(&self, val: &Value) -> (u8, u8) {
+    fn val_b_code(&self, val: &Value) -> Result<(u8, u8)> {
         match val {
-            Value::Reg(r) => (self.reg_b_code(r), 0),
-            Value::Immediate(imm) => (Microinstruction::IMM_B, *imm),
-            Value::Label(l) => (
-                Microinstruction::IMM_B,
-                *self
-                    .values
-                    .get(l.as_ref())
-                    .expect("Should be defined before"),
-            ),
+            Value::Reg(r) => Ok((self.reg_b_code(r), 0)),
+            _ => Ok((Microinstruction::IMM_B, self.val_immediate(val)?)),
         }
     }
 
@@ -545,11 +1624,12 @@ impl AsmEvaluator {
         }
     }
 
-    /// Returns the c bus field content of the MI. (20 bits)
+    /// Returns the c bus field content of the MI. (20 bits, plus CPP at bit 20)
     fn get_c_code(&self, regs: &Vec<Rc<Register>>) -> u32 {
         let mut c_code = 0;
         for reg in regs {
             match reg.as_ref() {
+                Register::Cpp => c_code |= 1 << 20,
                 Register::Mdr => c_code |= 1 << 19,
                 Register::Mar => c_code |= 1 << 18,
                 Register::Pc => c_code |= 1 << 17,
@@ -577,13 +1657,26 @@ impl AsmEvaluator {
     }
 }
 
+/// Lays out the program in the control store, keeping track of the words in use.
+///
+/// Sequential code grows from address 0 through the lower half. A JAM branch from a
+/// microinstruction whose next address is `n` lands on `n | 0x100`; when that word
+/// is already taken, the branch goes through a trampoline allocated downwards from
+/// the end of the lower half instead.
 #[derive(Default)]
 pub struct CsState {
     builder: CtrlStoreBuilder,
     pub curr_addr: u16,
+    used: HashSet<u16>,
+    /// Lowest address taken by a trampoline, where sequential code has to stop.
+    trampolines: Option<u16>,
+    /// Words reserved outside the sequential flow, in the order they were taken.
+    side_words: Vec<u16>,
 }
 
 impl CsState {
+    const JAM_BIT: u16 = 0b100000000;
+
     pub fn new() -> Self {
         Self::default()
     }
@@ -593,25 +1686,100 @@ impl CsState {
     }
 
     pub fn next_addr(&self) -> u16 {
-        (self.curr_addr & 0b011111111) + 1
+        self.curr_addr + 1
+    }
+
+    /// Amount of words available for sequential code.
+    fn limit(&self) -> u16 {
+        self.trampolines.unwrap_or(Self::JAM_BIT)
+    }
+
+    fn reserve(&mut self, addr: u16) -> Result<()> {
+        if !self.used.insert(addr) {
+            bail!(EvaluatorError::ControlStoreCollision { addr })
+        }
+        Ok(())
     }
 
-    pub fn add_instr(&mut self, inst: u64) {
+    pub fn add_instr(&mut self, inst: u64) -> Result<()> {
+        if self.curr_addr >= self.limit() {
+            bail!(EvaluatorError::ControlStoreOverflow {
+                addr: self.curr_addr,
+                limit: self.limit(),
+            })
+        }
+        self.reserve(self.curr_addr)?;
         self.builder.set_word(self.curr_addr, inst);
         self.curr_addr = self.next_addr();
+        Ok(())
+    }
+
+    /// Writes a word at a fixed address, outside the sequential flow.
+    pub fn place_instr(&mut self, addr: u16, inst: u64) -> Result<()> {
+        self.reserve(addr)?;
+        self.builder.set_word(addr, inst);
+        self.side_words.push(addr);
+        Ok(())
     }
 
+    /// Writes a word previously reserved with [`CsState::jam_slot`], or patches one
+    /// already written.
     pub fn set_instr(&mut self, addr: u16, inst: u64) {
         self.builder.set_word(addr, inst);
     }
 
-    /// Add a complex instructions from a functions that returns the address which
-    /// the next instructions should be stored.
-    pub fn add_complex_instr<F>(&mut self, func: F)
-    where
-        F: FnOnce(&mut CtrlStoreBuilder, u16) -> u16,
-    {
-        self.curr_addr = (func)(&mut self.builder, self.curr_addr)
+    /// Reserves the word a JAM branch from `mi` lands on and returns its address.
+    ///
+    /// If `mi.next | 0x100` is taken, `mi` is redirected to a trampoline that falls
+    /// through to the original next address, and the branch lands on the upper
+    /// half word of the trampoline.
+    fn jam_slot(&mut self, mi: &mut Microinstruction) -> Result<u16> {
+        if mi.next >= self.limit() {
+            bail!(EvaluatorError::ControlStoreOverflow {
+                addr: mi.next,
+                limit: self.limit(),
+            })
+        }
+
+        let target = mi.next | Self::JAM_BIT;
+        if !self.used.contains(&target) {
+            self.reserve(target)?;
+            self.side_words.push(target);
+            return Ok(target);
+        }
+
+        let Some(trampoline) = (mi.next + 1..self.limit())
+            .rev()
+            .find(|&a| !self.used.contains(&a) && !self.used.contains(&(a | Self::JAM_BIT)))
+        else {
+            bail!(EvaluatorError::ControlStoreOverflow {
+                addr: mi.next,
+                limit: self.limit(),
+            })
+        };
+
+        self.reserve(trampoline)?;
+        self.reserve(trampoline | Self::JAM_BIT)?;
+        self.builder
+            .set_word(trampoline, Microinstruction::new(mi.next).get());
+        self.trampolines = Some(trampoline);
+        self.side_words
+            .extend([trampoline, trampoline | Self::JAM_BIT]);
+        mi.next = trampoline;
+        Ok(trampoline | Self::JAM_BIT)
+    }
+
+    pub(crate) fn words(&self) -> Vec<u64> {
+        self.builder.firmware().to_vec()
+    }
+
+    /// Replaces the whole control store, with the sequential code ending at `end`.
+    pub(crate) fn load_words(&mut self, words: &[u64], end: u16) {
+        self.builder.load_words(0, words.iter().copied());
+        self.used = (0..words.len() as u16)
+            .filter(|&a| words[a as usize] != 0)
+            .collect();
+        self.curr_addr = end;
     }
 
     pub fn build_cs(self) -> CtrlStore {
@@ -619,11 +1787,42 @@ impl CsState {
     }
 }
 
+/// Operand of a branch read by one of its microinstructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Operand {
+    Rs1,
+    Rs2,
+}
+
+/// Where a microinstruction of a branch jumps to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BranchTo {
+    Label,
+    /// Another microinstruction of the branch, by its index. Past the last one is
+    /// the instruction after the branch.
+    Step(u16),
+}
+
+/// A microinstruction of a branch. With JAM flags it jumps when one of them holds
+/// for the ALU output and falls through to the next step otherwise, without them
+/// it always jumps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct BranchStep {
+    pub alu: u16,
+    pub a: Option<Operand>,
+    pub b: Option<Operand>,
+    pub jam: u8,
+    pub to: BranchTo,
+}
+
 #[derive(Clone)]
-struct Microinstruction {
+pub(crate) struct Microinstruction {
     pub next: u16,
     pub jam: u8,
+    /// The 9 bits of the ALU field, and the top bit of the shifter at bit 9, stored
+    /// in the spare bit 63.
     pub alu: u16,
+    /// The 20 bits of the C bus field, and CPP at bit 20, stored in the spare bit 62.
     pub c_bus: u32,
     pub mem: u8,
     pub a: u8,
@@ -636,6 +1835,8 @@ impl Microinstruction {
     pub const IMM_A: u8 = 0b01000;
     pub const IMM_B: u8 = 0b00011;
     pub const NO_B: u8 = 0b11111;
+    /// JAM bits that load the MPC from the C bus.
+    pub const JMPR: u8 = 0b111;
 
     /// Creates a new microinstruction.
     pub fn new(next_addr: u16) -> Self {
@@ -651,18 +1852,38 @@ impl Microinstruction {
         }
     }
 
+    /// Splits a word of the control store back into its fields.
+    pub fn decode(word: u64) -> Self {
+        Self {
+            next: (word >> 53) as u16 & 0x1FF,
+            jam: (word >> 50) as u8 & 0b111,
+            alu: (word >> 41) as u16 & 0x1FF | ((word >> 63) as u16) << 9,
+            c_bus: (word >> 21) as u32 & 0xFFFFF | ((word >> 62) as u32 & 1) << 20,
+            mem: (word >> 18) as u8 & 0b111,
+            a: (word >> 13) as u8 & 0x1F,
+            b: (word >> 8) as u8 & 0x1F,
+            immediate: word as u8,
+        }
+    }
+
     /// Get value of the Microinstruction
     pub fn get(&self) -> u64 {
-        let mut mi = self.next as u64;
+        let mut mi = (self.alu >> 9) as u64 & 1;
+
+        mi <<= 1;
+        mi |= (self.c_bus >> 20) as u64 & 1;
+
+        mi <<= 9;
+        mi |= self.next as u64;
 
         mi <<= 3;
         mi |= self.jam as u64;
 
         mi <<= 9;
-        mi |= self.alu as u64;
+        mi |= (self.alu & 0x1FF) as u64;
 
         mi <<= 20;
-        mi |= self.c_bus as u64;
+        mi |= (self.c_bus & 0xFFFFF) as u64;
 
         mi <<= 3;
         mi |= self.mem as u64;
@@ -683,6 +1904,38 @@ impl Microinstruction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        assembler::{diagnostics::Severity, tester},
+        uarch::{mem::Ram, Computer},
+    };
+
+    /// Assembles `src` and runs it until it halts.
+    fn run(src: &str) -> Computer {
+        let mut eval = AsmEvaluator::new();
+        let (cs, ram) = eval.evaluate_buffer(src).unwrap();
+        let mut mem = Ram::new();
+        mem.load(0, ram.iter().copied());
+        let mut computer = Computer::new(mem, cs);
+        computer.exec();
+        computer
+    }
+
+    /// Value of the register named `name` once `computer` halted.
+    fn reg(computer: &Computer, name: &str) -> u32 {
+        tester::register(computer.regs(), name)
+    }
+
+    /// Code, line and message of each error found assembling `input`.
+    fn eval_errors(input: &str) -> Vec<(&'static str, usize, String)> {
+        let err = AsmEvaluator::new().evaluate_buffer(input).err().unwrap();
+        let diags = err.downcast_ref::<Diagnostics>().unwrap();
+        diags
+            .items
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| (d.code, d.span.as_ref().unwrap().line, d.message.clone()))
+            .collect()
+    }
 
     #[test]
     fn cbus_eval() {
@@ -731,7 +1984,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_000111100_00000000000000001100_000_01010_10011_00000000;
 
@@ -756,7 +2009,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_000111100_00000000000000001100_000_01010_00011_00000101;
 
@@ -781,7 +2034,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_000111111_00000000000000001100_000_11000_00101_00000000;
 
@@ -806,7 +2059,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_000111111_00000000000000001100_000_01000_00101_00000111;
 
@@ -831,7 +2084,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_001001100_00000000000000001100_000_01010_10011_00000000;
 
@@ -856,7 +2109,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_001001100_00000000000000001100_000_01010_00011_00000101;
 
@@ -881,7 +2134,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_001011100_00000000000000001100_000_01010_10011_00000000;
 
@@ -906,7 +2159,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_001011100_00000000000000001100_000_01010_00011_00000101;
 
@@ -931,7 +2184,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_001101100_00000000000000001100_000_01010_10011_00000000;
 
@@ -956,7 +2209,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_001101100_00000000000000001100_000_01010_00011_00000101;
 
@@ -981,7 +2234,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_001111100_00000000000000001100_000_01010_10011_00000000;
 
@@ -1006,7 +2259,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_001111100_00000000000000001100_000_01010_00011_00000101;
 
@@ -1029,7 +2282,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_000011000_00000000000000000001_000_01000_11111_00000001;
@@ -1053,7 +2306,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_000011000_00000000000000000001_000_00000_11111_00000000;
@@ -1077,7 +2330,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_000011010_00000000000000000011_000_00000_11111_00000000;
@@ -1101,7 +2354,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_100011000_00000000000000000011_000_00000_11111_00000000;
@@ -1125,7 +2378,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_110011000_00000000000000000011_000_00000_11111_00000000;
@@ -1149,7 +2402,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         #[allow(clippy::unusual_byte_groupings)]
         let expected = 0b000000001_000_010011000_00000000000000000011_000_00000_11111_00000000;
@@ -1175,7 +2428,7 @@ mod tests {
         ];
         let seg = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![seg]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         let no_branch_mcode: Vec<u64> = vec![
             // JUMP if a1 > a2 (a2 - a1 < 0)
@@ -1265,6 +2518,7 @@ mod tests {
                 )]),
             ],
             errors: vec![],
+            spans: SourceSpans::default(),
         };
         let mut eval = AsmEvaluator::new();
         let (cs, _) = eval.eval_program(program).unwrap();
@@ -1280,8 +2534,8 @@ mod tests {
             0b000000011_000_000011000_01000000000000000000_010_01000_11111_00000001,
             // a1 <- mdr
             0b000000100_000_000011000_00000000000000000100_000_00000_11111_00000000,
-            // mar <- 0 and READ
-            0b000000101_000_000011000_01000000000000000000_010_01000_11111_00000000,
+            // mar <- address of tubias_addr that is 2 and READ
+            0b000000101_000_000011000_01000000000000000000_010_01000_11111_00000010,
             // a1 <- mdr
             0b000000110_000_000011000_00000000000000000100_000_00000_11111_00000000,
             Microinstruction::HALT,
@@ -1337,6 +2591,7 @@ mod tests {
                 )]),
             ],
             errors: vec![],
+            spans: SourceSpans::default(),
         };
         let mut eval = AsmEvaluator::new();
         let (cs, _) = eval.eval_program(program).unwrap();
@@ -1354,8 +2609,8 @@ mod tests {
             0b000000100_000_000011000_01000000000000000000_100_01000_11111_00000001,
             // mdr <- a1
             0b000000101_000_000011000_10000000000000000000_000_10110_11111_00000000,
-            // mar <- tubias_addr that is 0 and WRITE
-            0b000000110_000_000011000_01000000000000000000_100_01000_11111_00000000,
+            // mar <- address of tubias_addr that is 2 and WRITE
+            0b000000110_000_000011000_01000000000000000000_100_01000_11111_00000010,
             Microinstruction::HALT,
         ];
 
@@ -1382,7 +2637,7 @@ mod tests {
         );
         let main = TextSegment::new_labeled_section("main".into(), instructions);
         let secs = Sections::new_text_section(vec![tubias, main]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         let expected = [
             Microinstruction::HALT,
@@ -1422,13 +2677,13 @@ mod tests {
                 Instruction::new_branch_instruction(
                     BranchOp::Beq,
                     Rc::new(Register::A1),
-                    Rc::new(Register::A2),
+                    Value::Reg(Rc::new(Register::A2)),
                     Rc::from("done"),
                 ),
             ],
         );
         let secs = Sections::new_text_section(vec![done, main]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         let no_branch_mcode: Vec<u64> = vec![
             // add a0 <- a1, a2
@@ -1472,13 +2727,13 @@ mod tests {
                 Instruction::new_branch_instruction(
                     BranchOp::Bne,
                     Rc::new(Register::A2),
-                    Rc::new(Register::A3),
+                    Value::Reg(Rc::new(Register::A3)),
                     Rc::from("done"),
                 ),
             ],
         );
         let secs = Sections::new_text_section(vec![done, main]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         let no_branch_mcode: Vec<u64> = vec![
             // add a0 <- a1, a2
@@ -1490,7 +2745,7 @@ mod tests {
             0b000000100_010_000111111_00000000000000000000_000_11000_10010_00000000,
         ];
 
-        let branched_mcode = vec![
+        let branched_mcode = [
             0b000000000_000_000000000_00000000000000000000_000_11111_11111_00000000,
             0b000000000_000_000000000_00000000000000000000_000_11111_11111_00000000,
         ];
@@ -1527,32 +2782,50 @@ mod tests {
                 Instruction::new_branch_instruction(
                     BranchOp::Blt,
                     Rc::new(Register::A2),
-                    Rc::new(Register::A3),
+                    Value::Reg(Rc::new(Register::A3)),
                     Rc::from("done"),
                 ),
             ],
         );
         let secs = Sections::new_text_section(vec![done, main]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         let no_branch_mcode: Vec<u64> = vec![
             // add a0 <- a1, a2
             0b000000001_000_000111100_00000000000000001000_000_10110_10010_00000000,
             // add a0 <- a1, a2
             0b000000010_000_000111100_00000000000000001000_000_10110_10010_00000000,
-            // blt a2, a3, done
-            0b000000011_010_000111111_00000000000000000000_000_11000_10010_00000000,
+            // blt a2, a3, done: the signs of the operands differ
+            0b000000011_010_001001100_00000000000000000000_000_10111_10011_00000000,
+            // they don't, on the subtraction
+            0b000000100_010_000111111_00000000000000000000_000_11000_10010_00000000,
+            0b000000110_000_000000000_00000000000000000000_000_11111_11111_00000000,
+            // they do, on the sign of the one that decides
+            0b000000110_010_000011000_00000000000000000000_000_10111_11111_00000000,
         ];
 
-        let branched_mcode =
-            0b000000000_000_000000000_00000000000000000000_000_11111_11111_00000000;
+        let branched_mcode = [
+            (
+                0x103,
+                0b000000101_000_000000000_00000000000000000000_000_11111_11111_00000000,
+            ),
+            (
+                0x104,
+                0b000000000_000_000000000_00000000000000000000_000_11111_11111_00000000,
+            ),
+            (
+                0x106,
+                0b000000000_000_000000000_00000000000000000000_000_11111_11111_00000000,
+            ),
+        ];
 
         for (addr, &mi) in no_branch_mcode.iter().enumerate() {
             assert_eq!(mi, firmware[addr]);
         }
 
-        let branched_addr = 3 | 0b100000000;
-        assert_eq!(branched_mcode, firmware[branched_addr]);
+        for (addr, mi) in branched_mcode {
+            assert_eq!(mi, firmware[addr]);
+        }
     }
 
     #[test]
@@ -1578,32 +2851,245 @@ mod tests {
                 Instruction::new_branch_instruction(
                     BranchOp::Bgt,
                     Rc::new(Register::A2),
-                    Rc::new(Register::A3),
+                    Value::Reg(Rc::new(Register::A3)),
                     Rc::from("done"),
                 ),
             ],
         );
         let secs = Sections::new_text_section(vec![done, main]);
-        let firmware = AsmEvaluator::new().eval(&secs).firmware();
+        let firmware = AsmEvaluator::new().eval(&secs).unwrap().firmware();
 
         let no_branch_mcode: Vec<u64> = vec![
             // add a0 <- a1, a2
             0b000000001_000_000111100_00000000000000001000_000_10110_10010_00000000,
             // add a0 <- a1, a2
             0b000000010_000_000111100_00000000000000001000_000_10110_10010_00000000,
-            // bgt a2, a3, done
-            0b000000011_010_000111111_00000000000000000000_000_10111_10011_00000000,
+            // bgt a2, a3, done: the signs of the operands differ
+            0b000000011_010_001001100_00000000000000000000_000_10111_10011_00000000,
+            // they don't, on the subtraction
+            0b000000100_010_000111111_00000000000000000000_000_10111_10011_00000000,
+            0b000000110_000_000000000_00000000000000000000_000_11111_11111_00000000,
+            // they do, on the sign of the one that decides
+            0b000000110_010_000011000_00000000000000000000_000_11000_11111_00000000,
         ];
 
-        let branched_mcode =
-            0b000000000_000_000000000_00000000000000000000_000_11111_11111_00000000;
+        let branched_mcode = [
+            (
+                0x103,
+                0b000000101_000_000000000_00000000000000000000_000_11111_11111_00000000,
+            ),
+            (
+                0x104,
+                0b000000000_000_000000000_00000000000000000000_000_11111_11111_00000000,
+            ),
+            (
+                0x106,
+                0b000000000_000_000000000_00000000000000000000_000_11111_11111_00000000,
+            ),
+        ];
 
         for (addr, &mi) in no_branch_mcode.iter().enumerate() {
             assert_eq!(mi, firmware[addr]);
         }
 
-        let branched_addr = 3 | 0b100000000;
-        assert_eq!(branched_mcode, firmware[branched_addr]);
+        for (addr, mi) in branched_mcode {
+            assert_eq!(mi, firmware[addr]);
+        }
+    }
+
+    #[test]
+    fn branches_run() {
+        let taken = |x: i64, y: i64, branch: &str| {
+            let input = format!(
+                ".text\nmain:\n    li s0 <- {}\n    li s1 <- {}\n    {}, yes\n    halt\nyes:\n    lui a0 <- 1\n    halt\n",
+                x, y, branch
+            );
+            reg(&run(&input), "a0") == 1
+        };
+
+        type Cmp = fn(i64, i64) -> bool;
+        let signed: [(&str, Cmp); 6] = [
+            ("beq", |x, y| x == y),
+            ("bne", |x, y| x != y),
+            ("blt", |x, y| x < y),
+            ("bgt", |x, y| x > y),
+            ("bge", |x, y| x >= y),
+            ("ble", |x, y| x <= y),
+        ];
+        let unsigned: [(&str, Cmp); 4] = [
+            ("bltu", |x, y| (x as u32) < (y as u32)),
+            ("bgtu", |x, y| (x as u32) > (y as u32)),
+            ("bgeu", |x, y| (x as u32) >= (y as u32)),
+            ("bleu", |x, y| (x as u32) <= (y as u32)),
+        ];
+
+        // Pairs whose subtraction overflows, like i32::MAX and -1 or i32::MIN and 1,
+        // are compared exactly too
+        let values = [0, 1, -1, 5, 200, -300, 0x7FFFFFFF, -0x80000000];
+        for (op, cmp) in signed {
+            for x in values {
+                for y in values {
+                    let branch = format!("{} s0, s1", op);
+                    assert_eq!(taken(x, y, &branch), cmp(x, y), "{} {} {}", op, x, y);
+                }
+                let branch = format!("{} s0, 200", op);
+                assert_eq!(taken(x, 0, &branch), cmp(x, 200), "{} {} 200", op, x);
+                let branch = format!("{}z s0", op);
+                assert_eq!(taken(x, 0, &branch), cmp(x, 0), "{}z {}", op, x);
+            }
+        }
+
+        let values = [0, 1, 200, 0x7FFFFFFF, -0x80000000, -1, -2];
+        for (op, cmp) in unsigned {
+            for x in values {
+                for y in values {
+                    let branch = format!("{} s0, s1", op);
+                    assert_eq!(taken(x, y, &branch), cmp(x, y), "{} {} {}", op, x, y);
+                }
+                let branch = format!("{} s0, 200", op);
+                assert_eq!(taken(x, 0, &branch), cmp(x, 200), "{} {} 200", op, x);
+            }
+        }
+    }
+
+    #[test]
+    fn indexed_memory() {
+        let input = "
+.data
+    pad: .word 0
+    arr: .word 3, 5, 7, 9
+.equ LAST, 3
+.text
+main:
+    lui s0 <- arr
+    read a0 <- [s0]
+    read a1 <- [s0 + LAST]
+    addi s1 <- s0, 4
+    read a2 <- [s1 - 2]
+    lui t0 <- 42
+    write [s1 - 1] <- t0
+    read a3 <- [s0 + 1 + 2]
+    write [s0] <- a1
+    read s2 <- arr
+    mov mdr <- s0
+    write [mdr + 1] <- a2
+    read s3 <- [s0 + 1]
+    halt
+";
+        let computer = run(input);
+        let regs: Vec<u32> = ["a0", "a1", "a2", "a3", "s2", "s3"]
+            .iter()
+            .map(|r| reg(&computer, r))
+            .collect();
+        assert_eq!(regs, vec![3, 9, 7, 42, 9, 7]);
+
+        let (cs, _) = AsmEvaluator::new().evaluate_buffer(input).unwrap();
+        let firmware = cs.firmware();
+
+        // MAR <- s1 - 2, read
+        let mut mi = Microinstruction::new(7);
+        mi.c_bus = 1 << 18;
+        mi.alu = 0b000111111;
+        mi.mem = 0b010;
+        mi.a = Microinstruction::IMM_A;
+        mi.b = 10;
+        mi.immediate = 2;
+        assert_eq!(firmware[6], mi.get());
+    }
+
+    #[test]
+    fn subword_memory() {
+        // s0 holds the byte address of `msg`, and the result is left in a0
+        let a0_after = |code: &str| {
+            let input = format!(
+                ".data\n    pad: .word 0\n    msg: .word 0x80FF7F01, 0x12345678\n.text\nmain:\n    li s0 <- msg * 4\n    {}\n    halt\n",
+                code
+            );
+            reg(&run(&input), "a0") as i32
+        };
+
+        let loads = [
+            ("readb a0 <- [s0]", 0x01),
+            ("readb a0 <- [s0 + 1]", 0x7F),
+            ("readb a0 <- [s0 + 2]", -1),
+            ("readb a0 <- [s0 + 3]", -128),
+            ("readbu a0 <- [s0 + 3]", 0x80),
+            ("readbu a0, a1 <- [s0 + 6]", 0x34),
+            ("readh a0 <- [s0]", 0x7F01),
+            ("readh a0 <- [s0 + 2]", -0x7F01),
+            ("readhu a0 <- [s0 + 2]", 0x80FF),
+            ("addi s1 <- s0, 9\n    readhu a0 <- [s1 - 3]", 0x1234),
+        ];
+        for (code, expected) in loads {
+            assert_eq!(a0_after(code), expected, "{}", code);
+        }
+
+        let stores = [
+            ("li s1 <- 0x1AB\n    writeb [s0 + 5] <- s1", 0x1234AB78),
+            (
+                "li s1 <- -1\n    writeb [s0 + 7] <- s1",
+                0xFF345678u32 as i32,
+            ),
+            (
+                "li s1 <- 0xABCDEF\n    writeh [s0 + 6] <- s1",
+                0xCDEF5678u32 as i32,
+            ),
+            ("li s1 <- 0xABCDEF\n    writeh [s0 + 4] <- s1", 0x1234CDEF),
+            (
+                "li s1 <- 0xAB\n    addi s2 <- s0, 8\n    writeb [s2 - 4] <- s1",
+                0x123456AB,
+            ),
+        ];
+        for (code, expected) in stores {
+            let code = format!("{}\n    lui s3 <- msg\n    read a0 <- [s3 + 1]", code);
+            assert_eq!(a0_after(&code), expected, "{}", code);
+        }
+        // The rest of the word is kept
+        let code = "li s1 <- 0\n    writeb [s0 + 1] <- s1\n    lui s3 <- msg\n    read a0 <- [s3]";
+        assert_eq!(a0_after(code), 0x80FF0001u32 as i32);
+    }
+
+    #[test]
+    fn shifts_run() {
+        // Shifts s0 into s2, with the register amounts in s1
+        let s2_after = |x: i32, shift: &str| {
+            let input = format!(
+                ".text\nmain:\n    li s0 <- {}\n    {}\n    halt\n",
+                x, shift
+            );
+            reg(&run(&input), "s2") as i32
+        };
+
+        type Shift = fn(i32, u32) -> i32;
+        let ops: [(&str, &str, Shift); 3] = [
+            ("slli", "sllv", |x, n| x << n),
+            ("srli", "srlv", |x, n| ((x as u32) >> n) as i32),
+            ("srai", "srav", |x, n| x >> n),
+        ];
+        for (imm, reg, shift) in ops {
+            for x in [1, -1, 0x1234_5678, i32::MIN, -0x1234_5678] {
+                for n in [0, 1, 7, 8, 9, 16, 23, 31] {
+                    let ins = format!("{} s2 <- s0, {}", imm, n);
+                    assert_eq!(s2_after(x, &ins), shift(x, n), "{} {} {}", imm, x, n);
+                    let ins = format!("li s1 <- {}\n    {} s2 <- s0, s1", n, reg);
+                    assert_eq!(s2_after(x, &ins), shift(x, n), "{} {} {}", reg, x, n);
+                }
+                // Only the 5 low bits of the amount are used
+                let ins = format!("li s1 <- 33\n    {} s2 <- s0, s1", reg);
+                assert_eq!(s2_after(x, &ins), shift(x, 1), "{} {} 33", reg, x);
+            }
+        }
+
+        assert_eq!(s2_after(-8, "sra s2 <- s0"), -4);
+        assert_eq!(s2_after(-8, "srl s2 <- s0"), 0x7FFF_FFFC);
+        assert_eq!(s2_after(i32::MIN + 1, "rol s2 <- s0"), 3);
+        // The amount and the source can be the destination
+        assert_eq!(s2_after(5, "li s2 <- 2\n    sllv s2 <- s0, s2"), 20);
+        assert_eq!(s2_after(5, "srai s0, s2 <- s0, 1"), 2);
+
+        // Shifts by 8 first, then by 1
+        let steps = AsmEvaluator::shift_steps(&DoubleOperandOpcode::Slli, 10);
+        assert_eq!(steps, vec![0b0100011000, 0b0110011000, 0b0110011000]);
     }
 
     #[test]
@@ -1620,7 +3106,7 @@ mod tests {
                 Instruction::new_branch_instruction(
                     BranchOp::Beq,
                     Rc::new(Register::A2),
-                    Rc::new(Register::A3),
+                    Value::Reg(Rc::new(Register::A3)),
                     Rc::from("unresolved"),
                 ),
             ],
@@ -1639,7 +3125,8 @@ mod tests {
         let mut state = CsState::new();
         evaluator.eval_txt_seg(&main, &mut state);
         evaluator.eval_txt_seg(&unresolved, &mut state);
-        evaluator.resolve_unreachable(&mut state);
+        assert!(evaluator.errors.is_empty());
+        evaluator.resolve_unreachable(&mut state).unwrap();
 
         let firmware = state.build_cs().firmware();
 
@@ -1662,4 +3149,455 @@ mod tests {
         let branched_addr = 2 | 0b100000000;
         assert_eq!(branched_mcode, firmware[branched_addr]);
     }
+
+    #[test]
+    fn const_expressions() {
+        let input = r"
+.equ BASE, 0x10
+.data
+    first: .word 1
+    second: .word 2
+    mask: .byte BASE - 1
+.text
+main:
+    addi a0 <- a0, BASE * 2 + mask
+    read a1 <- second + 1
+    halt
+";
+        let mut eval = AsmEvaluator::new();
+        let (cs, _) = eval.evaluate_buffer(input).unwrap();
+        let firmware = cs.firmware();
+
+        // mask is the word address 2
+        assert_eq!(firmware[0] & 0xFF, 0x22);
+        assert_eq!(firmware[1] & 0xFF, 2);
+    }
+
+    #[test]
+    fn const_expression_out_of_range() {
+        let input = r"
+.equ BIG, 300
+.text
+main:
+    addi a0 <- a0, BIG
+    andi a0 <- a0, (BIG - 1) * 2
+";
+        assert_eq!(
+            eval_errors(input),
+            vec![
+                (
+                    "E0018",
+                    5,
+                    "Value 300 of 'BIG' does not fit in range 0..=255".to_string()
+                ),
+                (
+                    "E0018",
+                    6,
+                    "Value 598 of '(BIG - 1) * 2' does not fit in range 0..=255".to_string()
+                )
+            ]
+        );
+
+        let input = r"
+.text
+main:
+    addi a0 <- a0, missing + 1
+";
+        let err = AsmEvaluator::new().evaluate_buffer(input).err().unwrap();
+        assert!(err.to_string().contains("missing"));
+    }
+
+    #[test]
+    fn li_steps() {
+        assert_eq!(AsmEvaluator::li_steps(5).len(), 1);
+        assert_eq!(AsmEvaluator::li_steps(0xFFFFFFFF).len(), 1);
+        assert_eq!(AsmEvaluator::li_steps(0xFFFFFF80).len(), 1);
+        assert_eq!(AsmEvaluator::li_steps(0x1200).len(), 1);
+        assert_eq!(AsmEvaluator::li_steps(0x1234).len(), 2);
+        assert_eq!(AsmEvaluator::li_steps(100000).len(), 3);
+        assert_eq!(AsmEvaluator::li_steps(0x10000).len(), 2);
+        assert_eq!(AsmEvaluator::li_steps(0xDEADBEEF).len(), 4);
+    }
+
+    #[test]
+    fn li_runs() {
+        for value in [
+            0i64, 5, 255, 256, 0x1234, 100000, 0x10000, -1, -2, -300, -65536, 0x7FFFFFFF,
+            0x80000000, 0xDEADBEEF, 0xFFFF0012,
+        ] {
+            let input = format!(".text\nmain:\n    li a0, s0 <- {}\n    halt\n", value);
+            let computer = run(&input);
+            assert_eq!(reg(&computer, "a0"), value as u32, "{}", value);
+            assert_eq!(reg(&computer, "s0"), value as u32, "{}", value);
+        }
+    }
+
+    #[test]
+    fn subroutine_calls() {
+        let input = "
+            .text
+            main:
+                lui a0 <- 3
+                call double
+                mov s0 <- a0
+                call quad
+                mov s1 <- a0
+                halt
+            quad:
+                mov s6 <- ra
+                call double
+                call double
+                jalr t3, s6
+            double:
+                add a0 <- a0, a0
+                ret
+        ";
+        let mut eval = AsmEvaluator::new();
+        let (cs, _) = eval.evaluate_buffer(input).unwrap();
+        let firmware = cs.firmware();
+
+        // quad links the address after its jalr into t3, then jumps through s6
+        assert_eq!(
+            firmware[9],
+            0b000001010_000_000011000_00000000100000000000_000_01000_11111_00001011
+        );
+        assert_eq!(
+            firmware[10],
+            0b000000000_111_000011000_00000000000000000000_000_10100_11111_00000000
+        );
+        // ret jumps through ra
+        assert_eq!(
+            firmware[12],
+            0b000000000_111_000011000_00000000000000000000_000_01001_11111_00000000
+        );
+
+        let computer = run(input);
+        assert_eq!(reg(&computer, "s0"), 6);
+        assert_eq!(reg(&computer, "s1"), 24);
+        assert_eq!(reg(&computer, "t3"), 11);
+    }
+
+    #[test]
+    fn stack() {
+        let input = "
+            .text
+            main:
+                lui a0 <- 5
+                call fact
+                lui s0 <- 7
+                lui s1 <- 9
+                push s0
+                push s1
+                pop s2
+                pop s3
+                halt
+            fact:
+                beq a0, s6, base
+                push ra
+                push a0
+                subi a0 <- a0, 1
+                call fact
+                pop a0
+                pop ra
+                mul2 a1 <- a1, a0
+                ret
+            base:
+                lui a1 <- 1
+                ret
+        ";
+        let mut eval = AsmEvaluator::new();
+        let (cs, _) = eval.evaluate_buffer(input).unwrap();
+        let firmware = cs.firmware();
+
+        // push s0: MDR <- s0, then MAR, CPP <- CPP - 1; write
+        assert_eq!(
+            firmware[4],
+            0b000000101_000_000011000_10000000000000000000_000_01110_11111_00000000
+        );
+        assert_eq!(
+            firmware[5],
+            0b1_000000110_000_000110110_01000000000000000000_100_11111_00010_00000000
+        );
+        // pop s2: MAR <- CPP; read, s2 <- MDR, then CPP <- CPP + 1
+        assert_eq!(
+            firmware[8],
+            0b000001001_000_000011000_01000000000000000000_010_00111_11111_00000000
+        );
+        assert_eq!(
+            firmware[10],
+            0b1_000001011_000_000110101_00000000000000000000_000_11111_00010_00000000
+        );
+
+        let computer = run(input);
+        assert_eq!(reg(&computer, "a1"), 120);
+        assert_eq!(reg(&computer, "s2"), 9);
+        assert_eq!(reg(&computer, "s3"), 7);
+    }
+
+    #[test]
+    fn data_layout() {
+        let input = r#"
+.data
+    nums: .word 1, 2, 0x30
+    msg: .asciz "hi!"
+    bytes: .byte 1, 2
+           .byte 3
+    buf: .space 6
+    .align 16
+    last: .word -1
+.text
+main:
+    read a0 <- last
+    li a1 <- buf
+    halt
+"#;
+        let mut eval = AsmEvaluator::new();
+        let (cs, ram) = eval.evaluate_buffer(input).unwrap();
+
+        assert_eq!(
+            ram,
+            &[1, 2, 0x30, 0x00216968, 0x00030201, 0, 0, 0, 0xFFFFFFFF]
+        );
+        let firmware = cs.firmware();
+        assert_eq!(firmware[0] & 0xFF, 8);
+        assert_eq!(firmware[2] & 0xFF, 5);
+    }
+
+    #[test]
+    fn data_beyond_255_words() {
+        let input = r"
+.data
+    buf: .space 2000
+    x: .word 5
+.text
+main:
+    li a0 <- x
+    halt
+";
+        let mut eval = AsmEvaluator::new();
+        let (cs, ram) = eval.evaluate_buffer(input).unwrap();
+        assert_eq!(ram.len(), 501);
+        assert_eq!(ram[500], 5);
+        // li a0 <- 500 is (1 << 8) | 0xF4
+        let firmware = cs.firmware();
+        assert_eq!(firmware[0] & 0xFF, 1);
+        assert_eq!(firmware[1] & 0xFF, 0xF4);
+
+        let input = r"
+.data
+    buf: .space 2000
+    x: .word 5
+.text
+main:
+    read a0 <- x
+";
+        assert_eq!(
+            eval_errors(input),
+            vec![(
+                "E0018",
+                7,
+                "Value 500 of 'x' does not fit in range 0..=255".to_string()
+            )]
+        );
+
+        let input = ".data\n    .align 3\n";
+        assert_eq!(
+            eval_errors(input),
+            vec![(
+                "E0019",
+                2,
+                "Alignment must be a power of two, found: 3".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn control_store_overflow() {
+        let nops = |n: usize| format!(".text\nmain:\n{}", "    nop\n".repeat(n));
+
+        assert!(AsmEvaluator::new().evaluate_buffer(&nops(256)).is_ok());
+        // reported once, at the first instruction that doesn't fit
+        let overflow = |line| {
+            vec![(
+                "E0021",
+                line,
+                "Program does not fit in the control store: microinstruction at 0x100 is past the 256 words available for code".to_string(),
+            )]
+        };
+        assert_eq!(eval_errors(&nops(257)), overflow(259));
+        assert_eq!(eval_errors(&nops(300)), overflow(259));
+
+        // the branch target of the last word would be the fallthrough itself
+        let input = format!("{}    beq a0, a1, main\n", nops(255));
+        assert_eq!(eval_errors(&input), overflow(258));
+    }
+
+    #[test]
+    fn branch_target_relocation() {
+        for (branch, expected) in [("beq", 7), ("bne", 3)] {
+            let input = format!(
+                ".text\nmain:\n    {} a0, a1, taken\n    li a0 <- 3\n    halt\ntaken:\n    li a0 <- 7\n    halt\n",
+                branch
+            );
+            let toks = Includer::default()
+                .expand_buffer(&input, Rc::from("<input>"), Path::new("."))
+                .unwrap();
+            let program = Parser::new(toks.into()).get_deez_program();
+
+            let mut evaluator = AsmEvaluator::new();
+            let mut state = CsState::new();
+            // taken by someone else, both bne targets included
+            state.reserve(0x101).unwrap();
+            state.reserve(0x102).unwrap();
+            for sec in program.sections {
+                if let Sections::TextSection(segs) = sec {
+                    for seg in segs {
+                        evaluator.eval_txt_seg(&seg, &mut state);
+                    }
+                }
+            }
+            assert!(evaluator.errors.is_empty());
+            evaluator.resolve_unreachable(&mut state).unwrap();
+            assert!(state.trampolines.is_some());
+
+            let mut computer = Computer::new(Ram::new(), state.build_cs());
+            computer.exec();
+            assert_eq!(reg(&computer, "a0"), expected, "{}", branch);
+        }
+    }
+
+    #[test]
+    fn evaluation_errors_are_all_reported() {
+        let input = r"
+.equ K, 300
+.equ Z, 1 / 0
+.data
+    .align 3
+    a: .word 1
+.text
+main:
+    addi t0 <- t0, K
+    read t0 <- a + 400
+    addi t0 <- t0, Z
+    halt
+";
+        let found: Vec<_> = eval_errors(input)
+            .into_iter()
+            .map(|(code, line, _)| (code, line))
+            .collect();
+        assert_eq!(
+            found,
+            vec![("E0023", 3), ("E0019", 5), ("E0018", 9), ("E0018", 10)]
+        );
+    }
+
+    #[test]
+    fn undefined_symbols_diagnostics() {
+        let input = r"
+.data
+    used: .word 1
+    unused: .word 2
+.text
+main:
+    beq a0, a1, nowhere
+    read a0 <- used
+    read a1 <- missing
+    jal nowhere
+    addi a0 <- a0, main
+    halt
+";
+        let mut eval = AsmEvaluator::new();
+        let err = eval.evaluate_buffer(input).err().unwrap();
+        let diags = err.downcast_ref::<Diagnostics>().unwrap();
+
+        let found: Vec<_> = diags
+            .items
+            .iter()
+            .map(|d| (d.code, d.span.as_ref().unwrap().line, d.message.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("W0001", 4, "Symbol 'unused' is never used"),
+                ("E0016", 7, "Undefined label 'nowhere'"),
+                (
+                    "E0017",
+                    9,
+                    "Undefined symbol 'missing' in constant expression"
+                ),
+                (
+                    "E0017",
+                    11,
+                    "Undefined symbol 'main' in constant expression"
+                ),
+            ]
+        );
+        assert_eq!(diags.items[3].notes[0].span.as_ref().unwrap().line, 6);
+        assert!(diags.has_errors());
+        assert!(diags
+            .to_string()
+            .contains("7 |     beq a0, a1, nowhere\n  |                 ^^^^^^^\n"));
+
+        let diags = eval.diagnostics(Some(&err));
+        assert_eq!(diags.items.len(), 4);
+    }
+
+    #[test]
+    fn undefined_label_without_parser() {
+        let main = TextSegment::new_labeled_section(
+            "main".into(),
+            vec![Instruction::new_jal_instruction(Rc::from("nowhere"))],
+        );
+        let mut evaluator = AsmEvaluator::new();
+        let mut state = CsState::new();
+        evaluator.eval_txt_seg(&main, &mut state);
+        assert!(evaluator.errors.is_empty());
+        let err = evaluator.resolve_unreachable(&mut state).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvaluatorError>(),
+            Some(&EvaluatorError::UndefinedLabel {
+                label: "nowhere".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn listing() {
+        let input = r"
+.data
+    .equ big, 0x1234
+    flag: .byte 1
+    value: .word big
+.text
+main:
+    li a0 <- big
+    beq a0, a1, main
+    halt
+";
+        let mut eval = AsmEvaluator::new();
+        let (cs, _) = eval.evaluate_buffer(input).unwrap();
+        let firmware = cs.firmware();
+        let listing = eval.listing(&cs);
+
+        let expected_text = format!(
+            "000                                7  main:\n\
+             000..001  {:#018x}       8  li a0 <- big\n\
+             001       {:#018x}\n\
+             002       {:#018x}       9  beq a0, a1, main\n\
+             003       {:#018x}      10  halt\n",
+            firmware[0], firmware[1], firmware[2], firmware[3]
+        );
+        assert!(listing.contains(&expected_text), "{}", listing);
+        assert!(listing.contains(&format!(
+            "103       {:#018x}    002 (line 9)\n",
+            firmware[0x103]
+        )));
+        assert!(listing.contains("main                  text  000\n"));
+        assert!(listing.contains("value                 data  0x0001\n"));
+        assert!(listing.contains("big                   equ   4660\n"));
+        assert!(listing.contains("0x0000:0       1     4  flag: .byte 1\n"));
+        assert!(listing.contains("0x0001:0       4     5  value: .word big\n"));
+        let data = &listing[listing.find("; Data").unwrap()..];
+        assert!(!data.contains(".equ"), "{}", data);
+    }
 }
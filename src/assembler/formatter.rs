@@ -0,0 +1,359 @@
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::{bail, Result};
+
+use crate::assembler::{
+    diagnostics::{Diagnostic, Diagnostics},
+    lexer::Lexer,
+    parser::Parser,
+    tokens::{AsmToken, PseudoOps, TokWithCtx, OPCODE_NAMES, PSEUDO_OP_NAMES, REGISTER_NAMES},
+};
+
+const INDENT: &str = "    ";
+
+/// A line of the formatted output, before the layout of its block is known.
+enum Line {
+    Blank,
+    /// A comment on a line of its own, indented like the code that follows it.
+    Comment(Rc<str>),
+    /// Section directives, text labels and other lines that start at the margin.
+    Margin(String, Option<Rc<str>>),
+    Instruction(String, Option<Rc<str>>),
+    /// A data directive, with the label in front of it, if any, aligned with the
+    /// others of its block.
+    Data(Option<String>, String, Option<Rc<str>>),
+}
+
+/// Formats a source in the canonical layout, refusing sources that don't parse.
+pub fn format_source(src: &str, file: &str) -> Result<String> {
+    let toks = Lexer::with_trivia(src, Rc::from(file)).get_deez_toks_w_ctx();
+    check_syntax(&toks, src, file)?;
+
+    let mut lines = Vec::new();
+    let mut in_data = false;
+    let mut last_line = None;
+    for (num, toks, comment) in split_lines(&toks) {
+        if last_line.is_some_and(|last| num > last + 1) && !lines.is_empty() {
+            lines.push(Line::Blank);
+        }
+        last_line = Some(num);
+
+        let toks = &toks[..];
+        match toks {
+            [] => lines.push(Line::Comment(comment.expect("lines have a token"))),
+            [AsmToken::PseudoOp(op), ..] if !matches!(**op, PseudoOps::Equ) => {
+                in_data = match **op {
+                    PseudoOps::Data => true,
+                    PseudoOps::Text => false,
+                    _ => in_data,
+                };
+                let line = render(toks);
+                if in_data && !matches!(**op, PseudoOps::Data | PseudoOps::Include) {
+                    lines.push(Line::Data(None, line, comment));
+                } else {
+                    lines.push(Line::Margin(line, comment));
+                }
+            }
+            [AsmToken::Label(_), AsmToken::Colon, rest @ ..] if in_data => {
+                let label = render(&toks[..2]);
+                lines.push(Line::Data(Some(label), render(rest), comment));
+            }
+            _ if in_data => lines.push(Line::Data(None, render(toks), comment)),
+            [AsmToken::Label(_), AsmToken::Colon] => {
+                lines.push(Line::Margin(render(toks), comment));
+            }
+            [AsmToken::Label(_), AsmToken::Colon, rest @ ..] => {
+                lines.push(Line::Margin(render(&toks[..2]), None));
+                lines.push(Line::Instruction(render(rest), comment));
+            }
+            _ => lines.push(Line::Instruction(render(toks), comment)),
+        }
+    }
+
+    Ok(layout(&lines))
+}
+
+/// Parses the code, leaving out the comments and the includes, which the parser
+/// never sees.
+fn check_syntax(toks: &[TokWithCtx], src: &str, file: &str) -> Result<()> {
+    let mut code = Vec::with_capacity(toks.len());
+    let mut toks = toks.iter();
+    while let Some(tok) = toks.next() {
+        match &*tok.tok {
+            AsmToken::Comment(_) => {}
+            AsmToken::PseudoOp(op) if **op == PseudoOps::Include => {
+                toks.next();
+            }
+            _ => code.push(tok.clone()),
+        }
+    }
+
+    let program = Parser::new(code.into()).get_deez_program();
+    if !program.errors.is_empty() {
+        bail!(Diagnostics {
+            items: program.errors.iter().map(Diagnostic::from_error).collect(),
+            sources: HashMap::from([(file.to_string(), src.to_string())]),
+        });
+    }
+    Ok(())
+}
+
+/// Groups the tokens by line, taking the comment that ends each line apart.
+fn split_lines(toks: &[TokWithCtx]) -> Vec<(usize, Vec<AsmToken>, Option<Rc<str>>)> {
+    let mut lines: Vec<(usize, Vec<AsmToken>, Option<Rc<str>>)> = Vec::new();
+    for tok in toks.iter() {
+        if *tok.tok == AsmToken::Eof {
+            break;
+        }
+        if lines.last().map(|l| l.0) != Some(tok.cur_line) {
+            lines.push((tok.cur_line, Vec::new(), None));
+        }
+        let line = lines.last_mut().expect("a line was just pushed");
+        match &*tok.tok {
+            AsmToken::Comment(text) => line.2 = Some(Rc::clone(text)),
+            t => line.1.push(t.clone()),
+        }
+    }
+    lines
+}
+
+fn layout(lines: &[Line]) -> String {
+    let mut rows = Vec::with_capacity(lines.len());
+    for (i, line) in lines.iter().enumerate() {
+        let (text, comment) = match line {
+            Line::Blank => (String::new(), None),
+            Line::Comment(c) => {
+                // Indented like the code that follows it
+                let next = lines[i + 1..]
+                    .iter()
+                    .find(|l| !matches!(l, Line::Blank | Line::Comment(_)));
+                let indent = match next {
+                    Some(Line::Instruction(..) | Line::Data(..)) => INDENT,
+                    _ => "",
+                };
+                (format!("{}{}", indent, c), None)
+            }
+            Line::Margin(text, comment) => (text.clone(), comment.as_ref()),
+            Line::Instruction(text, comment) => (format!("{}{}", INDENT, text), comment.as_ref()),
+            Line::Data(label, text, comment) => {
+                let width = data_block(lines, i)
+                    .filter_map(|l| match l {
+                        Line::Data(Some(label), ..) => Some(label.chars().count()),
+                        _ => None,
+                    })
+                    .max();
+                let text = match (label, width) {
+                    (Some(label), _) if text.is_empty() => label.clone(),
+                    (Some(label), Some(width)) => format!("{:width$} {}", label, text),
+                    _ => text.clone(),
+                };
+                (format!("{}{}", INDENT, text), comment.as_ref())
+            }
+        };
+        rows.push((text, comment));
+    }
+
+    // Trailing comments of consecutive lines start on the same column
+    let mut out = String::new();
+    let mut i = 0;
+    while i < rows.len() {
+        let run = rows[i..]
+            .iter()
+            .take_while(|(_, comment)| comment.is_some())
+            .count()
+            .max(1);
+        let width = rows[i..i + run]
+            .iter()
+            .map(|(text, _)| text.chars().count())
+            .max()
+            .unwrap_or(0);
+        for (text, comment) in rows[i..i + run].iter() {
+            match comment {
+                Some(comment) => out.push_str(&format!("{:width$} {}", text, comment)),
+                None => out.push_str(text),
+            }
+            out.push('\n');
+        }
+        i += run;
+    }
+    out
+}
+
+/// The run of data lines around `i`, which align their directives.
+fn data_block(lines: &[Line], i: usize) -> impl Iterator<Item = &Line> {
+    let is_data = |l: &Line| matches!(l, Line::Data(..));
+    let start = lines[..i]
+        .iter()
+        .rposition(|l| !is_data(l))
+        .map_or(0, |p| p + 1);
+    let end = lines[i..]
+        .iter()
+        .position(|l| !is_data(l))
+        .map_or(lines.len(), |p| i + p);
+    lines[start..end].iter()
+}
+
+/// Renders the tokens of a statement with the canonical spacing.
+fn render(toks: &[AsmToken]) -> String {
+    let mut out = String::new();
+    let mut prev: Option<&AsmToken> = None;
+    let mut prev_unary = false;
+    for tok in toks {
+        let unary = *tok == AsmToken::Minus && prev.is_none_or(starts_operand);
+        let space = match (prev, tok) {
+            (None, _) => false,
+            (_, AsmToken::Comma | AsmToken::Colon | AsmToken::RParen | AsmToken::RBracket) => false,
+            (Some(AsmToken::LParen | AsmToken::LBracket), _) => false,
+            _ => !prev_unary,
+        };
+        if space {
+            out.push(' ');
+        }
+        out.push_str(&text(tok));
+        prev = Some(tok);
+        prev_unary = unary;
+    }
+    out
+}
+
+/// Whether an operand can start after a token, making a `-` after it unary.
+fn starts_operand(tok: &AsmToken) -> bool {
+    matches!(
+        tok,
+        AsmToken::Comma
+            | AsmToken::Assign
+            | AsmToken::LParen
+            | AsmToken::LBracket
+            | AsmToken::Plus
+            | AsmToken::Minus
+            | AsmToken::Asterisk
+            | AsmToken::Slash
+            | AsmToken::Shl
+            | AsmToken::Shr
+            | AsmToken::Ampersand
+            | AsmToken::Pipe
+            | AsmToken::Opcode(_)
+            | AsmToken::PseudoOp(_)
+    )
+}
+
+fn text(tok: &AsmToken) -> String {
+    let keyword = |names: &[&str]| {
+        names
+            .iter()
+            .find(|name| AsmToken::name_to_tok(name) == *tok)
+            .map_or_else(String::new, |name| name.to_string())
+    };
+    match tok {
+        AsmToken::Number(n) | AsmToken::Label(n) | AsmToken::Comment(n) => n.to_string(),
+        AsmToken::Str(s) => {
+            let mut res = String::from('"');
+            for c in s.chars() {
+                match c {
+                    '\n' => res.push_str("\\n"),
+                    '\t' => res.push_str("\\t"),
+                    '\0' => res.push_str("\\0"),
+                    '\\' => res.push_str("\\\\"),
+                    '"' => res.push_str("\\\""),
+                    c => res.push(c),
+                }
+            }
+            res.push('"');
+            res
+        }
+        AsmToken::Reg(_) => keyword(REGISTER_NAMES),
+        AsmToken::Opcode(_) => keyword(OPCODE_NAMES),
+        AsmToken::PseudoOp(_) => keyword(PSEUDO_OP_NAMES),
+        AsmToken::Comma => ",".to_string(),
+        AsmToken::Colon => ":".to_string(),
+        AsmToken::Assign => "<-".to_string(),
+        AsmToken::Plus => "+".to_string(),
+        AsmToken::Minus => "-".to_string(),
+        AsmToken::Asterisk => "*".to_string(),
+        AsmToken::Slash => "/".to_string(),
+        AsmToken::Shl => "<<".to_string(),
+        AsmToken::Shr => ">>".to_string(),
+        AsmToken::Ampersand => "&".to_string(),
+        AsmToken::Pipe => "|".to_string(),
+        AsmToken::LParen => "(".to_string(),
+        AsmToken::RParen => ")".to_string(),
+        AsmToken::LBracket => "[".to_string(),
+        AsmToken::RBracket => "]".to_string(),
+        // Sources with these don't parse, so they never get here
+        AsmToken::Illegal | AsmToken::Eof => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_layout() {
+        let input = "
+# Sums a table
+
+.DATA
+  .equ N, 2   # entries
+  table: .word 1,2
+    result:   .word -1
+  msg: .asciz \"a\\tb\\\"\"
+
+
+.text
+  .global main
+main: LUI s0<-table*4 ; the table
+; a comment above an instruction
+     READ A0, a1 <- [ s0+4 ]
+\twrite result<-a0
+   addi a0<-a1,-( N-1 ) << 2 # shifted
+done:
+  halt
+";
+        let expected = "# Sums a table
+
+.data
+    .equ N, 2 # entries
+    table:  .word 1, 2
+    result: .word -1
+    msg:    .asciz \"a\\tb\\\"\"
+
+.text
+.global main
+main:
+    lui s0 <- table * 4 ; the table
+    ; a comment above an instruction
+    read a0, a1 <- [s0 + 4]
+    write result <- a0
+    addi a0 <- a1, -(N - 1) << 2 # shifted
+done:
+    halt
+";
+        let formatted = format_source(input, "<input>").unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_source(&formatted, "<input>").unwrap(), formatted);
+    }
+
+    #[test]
+    fn programs_are_formatted() {
+        let sources = [
+            include_str!("../../programs/csw.asm"),
+            include_str!("../../programs/div.asm"),
+            include_str!("../../programs/div_hardware.asm"),
+            include_str!("../../programs/factorial.asm"),
+            include_str!("../../programs/factorial_hardware.asm"),
+            include_str!("../../programs/power.asm"),
+            include_str!("../../programs/power_hardware.asm"),
+        ];
+        for src in sources {
+            assert_eq!(format_source(src, "<input>").unwrap(), src);
+        }
+    }
+
+    #[test]
+    fn refuses_what_does_not_parse() {
+        let err = format_source(".text\nmain:\n    add a0 <-\n", "<input>").unwrap_err();
+        let diags = err.downcast_ref::<Diagnostics>().unwrap();
+        assert_eq!(diags.items[0].code, "E0002");
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use anyhow::{bail, Context, Result};
+use thiserror::Error;
+
+use crate::assembler::{
+    diagnostics::Span,
+    lexer::Lexer,
+    tokens::{AsmToken, PseudoOps, TokWithCtx},
+};
+
+#[derive(Debug, PartialEq, Error)]
+pub enum IncludeError {
+    #[error("Expected a file path after '.include', found: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    ExpectedPath {
+        found: String,
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error("Cannot read included file '{path}': {reason}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    CannotRead {
+        path: String,
+        reason: String,
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error("Include cycle detected: {cycle}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    Cycle {
+        cycle: String,
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+}
+
+impl IncludeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            IncludeError::ExpectedPath { .. } => "E0013",
+            IncludeError::CannotRead { .. } => "E0014",
+            IncludeError::Cycle { .. } => "E0015",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            IncludeError::ExpectedPath {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | IncludeError::CannotRead {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | IncludeError::Cycle {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            } => Span::new(cur_file, *cur_line, *cur_column),
+        }
+    }
+}
+
+/// Expands `.include "path"` directives into the token stream of the included file.
+///
+/// Paths are resolved relative to the directory of the including file. A file that
+/// was already included once is skipped, so shared definitions can be included from
+/// several places without being duplicated.
+#[derive(Default)]
+pub struct Includer {
+    stack: Vec<PathBuf>,
+    seen: HashSet<PathBuf>,
+    /// Contents of every file read, by the name used in the tokens.
+    sources: HashMap<String, String>,
+}
+
+impl Includer {
+    pub fn expand_file(&mut self, path: &Path) -> Result<Vec<TokWithCtx>> {
+        let buf = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read file '{}'", path.display()))?;
+        let canonical = path.canonicalize()?;
+        let dir = canonical.parent().unwrap_or(Path::new(".")).to_path_buf();
+
+        self.seen.insert(canonical.clone());
+        self.stack.push(canonical);
+        let toks = self.expand_buffer(&buf, Rc::from(path.display().to_string()), &dir);
+        self.stack.pop();
+
+        toks
+    }
+
+    /// Expands the includes of an in-memory buffer, resolving paths from `dir`.
+    pub fn expand_buffer(
+        &mut self,
+        buf: &str,
+        file: Rc<str>,
+        dir: &Path,
+    ) -> Result<Vec<TokWithCtx>> {
+        self.sources.insert(file.to_string(), buf.to_string());
+        let toks = Lexer::with_file(buf, file).get_deez_toks_w_ctx();
+        let mut res = Vec::with_capacity(toks.len());
+        let mut toks = toks.into_iter();
+
+        while let Some(tok) = toks.next() {
+            if !matches!(&*tok.tok, AsmToken::PseudoOp(p) if **p == PseudoOps::Include) {
+                res.push(tok);
+                continue;
+            }
+
+            let path_tok = toks.next().unwrap_or(tok);
+            let rel = match *path_tok.tok {
+                AsmToken::Str(ref s) => Rc::clone(s),
+                _ => bail!(IncludeError::ExpectedPath {
+                    found: format!("{:?}", path_tok.tok),
+                    cur_line: path_tok.cur_line,
+                    cur_column: path_tok.cur_column,
+                    cur_file: path_tok.file.to_string(),
+                }),
+            };
+
+            res.extend(self.include(&dir.join(&*rel), &path_tok)?);
+        }
+
+        Ok(res)
+    }
+
+    pub fn into_sources(self) -> HashMap<String, String> {
+        self.sources
+    }
+
+    fn include(&mut self, path: &Path, ctx: &TokWithCtx) -> Result<Vec<TokWithCtx>> {
+        let canonical = match path.canonicalize() {
+            Ok(p) => p,
+            Err(e) => bail!(IncludeError::CannotRead {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+                cur_line: ctx.cur_line,
+                cur_column: ctx.cur_column,
+                cur_file: ctx.file.to_string(),
+            }),
+        };
+
+        if self.stack.contains(&canonical) {
+            let mut cycle: Vec<String> = self
+                .stack
+                .iter()
+                .skip_while(|p| **p != canonical)
+                .map(|p| p.display().to_string())
+                .collect();
+            cycle.push(canonical.display().to_string());
+            bail!(IncludeError::Cycle {
+                cycle: cycle.join(" -> "),
+                cur_line: ctx.cur_line,
+                cur_column: ctx.cur_column,
+                cur_file: ctx.file.to_string(),
+            })
+        }
+
+        if self.seen.contains(&canonical) {
+            return Ok(Vec::new());
+        }
+
+        let buf = match std::fs::read_to_string(&canonical) {
+            Ok(buf) => buf,
+            Err(e) => bail!(IncludeError::CannotRead {
+                path: path.display().to_string(),
+                reason: e.to_string(),
+                cur_line: ctx.cur_line,
+                cur_column: ctx.cur_column,
+                cur_file: ctx.file.to_string(),
+            }),
+        };
+
+        let dir = canonical.parent().unwrap_or(Path::new(".")).to_path_buf();
+        self.seen.insert(canonical.clone());
+        self.stack.push(canonical);
+        let toks = self.expand_buffer(&buf, Rc::from(path.display().to_string()), &dir);
+        self.stack.pop();
+
+        let mut toks = toks?;
+        toks.retain(|t| *t.tok != AsmToken::Eof);
+        Ok(toks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        ops::Deref,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+
+    /// A directory of fixtures only used by one test, removed when dropped.
+    struct ScratchDir(PathBuf);
+
+    impl ScratchDir {
+        fn new(name: &str) -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let dir = std::env::temp_dir().join(format!(
+                "vondel-include-{}-{}-{}",
+                name,
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            fs::create_dir_all(dir.join("lib")).unwrap();
+            ScratchDir(dir)
+        }
+    }
+
+    impl Deref for ScratchDir {
+        type Target = Path;
+
+        fn deref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for ScratchDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn labels(toks: &[TokWithCtx]) -> Vec<String> {
+        toks.iter()
+            .filter_map(|t| match *t.tok {
+                AsmToken::Label(ref l) => Some(l.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn include_relative_to_including_file() -> Result<()> {
+        let dir = ScratchDir::new("relative");
+        fs::write(
+            dir.join("main.asm"),
+            ".include \"lib/consts.asm\"\nmain_label",
+        )?;
+        fs::write(
+            dir.join("lib/consts.asm"),
+            ".include \"more.asm\"\nconsts_label",
+        )?;
+        fs::write(dir.join("lib/more.asm"), "more_label")?;
+
+        let toks = Includer::default().expand_file(&dir.join("main.asm"))?;
+        assert_eq!(
+            labels(&toks),
+            vec!["more_label", "consts_label", "main_label"]
+        );
+        assert_eq!(toks.iter().filter(|t| *t.tok == AsmToken::Eof).count(), 1);
+        assert!(toks[0].file.ends_with("more.asm"));
+        assert_eq!(toks[0].cur_line, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn include_guard() -> Result<()> {
+        let dir = ScratchDir::new("guard");
+        fs::write(
+            dir.join("main.asm"),
+            ".include \"lib/a.asm\"\n.include \"lib/b.asm\"\n.include \"lib/a.asm\"",
+        )?;
+        fs::write(dir.join("lib/a.asm"), "a_label")?;
+        fs::write(dir.join("lib/b.asm"), ".include \"a.asm\"\nb_label")?;
+
+        let toks = Includer::default().expand_file(&dir.join("main.asm"))?;
+        assert_eq!(labels(&toks), vec!["a_label", "b_label"]);
+        Ok(())
+    }
+
+    #[test]
+    fn include_cycle() {
+        let dir = ScratchDir::new("cycle");
+        fs::write(dir.join("main.asm"), ".include \"lib/a.asm\"").unwrap();
+        fs::write(dir.join("lib/a.asm"), "\n.include \"b.asm\"").unwrap();
+        fs::write(dir.join("lib/b.asm"), ".include \"a.asm\"").unwrap();
+
+        let err = Includer::default()
+            .expand_file(&dir.join("main.asm"))
+            .unwrap_err();
+        match err.downcast_ref::<IncludeError>() {
+            Some(IncludeError::Cycle {
+                cur_line, cur_file, ..
+            }) => {
+                assert_eq!(*cur_line, 1);
+                assert!(cur_file.ends_with("b.asm"));
+            }
+            e => panic!("Expected cycle error, got {:?}", e),
+        }
+    }
+
+    #[test]
+    fn include_missing_file() {
+        let dir = ScratchDir::new("missing");
+        fs::write(dir.join("main.asm"), "\n\n  .include \"nope.asm\"").unwrap();
+
+        let err = Includer::default()
+            .expand_file(&dir.join("main.asm"))
+            .unwrap_err();
+        match err.downcast_ref::<IncludeError>() {
+            Some(IncludeError::CannotRead {
+                cur_line, cur_file, ..
+            }) => {
+                assert_eq!(*cur_line, 3);
+                assert!(cur_file.ends_with("main.asm"));
+            }
+            e => panic!("Expected read error, got {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,350 @@
+use std::{fmt, fmt::Write, iter::Peekable, str::Chars};
+
+use thiserror::Error;
+
+#[derive(Debug, PartialEq, Error)]
+pub enum JsonError {
+    #[error("Unexpected end of JSON")]
+    UnexpectedEnd,
+    #[error("Unexpected character '{found}' in JSON")]
+    UnexpectedChar { found: char },
+    #[error("Invalid number '{found}' in JSON")]
+    InvalidNumber { found: String },
+}
+
+/// A JSON value, enough to speak the protocols the tools use. Objects keep the order
+/// of their keys.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn parse(input: &str) -> Result<Json, JsonError> {
+        let mut chars = input.chars().peekable();
+        let value = parse_value(&mut chars)?;
+        skip_whitespace(&mut chars);
+        match chars.next() {
+            Some(c) => Err(JsonError::UnexpectedChar { found: c }),
+            None => Ok(value),
+        }
+    }
+
+    pub fn object<K: Into<String>, I: IntoIterator<Item = (K, Json)>>(pairs: I) -> Json {
+        Json::Object(pairs.into_iter().map(|(k, v)| (k.into(), v)).collect())
+    }
+
+    /// Value of a key of an object.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(pairs) => pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Follows a path of keys through nested objects.
+    pub fn path(&self, keys: &[&str]) -> Option<&Json> {
+        keys.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Self {
+        Json::Array(items)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) => write!(f, "{}", n),
+            Json::String(s) => write!(f, "{}", quote(s)),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(pairs) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", quote(key), value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Quotes a string as a JSON string literal.
+pub fn quote(s: &str) -> String {
+    let mut res = String::from('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\t' => res.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(res, "\\u{:04x}", c as u32);
+            }
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+type Input<'a> = Peekable<Chars<'a>>;
+
+fn skip_whitespace(chars: &mut Input) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Input, expected: char) -> Result<(), JsonError> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(JsonError::UnexpectedChar { found: c }),
+        None => Err(JsonError::UnexpectedEnd),
+    }
+}
+
+fn parse_value(chars: &mut Input) -> Result<Json, JsonError> {
+    skip_whitespace(chars);
+    match chars.peek().copied().ok_or(JsonError::UnexpectedEnd)? {
+        '{' => parse_object(chars),
+        '[' => parse_array(chars),
+        '"' => parse_string(chars).map(Json::String),
+        '-' | '0'..='9' => parse_number(chars),
+        c if c.is_ascii_alphabetic() => {
+            let mut word = String::new();
+            while let Some(c) = chars.next_if(|c| c.is_ascii_alphabetic()) {
+                word.push(c);
+            }
+            match word.as_str() {
+                "null" => Ok(Json::Null),
+                "true" => Ok(Json::Bool(true)),
+                "false" => Ok(Json::Bool(false)),
+                _ => Err(JsonError::UnexpectedChar { found: c }),
+            }
+        }
+        c => Err(JsonError::UnexpectedChar { found: c }),
+    }
+}
+
+fn parse_object(chars: &mut Input) -> Result<Json, JsonError> {
+    expect(chars, '{')?;
+    let mut pairs = Vec::new();
+    skip_whitespace(chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Ok(Json::Object(pairs));
+    }
+    loop {
+        skip_whitespace(chars);
+        let key = parse_string(chars)?;
+        skip_whitespace(chars);
+        expect(chars, ':')?;
+        pairs.push((key, parse_value(chars)?));
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some('}') => return Ok(Json::Object(pairs)),
+            Some(c) => return Err(JsonError::UnexpectedChar { found: c }),
+            None => return Err(JsonError::UnexpectedEnd),
+        }
+    }
+}
+
+fn parse_array(chars: &mut Input) -> Result<Json, JsonError> {
+    expect(chars, '[')?;
+    let mut items = Vec::new();
+    skip_whitespace(chars);
+    if chars.next_if_eq(&']').is_some() {
+        return Ok(Json::Array(items));
+    }
+    loop {
+        items.push(parse_value(chars)?);
+        skip_whitespace(chars);
+        match chars.next() {
+            Some(',') => continue,
+            Some(']') => return Ok(Json::Array(items)),
+            Some(c) => return Err(JsonError::UnexpectedChar { found: c }),
+            None => return Err(JsonError::UnexpectedEnd),
+        }
+    }
+}
+
+fn parse_string(chars: &mut Input) -> Result<String, JsonError> {
+    expect(chars, '"')?;
+    let mut res = String::new();
+    loop {
+        match chars.next().ok_or(JsonError::UnexpectedEnd)? {
+            '"' => return Ok(res),
+            '\\' => match chars.next().ok_or(JsonError::UnexpectedEnd)? {
+                'n' => res.push('\n'),
+                't' => res.push('\t'),
+                'r' => res.push('\r'),
+                'b' => res.push('\u{8}'),
+                'f' => res.push('\u{c}'),
+                'u' => {
+                    let unit = parse_hex4(chars)?;
+                    // Characters outside the BMP come as a surrogate pair
+                    let code = if (0xD800..0xDC00).contains(&unit) {
+                        expect(chars, '\\')?;
+                        expect(chars, 'u')?;
+                        let low = parse_hex4(chars)?;
+                        0x10000 + ((unit - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+                    } else {
+                        unit
+                    };
+                    res.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                c => res.push(c),
+            },
+            c => res.push(c),
+        }
+    }
+}
+
+fn parse_hex4(chars: &mut Input) -> Result<u32, JsonError> {
+    let mut code = 0;
+    for _ in 0..4 {
+        let c = chars.next().ok_or(JsonError::UnexpectedEnd)?;
+        let digit = c
+            .to_digit(16)
+            .ok_or(JsonError::UnexpectedChar { found: c })?;
+        code = code * 16 + digit;
+    }
+    Ok(code)
+}
+
+fn parse_number(chars: &mut Input) -> Result<Json, JsonError> {
+    let mut num = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+        num.push(c);
+    }
+    num.parse()
+        .map(Json::Number)
+        .map_err(|_| JsonError::InvalidNumber { found: num })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_print() {
+        let input = r#" {"id": 3, "params": {"text": "a\n\"b\" é😀", "list": [true, false, null, -1.5, 2e3]}, "empty": {}, "none": []} "#;
+        let value = Json::parse(input).unwrap();
+
+        assert_eq!(value.get("id").and_then(Json::as_u64), Some(3));
+        assert_eq!(
+            value.path(&["params", "text"]).and_then(Json::as_str),
+            Some("a\n\"b\" é😀")
+        );
+        let list = value.path(&["params", "list"]).and_then(Json::as_array);
+        assert_eq!(
+            list,
+            Some(
+                &[
+                    Json::Bool(true),
+                    Json::Bool(false),
+                    Json::Null,
+                    Json::Number(-1.5),
+                    Json::Number(2000.0)
+                ][..]
+            )
+        );
+        assert_eq!(
+            value.to_string(),
+            r#"{"id":3,"params":{"text":"a\n\"b\" é😀","list":[true,false,null,-1.5,2000]},"empty":{},"none":[]}"#
+        );
+        assert_eq!(Json::parse(&value.to_string()), Ok(value));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Json::parse(r#"{"a": 1"#), Err(JsonError::UnexpectedEnd));
+        assert_eq!(
+            Json::parse("[1, 2] x"),
+            Err(JsonError::UnexpectedChar { found: 'x' })
+        );
+        assert_eq!(
+            Json::parse("nope"),
+            Err(JsonError::UnexpectedChar { found: 'n' })
+        );
+        assert_eq!(
+            Json::parse("1.2.3"),
+            Err(JsonError::InvalidNumber {
+                found: "1.2.3".to_string()
+            })
+        );
+    }
+}
@@ -7,20 +7,39 @@ pub struct Lexer<'a> {
     cur_line: usize,
     cur_column: usize,
     cur_char: char,
+    file: Rc<str>,
+    /// Keeps comments as tokens and reads mnemonics in any case, for the formatter.
+    trivia: bool,
 }
 
 impl<'a> Lexer<'a> {
+    #[allow(dead_code)]
     pub fn new(input: &'a str) -> Self {
+        Lexer::with_file(input, Rc::from("<input>"))
+    }
+
+    pub fn with_file(input: &'a str, file: Rc<str>) -> Self {
         let mut l = Lexer {
             chars: input.chars().peekable(),
             cur_line: 1,
             cur_column: 0,
             cur_char: '\0',
+            file,
+            trivia: false,
         };
         l.read_char();
         l
     }
 
+    /// A lexer that keeps what the assembler throws away but a formatter has to
+    /// reproduce.
+    pub fn with_trivia(input: &'a str, file: Rc<str>) -> Self {
+        Lexer {
+            trivia: true,
+            ..Lexer::with_file(input, file)
+        }
+    }
+
     fn read_char(&mut self) {
         match self.chars.next() {
             Some(c) => {
@@ -49,7 +68,7 @@ impl<'a> Lexer<'a> {
     }
 
     fn ignore_comment(&mut self) {
-        if self.cur_char == '#' || self.cur_char == ';' {
+        while self.cur_char == '#' || self.cur_char == ';' {
             self.read_char();
             while self.cur_char != '\0' && self.cur_char != '\n' {
                 self.read_char();
@@ -58,6 +77,19 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Reads a comment up to the end of the line, marker included.
+    fn read_comment(&mut self) -> AsmToken {
+        let mut text = String::from(self.cur_char);
+        while let Some(&c) = self.chars.peek() {
+            if c == '\n' {
+                break;
+            }
+            text.push(c);
+            self.read_char();
+        }
+        AsmToken::Comment(Rc::from(text.trim_end()))
+    }
+
     fn read_identifier(&mut self) -> AsmToken {
         let mut ident = String::from(self.cur_char);
         while let Some(&c) = self.chars.peek() {
@@ -68,13 +100,21 @@ impl<'a> Lexer<'a> {
                 break;
             }
         }
-        AsmToken::name_to_tok(&ident)
+        let tok = AsmToken::name_to_tok(&ident);
+        if self.trivia && matches!(tok, AsmToken::Label(_) | AsmToken::Illegal) {
+            let lower = AsmToken::name_to_tok(&ident.to_lowercase());
+            if !matches!(lower, AsmToken::Label(_) | AsmToken::Illegal) {
+                return lower;
+            }
+        }
+        tok
     }
 
+    /// Reads a decimal, `0x`, `0b` or `0o` literal. The digits are validated by the parser.
     fn read_number(&mut self) -> AsmToken {
         let mut num = String::from(self.cur_char);
         while let Some(&c) = self.chars.peek() {
-            if c.is_numeric() {
+            if c.is_alphanumeric() || c == '_' {
                 num.push(c);
                 self.read_char();
             } else {
@@ -84,19 +124,87 @@ impl<'a> Lexer<'a> {
         AsmToken::Number(Rc::from(num))
     }
 
+    /// Reads a character literal such as `'A'` or `'\n'`, keeping the quotes in the token.
+    fn read_char_literal(&mut self) -> AsmToken {
+        let mut lit = String::from('\'');
+        self.read_char();
+        match self.cur_char {
+            '\0' | '\n' | '\'' => return AsmToken::Illegal,
+            '\\' => {
+                lit.push('\\');
+                self.read_char();
+                lit.push(self.cur_char);
+            }
+            c => lit.push(c),
+        }
+        self.read_char();
+        if self.cur_char != '\'' {
+            return AsmToken::Illegal;
+        }
+        lit.push('\'');
+        AsmToken::Number(Rc::from(lit))
+    }
+
+    fn read_string(&mut self) -> AsmToken {
+        let mut buf = String::new();
+        loop {
+            self.read_char();
+            match self.cur_char {
+                '"' => break,
+                '\0' | '\n' => return AsmToken::Illegal,
+                '\\' => {
+                    self.read_char();
+                    match self.cur_char {
+                        'n' => buf.push('\n'),
+                        't' => buf.push('\t'),
+                        '0' => buf.push('\0'),
+                        '\\' => buf.push('\\'),
+                        '"' => buf.push('"'),
+                        _ => return AsmToken::Illegal,
+                    }
+                }
+                c => buf.push(c),
+            }
+        }
+        AsmToken::Str(Rc::from(buf))
+    }
+
     fn tokenizer(&mut self) -> AsmToken {
         match self.cur_char {
             ':' => AsmToken::Colon,
+            '"' => self.read_string(),
             ',' => AsmToken::Comma,
+            '+' => AsmToken::Plus,
+            '-' => AsmToken::Minus,
+            '*' => AsmToken::Asterisk,
+            '/' => AsmToken::Slash,
+            '&' => AsmToken::Ampersand,
+            '|' => AsmToken::Pipe,
+            '(' => AsmToken::LParen,
+            ')' => AsmToken::RParen,
+            '[' => AsmToken::LBracket,
+            ']' => AsmToken::RBracket,
             '<' => match self.chars.peek() {
                 Some(&'-') => {
                     self.read_char();
                     AsmToken::Assign
                 }
+                Some(&'<') => {
+                    self.read_char();
+                    AsmToken::Shl
+                }
+                _ => AsmToken::Illegal,
+            },
+            '>' => match self.chars.peek() {
+                Some(&'>') => {
+                    self.read_char();
+                    AsmToken::Shr
+                }
                 _ => AsmToken::Illegal,
             },
+            '\'' => self.read_char_literal(),
             c if c.is_alphabetic() || c == '.' || c == '_' => self.read_identifier(),
-            c if c == '-' || c.is_numeric() => self.read_number(),
+            c if c.is_ascii_digit() => self.read_number(),
             '\0' => AsmToken::Eof,
             _ => AsmToken::Illegal,
         }
@@ -113,11 +221,16 @@ impl<'a> Lexer<'a> {
 
     pub fn next_with_ctx(&mut self) -> TokWithCtx {
         self.skip_whitespace();
-        self.ignore_comment();
+        if !self.trivia {
+            self.ignore_comment();
+        }
         let cur_line = self.cur_line;
         let cur_column = self.cur_column;
-        let tok = self.tokenizer();
-        let tok_ctx = TokWithCtx::new(tok, cur_line, cur_column);
+        let tok = match self.cur_char {
+            '#' | ';' if self.trivia => self.read_comment(),
+            _ => self.tokenizer(),
+        };
+        let tok_ctx = TokWithCtx::new(tok, cur_line, cur_column, self.file.clone());
         self.read_char();
 
         tok_ctx
@@ -166,13 +279,55 @@ mod tests {
 
     #[test]
     fn ignore_comments() {
-        let input = "# this is a comment\n tubias ; another comment here \n another_tubias #comment until end";
+        let input = "# this is a comment\n# and another one\n tubias ; another comment here \n ; and another\n another_tubias #comment until end";
         let mut l = Lexer::new(input);
         assert_eq!(l.next_token(), AsmToken::Label(Rc::from("tubias")));
         assert_eq!(l.next_token(), AsmToken::Label(Rc::from("another_tubias")));
         assert_eq!(l.next_token(), AsmToken::Eof);
     }
 
+    #[test]
+    fn name_tables_match_the_lexer() {
+        use crate::assembler::tokens::{OPCODE_NAMES, PSEUDO_OP_NAMES, REGISTER_NAMES};
+        for name in OPCODE_NAMES {
+            assert!(matches!(AsmToken::name_to_tok(name), AsmToken::Opcode(_)));
+        }
+        for name in REGISTER_NAMES {
+            assert!(matches!(AsmToken::name_to_tok(name), AsmToken::Reg(_)));
+        }
+        for name in PSEUDO_OP_NAMES {
+            assert!(matches!(AsmToken::name_to_tok(name), AsmToken::PseudoOp(_)));
+        }
+    }
+
+    #[test]
+    fn keep_trivia() {
+        use super::AsmToken::{Comment, Eof, Label, Opcode, PseudoOp, Reg};
+        use crate::assembler::tokens::{Opcode as Op, PseudoOps, Register};
+
+        let input = "# header\n.TEXT\nMain: ADD a0 <- A1 ; tail  \n";
+        let toks: Vec<_> = Lexer::with_trivia(input, Rc::from("<input>"))
+            .get_deez_toks_w_ctx()
+            .into_iter()
+            .map(|t| ((*t.tok).clone(), t.cur_line))
+            .collect();
+        assert_eq!(
+            toks,
+            vec![
+                (Comment(Rc::from("# header")), 1),
+                (PseudoOp(Rc::new(PseudoOps::Text)), 2),
+                (Label(Rc::from("Main")), 3),
+                (AsmToken::Colon, 3),
+                (Opcode(Rc::new(Op::Add)), 3),
+                (Reg(Rc::new(Register::A0)), 3),
+                (AsmToken::Assign, 3),
+                (Reg(Rc::new(Register::A1)), 3),
+                (Comment(Rc::from("; tail")), 3),
+                (Eof, 4),
+            ]
+        );
+    }
+
     #[test]
     fn get_registers() {
         use super::AsmToken::{Eof, Reg};
@@ -294,6 +449,84 @@ mod tests {
         }
     }
 
+    #[test]
+    fn get_indexed_address() {
+        use super::AsmToken::*;
+        use crate::assembler::tokens::Register;
+        let input = "[s0 - 4]";
+        let mut l = Lexer::new(input);
+        let toks = vec![
+            LBracket,
+            Reg(Rc::new(Register::S0)),
+            Minus,
+            Number(Rc::from("4")),
+            RBracket,
+            Eof,
+        ];
+
+        for i in toks.into_iter() {
+            assert_eq!(l.next_token(), i);
+        }
+    }
+
+    #[test]
+    fn get_expression_operators() {
+        use super::AsmToken::*;
+        let input = "(a + 1) * 2 - b / 4 << 1 >> 2 & c | d <- <";
+        let mut l = Lexer::new(input);
+        let toks = vec![
+            LParen,
+            Label(Rc::from("a")),
+            Plus,
+            Number(Rc::from("1")),
+            RParen,
+            Asterisk,
+            Number(Rc::from("2")),
+            Minus,
+            Label(Rc::from("b")),
+            Slash,
+            Number(Rc::from("4")),
+            Shl,
+            Number(Rc::from("1")),
+            Shr,
+            Number(Rc::from("2")),
+            Ampersand,
+            Label(Rc::from("c")),
+            Pipe,
+            Label(Rc::from("d")),
+            Assign,
+            Illegal,
+            Eof,
+        ];
+
+        for t in toks.into_iter() {
+            assert_eq!(l.next_token(), t);
+        }
+    }
+
+    #[test]
+    fn get_strings() {
+        let input = r#".include "lib/consts.asm" "a\"b\n" "unterminated"#;
+        let mut l = Lexer::new(input);
+        let toks = vec![
+            AsmToken::PseudoOp(Rc::new(crate::assembler::tokens::PseudoOps::Include)),
+            AsmToken::Str(Rc::from("lib/consts.asm")),
+            AsmToken::Str(Rc::from("a\"b\n")),
+            AsmToken::Illegal,
+            AsmToken::Eof,
+        ];
+
+        for t in toks.into_iter() {
+            assert_eq!(l.next_token(), t);
+        }
+    }
+
+    #[test]
+    fn file_in_ctx() {
+        let mut l = Lexer::with_file("add", Rc::from("lib.asm"));
+        assert_eq!(&*l.next_with_ctx().file, "lib.asm");
+    }
+
     #[test]
     fn get_identifier() {
         let input = r"tubias, tubias2, tubias3";
@@ -314,11 +547,19 @@ mod tests {
 
     #[test]
     fn get_numbers() {
-        let input = "1234 -123";
+        let input = "1234 -123 0xFf 0b1010 0o17 'A' '\\n' 'AB'";
         let mut l = Lexer::new(input);
         let toks = vec![
             AsmToken::Number(Rc::from("1234")),
-            AsmToken::Number(Rc::from("-123")),
+            AsmToken::Minus,
+            AsmToken::Number(Rc::from("123")),
+            AsmToken::Number(Rc::from("0xFf")),
+            AsmToken::Number(Rc::from("0b1010")),
+            AsmToken::Number(Rc::from("0o17")),
+            AsmToken::Number(Rc::from("'A'")),
+            AsmToken::Number(Rc::from("'\\n'")),
+            AsmToken::Illegal,
+            AsmToken::Illegal,
             AsmToken::Eof,
         ];
 
@@ -343,56 +584,67 @@ read write"#;
                 tok: Rc::new(Opcode(Rc::new(Add))),
                 cur_line: 2,
                 cur_column: 1,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Comma),
                 cur_line: 2,
                 cur_column: 4,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Label(Rc::from("tubias"))),
                 cur_line: 2,
                 cur_column: 6,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Opcode(Rc::new(Add))),
                 cur_line: 3,
                 cur_column: 1,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Reg(Rc::new(Ra))),
                 cur_line: 3,
                 cur_column: 5,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Comma),
                 cur_line: 3,
                 cur_column: 7,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Reg(Rc::new(T0))),
                 cur_line: 3,
                 cur_column: 9,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Assign),
                 cur_line: 3,
                 cur_column: 12,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Reg(Rc::new(T1))),
                 cur_line: 3,
                 cur_column: 15,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Opcode(Rc::new(Read))),
                 cur_line: 4,
                 cur_column: 1,
+                file: Rc::from("<input>"),
             },
             TokWithCtx {
                 tok: Rc::new(Opcode(Rc::new(Write))),
                 cur_line: 4,
                 cur_column: 6,
+                file: Rc::from("<input>"),
             },
         ];
 
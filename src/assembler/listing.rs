@@ -0,0 +1,178 @@
+use std::{collections::HashMap, fmt::Write, ops::Range, rc::Rc};
+
+use crate::assembler::diagnostics::Span;
+
+#[derive(Debug, PartialEq)]
+pub enum TextEntry {
+    Label {
+        name: Rc<str>,
+        addr: u16,
+        span: Option<Span>,
+    },
+    Instruction {
+        /// Sequential words the instruction expanded to.
+        addrs: Range<u16>,
+        /// Words written outside the sequential flow: JAM targets and trampolines.
+        side_words: Vec<u16>,
+        span: Option<Span>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DataEntry {
+    /// Byte range taken in the RAM, without the padding before it.
+    pub bytes: Range<usize>,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone, Copy)]
+pub enum SymbolKind {
+    Text,
+    Data,
+    Equ,
+}
+
+/// What the evaluator produced for each line of the sources, to review the generated
+/// microcode.
+#[derive(Debug, Default)]
+pub struct Listing {
+    pub text: Vec<TextEntry>,
+    pub data: Vec<DataEntry>,
+    pub symbols: Vec<(Rc<str>, SymbolKind, i64)>,
+}
+
+impl Listing {
+    /// Amount of instructions listed so far.
+    pub fn instructions(&self) -> usize {
+        self.text
+            .iter()
+            .filter(|e| matches!(e, TextEntry::Instruction { .. }))
+            .count()
+    }
+
+    pub fn render(&self, firmware: &[u64], sources: &HashMap<String, String>) -> String {
+        let mut out = String::new();
+        let mut file = None;
+        let source = |span: &Option<Span>| -> (String, &str) {
+            let Some(span) = span else {
+                return (String::new(), "");
+            };
+            let text = sources
+                .get(&span.file)
+                .and_then(|src| src.lines().nth(span.line.saturating_sub(1)))
+                .unwrap_or("");
+            (span.line.to_string(), text.trim())
+        };
+
+        let _ = writeln!(out, "; Text");
+        let _ = writeln!(out, "; ADDR     MICROINSTRUCTION      LINE  SOURCE");
+        let mut jam = Vec::new();
+        for entry in &self.text {
+            let span = match entry {
+                TextEntry::Label { span, .. } | TextEntry::Instruction { span, .. } => span,
+            };
+            Self::file_marker(&mut out, &mut file, span);
+            let (line, text) = source(span);
+            match entry {
+                TextEntry::Label { addr, name, .. } => {
+                    let label = format!("{}:", name);
+                    let text = if text.is_empty() { &label } else { text };
+                    let _ = writeln!(
+                        out,
+                        "{:<10}{:<20}{:>6}  {}",
+                        fmt_addr(*addr),
+                        "",
+                        line,
+                        text
+                    );
+                }
+                TextEntry::Instruction {
+                    addrs, side_words, ..
+                } => {
+                    let range = match addrs.len() {
+                        0 => String::new(),
+                        1 => fmt_addr(addrs.start),
+                        _ => format!("{}..{}", fmt_addr(addrs.start), fmt_addr(addrs.end - 1)),
+                    };
+                    let first = addrs.clone().next().map(|a| fmt_word(firmware[a as usize]));
+                    let _ = writeln!(
+                        out,
+                        "{:<10}{:<20}{:>6}  {}",
+                        range,
+                        first.unwrap_or_default(),
+                        line,
+                        text
+                    );
+                    for addr in addrs.clone().skip(1) {
+                        let _ = writeln!(
+                            out,
+                            "{:<10}{}",
+                            fmt_addr(addr),
+                            fmt_word(firmware[addr as usize])
+                        );
+                    }
+                    jam.extend(side_words.iter().map(|w| (*w, addrs.start, line.clone())));
+                }
+            }
+        }
+
+        let _ = writeln!(out, "\n; JAM targets and trampolines");
+        let _ = writeln!(out, "; ADDR     MICROINSTRUCTION      FROM");
+        for (addr, from, line) in jam {
+            let _ = writeln!(
+                out,
+                "{:<10}{:<20}  {} (line {})",
+                fmt_addr(addr),
+                fmt_word(firmware[addr as usize]),
+                fmt_addr(from),
+                line
+            );
+        }
+
+        let _ = writeln!(out, "\n; Symbols");
+        let _ = writeln!(out, "; NAME                KIND  VALUE");
+        for (name, kind, value) in &self.symbols {
+            let (kind, value) = match kind {
+                SymbolKind::Text => ("text", fmt_addr(*value as u16)),
+                SymbolKind::Data => ("data", format!("{:#06x}", value)),
+                SymbolKind::Equ => ("equ", value.to_string()),
+            };
+            let _ = writeln!(out, "{:<22}{:<6}{}", name, kind, value);
+        }
+
+        let _ = writeln!(out, "\n; Data");
+        let _ = writeln!(out, "; RAM        SIZE  LINE  SOURCE");
+        file = None;
+        for entry in &self.data {
+            Self::file_marker(&mut out, &mut file, &entry.span);
+            let (line, text) = source(&entry.span);
+            let _ = writeln!(
+                out,
+                "{:<10}{:>6}{:>6}  {}",
+                format!("{:#06x}:{}", entry.bytes.start / 4, entry.bytes.start % 4),
+                entry.bytes.len(),
+                line,
+                text
+            );
+        }
+        out
+    }
+
+    /// Marks where the listed lines switch to another file, like into an include.
+    fn file_marker<'a>(out: &mut String, file: &mut Option<&'a str>, span: &'a Option<Span>) {
+        if let Some(span) = span {
+            if *file != Some(span.file.as_str()) {
+                let _ = writeln!(out, "; {}", span.file);
+                *file = Some(&span.file);
+            }
+        }
+    }
+}
+
+fn fmt_addr(addr: u16) -> String {
+    format!("{:03x}", addr)
+}
+
+fn fmt_word(word: u64) -> String {
+    format!("{:#018x}", word)
+}
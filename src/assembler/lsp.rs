@@ -0,0 +1,832 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use anyhow::{bail, Result};
+use thiserror::Error;
+
+use crate::assembler::{
+    diagnostics::{Diagnostic, Severity, SourceSpans, Span},
+    evaluator::AsmEvaluator,
+    include::Includer,
+    json::Json,
+    listing::TextEntry,
+    parser::Parser,
+    tokens::{AsmToken, OPCODE_NAMES, REGISTER_NAMES},
+};
+
+#[derive(Debug, PartialEq, Error)]
+pub enum LspError {
+    #[error("Message without a Content-Length header")]
+    MissingContentLength,
+    #[error("Invalid header '{header}'")]
+    InvalidHeader { header: String },
+}
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// LSP completion item kinds
+const KIND_KEYWORD: usize = 14;
+const KIND_VARIABLE: usize = 6;
+const KIND_REFERENCE: usize = 18;
+
+/// What is known about an open document, refreshed on every change.
+#[derive(Default)]
+struct Analysis {
+    spans: SourceSpans,
+    diagnostics: Vec<Diagnostic>,
+    /// Microinstructions each instruction expands to, by the line it starts on.
+    sizes: HashMap<usize, usize>,
+}
+
+struct Document {
+    uri: String,
+    /// Path the document is known by in spans and diagnostics.
+    path: String,
+    text: String,
+    analysis: Analysis,
+}
+
+/// A language server for Vondel assembly. It is driven one message at a time, so it
+/// can be run over any transport, see [`run`] for the standard one.
+#[derive(Default)]
+pub struct Server {
+    docs: HashMap<String, Document>,
+    shutdown: bool,
+    exit: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Exit code, once the client asked the server to exit.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit
+    }
+
+    /// Handles a message from the client, returning the messages to send back.
+    pub fn handle(&mut self, msg: &Json) -> Vec<Json> {
+        let method = msg.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = msg.get("params").unwrap_or(&Json::Null);
+        let Some(id) = msg.get("id").cloned() else {
+            return self.notification(method, params);
+        };
+
+        let result = match method {
+            "initialize" => Some(capabilities()),
+            "shutdown" => {
+                self.shutdown = true;
+                Some(Json::Null)
+            }
+            "textDocument/definition" => Some(self.definition(params)),
+            "textDocument/references" => Some(self.references(params)),
+            "textDocument/hover" => Some(self.hover(params)),
+            "textDocument/completion" => Some(self.completion(params)),
+            _ => None,
+        };
+        match result {
+            Some(result) => vec![response(id, result)],
+            None => vec![error(
+                id,
+                METHOD_NOT_FOUND,
+                format!("Unknown method '{}'", method),
+            )],
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params
+            .path(&["textDocument", "uri"])
+            .and_then(Json::as_str)
+            .unwrap_or("");
+        match method {
+            "exit" => {
+                self.exit = Some(if self.shutdown { 0 } else { 1 });
+                Vec::new()
+            }
+            "textDocument/didOpen" => {
+                let text = params
+                    .path(&["textDocument", "text"])
+                    .and_then(Json::as_str);
+                self.update(uri, text.unwrap_or(""))
+            }
+            "textDocument/didChange" => {
+                // Documents are synced in full, so the last change has the whole text
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                match text {
+                    Some(text) => self.update(uri, text),
+                    None => Vec::new(),
+                }
+            }
+            "textDocument/didClose" => {
+                self.docs.remove(uri);
+                vec![publish_diagnostics(uri, Vec::new())]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn update(&mut self, uri: &str, text: &str) -> Vec<Json> {
+        let path = uri_to_path(uri);
+        let doc = Document {
+            analysis: analyze(&path, text),
+            uri: uri.to_string(),
+            path,
+            text: text.to_string(),
+        };
+        let diagnostics = doc
+            .analysis
+            .diagnostics
+            .iter()
+            .map(|d| diagnostic_json(d, &doc))
+            .collect();
+        self.docs.insert(uri.to_string(), doc);
+
+        vec![publish_diagnostics(uri, diagnostics)]
+    }
+
+    /// The document and the word under the position a request points at.
+    fn word_at(&self, params: &Json) -> Option<(&Document, String)> {
+        let uri = params.path(&["textDocument", "uri"])?.as_str()?;
+        let doc = self.docs.get(uri)?;
+        let line = params.path(&["position", "line"])?.as_u64()? as usize;
+        let character = params.path(&["position", "character"])?.as_u64()? as usize;
+        let text = doc.text.lines().nth(line)?;
+        let (start, end) = word_bounds(text, utf16_to_char(text, character))?;
+
+        Some((doc, text.chars().skip(start).take(end - start).collect()))
+    }
+
+    fn definition(&self, params: &Json) -> Json {
+        let Some((doc, word)) = self.word_at(params) else {
+            return Json::Null;
+        };
+        let spans = &doc.analysis.spans;
+        match spans.labels.get(&*word).or(spans.symbols.get(&*word)) {
+            Some(span) => location(doc, span, &word),
+            None => Json::Null,
+        }
+    }
+
+    fn references(&self, params: &Json) -> Json {
+        let Some((doc, word)) = self.word_at(params) else {
+            return Json::Null;
+        };
+        let spans = &doc.analysis.spans;
+        let declaration = params
+            .path(&["context", "includeDeclaration"])
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+
+        let mut locations = Vec::new();
+        if declaration {
+            let def = spans.labels.get(&*word).or(spans.symbols.get(&*word));
+            locations.extend(def.map(|span| location(doc, span, &word)));
+        }
+        let refs = spans.label_refs.iter().chain(spans.symbol_refs.iter());
+        for (name, span) in refs.filter(|(name, _)| **name == *word) {
+            locations.push(location(doc, span, name));
+        }
+        Json::Array(locations)
+    }
+
+    fn hover(&self, params: &Json) -> Json {
+        let Some((doc, word)) = self.word_at(params) else {
+            return Json::Null;
+        };
+        let line = params.path(&["position", "line"]).and_then(Json::as_u64);
+        let contents = match AsmToken::name_to_tok(&word) {
+            AsmToken::Reg(reg) => {
+                let [a, b, c] = Parser::bus_eligibility(&reg);
+                let yes_no = |ok: bool| if ok { "yes" } else { "no" };
+                format!(
+                    "`{}`\n\nA bus (read): {}  \nB bus (read): {}  \nC bus (write): {}",
+                    word,
+                    yes_no(a),
+                    yes_no(b),
+                    yes_no(c)
+                )
+            }
+            AsmToken::Opcode(_) => {
+                let size = line.and_then(|l| doc.analysis.sizes.get(&(l as usize + 1)));
+                match size {
+                    Some(1) => format!("`{}` expands to 1 microinstruction", word),
+                    Some(n) => format!("`{}` expands to {} microinstructions", word, n),
+                    None => return Json::Null,
+                }
+            }
+            _ => return Json::Null,
+        };
+        Json::object([(
+            "contents",
+            Json::object([("kind", "markdown".into()), ("value", contents.into())]),
+        )])
+    }
+
+    fn completion(&self, params: &Json) -> Json {
+        let item = |label: &str, kind: usize| {
+            Json::object([("label", label.into()), ("kind", kind.into())])
+        };
+        // Opcodes start a statement, operands come after one
+        let after_opcode = self.word_before(params).is_some_and(|prefix| {
+            prefix
+                .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .any(|w| OPCODE_NAMES.contains(&w))
+        });
+        if !after_opcode {
+            return OPCODE_NAMES
+                .iter()
+                .map(|op| item(op, KIND_KEYWORD))
+                .collect::<Vec<_>>()
+                .into();
+        }
+
+        let mut items: Vec<Json> = REGISTER_NAMES
+            .iter()
+            .map(|r| item(r, KIND_VARIABLE))
+            .collect();
+        if let Some(doc) = params
+            .path(&["textDocument", "uri"])
+            .and_then(Json::as_str)
+            .and_then(|uri| self.docs.get(uri))
+        {
+            let spans = &doc.analysis.spans;
+            let mut names: Vec<&str> = spans
+                .labels
+                .keys()
+                .chain(spans.symbols.keys())
+                .map(|n| &**n)
+                .collect();
+            names.sort();
+            items.extend(names.into_iter().map(|name| item(name, KIND_REFERENCE)));
+        }
+        items.into()
+    }
+
+    /// Text of the line of a request up to its position.
+    fn word_before(&self, params: &Json) -> Option<String> {
+        let uri = params.path(&["textDocument", "uri"])?.as_str()?;
+        let doc = self.docs.get(uri)?;
+        let line = params.path(&["position", "line"])?.as_u64()? as usize;
+        let character = params.path(&["position", "character"])?.as_u64()? as usize;
+        let text = doc.text.lines().nth(line).unwrap_or("");
+        let text = text.split([';', '#']).next().unwrap_or("");
+
+        Some(text.chars().take(utf16_to_char(text, character)).collect())
+    }
+}
+
+/// Where a name at a span is, for the editor to jump to.
+fn location(doc: &Document, span: &Span, name: &str) -> Json {
+    let (uri, text) = if span.file == doc.path {
+        (doc.uri.clone(), Some(Cow::Borrowed(doc.text.as_str())))
+    } else {
+        let text = std::fs::read_to_string(&span.file).ok().map(Cow::Owned);
+        (path_to_uri(&span.file), text)
+    };
+    let line = text
+        .as_deref()
+        .and_then(|t| t.lines().nth(span.line.saturating_sub(1)));
+    let start = line.map_or(span.column.saturating_sub(1), |l| {
+        column_to_utf16(l, span.column)
+    });
+    Json::object([
+        ("uri", uri.into()),
+        (
+            "range",
+            range(
+                span.line.saturating_sub(1),
+                start,
+                start + name.encode_utf16().count(),
+            ),
+        ),
+    ])
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                // Full document sync
+                ("textDocumentSync", 1usize.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("hoverProvider", true.into()),
+                ("completionProvider", Json::object::<&str, _>([])),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", "vondel-lsp".into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+/// Assembles a document, keeping what the editor features need even when it doesn't
+/// assemble.
+fn analyze(path: &str, text: &str) -> Analysis {
+    let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+    let toks = match Includer::default().expand_buffer(text, Rc::from(path), dir) {
+        Ok(toks) => toks,
+        Err(e) => {
+            return Analysis {
+                diagnostics: vec![Diagnostic::from_error(&e)],
+                ..Analysis::default()
+            }
+        }
+    };
+    let program = Parser::new(toks.into()).get_deez_program();
+    let spans = program.spans.clone();
+    let mut evaluator = AsmEvaluator::new();
+    let res = evaluator.eval_program(program).map(|_| ());
+    let diagnostics = evaluator.diagnostics(res.as_ref().err()).items;
+
+    let mut sizes = HashMap::new();
+    if res.is_ok() {
+        for entry in evaluator.listing_entries().text.iter() {
+            if let TextEntry::Instruction {
+                addrs,
+                side_words,
+                span: Some(span),
+            } = entry
+            {
+                if span.file == path {
+                    sizes.insert(span.line, addrs.len() + side_words.len());
+                }
+            }
+        }
+    }
+
+    Analysis {
+        spans,
+        diagnostics,
+        sizes,
+    }
+}
+
+fn diagnostic_json(diag: &Diagnostic, doc: &Document) -> Json {
+    let severity: usize = match diag.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    let mut message = diag.message.clone();
+    // Problems without a place in this document go on its first line
+    let (line, start, end) = match &diag.span {
+        Some(span) if span.file == doc.path => {
+            let text = doc
+                .text
+                .lines()
+                .nth(span.line.saturating_sub(1))
+                .unwrap_or("");
+            let start = column_to_utf16(text, span.column);
+            let chars: Vec<char> = text.chars().collect();
+            let first = utf16_to_char(text, start);
+            let end = match word_bounds(text, first) {
+                Some((s, e)) if s == first => e,
+                _ => (first + 1).min(chars.len()),
+            };
+            let end = chars[..end]
+                .iter()
+                .map(|c| c.len_utf16())
+                .sum::<usize>()
+                .max(start);
+            (span.line.saturating_sub(1), start, end)
+        }
+        Some(span) => {
+            message = format!("{} ({}:{}:{})", message, span.file, span.line, span.column);
+            (0, 0, 0)
+        }
+        None => (0, 0, 0),
+    };
+    let notes: Vec<Json> = diag
+        .notes
+        .iter()
+        .filter_map(|note| {
+            let span = note.span.as_ref()?;
+            let location = Json::object([
+                ("uri", path_to_uri(&span.file).into()),
+                (
+                    "range",
+                    range(
+                        span.line.saturating_sub(1),
+                        span.column.saturating_sub(1),
+                        span.column,
+                    ),
+                ),
+            ]);
+            Some(Json::object([
+                ("location", location),
+                ("message", note.message.clone().into()),
+            ]))
+        })
+        .collect();
+
+    let mut fields = vec![
+        ("range", range(line, start, end)),
+        ("severity", severity.into()),
+        ("code", diag.code.into()),
+        ("source", "vondel".into()),
+        ("message", message.into()),
+    ];
+    if !notes.is_empty() {
+        fields.push(("relatedInformation", notes.into()));
+    }
+    Json::object(fields)
+}
+
+fn publish_diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+        ),
+    ])
+}
+
+fn response(id: Json, result: Json) -> Json {
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), ("result", result)])
+}
+
+fn error(id: Json, code: i64, message: String) -> Json {
+    let error = Json::object([
+        ("code", Json::Number(code as f64)),
+        ("message", message.into()),
+    ]);
+    Json::object([("jsonrpc", "2.0".into()), ("id", id), ("error", error)])
+}
+
+fn range(line: usize, start: usize, end: usize) -> Json {
+    let position =
+        |character: usize| Json::object([("line", line.into()), ("character", character.into())]);
+    Json::object([("start", position(start)), ("end", position(end))])
+}
+
+/// Character index of a UTF-16 offset into a line, which is how LSP counts positions.
+fn utf16_to_char(text: &str, offset: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in text.chars().enumerate() {
+        if units >= offset {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    text.chars().count()
+}
+
+/// UTF-16 offset of a lexer column, which counts tabs as four columns and starts at 1.
+fn column_to_utf16(text: &str, column: usize) -> usize {
+    let mut col = 1;
+    let mut units = 0;
+    for c in text.chars() {
+        if col >= column {
+            break;
+        }
+        col += if c == '\t' { 4 } else { 1 };
+        units += c.len_utf16();
+    }
+    units
+}
+
+/// Character range of the name around a character index, if there is one.
+fn word_bounds(text: &str, at: usize) -> Option<(usize, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut start = at.min(chars.len());
+    while start > 0 && is_word(chars[start - 1]) {
+        start -= 1;
+    }
+    let mut end = at.min(chars.len());
+    while end < chars.len() && is_word(chars[end]) {
+        end += 1;
+    }
+    (start < end).then_some((start, end))
+}
+
+fn uri_to_path(uri: &str) -> String {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let bytes = path.as_bytes();
+    let mut res = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok());
+        match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+            Some(byte) if bytes[i] == b'%' => {
+                res.push(byte);
+                i += 3;
+            }
+            _ => {
+                res.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&res).into_owned()
+}
+
+fn path_to_uri(path: &str) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// Reads a message framed by a `Content-Length` header, or `None` at the end of the
+/// input.
+fn read_message<R: BufRead>(input: &mut R) -> Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        match header.split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                length = value.trim().parse::<usize>().ok();
+            }
+            Some(_) => {}
+            None => bail!(LspError::InvalidHeader {
+                header: header.to_string()
+            }),
+        }
+    }
+    let Some(length) = length else {
+        bail!(LspError::MissingContentLength);
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    Ok(Some(String::from_utf8_lossy(&body).into_owned()))
+}
+
+fn write_message<W: Write>(output: &mut W, msg: &Json) -> Result<()> {
+    let body = msg.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
+
+/// Serves the protocol until the client asks to exit, returning the exit code.
+pub fn run<R: BufRead, W: Write>(mut input: R, mut output: W) -> Result<i32> {
+    let mut server = Server::new();
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match Json::parse(&body) {
+            Ok(msg) => server.handle(&msg),
+            Err(e) => vec![error(Json::Null, PARSE_ERROR, e.to_string())],
+        };
+        for reply in replies.iter() {
+            write_message(&mut output, reply)?;
+        }
+        if let Some(code) = server.exit_code() {
+            return Ok(code);
+        }
+    }
+    // The client went away without asking
+    Ok(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///work/my%20prog.asm";
+
+    fn request(id: usize, method: &str, params: Json) -> Json {
+        Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("id", id.into()),
+            ("method", method.into()),
+            ("params", params),
+        ])
+    }
+
+    fn notification(method: &str, params: Json) -> Json {
+        Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ])
+    }
+
+    fn open(server: &mut Server, text: &str) -> Vec<Json> {
+        let doc = Json::object([("uri", URI.into()), ("text", text.into())]);
+        server.handle(&notification(
+            "textDocument/didOpen",
+            Json::object([("textDocument", doc)]),
+        ))
+    }
+
+    fn at(line: usize, character: usize) -> Json {
+        Json::object([
+            ("textDocument", Json::object([("uri", URI.into())])),
+            (
+                "position",
+                Json::object([("line", line.into()), ("character", character.into())]),
+            ),
+        ])
+    }
+
+    fn result(server: &mut Server, method: &str, params: Json) -> Json {
+        let replies = server.handle(&request(1, method, params));
+        replies[0].get("result").cloned().unwrap()
+    }
+
+    const SRC: &str = ".data
+    n: .word 5
+.text
+main:
+\tread a0 <- n
+    mul a1 <- a0, a0
+loop:
+    beq a1, a0, main
+    jal loop
+";
+
+    #[test]
+    fn lifecycle() {
+        let mut server = Server::new();
+        let init = result(&mut server, "initialize", Json::object::<&str, _>([]));
+        let caps = init.get("capabilities").unwrap();
+        assert_eq!(caps.get("hoverProvider"), Some(&Json::Bool(true)));
+
+        let replies = server.handle(&request(2, "textDocument/formatting", Json::Null));
+        assert_eq!(
+            replies[0].path(&["error", "code"]),
+            Some(&Json::Number(-32601.0))
+        );
+
+        server.handle(&request(3, "shutdown", Json::Null));
+        assert_eq!(server.exit_code(), None);
+        server.handle(&notification("exit", Json::Null));
+        assert_eq!(server.exit_code(), Some(0));
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let mut server = Server::new();
+        let replies = open(&mut server, SRC);
+        let diags = replies[0].path(&["params", "diagnostics"]).unwrap();
+        assert_eq!(diags, &Json::Array(Vec::new()));
+
+        let replies = open(&mut server, ".text\nmain:\n    jal nowhere\n");
+        let params = replies[0].get("params").unwrap();
+        assert_eq!(params.get("uri").and_then(Json::as_str), Some(URI));
+        assert_eq!(
+            params.get("diagnostics").map(|d| d.to_string()),
+            Some(
+                r#"[{"range":{"start":{"line":2,"character":8},"end":{"line":2,"character":15}},"severity":1,"code":"E0016","source":"vondel","message":"Undefined label 'nowhere'"}]"#
+                    .to_string()
+            )
+        );
+
+        let close = Json::object([("textDocument", Json::object([("uri", URI.into())]))]);
+        let replies = server.handle(&notification("textDocument/didClose", close));
+        let diags = replies[0].path(&["params", "diagnostics"]).unwrap();
+        assert_eq!(diags, &Json::Array(Vec::new()));
+    }
+
+    #[test]
+    fn navigates_symbols() {
+        let mut server = Server::new();
+        open(&mut server, SRC);
+
+        // `n` in `read a0 <- n`, after a tab
+        let def = result(&mut server, "textDocument/definition", at(4, 12));
+        assert_eq!(def.get("uri").and_then(Json::as_str), Some(URI));
+        assert_eq!(def.get("range"), Some(&range(1, 4, 5)));
+
+        let def = result(&mut server, "textDocument/definition", at(8, 9));
+        assert_eq!(def.get("range"), Some(&range(6, 0, 4)));
+
+        let mut params = at(3, 1);
+        if let Json::Object(pairs) = &mut params {
+            let context = Json::object([("includeDeclaration", true.into())]);
+            pairs.push(("context".to_string(), context));
+        }
+        let refs = result(&mut server, "textDocument/references", params);
+        let ranges: Vec<_> = refs
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|l| l.get("range").cloned().unwrap())
+            .collect();
+        assert_eq!(ranges, vec![range(3, 0, 4), range(7, 16, 20)]);
+
+        assert_eq!(
+            result(&mut server, "textDocument/definition", at(5, 5)),
+            Json::Null
+        );
+    }
+
+    #[test]
+    fn hovers_and_completes() {
+        let mut server = Server::new();
+        open(&mut server, SRC);
+        let hover = |server: &mut Server, line, character| {
+            let hover = result(server, "textDocument/hover", at(line, character));
+            hover
+                .path(&["contents", "value"])
+                .and_then(Json::as_str)
+                .map(str::to_string)
+        };
+
+        assert_eq!(
+            hover(&mut server, 5, 5),
+            Some("`mul` expands to 8 microinstructions".to_string())
+        );
+        assert_eq!(
+            hover(&mut server, 4, 2),
+            Some("`read` expands to 2 microinstructions".to_string())
+        );
+        assert_eq!(
+            hover(&mut server, 5, 9),
+            Some(
+                "`a1`\n\nA bus (read): yes  \nB bus (read): yes  \nC bus (write): yes".to_string()
+            )
+        );
+        open(&mut server, ".text\nmain:\n    mov a0 <- mbr\n    halt\n");
+        assert_eq!(
+            hover(&mut server, 2, 15),
+            Some("`mbr`\n\nA bus (read): yes  \nB bus (read): no  \nC bus (write): no".to_string())
+        );
+        assert_eq!(hover(&mut server, 1, 1), None);
+
+        let labels = |items: Json| -> Vec<String> {
+            items
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i.get("label").and_then(Json::as_str).unwrap().to_string())
+                .collect()
+        };
+        open(&mut server, SRC);
+        let items = labels(result(&mut server, "textDocument/completion", at(6, 0)));
+        assert_eq!(items.len(), OPCODE_NAMES.len());
+        assert!(items.contains(&"readbu".to_string()));
+
+        let items = labels(result(&mut server, "textDocument/completion", at(7, 12)));
+        assert_eq!(items[0], "mar");
+        assert!(items.contains(&"a3".to_string()));
+        assert!(items.ends_with(&["loop".to_string(), "main".to_string(), "n".to_string()]));
+    }
+
+    #[test]
+    fn transport() {
+        let msg = |body: &str| format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let input = [
+            msg(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#),
+            msg("{oops"),
+            msg(r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#),
+            msg(r#"{"jsonrpc":"2.0","method":"exit"}"#),
+        ]
+        .concat();
+        let mut output = Vec::new();
+        assert_eq!(run(input.as_bytes(), &mut output).unwrap(), 0);
+
+        let output = String::from_utf8(output).unwrap();
+        let mut reader = output.as_bytes();
+        let mut replies = Vec::new();
+        while let Some(body) = read_message(&mut reader).unwrap() {
+            replies.push(Json::parse(&body).unwrap());
+        }
+        assert_eq!(replies.len(), 3);
+        assert_eq!(
+            replies[1].path(&["error", "code"]),
+            Some(&Json::Number(-32700.0))
+        );
+        assert_eq!(replies[2].get("result"), Some(&Json::Null));
+
+        let err = read_message(&mut "Content-Type: x\r\n\r\n{}".as_bytes()).unwrap_err();
+        assert_eq!(err.downcast_ref(), Some(&LspError::MissingContentLength));
+    }
+}
@@ -0,0 +1,471 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::assembler::evaluator::Microinstruction;
+
+const JAM_BIT: u16 = 0b100000000;
+
+/// What the optimizer saved on a program.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OptStats {
+    /// Words of sequential code removed.
+    pub words: usize,
+    /// Microinstructions taken out of the execution paths. Each one saves a cycle
+    /// every time its path runs.
+    pub cycles: usize,
+}
+
+/// Where each word of the control store went after optimizing, to keep labels
+/// and the listing pointing at the right code.
+pub(crate) struct Relocation {
+    /// Address the execution continues at when jumping to the old address.
+    entry: Vec<u16>,
+    /// Address holding the old word, `None` when it was removed. A word merged
+    /// into another one is placed with it.
+    place: Vec<Option<u16>>,
+    /// New end of the sequential code.
+    pub end: u16,
+}
+
+impl Relocation {
+    pub fn entry(&self, addr: u16) -> u16 {
+        self.entry[addr as usize]
+    }
+
+    pub fn place(&self, addr: u16) -> Option<u16> {
+        self.place[addr as usize]
+    }
+}
+
+/// Peephole optimizations over the words of the sequential code, `0..end`.
+///
+/// - Jumps to jumps, including `nop`s, are threaded to their final target.
+/// - A word computing the same value as the word that runs right before it is
+///   merged into it, as one write to several registers of the C bus.
+/// - The words no longer reached are removed and the rest of the code is moved
+///   up, along with the JAM targets of its branches.
+///
+/// Words that may jump through `MBR` or a register can't be moved, since the
+/// addresses they reach are only known at run time, so programs using them are
+/// left untouched, returning `None`. So are programs where moving a JAM target
+/// would collide with a word placed at a fixed address.
+pub(crate) fn optimize(
+    words: &mut [u64],
+    end: u16,
+    labels: &[u16],
+) -> Option<(OptStats, Relocation)> {
+    let mut opt = Optimizer {
+        words: words.to_vec(),
+        end,
+        hops: vec![0; words.len()],
+        merged: HashMap::new(),
+    };
+    if (0..words.len() as u16).any(|a| opt.code(a).is_some_and(|mi| mi.jam & 0b100 != 0)) {
+        return None;
+    }
+
+    let live = opt.live();
+    opt.thread_jumps();
+    let merges = opt.merge_writes(labels);
+    let reached = opt.live();
+    let removed: HashSet<u16> = (0..end)
+        .filter(|a| live.contains(a) && !reached.contains(a))
+        .collect();
+
+    let reloc = opt.compact(&removed)?;
+    let cycles = merges
+        + (0..end)
+            .filter(|a| !removed.contains(a))
+            .map(|a| opt.hops[a as usize])
+            .sum::<usize>();
+    words.copy_from_slice(&opt.words);
+    let stats = OptStats {
+        words: (end - reloc.end) as usize,
+        cycles,
+    };
+    Some((stats, reloc))
+}
+
+struct Optimizer {
+    words: Vec<u64>,
+    end: u16,
+    /// Jumps skipped by the word at each address.
+    hops: Vec<usize>,
+    /// Words merged into the word that runs before them.
+    merged: HashMap<u16, u16>,
+}
+
+impl Optimizer {
+    /// The microinstruction at `addr`, unless it's empty or a `halt`.
+    fn code(&self, addr: u16) -> Option<Microinstruction> {
+        match self.words[addr as usize] {
+            0 | Microinstruction::HALT => None,
+            word => Some(Microinstruction::decode(word)),
+        }
+    }
+
+    /// The target of the word at `addr` when it does nothing but jump.
+    fn jump(&self, addr: u16) -> Option<u16> {
+        let next = self.code(addr)?.next;
+        (self.words[addr as usize] == Microinstruction::new(next).get()).then_some(next)
+    }
+
+    /// Addresses the word at `addr` can continue at.
+    fn successors(&self, addr: u16) -> Vec<u16> {
+        match self.code(addr) {
+            Some(mi) if mi.jam == 0 => vec![mi.next],
+            Some(mi) => vec![mi.next, mi.next | JAM_BIT],
+            None => Vec::new(),
+        }
+    }
+
+    /// Words reached from the start of the program.
+    fn live(&self) -> HashSet<u16> {
+        let mut live = HashSet::from([0]);
+        let mut stack = vec![0];
+        while let Some(addr) = stack.pop() {
+            for next in self.successors(addr) {
+                if (next as usize) < self.words.len() && live.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        live
+    }
+
+    fn thread_jumps(&mut self) {
+        for addr in 0..self.words.len() as u16 {
+            // The next address of a JAM branch also picks its target
+            let Some(mut mi) = self.code(addr).filter(|mi| mi.jam == 0) else {
+                continue;
+            };
+            let mut hops = 0;
+            while let Some(next) = self.jump(mi.next) {
+                // Jumps around in circles
+                if next == mi.next || hops >= self.words.len() {
+                    break;
+                }
+                mi.next = next;
+                hops += 1;
+            }
+            if hops > 0 {
+                self.words[addr as usize] = mi.get();
+                self.hops[addr as usize] = hops;
+            }
+        }
+    }
+
+    /// Merges the words that compute the same value as the word before them,
+    /// returning how many were merged.
+    fn merge_writes(&mut self, labels: &[u16]) -> usize {
+        let mut refs = vec![0; self.words.len()];
+        refs[0] += 1;
+        for addr in self.live() {
+            for next in self.successors(addr) {
+                if let Some(r) = refs.get_mut(next as usize) {
+                    *r += 1;
+                }
+            }
+        }
+
+        let mut merges = 0;
+        for addr in 0..self.end {
+            while let Some(mut first) = self.code(addr) {
+                let next = first.next;
+                let Some(second) = self.code(next) else {
+                    break;
+                };
+                let mergeable = next < self.end
+                    && next != addr
+                    && refs[next as usize] == 1
+                    && !labels.contains(&next)
+                    && [&first, &second]
+                        .iter()
+                        .all(|mi| mi.jam == 0 && mi.mem == 0)
+                    && (first.alu, first.a, first.b, first.immediate)
+                        == (second.alu, second.a, second.b, second.immediate)
+                    && reads(&second).is_some_and(|r| r & first.c_bus == 0);
+                if !mergeable {
+                    break;
+                }
+
+                first.c_bus |= second.c_bus;
+                first.next = second.next;
+                self.words[addr as usize] = first.get();
+                self.hops[addr as usize] += self.hops[next as usize];
+                self.merged.insert(next, addr);
+                refs[next as usize] = 0;
+                merges += 1;
+            }
+        }
+        merges
+    }
+
+    /// Removes the words and moves the rest of the sequential code up.
+    fn compact(&mut self, removed: &HashSet<u16>) -> Option<Relocation> {
+        let len = self.words.len();
+        let mut place: Vec<Option<u16>> = (0..len as u16).map(Some).collect();
+        let mut new_addr = 0;
+        for addr in 0..self.end {
+            if removed.contains(&addr) {
+                place[addr as usize] = None;
+            } else {
+                place[addr as usize] = Some(new_addr);
+                new_addr += 1;
+            }
+        }
+        let new_end = new_addr;
+
+        // A removed jump goes to the same place as its target, anything else removed
+        // falls through to the next word
+        let entry: Vec<u16> = (0..len as u16)
+            .map(|addr| {
+                let mut at = addr;
+                for _ in 0..len {
+                    if at >= self.end {
+                        break;
+                    }
+                    if let Some(new) = place[at as usize] {
+                        return new;
+                    }
+                    at = match self.jump(at) {
+                        Some(next) if !self.merged.contains_key(&at) => next,
+                        _ => at + 1,
+                    };
+                }
+                if at == self.end {
+                    new_end
+                } else {
+                    at
+                }
+            })
+            .collect();
+        for (&second, &first) in &self.merged {
+            place[second as usize] = place[first as usize];
+        }
+
+        // The JAM targets of the sequential code follow their branch
+        let mut slots = HashMap::new();
+        for addr in (0..self.end).filter(|a| !removed.contains(a)) {
+            if let Some(mi) = self
+                .code(addr)
+                .filter(|mi| mi.jam != 0 && mi.next <= self.end)
+            {
+                let slot = mi.next | JAM_BIT;
+                let new_slot = entry[mi.next as usize] | JAM_BIT;
+                if *slots.entry(slot).or_insert(new_slot) != new_slot {
+                    return None;
+                }
+            }
+        }
+        let mut targets = HashSet::new();
+        for (&slot, &new_slot) in &slots {
+            place[slot as usize] = Some(new_slot);
+            if !targets.insert(new_slot) {
+                return None;
+            }
+        }
+
+        let moved = |a: u16| a < self.end || slots.contains_key(&a);
+        let mut words = self.words.clone();
+        for addr in (0..len as u16).filter(|&a| moved(a)) {
+            words[addr as usize] = 0;
+        }
+        for addr in 0..len as u16 {
+            if !moved(addr) && words[addr as usize] != 0 && targets.contains(&addr) {
+                return None;
+            }
+        }
+
+        for addr in 0..len as u16 {
+            let Some(new) = place[addr as usize]
+                .filter(|_| !removed.contains(&addr) && !self.merged.contains_key(&addr))
+            else {
+                continue;
+            };
+            let word = match self.code(addr) {
+                Some(mut mi) => {
+                    if mi.next <= self.end {
+                        // A JAM branch out of the code would need its target moved too
+                        if mi.jam != 0 && !moved(addr) {
+                            return None;
+                        }
+                        mi.next = entry[mi.next as usize];
+                    }
+                    mi.get()
+                }
+                None => self.words[addr as usize],
+            };
+            if moved(addr) || word != self.words[addr as usize] {
+                words[new as usize] = word;
+            }
+        }
+
+        self.words = words;
+        Some(Relocation {
+            entry,
+            place,
+            end: new_end,
+        })
+    }
+}
+
+/// Bits of the C bus for the registers the word reads, `None` when it reads one
+/// that memory operations can change under it.
+fn reads(mi: &Microinstruction) -> Option<u32> {
+    let a = match mi.a {
+        // MDR and the MBRs
+        0 | 2..=5 => return None,
+        1 => 1 << 17,
+        6 => 1 << 16,
+        7 => 1 << 20,
+        9..=24 => 1 << (24 - mi.a),
+        _ => 0,
+    };
+    let b = match mi.b {
+        0 | 20..=30 => return None,
+        1 => 1 << 16,
+        2 => 1 << 20,
+        4..=19 => 1 << (19 - mi.b),
+        _ => 0,
+    };
+    Some(a | b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        assembler::evaluator::AsmEvaluator,
+        uarch::{mem::Ram, Computer},
+    };
+
+    fn run(src: &str, optimize: bool) -> (Vec<u32>, f64, Option<OptStats>) {
+        let mut eval = AsmEvaluator::new();
+        eval.set_optimize(optimize);
+        let (cs, ram) = eval.evaluate_buffer(src).unwrap();
+        let mut mem = Ram::new();
+        mem.load(0, ram.iter().copied());
+
+        let mut computer = Computer::new(mem, cs);
+        computer.exec();
+        let regs = (0..16)
+            .map(|i| computer.regs().gen.get(i).unwrap())
+            .collect();
+        (regs, computer.cycles(), eval.optimizations())
+    }
+
+    #[test]
+    fn same_results_in_fewer_cycles() {
+        for src in [
+            include_str!("../../programs/power.asm"),
+            include_str!("../../programs/factorial.asm"),
+            include_str!("../../programs/div.asm"),
+            "
+            .text
+            main:
+                li s0 <- -2
+                li s1 <- -6
+            loop:
+                addi s2 <- s2, 1
+                nop
+                addi s1 <- s1, 1
+                bltu s1, s0, loop
+                bgeu s2, 3, exit
+                nop
+            exit:
+                halt
+            ",
+            "
+            .text
+            main:
+                lui s0 <- 3
+                mov t0 <- s0
+                mov t1 <- s0
+                mov a0 <- s0
+                nop
+                nop
+                jal skip
+            back:
+                addi a1 <- a1, 1
+                beq a1, s0, done
+                jal hop
+            hop:
+                jal back
+            skip:
+                nop
+                jal back
+            done:
+                halt
+            ",
+        ] {
+            let (plain, plain_cycles, stats) = run(src, false);
+            assert_eq!(stats, None);
+            let (optimized, cycles, stats) = run(src, true);
+            assert_eq!(plain, optimized);
+            assert!(cycles <= plain_cycles);
+            let stats = stats.unwrap();
+            assert_eq!(stats.cycles == 0, cycles == plain_cycles);
+        }
+    }
+
+    #[test]
+    fn merge_and_thread() {
+        let src = "
+            .text
+            main:
+                mov t0 <- s0
+                mov t1 <- s0
+                nop
+                jal end
+            end:
+                halt
+            ";
+        let mut eval = AsmEvaluator::new();
+        eval.set_optimize(true);
+        let (cs, _) = eval.evaluate_buffer(src).unwrap();
+        let firmware = cs.firmware();
+
+        let mut mov = Microinstruction::new(1);
+        mov.c_bus = 0b11 << 13;
+        mov.alu = 0b000011000;
+        mov.a = 14;
+        mov.b = Microinstruction::NO_B;
+        assert_eq!(firmware[0], mov.get());
+        assert_eq!(firmware[1], Microinstruction::HALT);
+        assert_eq!(firmware[2], 0);
+        assert_eq!(
+            eval.optimizations(),
+            Some(OptStats {
+                words: 3,
+                cycles: 3
+            })
+        );
+
+        // Every line still points at the word doing its work
+        let listing = eval.listing(&cs);
+        assert!(listing.contains("000       0x0020300c0001df00       4  mov t0 <- s0"));
+        assert!(listing.contains("000       0x0020300c0001df00       5  mov t1 <- s0"));
+        assert!(listing.contains("001                                8  end:"));
+    }
+
+    #[test]
+    fn moves_jam_targets() {
+        let src = "
+            .text
+            main:
+                lui a0 <- 1
+                nop
+                beq a0, a1, main
+                halt
+            ";
+        let mut eval = AsmEvaluator::new();
+        eval.set_optimize(true);
+        let (cs, _) = eval.evaluate_buffer(src).unwrap();
+        let firmware = cs.firmware();
+
+        // The branch went from 0x002 to 0x001, its target from 0x103 to 0x102
+        assert_eq!(Microinstruction::decode(firmware[1]).next, 2);
+        assert_eq!(firmware[0x102], Microinstruction::new(0).get());
+        assert_eq!(firmware[0x103], 0);
+    }
+}
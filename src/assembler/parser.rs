@@ -1,12 +1,16 @@
 use super::sections::DataKind;
 use super::sections::Sections;
+use std::collections::HashMap;
 use std::mem::discriminant;
 use std::rc::Rc;
 
 use crate::assembler::{
+    diagnostics::{SourceSpans, Span},
+    evaluator::{AsmEvaluator, Operand},
     sections::{
-        BranchOp, DataWrited, DoubleOperandOpcode, ImmediateOrLabel, Instruction, NoOperandOpcode,
-        SingleOperandOpcode, TextSegment, Value,
+        BranchOp, ConstExpr, ConstOp, DataWrited, DoubleOperandOpcode, ExprError, ImmediateOrLabel,
+        IndexedAddress, Instruction, LoadOp, NoOperandOpcode, SingleOperandOpcode, StoreOp,
+        TextSegment, Value,
     },
     tokens::{Opcode, PseudoOps, Register},
 };
@@ -18,75 +22,335 @@ use thiserror::Error;
 
 #[derive(Debug, PartialEq, Error)]
 pub enum ParserError {
-    #[error("Unexpected token: {tok}\nContext: line {cur_line}, column {cur_column}")]
+    #[error("Unexpected token: {tok}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
     UnexpectedToken {
         tok: String,
         cur_line: usize,
         cur_column: usize,
+        cur_file: String,
     },
 
     #[error(
-        "Expected token: {expected}, found: {found}\nContext: line {cur_line}, column {cur_column}"
+        "Expected token: {expected}, found: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}"
     )]
     ExpectedToken {
         expected: String,
         found: String,
         cur_line: usize,
         cur_column: usize,
+        cur_file: String,
     },
 
-    #[error("Expected '.byte' or '.word', found: {found}\nContext: line {cur_line}, column {cur_column}")]
+    #[error("Expected a data directive ('.byte', '.word', '.space', '.ascii', '.asciz' or '.align'), found: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
     ExpectedByteOrWordType {
         found: String,
         cur_line: usize,
         cur_column: usize,
+        cur_file: String,
     },
 
     #[error(
-        "Expected to be in section, found: {found}\nContext: line {cur_line}, column {cur_column}"
+        "Expected to be in section, found: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}"
     )]
     ExpectedToBeInSection {
         found: String,
         cur_line: usize,
         cur_column: usize,
+        cur_file: String,
     },
 
-    #[error("Register cannot be used in A Bus, found: {found}\nContext: line {cur_line}, column {cur_column}")]
+    #[error("Register cannot be used in A Bus, found: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
     RegisterCannotBeUsedInABus {
         found: String,
         cur_line: usize,
         cur_column: usize,
+        cur_file: String,
     },
 
-    #[error("Register cannot be used in B Bus, found: {found}\nContext: line {cur_line}, column {cur_column}")]
+    #[error("Register cannot be used in B Bus, found: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
     RegisterCannotBeUsedInBBus {
         found: String,
         cur_line: usize,
         cur_column: usize,
+        cur_file: String,
     },
 
-    #[error("Register cannot be used in C Bus, found: {found}\nContext: line {cur_line}, column {cur_column}")]
+    #[error("Register cannot be used in C Bus, found: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
     RegisterCannotBeUsedInCBus {
         found: String,
         cur_line: usize,
         cur_column: usize,
+        cur_file: String,
     },
 
-    #[error("Operation only accepts an 'Immediate' or a 'Label'")]
-    OnlyAcceptsImmediateOrLabel {
+    #[error("Invalid number literal: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    InvalidNumber {
         found: String,
         cur_line: usize,
         cur_column: usize,
+        cur_file: String,
     },
 
-    #[error("Temp register cannot be used in mul instruction\nContext: line {cur_line}, column {cur_column}")]
-    TempRegisterCannotBeUsedInMul { cur_line: usize, cur_column: usize },
+    #[error("Value {value} does not fit in range {min}..={max}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    ValueOutOfRange {
+        value: i64,
+        min: i64,
+        max: i64,
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error("Invalid constant expression: {reason}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    InvalidExpression {
+        reason: String,
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error("Temp register cannot be used in mul instruction\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    TempRegisterCannotBeUsedInMul {
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error("Register cannot be both the link and the target of jalr, found: {found}\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    JalrLinksIntoTarget {
+        found: String,
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error("t0 counts the shifts and cannot be an operand of a register shift\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    ShiftCounterAsOperand {
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error("Temp registers t0, t1 and t2 cannot be used in byte and halfword accesses\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    TempRegisterCannotBeUsedInSubword {
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error(
+        "Unknown instruction '{found}'\nContext: {cur_file}, line {cur_line}, column {cur_column}"
+    )]
+    UnknownInstruction {
+        found: String,
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+    },
+
+    #[error("Label '{label}' is already defined\nContext: {cur_file}, line {cur_line}, column {cur_column}")]
+    DuplicateLabel {
+        label: String,
+        cur_line: usize,
+        cur_column: usize,
+        cur_file: String,
+        first_line: usize,
+        first_column: usize,
+        first_file: String,
+    },
+}
+
+impl ParserError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParserError::UnexpectedToken { .. } => "E0001",
+            ParserError::ExpectedToken { .. } => "E0002",
+            ParserError::ExpectedByteOrWordType { .. } => "E0003",
+            ParserError::ExpectedToBeInSection { .. } => "E0004",
+            ParserError::RegisterCannotBeUsedInABus { .. } => "E0005",
+            ParserError::RegisterCannotBeUsedInBBus { .. } => "E0006",
+            ParserError::RegisterCannotBeUsedInCBus { .. } => "E0007",
+            ParserError::InvalidNumber { .. } => "E0008",
+            ParserError::ValueOutOfRange { .. } => "E0009",
+            ParserError::InvalidExpression { .. } => "E0010",
+            ParserError::TempRegisterCannotBeUsedInMul { .. } => "E0011",
+            ParserError::DuplicateLabel { .. } => "E0012",
+            ParserError::JalrLinksIntoTarget { .. } => "E0025",
+            ParserError::ShiftCounterAsOperand { .. } => "E0026",
+            ParserError::TempRegisterCannotBeUsedInSubword { .. } => "E0027",
+            ParserError::UnknownInstruction { .. } => "E0028",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            ParserError::UnexpectedToken {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::ExpectedToken {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::ExpectedByteOrWordType {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::ExpectedToBeInSection {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::RegisterCannotBeUsedInABus {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::RegisterCannotBeUsedInBBus {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::RegisterCannotBeUsedInCBus {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::InvalidNumber {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::ValueOutOfRange {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::InvalidExpression {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::TempRegisterCannotBeUsedInMul {
+                cur_line,
+                cur_column,
+                cur_file,
+            }
+            | ParserError::JalrLinksIntoTarget {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::ShiftCounterAsOperand {
+                cur_line,
+                cur_column,
+                cur_file,
+            }
+            | ParserError::TempRegisterCannotBeUsedInSubword {
+                cur_line,
+                cur_column,
+                cur_file,
+            }
+            | ParserError::UnknownInstruction {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            }
+            | ParserError::DuplicateLabel {
+                cur_line,
+                cur_column,
+                cur_file,
+                ..
+            } => Span::new(cur_file, *cur_line, *cur_column),
+        }
+    }
+}
+
+/// Range accepted by the 8-bit immediate field, which the ALU reads zero-extended.
+pub const IMMEDIATE_RANGE: (i64, i64) = (0, 255);
+/// Range of addresses reachable by an immediate `read`/`write`.
+pub const ADDRESS_RANGE: (i64, i64) = (0, 255);
+/// Range of an immediate a branch compares with, which the bus zero-extends.
+pub const COMPARE_RANGE: (i64, i64) = (0, 255);
+/// Range of the offset of an indexed address, added to or subtracted from its register.
+pub const OFFSET_RANGE: (i64, i64) = (-255, 255);
+/// Range of the amount of bits of an immediate shift.
+pub const SHIFT_RANGE: (i64, i64) = (0, 31);
+/// Range of the addresses of the control store.
+pub const CONTROL_STORE_RANGE: (i64, i64) = (0, 511);
+/// Range of a `.byte` value. Negative values are stored as their low byte.
+pub const BYTE_RANGE: (i64, i64) = (-128, 255);
+/// Range of a 32-bit constant, either signed or unsigned.
+pub const WORD_RANGE: (i64, i64) = (i32::MIN as i64, u32::MAX as i64);
+
+/// Parses a decimal, hexadecimal (`0x`), binary (`0b`), octal (`0o`) or character (`'A'`) literal.
+pub fn parse_literal(lit: &str) -> Option<i64> {
+    if let Some(c) = lit.strip_prefix('\'').and_then(|l| l.strip_suffix('\'')) {
+        let mut chars = c.chars();
+        let value = match (chars.next()?, chars.next()) {
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('t')) => '\t',
+            ('\\', Some('r')) => '\r',
+            ('\\', Some('0')) => '\0',
+            ('\\', Some('\\')) => '\\',
+            ('\\', Some('\'')) => '\'',
+            ('\\', _) => return None,
+            (c, None) => c,
+            _ => return None,
+        };
+        if chars.next().is_some() {
+            return None;
+        }
+        return Some(value as i64);
+    }
+
+    let lit = lit.replace('_', "");
+    let (digits, radix) = match lit.get(..2) {
+        Some("0x") | Some("0X") => (&lit[2..], 16),
+        Some("0b") | Some("0B") => (&lit[2..], 2),
+        Some("0o") | Some("0O") => (&lit[2..], 8),
+        _ => (&lit[..], 10),
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+impl ConstOp {
+    fn from_tok(tok: &AsmToken) -> Option<(ConstOp, u8)> {
+        let op = match tok {
+            AsmToken::Pipe => ConstOp::Or,
+            AsmToken::Ampersand => ConstOp::And,
+            AsmToken::Shl => ConstOp::Shl,
+            AsmToken::Shr => ConstOp::Shr,
+            AsmToken::Plus => ConstOp::Add,
+            AsmToken::Minus => ConstOp::Sub,
+            AsmToken::Asterisk => ConstOp::Mul,
+            AsmToken::Slash => ConstOp::Div,
+            _ => return None,
+        };
+        Some((op, op.precedence()))
+    }
 }
 
 #[derive(Debug, Default)]
 pub struct Program {
     pub sections: Vec<Sections>,
     pub errors: Vec<Error>,
+    pub spans: SourceSpans,
 }
 
 pub struct Parser {
@@ -96,6 +360,12 @@ pub struct Parser {
     idx: usize,
     cur_line: usize,
     cur_column: usize,
+    cur_file: Rc<str>,
+    peek_line: usize,
+    peek_column: usize,
+    peek_file: Rc<str>,
+    errors: Vec<Error>,
+    spans: SourceSpans,
 }
 
 impl Parser {
@@ -108,6 +378,12 @@ impl Parser {
             idx: 0,
             cur_line: 0,
             cur_column: 0,
+            cur_file: Rc::from(""),
+            peek_line: 0,
+            peek_column: 0,
+            peek_file: Rc::from(""),
+            errors: Vec::new(),
+            spans: SourceSpans::default(),
         };
 
         p.next_token();
@@ -118,16 +394,84 @@ impl Parser {
 
     fn next_token(&mut self) {
         self.cur_tok = Rc::clone(&self.peek_tok);
-        self.cur_line = self.toks[self.idx].cur_line;
-        self.cur_column = self.toks[self.idx].cur_column;
+        self.cur_line = self.peek_line;
+        self.cur_column = self.peek_column;
+        self.cur_file = Rc::clone(&self.peek_file);
+
+        let peek = &self.toks[self.idx];
+        self.peek_line = peek.cur_line;
+        self.peek_column = peek.cur_column;
+        self.peek_file = Rc::clone(&peek.file);
         if self.idx + 1 >= self.toks.len() {
             self.peek_tok = Rc::new(AsmToken::Eof);
         } else {
-            self.peek_tok = Rc::clone(&self.toks[self.idx].tok);
+            self.peek_tok = Rc::clone(&peek.tok);
             self.idx += 1;
         }
     }
 
+    fn span(&self) -> Span {
+        Span::new(&self.cur_file, self.cur_line, self.cur_column)
+    }
+
+    /// Skips what is left of a statement starting at `start` that failed to parse,
+    /// stopping before the first token on a following line accepted by `is_start`.
+    /// A statement missing its last operand may have been read into the next line,
+    /// so the parser first steps back to the line the statement is on.
+    fn synchronize(&mut self, start: &Span, is_start: fn(&AsmToken) -> bool) {
+        if self.cur_line != start.line || *self.cur_file != *start.file {
+            let end = self.toks[..self.idx]
+                .iter()
+                .rposition(|t| t.cur_line == start.line && *t.file == *start.file);
+            if let Some(end) = end {
+                self.idx = end;
+                self.next_token();
+                self.next_token();
+            }
+        }
+        let (line, file) = (self.cur_line, Rc::clone(&self.cur_file));
+        while *self.peek_tok != AsmToken::Eof
+            && ((self.peek_line == line && self.peek_file == file) || !is_start(&self.peek_tok))
+        {
+            self.next_token();
+        }
+    }
+
+    fn is_statement_start(tok: &AsmToken) -> bool {
+        matches!(
+            tok,
+            AsmToken::Opcode(_) | AsmToken::Label(_) | AsmToken::PseudoOp(_)
+        )
+    }
+
+    fn is_segment_start(tok: &AsmToken) -> bool {
+        matches!(tok, AsmToken::Label(_) | AsmToken::PseudoOp(_))
+    }
+
+    fn is_section_start(tok: &AsmToken) -> bool {
+        matches!(tok, AsmToken::PseudoOp(_))
+    }
+
+    fn define(
+        defs: &mut HashMap<Rc<str>, Span>,
+        label: &Rc<str>,
+        span: Span,
+    ) -> Result<(), ParserError> {
+        if let Some(first) = defs.get(label) {
+            return Err(ParserError::DuplicateLabel {
+                label: label.to_string(),
+                cur_line: span.line,
+                cur_column: span.column,
+                cur_file: span.file,
+                first_line: first.line,
+                first_column: first.column,
+                first_file: first.file.clone(),
+            });
+        }
+        defs.insert(Rc::clone(label), span);
+        Ok(())
+    }
+
     fn expect_peek(&mut self, expected: AsmToken) -> Result<()> {
         let disc_peek = discriminant(&(*self.peek_tok));
         let disc_expected = discriminant(&expected);
@@ -137,8 +481,9 @@ impl Parser {
             bail!(ParserError::ExpectedToken {
                 expected: format!("{:?}", expected),
                 found: format!("{:?}", self.peek_tok),
-                cur_line: self.cur_line,
-                cur_column: self.cur_column
+                cur_line: self.peek_line,
+                cur_column: self.peek_column,
+                cur_file: self.peek_file.to_string()
             })
         }
         Ok(())
@@ -165,26 +510,167 @@ impl Parser {
                     expected: format!("{:?}", AsmToken::Label(Rc::from(""))),
                     found: format!("{:?}", self.cur_tok),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
         Ok(label)
     }
 
-    fn get_number(&mut self) -> Result<Rc<str>> {
-        let number = match *self.cur_tok {
-            AsmToken::Number(ref n) => Rc::clone(n),
-            _ => {
-                bail!(ParserError::ExpectedToken {
-                    expected: format!("{:?}", AsmToken::Number(Rc::from(""))),
-                    found: format!("{:?}", self.cur_tok),
+    /// Parses a constant expression starting at the current token, leaving the
+    /// current token at the end of the expression.
+    fn parse_const_expr(&mut self, min_prec: u8) -> Result<ConstExpr> {
+        let left = self.parse_const_primary()?;
+        self.parse_const_ops(left, min_prec)
+    }
+
+    /// Parses the operators that follow `left`, the first operand of an expression.
+    fn parse_const_ops(&mut self, mut left: ConstExpr, min_prec: u8) -> Result<ConstExpr> {
+        while let Some((op, prec)) = ConstOp::from_tok(&self.peek_tok) {
+            if prec <= min_prec {
+                break;
+            }
+            self.next_token();
+            self.next_token();
+            let right = self.parse_const_expr(prec)?;
+            left = ConstExpr::new_binary(op, left, right);
+        }
+        Ok(left)
+    }
+
+    fn parse_const_primary(&mut self) -> Result<ConstExpr> {
+        let res = match *self.cur_tok {
+            AsmToken::Number(ref n) => match parse_literal(n) {
+                Some(v) => ConstExpr::Number(v),
+                None => bail!(ParserError::InvalidNumber {
+                    found: n.to_string(),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
-                })
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
+                }),
+            },
+            AsmToken::Label(ref l) => {
+                self.spans.symbol_refs.push((Rc::clone(l), self.span()));
+                ConstExpr::Symbol(Rc::clone(l))
             }
+            AsmToken::Minus => {
+                self.next_token();
+                ConstExpr::Neg(Box::new(self.parse_const_primary()?))
+            }
+            AsmToken::LParen => {
+                self.next_token();
+                let expr = self.parse_const_expr(0)?;
+                self.expect_peek(AsmToken::RParen)?;
+                expr
+            }
+            _ => bail!(ParserError::ExpectedToken {
+                expected: format!("{:?}", "Constant expression"),
+                found: format!("{:?}", self.cur_tok),
+                cur_line: self.cur_line,
+                cur_column: self.cur_column,
+                cur_file: self.cur_file.to_string()
+            }),
         };
-        Ok(number)
+        Ok(res)
+    }
+
+    /// Folds an expression without symbols, checking that it fits in `min..=max`.
+    /// Returns `None` when the expression depends on symbols. Errors point at `at`,
+    /// the start of the expression.
+    fn fold_const_expr(
+        &self,
+        expr: &ConstExpr,
+        (min, max): (i64, i64),
+        at: Span,
+    ) -> Result<Option<i64>> {
+        let value = match expr.eval(&|_| None) {
+            Ok(v) => v,
+            Err(ExprError::UndefinedSymbol { .. }) => return Ok(None),
+            Err(e) => bail!(ParserError::InvalidExpression {
+                reason: e.to_string(),
+                cur_line: at.line,
+                cur_column: at.column,
+                cur_file: at.file
+            }),
+        };
+        if value < min || value > max {
+            bail!(ParserError::ValueOutOfRange {
+                value,
+                min,
+                max,
+                cur_line: at.line,
+                cur_column: at.column,
+                cur_file: at.file
+            })
+        }
+        Ok(Some(value))
+    }
+
+    fn get_immediate_value(&mut self) -> Result<Value> {
+        self.get_value_in(IMMEDIATE_RANGE)
+    }
+
+    fn get_compare_value(&mut self) -> Result<Value> {
+        self.get_value_in(COMPARE_RANGE)
+    }
+
+    fn get_shift_value(&mut self) -> Result<Value> {
+        self.get_value_in(SHIFT_RANGE)
+    }
+
+    /// Parses a constant expression that goes in the immediate field, checking it
+    /// against `range` when it has no symbols.
+    fn get_value_in(&mut self, range: (i64, i64)) -> Result<Value> {
+        let at = self.span();
+        let expr = self.parse_const_expr(0)?;
+        let res = match self.fold_const_expr(&expr, range, at)? {
+            Some(v) => Value::Immediate(v as u8),
+            None => match expr {
+                ConstExpr::Symbol(l) => Value::Label(l),
+                expr => Value::Expr(expr),
+            },
+        };
+        Ok(res)
+    }
+
+    fn get_address(&mut self) -> Result<ImmediateOrLabel> {
+        let at = self.span();
+        let expr = self.parse_const_expr(0)?;
+        let res = match self.fold_const_expr(&expr, ADDRESS_RANGE, at)? {
+            Some(v) => ImmediateOrLabel::Immediate(v as u8),
+            None => match expr {
+                ConstExpr::Symbol(l) => ImmediateOrLabel::Label(l),
+                expr => ImmediateOrLabel::Expr(expr),
+            },
+        };
+        Ok(res)
+    }
+
+    /// Parses `[rs]`, `[rs + offset]` or `[rs - offset]`. The base goes through the
+    /// A bus to add the offset and through the B bus to subtract it.
+    fn get_indexed_address(&mut self) -> Result<IndexedAddress> {
+        self.next_token();
+        let base = self.get_register()?;
+        let mut offset = ConstExpr::Number(0);
+        if self.peek_token_is(AsmToken::Plus) || self.peek_token_is(AsmToken::Minus) {
+            let at = Span::new(&self.peek_file, self.peek_line, self.peek_column);
+            // The offset is parsed as an expression starting at 0
+            let expr = self.parse_const_ops(ConstExpr::Number(0), 0)?;
+            offset = match self.fold_const_expr(&expr, OFFSET_RANGE, at)? {
+                Some(v) => ConstExpr::Number(v),
+                None => expr,
+            };
+        }
+        self.expect_peek(AsmToken::RBracket)?;
+
+        let base = match offset {
+            ConstExpr::Number(v) if v < 0 => self.guard_b_bus(base)?,
+            ConstExpr::Number(_) => self.guard_a_bus(base)?,
+            // The sign of a symbolic offset is only known by the evaluator
+            _ => self.guard_b_bus(self.guard_a_bus(base)?)?,
+        };
+        Ok(IndexedAddress { base, offset })
     }
 
     fn get_register(&self) -> Result<Rc<Register>> {
@@ -195,7 +681,8 @@ impl Parser {
                     expected: format!("{:?}", "Register"),
                     found: format!("{:?}", self.cur_tok),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
@@ -205,12 +692,21 @@ impl Parser {
     fn get_opcode(&mut self) -> Result<Rc<Opcode>> {
         let instr = match *self.cur_tok {
             AsmToken::Opcode(ref o) => Rc::clone(o),
+            AsmToken::Label(ref l) => {
+                bail!(ParserError::UnknownInstruction {
+                    found: l.to_string(),
+                    cur_line: self.cur_line,
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
+                })
+            }
             _ => {
                 bail!(ParserError::ExpectedToken {
                     expected: format!("{:?}", "Opcode"),
                     found: format!("{:?}", self.cur_tok),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
@@ -219,16 +715,23 @@ impl Parser {
 
     fn op_to_branch_op(&mut self, op: Rc<Opcode>) -> Result<BranchOp> {
         let res = match *op {
-            Opcode::Beq => BranchOp::Beq,
-            Opcode::Bne => BranchOp::Bne,
-            Opcode::Blt => BranchOp::Blt,
-            Opcode::Bgt => BranchOp::Bgt,
+            Opcode::Beq | Opcode::Beqz => BranchOp::Beq,
+            Opcode::Bne | Opcode::Bnez => BranchOp::Bne,
+            Opcode::Blt | Opcode::Bltz => BranchOp::Blt,
+            Opcode::Bgt | Opcode::Bgtz => BranchOp::Bgt,
+            Opcode::Bge | Opcode::Bgez => BranchOp::Bge,
+            Opcode::Ble | Opcode::Blez => BranchOp::Ble,
+            Opcode::Bltu => BranchOp::Bltu,
+            Opcode::Bgtu => BranchOp::Bgtu,
+            Opcode::Bgeu => BranchOp::Bgeu,
+            Opcode::Bleu => BranchOp::Bleu,
             _ => {
                 bail!(ParserError::ExpectedToken {
                     expected: format!("{:?}", "BranchOp"),
                     found: format!("{:?}", op),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
@@ -240,12 +743,14 @@ impl Parser {
         let res = match *op {
             Opcode::Halt => NoOperandOpcode::Halt,
             Opcode::Nop => NoOperandOpcode::Nop,
+            Opcode::Ret => NoOperandOpcode::Ret,
             _ => {
                 bail!(ParserError::ExpectedToken {
                     expected: format!("{:?}", "NoOperandOpcode"),
                     found: format!("{:?}", op),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
@@ -259,13 +764,16 @@ impl Parser {
             Opcode::Sll => SingleOperandOpcode::Sll,
             Opcode::Sra => SingleOperandOpcode::Sra,
             Opcode::Sla => SingleOperandOpcode::Sla,
+            Opcode::Srl => SingleOperandOpcode::Srl,
+            Opcode::Rol => SingleOperandOpcode::Rol,
             Opcode::Mov => SingleOperandOpcode::Mov,
             _ => {
                 bail!(ParserError::ExpectedToken {
                     expected: format!("{:?}", "SingleOperandOpcode"),
                     found: format!("{:?}", op),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
@@ -291,12 +799,19 @@ impl Parser {
             Opcode::Xori => DoubleOperandOpcode::Xori,
             Opcode::Divi => DoubleOperandOpcode::Divi,
             Opcode::Modi => DoubleOperandOpcode::Modi,
+            Opcode::Slli => DoubleOperandOpcode::Slli,
+            Opcode::Srli => DoubleOperandOpcode::Srli,
+            Opcode::Srai => DoubleOperandOpcode::Srai,
+            Opcode::Sllv => DoubleOperandOpcode::Sllv,
+            Opcode::Srlv => DoubleOperandOpcode::Srlv,
+            Opcode::Srav => DoubleOperandOpcode::Srav,
             _ => {
                 bail!(ParserError::ExpectedToken {
                     expected: format!("{:?}", "DoubleOperandOpcode"),
                     found: format!("{:?}", op),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
@@ -311,20 +826,35 @@ impl Parser {
                     expected: format!("{:?}", "PseudoOp"),
                     found: format!("{:?}", self.cur_tok),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
         Ok(pseudo_op)
     }
 
+    /// Whether a register can go on the A, B and C buses, by the rules the parser
+    /// enforces on operands.
+    pub(crate) fn bus_eligibility(reg: &Register) -> [bool; 3] {
+        let eof = TokWithCtx::new(AsmToken::Eof, 0, 0, Rc::from(""));
+        let p = Parser::new(Rc::from([eof]));
+        let reg = Rc::new(reg.clone());
+        [
+            p.guard_a_bus(Rc::clone(&reg)).is_ok(),
+            p.guard_b_bus(Rc::clone(&reg)).is_ok(),
+            p.guard_c_bus(reg).is_ok(),
+        ]
+    }
+
     fn guard_a_bus(&self, reg: Rc<Register>) -> Result<Rc<Register>> {
         match *reg {
             Register::Mar => {
                 bail!(ParserError::RegisterCannotBeUsedInABus {
                     found: format!("{:?}", reg),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
             _ => Ok(reg),
@@ -342,7 +872,8 @@ impl Parser {
                 bail!(ParserError::RegisterCannotBeUsedInBBus {
                     found: format!("{:?}", reg),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
             _ => Ok(reg),
@@ -351,11 +882,59 @@ impl Parser {
 
     fn guard_c_bus(&self, reg: Rc<Register>) -> Result<Rc<Register>> {
         match *reg {
-            Register::Cpp | Register::Mbr | Register::Mbr2 | Register::Mbru | Register::Mbr2u => {
+            Register::Mbr | Register::Mbr2 | Register::Mbru | Register::Mbr2u => {
                 bail!(ParserError::RegisterCannotBeUsedInCBus {
                     found: format!("{:?}", reg),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
+                })
+            }
+            _ => Ok(reg),
+        }
+    }
+
+    /// Checks the registers of a branch can go on every bus its comparisons put
+    /// them on.
+    fn guard_branch_operands(&self, op: BranchOp, rs1: &Rc<Register>, rs2: &Value) -> Result<()> {
+        let reg = |o: Operand| match o {
+            Operand::Rs1 => Some(rs1),
+            Operand::Rs2 => match rs2 {
+                Value::Reg(r) => Some(r),
+                _ => None,
+            },
+        };
+        for step in AsmEvaluator::branch_steps(op) {
+            if let Some(r) = step.a.and_then(reg) {
+                self.guard_a_bus(Rc::clone(r))?;
+            }
+            if let Some(r) = step.b.and_then(reg) {
+                self.guard_b_bus(Rc::clone(r))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn guard_shift_counter(&self, reg: Rc<Register>) -> Result<Rc<Register>> {
+        match *reg {
+            Register::T0 => {
+                bail!(ParserError::ShiftCounterAsOperand {
+                    cur_line: self.cur_line,
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
+                })
+            }
+            _ => Ok(reg),
+        }
+    }
+
+    fn guard_subword_temps(&self, reg: Rc<Register>) -> Result<Rc<Register>> {
+        match *reg {
+            Register::T0 | Register::T1 | Register::T2 => {
+                bail!(ParserError::TempRegisterCannotBeUsedInSubword {
+                    cur_line: self.cur_line,
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
             _ => Ok(reg),
@@ -367,45 +946,150 @@ impl Parser {
             Register::T0 | Register::T1 | Register::T2 | Register::T3 => {
                 bail!(ParserError::TempRegisterCannotBeUsedInMul {
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
             _ => Ok(reg),
         }
     }
 
-    fn parse_data_to_write(&mut self) -> Result<DataWrited> {
+    fn parse_equ(&mut self) -> Result<DataWrited> {
+        self.expect_peek(AsmToken::Label(Rc::from("")))?;
         let label = self.get_label()?;
-        self.expect_peek(AsmToken::Colon)?;
+        let span = self.span();
+        Self::define(&mut self.spans.symbols, &label, span)?;
+        self.expect_peek(AsmToken::Comma)?;
         self.next_token();
+        let expr = self.parse_const_expr(0)?;
+        Ok(Sections::new_data_writed(DataKind::Equ(expr), label))
+    }
+
+    /// Parses a comma separated list of constant expressions, checking the
+    /// ones without symbols against `range`.
+    fn parse_const_list(&mut self, range: (i64, i64)) -> Result<Vec<ConstExpr>> {
+        let mut list = Vec::new();
+        loop {
+            let at = self.span();
+            let expr = self.parse_const_expr(0)?;
+            list.push(match self.fold_const_expr(&expr, range, at)? {
+                Some(v) => ConstExpr::Number(v),
+                None => expr,
+            });
+            if !self.peek_token_is(AsmToken::Comma) {
+                break;
+            }
+            self.next_token();
+            self.next_token();
+        }
+        Ok(list)
+    }
+
+    fn get_string(&mut self) -> Result<Rc<str>> {
+        self.expect_peek(AsmToken::Str(Rc::from("")))?;
+        match *self.cur_tok {
+            AsmToken::Str(ref s) => Ok(Rc::clone(s)),
+            _ => unreachable!("expect_peek checked the token"),
+        }
+    }
+
+    fn peek_is_data(&self) -> bool {
+        match *self.peek_tok {
+            AsmToken::Label(_) => true,
+            AsmToken::PseudoOp(ref p) => matches!(
+                **p,
+                PseudoOps::Equ
+                    | PseudoOps::Byte
+                    | PseudoOps::Word
+                    | PseudoOps::Space
+                    | PseudoOps::Ascii
+                    | PseudoOps::Asciz
+                    | PseudoOps::Align
+            ),
+            _ => false,
+        }
+    }
+
+    fn parse_data_to_write(&mut self) -> Result<DataWrited> {
+        if matches!(*self.cur_tok, AsmToken::PseudoOp(ref p) if **p == PseudoOps::Equ) {
+            return self.parse_equ();
+        }
+        let label = match *self.cur_tok {
+            AsmToken::Label(_) => {
+                let label = self.get_label()?;
+                let span = self.span();
+                Self::define(&mut self.spans.symbols, &label, span)?;
+                self.expect_peek(AsmToken::Colon)?;
+                self.next_token();
+                Some(label)
+            }
+            _ => None,
+        };
         let op = self.get_pseudo_op()?;
-        let res = match *op {
+        let kind = match *op {
             PseudoOps::Byte => {
-                self.expect_peek(AsmToken::Number(Rc::from("")))?;
-                let number_str = self.get_number()?;
-                let number = number_str.parse::<u8>()?;
-                Sections::new_data_writed(DataKind::Byte(number), Rc::clone(&label))
+                self.next_token();
+                let mut list = self.parse_const_list(BYTE_RANGE)?;
+                match list.pop() {
+                    Some(ConstExpr::Number(v)) if list.is_empty() => DataKind::Byte(v as u8),
+                    Some(expr) if list.is_empty() => DataKind::ByteExpr(expr),
+                    last => {
+                        list.extend(last);
+                        DataKind::ByteList(list)
+                    }
+                }
             }
             PseudoOps::Word => {
-                self.expect_peek(AsmToken::Number(Rc::from("")))?;
-                let number_str = self.get_number()?;
-                let number = number_str.parse::<i32>()?;
-                Sections::new_data_writed(DataKind::Word(number), label)
+                self.next_token();
+                let mut list = self.parse_const_list(WORD_RANGE)?;
+                match list.pop() {
+                    Some(ConstExpr::Number(v)) if list.is_empty() => DataKind::Word(v as i32),
+                    Some(expr) if list.is_empty() => DataKind::WordExpr(expr),
+                    last => {
+                        list.extend(last);
+                        DataKind::WordList(list)
+                    }
+                }
+            }
+            PseudoOps::Space => {
+                self.next_token();
+                DataKind::Space(self.parse_const_expr(0)?)
+            }
+            PseudoOps::Align => {
+                self.next_token();
+                DataKind::Align(self.parse_const_expr(0)?)
             }
+            PseudoOps::Ascii => DataKind::Ascii(self.get_string()?),
+            PseudoOps::Asciz => DataKind::Asciz(self.get_string()?),
             _ => bail!(ParserError::ExpectedByteOrWordType {
                 found: format!("{:?}", self.cur_tok),
                 cur_line: self.cur_line,
-                cur_column: self.cur_column
+                cur_column: self.cur_column,
+                cur_file: self.cur_file.to_string()
             }),
         };
+        let res = match label {
+            Some(label) => Sections::new_data_writed(kind, label),
+            None => Sections::new_unlabeled_data(kind),
+        };
         Ok(res)
     }
 
     fn parse_data_directive(&mut self) -> Result<Sections> {
         let mut data = Vec::new();
-        while discriminant(&(*self.peek_tok)) == discriminant(&AsmToken::Label(Rc::from(""))) {
+        while self.peek_is_data() {
             self.next_token();
-            data.push(self.parse_data_to_write()?);
+            let span = self.span();
+            match self.parse_data_to_write() {
+                Ok(d) => {
+                    self.spans.data.push(span);
+                    data.push(d);
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize(&span, Self::is_segment_start);
+                }
+            }
         }
 
         Ok(Sections::new_data_section(data))
@@ -432,7 +1116,70 @@ impl Parser {
         Ok((dest_regs, rs1))
     }
 
+    /// Parses `.micro <word>` or `.micro <addr>, <word>`.
+    fn parse_micro(&mut self) -> Result<Instruction> {
+        self.next_token();
+        let first = self.get_word_literal()?;
+        if !self.peek_token_is(AsmToken::Comma) {
+            return Ok(Instruction::new_micro_instruction(None, first));
+        }
+        if first > CONTROL_STORE_RANGE.1 as u64 {
+            bail!(ParserError::ValueOutOfRange {
+                value: first as i64,
+                min: CONTROL_STORE_RANGE.0,
+                max: CONTROL_STORE_RANGE.1,
+                cur_line: self.cur_line,
+                cur_column: self.cur_column,
+                cur_file: self.cur_file.to_string()
+            })
+        }
+        self.next_token();
+        self.next_token();
+        let word = self.get_word_literal()?;
+        Ok(Instruction::new_micro_instruction(Some(first as u16), word))
+    }
+
+    /// A number literal taking the full 64 bits of a microinstruction.
+    fn get_word_literal(&mut self) -> Result<u64> {
+        let AsmToken::Number(ref n) = *self.cur_tok else {
+            bail!(ParserError::ExpectedToken {
+                expected: format!("{:?}", AsmToken::Number(Rc::from(""))),
+                found: format!("{:?}", self.cur_tok),
+                cur_line: self.cur_line,
+                cur_column: self.cur_column,
+                cur_file: self.cur_file.to_string()
+            })
+        };
+        let lit = n.replace('_', "");
+        let (digits, radix) = match lit.get(..2) {
+            Some("0x") | Some("0X") => (&lit[2..], 16),
+            Some("0b") | Some("0B") => (&lit[2..], 2),
+            Some("0o") | Some("0O") => (&lit[2..], 8),
+            _ => (&lit[..], 10),
+        };
+        match u64::from_str_radix(digits, radix) {
+            Ok(v) => Ok(v),
+            Err(_) => bail!(ParserError::InvalidNumber {
+                found: n.to_string(),
+                cur_line: self.cur_line,
+                cur_column: self.cur_column,
+                cur_file: self.cur_file.to_string()
+            }),
+        }
+    }
+
+    fn is_instruction_start(tok: &AsmToken) -> bool {
+        match tok {
+            AsmToken::Opcode(_) => true,
+            AsmToken::PseudoOp(op) => **op == PseudoOps::Micro,
+            _ => false,
+        }
+    }
+
     fn get_instruction(&mut self) -> Result<Instruction> {
+        if matches!(*self.cur_tok, AsmToken::PseudoOp(ref p) if **p == PseudoOps::Micro) {
+            return self.parse_micro();
+        }
         let op = self.get_opcode()?;
 
         let res = match *op {
@@ -496,16 +1243,23 @@ impl Parser {
                 let (dest_regs, rs1) = self.parse_instruction_til_rs1()?;
                 self.expect_peek(AsmToken::Comma)?;
                 self.next_token();
-                let rs2 = match *self.cur_tok {
-                    AsmToken::Label(ref l) => Value::Label(Rc::clone(l)),
-                    AsmToken::Number(ref n) => Value::Immediate(n.parse::<u8>()?),
-                    _ => bail!(ParserError::ExpectedToken {
-                        expected: format!("{:?}", "Immediate or Label"),
-                        found: format!("{:?}", self.cur_tok),
-                        cur_line: self.cur_line,
-                        cur_column: self.cur_column
-                    }),
-                };
+                let rs2 = self.get_immediate_value()?;
+                Instruction::new_double_operand_instruction(
+                    self.op_to_double_op(op)?,
+                    dest_regs,
+                    rs1,
+                    rs2,
+                )
+            }
+            // Shifts
+            Opcode::Slli | Opcode::Srli | Opcode::Srai => {
+                self.next_token();
+                let (dest_regs, rs1) = self.parse_instruction_til_rs1()?;
+                // The first destination accumulates the shifts through the A bus
+                self.guard_a_bus(Rc::clone(&dest_regs[0]))?;
+                self.expect_peek(AsmToken::Comma)?;
+                self.next_token();
+                let rs2 = self.get_shift_value()?;
                 Instruction::new_double_operand_instruction(
                     self.op_to_double_op(op)?,
                     dest_regs,
@@ -513,29 +1267,59 @@ impl Parser {
                     rs2,
                 )
             }
+            Opcode::Sllv | Opcode::Srlv | Opcode::Srav => {
+                self.next_token();
+                let (dest_regs, rs1) = self.parse_instruction_til_rs1()?;
+                self.guard_a_bus(Rc::clone(&dest_regs[0]))?;
+                for rd in &dest_regs {
+                    self.guard_shift_counter(Rc::clone(rd))?;
+                }
+                let rs1 = self.guard_shift_counter(rs1)?;
+                self.expect_peek(AsmToken::Comma)?;
+                self.next_token();
+                let rs2 = self.guard_shift_counter(self.guard_a_bus(self.get_register()?)?)?;
+                Instruction::new_double_operand_instruction(
+                    self.op_to_double_op(op)?,
+                    dest_regs,
+                    rs1,
+                    Value::Reg(rs2),
+                )
+            }
             Opcode::Lui => {
                 self.next_token();
                 let dest_regs = self.get_dest_regs()?;
                 self.expect_peek(AsmToken::Assign)?;
                 self.next_token();
-                let rs1 = match *self.cur_tok {
-                    AsmToken::Label(ref l) => Value::Label(Rc::clone(l)),
-                    AsmToken::Number(ref n) => Value::Immediate(n.parse::<u8>()?),
-                    _ => bail!(ParserError::ExpectedToken {
-                        expected: format!("{:?}", "Immediate or Label"),
-                        found: format!("{:?}", self.cur_tok),
-                        cur_line: self.cur_line,
-                        cur_column: self.cur_column
-                    }),
-                };
+                let rs1 = self.get_immediate_value()?;
                 Instruction::new_single_operand_instruction(
                     self.op_to_single_op(op)?,
                     dest_regs,
                     rs1,
                 )
             }
+            Opcode::Li => {
+                self.next_token();
+                let dest_regs = self.get_dest_regs()?;
+                // The first destination accumulates the constant through the A bus
+                self.guard_a_bus(Rc::clone(&dest_regs[0]))?;
+                self.expect_peek(AsmToken::Assign)?;
+                self.next_token();
+                let at = self.span();
+                let expr = self.parse_const_expr(0)?;
+                let value = match self.fold_const_expr(&expr, WORD_RANGE, at)? {
+                    Some(v) => ConstExpr::Number(v),
+                    None => expr,
+                };
+                Instruction::new_li_instruction(value, dest_regs)
+            }
             // Single Operand Instructions
-            Opcode::Not | Opcode::Sll | Opcode::Sra | Opcode::Sla | Opcode::Mov => {
+            Opcode::Not
+            | Opcode::Sll
+            | Opcode::Sra
+            | Opcode::Sla
+            | Opcode::Srl
+            | Opcode::Rol
+            | Opcode::Mov => {
                 self.next_token();
                 let (dest_regs, rs1) = self.parse_instruction_til_rs1()?;
                 let rs1 = Value::Reg(rs1);
@@ -546,57 +1330,153 @@ impl Parser {
                 )
             }
             // Branch motherfucker
-            Opcode::Beq | Opcode::Bne | Opcode::Blt | Opcode::Bgt => {
+            Opcode::Beq
+            | Opcode::Bne
+            | Opcode::Blt
+            | Opcode::Bgt
+            | Opcode::Bge
+            | Opcode::Ble
+            | Opcode::Bltu
+            | Opcode::Bgtu
+            | Opcode::Bgeu
+            | Opcode::Bleu => {
+                let opcode = self.op_to_branch_op(op)?;
                 self.next_token();
-                let rs1 = self.guard_a_bus(self.get_register()?)?;
+                let rs1 = self.get_register()?;
                 self.expect_peek(AsmToken::Comma)?;
                 self.next_token();
-                let rs2 = self.guard_b_bus(self.get_register()?)?;
+                let rs2 = match *self.cur_tok {
+                    AsmToken::Reg(_) => Value::Reg(self.get_register()?),
+                    _ => self.get_compare_value()?,
+                };
+                self.guard_branch_operands(opcode, &rs1, &rs2)?;
                 self.expect_peek(AsmToken::Comma)?;
                 self.next_token();
                 let label = self.get_label()?;
-                Instruction::new_branch_instruction(self.op_to_branch_op(op)?, rs1, rs2, label)
+                self.spans.label_refs.push((Rc::clone(&label), self.span()));
+                Instruction::new_branch_instruction(opcode, rs1, rs2, label)
+            }
+            // Compare with zero
+            Opcode::Beqz
+            | Opcode::Bnez
+            | Opcode::Bltz
+            | Opcode::Bgtz
+            | Opcode::Bgez
+            | Opcode::Blez => {
+                let opcode = self.op_to_branch_op(op)?;
+                self.next_token();
+                let rs1 = self.get_register()?;
+                let rs2 = Value::Immediate(0);
+                self.guard_branch_operands(opcode, &rs1, &rs2)?;
+                self.expect_peek(AsmToken::Comma)?;
+                self.next_token();
+                let label = self.get_label()?;
+                self.spans.label_refs.push((Rc::clone(&label), self.span()));
+                Instruction::new_branch_instruction(opcode, rs1, rs2, label)
             }
             // Jal
             Opcode::Jal => {
                 self.next_token();
                 let label = self.get_label()?;
+                self.spans.label_refs.push((Rc::clone(&label), self.span()));
                 Instruction::new_jal_instruction(label)
             }
-            Opcode::Read => {
+            Opcode::Jalr => {
+                self.next_token();
+                let rd = self.guard_c_bus(self.get_register()?)?;
+                self.expect_peek(AsmToken::Comma)?;
+                self.next_token();
+                let rs = self.guard_a_bus(self.get_register()?)?;
+                if rd == rs {
+                    bail!(ParserError::JalrLinksIntoTarget {
+                        found: format!("{:?}", rs),
+                        cur_line: self.cur_line,
+                        cur_column: self.cur_column,
+                        cur_file: self.cur_file.to_string()
+                    })
+                }
+                Instruction::new_jalr_instruction(rd, rs)
+            }
+            Opcode::Call => {
+                self.next_token();
+                let label = self.get_label()?;
+                self.spans.label_refs.push((Rc::clone(&label), self.span()));
+                Instruction::new_call_instruction(label)
+            }
+            Opcode::Read => {
+                self.next_token();
+                let rds = self.get_dest_regs()?;
+                self.expect_peek(AsmToken::Assign)?;
+                self.next_token();
+                if *self.cur_tok == AsmToken::LBracket {
+                    let addr = self.get_indexed_address()?;
+                    Instruction::new_read_indexed_instruction(addr, rds)
+                } else {
+                    let addr = self.get_address()?;
+                    Instruction::new_read_instruction(addr, rds)
+                }
+            }
+            Opcode::Write => {
+                self.next_token();
+                if *self.cur_tok == AsmToken::LBracket {
+                    let addr = self.get_indexed_address()?;
+                    self.expect_peek(AsmToken::Assign)?;
+                    self.next_token();
+                    let rs = self.guard_a_bus(self.get_register()?)?;
+                    Instruction::new_write_indexed_instruction(addr, rs)
+                } else {
+                    let addr = self.get_address()?;
+                    self.expect_peek(AsmToken::Assign)?;
+                    self.next_token();
+                    let rd = self.guard_a_bus(self.get_register()?)?;
+                    Instruction::new_write_instruction(addr, rd)
+                }
+            }
+            Opcode::Readb | Opcode::Readbu | Opcode::Readh | Opcode::Readhu => {
+                let load = match *op {
+                    Opcode::Readb => LoadOp::Readb,
+                    Opcode::Readbu => LoadOp::Readbu,
+                    Opcode::Readh => LoadOp::Readh,
+                    _ => LoadOp::Readhu,
+                };
                 self.next_token();
                 let rds = self.get_dest_regs()?;
+                // The first destination accumulates the shifts through the A bus
+                self.guard_a_bus(Rc::clone(&rds[0]))?;
+                for rd in &rds {
+                    self.guard_subword_temps(Rc::clone(rd))?;
+                }
                 self.expect_peek(AsmToken::Assign)?;
-                self.next_token();
-                let addr = match *self.cur_tok {
-                    AsmToken::Number(ref n) => ImmediateOrLabel::Immediate(Rc::clone(n).parse()?),
-                    AsmToken::Label(ref l) => ImmediateOrLabel::Label(Rc::clone(l)),
-                    _ => bail!(ParserError::OnlyAcceptsImmediateOrLabel {
-                        found: format!("{:?}", self.cur_tok),
-                        cur_line: self.cur_line,
-                        cur_column: self.cur_column
-                    }),
-                };
-                Instruction::new_read_instruction(addr, rds)
+                self.expect_peek(AsmToken::LBracket)?;
+                let addr = self.get_indexed_address()?;
+                self.guard_subword_temps(Rc::clone(&addr.base))?;
+                Instruction::new_read_subword_instruction(load, addr, rds)
             }
-            Opcode::Write => {
-                self.next_token();
-                let addr = match *self.cur_tok {
-                    AsmToken::Number(ref n) => ImmediateOrLabel::Immediate(Rc::clone(n).parse()?),
-                    AsmToken::Label(ref l) => ImmediateOrLabel::Label(Rc::clone(l)),
-                    _ => bail!(ParserError::OnlyAcceptsImmediateOrLabel {
-                        found: format!("{:?}", self.cur_tok),
-                        cur_line: self.cur_line,
-                        cur_column: self.cur_column
-                    }),
+            Opcode::Writeb | Opcode::Writeh => {
+                let store = match *op {
+                    Opcode::Writeb => StoreOp::Writeb,
+                    _ => StoreOp::Writeh,
                 };
+                self.expect_peek(AsmToken::LBracket)?;
+                let addr = self.get_indexed_address()?;
+                self.guard_subword_temps(Rc::clone(&addr.base))?;
                 self.expect_peek(AsmToken::Assign)?;
                 self.next_token();
-                let rd = self.guard_a_bus(self.get_register()?)?;
-                Instruction::new_write_instruction(addr, rd)
+                let rs = self.guard_subword_temps(self.guard_a_bus(self.get_register()?)?)?;
+                Instruction::new_write_subword_instruction(store, addr, rs)
+            }
+            Opcode::Push => {
+                self.next_token();
+                let rs = self.guard_a_bus(self.get_register()?)?;
+                Instruction::new_push_instruction(rs)
+            }
+            Opcode::Pop => {
+                self.next_token();
+                let rds = self.get_dest_regs()?;
+                Instruction::new_pop_instruction(rds)
             }
             // No Operand Instructions
-            Opcode::Halt | Opcode::Nop => {
+            Opcode::Halt | Opcode::Nop | Opcode::Ret => {
                 Instruction::new_no_operand_instruction(self.op_to_no_op(op)?)
             }
         };
@@ -604,40 +1484,74 @@ impl Parser {
         Ok(res)
     }
 
+    /// Whether the next token is a name used where an instruction goes, which is
+    /// a mistyped mnemonic rather than a label as no `:` follows it.
+    fn peek_is_unknown_instruction(&self) -> bool {
+        matches!(*self.peek_tok, AsmToken::Label(_))
+            && self
+                .toks
+                .get(self.idx)
+                .is_none_or(|t| *t.tok != AsmToken::Colon)
+    }
+
     fn parse_labeled_section(&mut self) -> Result<TextSegment> {
         let label = self.get_label()?;
+        let span = self.span();
+        Self::define(&mut self.spans.labels, &label, span)?;
         self.expect_peek(AsmToken::Colon)?;
+        Ok(TextSegment::new_labeled_section(
+            label,
+            self.parse_instructions(),
+        ))
+    }
+
+    /// Parses the instructions up to the next label or section, recording the
+    /// errors of the ones that are malformed.
+    fn parse_instructions(&mut self) -> Vec<Instruction> {
         let mut ins = Vec::new();
 
-        while discriminant(&(*self.peek_tok))
-            == discriminant(&AsmToken::Opcode(Rc::new(Opcode::Add)))
-        {
+        while Self::is_instruction_start(&self.peek_tok) || self.peek_is_unknown_instruction() {
             self.next_token();
-            ins.push(self.get_instruction()?);
+            let span = self.span();
+            match self.get_instruction() {
+                Ok(i) => {
+                    self.spans.instructions.push(span);
+                    ins.push(i);
+                }
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize(&span, Self::is_statement_start);
+                }
+            }
         }
-        self.next_token();
 
-        Ok(TextSegment::new_labeled_section(label, ins))
+        ins
     }
 
     fn parse_text_directive(&mut self) -> Result<Sections> {
         let mut data = Vec::new();
-        self.next_token();
         loop {
-            match *self.cur_tok {
-                AsmToken::PseudoOp(ref v) => {
-                    let v = Rc::clone(v);
-                    match *v {
-                        PseudoOps::Global => {
-                            self.next_token();
-                            data.push(TextSegment::new_global_section(self.get_label()?));
-                            self.next_token();
+            match *self.peek_tok {
+                AsmToken::PseudoOp(ref v) if **v == PseudoOps::Global => {
+                    self.next_token();
+                    self.next_token();
+                    data.push(TextSegment::new_global_section(self.get_label()?));
+                }
+                AsmToken::Label(_) if !self.peek_is_unknown_instruction() => {
+                    self.next_token();
+                    let span = self.span();
+                    match self.parse_labeled_section() {
+                        Ok(seg) => data.push(seg),
+                        Err(e) => {
+                            self.errors.push(e);
+                            self.synchronize(&span, Self::is_statement_start);
+                            // The instructions of a segment whose label is wrong are still checked
+                            self.parse_instructions();
                         }
-                        _ => break,
                     }
                 }
                 AsmToken::Label(_) => {
-                    data.push(self.parse_labeled_section()?);
+                    self.parse_instructions();
                 }
                 _ => break,
             }
@@ -651,17 +1565,18 @@ impl Parser {
         let res = match *op {
             PseudoOps::Data => self.parse_data_directive()?,
             PseudoOps::Text => self.parse_text_directive()?,
+            PseudoOps::Equ => {
+                let span = self.span();
+                let equ = self.parse_equ()?;
+                self.spans.data.push(span);
+                Sections::new_data_section(vec![equ])
+            }
             _ => {
-                let tok = Rc::clone(&op);
-                while discriminant(&(*self.cur_tok))
-                    != discriminant(&AsmToken::PseudoOp(Rc::from(PseudoOps::Text)))
-                {
-                    self.next_token();
-                }
                 bail!(ParserError::ExpectedToBeInSection {
-                    found: format!("{:?}", tok),
+                    found: format!("{:?}", op),
                     cur_line: self.cur_line,
-                    cur_column: self.cur_column
+                    cur_column: self.cur_column,
+                    cur_file: self.cur_file.to_string()
                 })
             }
         };
@@ -674,7 +1589,8 @@ impl Parser {
             AsmToken::Illegal => bail!(ParserError::UnexpectedToken {
                 tok: format!("{:?}", self.cur_tok),
                 cur_line: self.cur_line,
-                cur_column: self.cur_column
+                cur_column: self.cur_column,
+                cur_file: self.cur_file.to_string()
             }),
             _ => self.parse_pseudo_ops()?,
         };
@@ -685,12 +1601,18 @@ impl Parser {
     pub fn get_deez_program(&mut self) -> Program {
         let mut program = Program::default();
         while *self.cur_tok != AsmToken::Eof {
+            let span = self.span();
             match self.parse_shit() {
                 Ok(sec) => program.sections.push(sec),
-                Err(e) => program.errors.push(e),
+                Err(e) => {
+                    self.errors.push(e);
+                    self.synchronize(&span, Self::is_section_start);
+                }
             };
             self.next_token();
         }
+        program.errors = std::mem::take(&mut self.errors);
+        program.spans = std::mem::take(&mut self.spans);
         program
     }
 }
@@ -857,8 +1779,17 @@ error2:
                 ),
             ],
         )]);
-        assert_eq!(program.sections.len(), 1);
+        // The sections with errors are kept, without the bad instructions
+        assert_eq!(program.sections.len(), 3);
+        assert_eq!(program.errors.len(), 2);
         assert_eq!(program.sections[0], expected);
+        assert_eq!(
+            program.sections[2],
+            Sections::TextSection(vec![TextSegment::new_labeled_section(
+                Rc::from("error2"),
+                vec![]
+            )])
+        );
 
         Ok(())
     }
@@ -1066,25 +1997,25 @@ main:
                 Instruction::new_branch_instruction(
                     BranchOp::Beq,
                     Rc::from(T0),
-                    Rc::from(T1),
+                    Value::Reg(Rc::from(T1)),
                     Rc::from("main"),
                 ),
                 Instruction::new_branch_instruction(
                     BranchOp::Bne,
                     Rc::from(T0),
-                    Rc::from(T1),
+                    Value::Reg(Rc::from(T1)),
                     Rc::from("kkk"),
                 ),
                 Instruction::new_branch_instruction(
                     BranchOp::Blt,
                     Rc::from(T0),
-                    Rc::from(T1),
+                    Value::Reg(Rc::from(T1)),
                     Rc::from("tubias"),
                 ),
                 Instruction::new_branch_instruction(
                     BranchOp::Bgt,
                     Rc::from(T0),
-                    Rc::from(T1),
+                    Value::Reg(Rc::from(T1)),
                     Rc::from("gepeto"),
                 ),
             ],
@@ -1226,11 +2157,11 @@ main:
 
         let data = Sections::DataSection(vec![
             DataWrited {
-                label: Rc::from("test"),
+                label: Some(Rc::from("test")),
                 kind: DataKind::Word(123),
             },
             DataWrited {
-                label: Rc::from("test2"),
+                label: Some(Rc::from("test2")),
                 kind: DataKind::Byte(77),
             },
         ]);
@@ -1263,4 +2194,563 @@ main:
 
         Ok(())
     }
+
+    #[test]
+    fn parse_literals() {
+        assert_eq!(parse_literal("42"), Some(42));
+        assert_eq!(parse_literal("0xFF"), Some(255));
+        assert_eq!(parse_literal("0b1010"), Some(10));
+        assert_eq!(parse_literal("0o17"), Some(15));
+        assert_eq!(parse_literal("1_000"), Some(1000));
+        assert_eq!(parse_literal("'A'"), Some(65));
+        assert_eq!(parse_literal("'\\n'"), Some(10));
+        assert_eq!(parse_literal("0xZZ"), None);
+        assert_eq!(parse_literal("12ab"), None);
+    }
+
+    #[test]
+    fn parse_const_expressions() -> Result<()> {
+        let input = r"
+.equ SIZE, 4 * 2
+.data
+    arr: .word -1
+    .equ MASK, (1 << 4) - 1
+    last: .byte SIZE - 1
+
+.text
+main:
+    addi a0 <- a0, 0x10 | 0b1
+    lui a1 <- 0x100 - 1
+    andi a2 <- a2, MASK
+    read a3 <- arr + 1
+";
+        let program = create_program(input);
+
+        let equ = Sections::new_data_section(vec![Sections::new_data_writed(
+            DataKind::Equ(ConstExpr::new_binary(
+                ConstOp::Mul,
+                ConstExpr::Number(4),
+                ConstExpr::Number(2),
+            )),
+            Rc::from("SIZE"),
+        )]);
+        let data = Sections::new_data_section(vec![
+            Sections::new_data_writed(DataKind::Word(-1), Rc::from("arr")),
+            Sections::new_data_writed(
+                DataKind::Equ(ConstExpr::new_binary(
+                    ConstOp::Sub,
+                    ConstExpr::new_binary(ConstOp::Shl, ConstExpr::Number(1), ConstExpr::Number(4)),
+                    ConstExpr::Number(1),
+                )),
+                Rc::from("MASK"),
+            ),
+            Sections::new_data_writed(
+                DataKind::ByteExpr(ConstExpr::new_binary(
+                    ConstOp::Sub,
+                    ConstExpr::Symbol(Rc::from("SIZE")),
+                    ConstExpr::Number(1),
+                )),
+                Rc::from("last"),
+            ),
+        ]);
+        let text = Sections::new_text_section(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                Instruction::new_double_operand_instruction(
+                    DoubleOperandOpcode::Addi,
+                    vec![Rc::new(Register::A0)],
+                    Rc::new(Register::A0),
+                    Value::Immediate(0x11),
+                ),
+                Instruction::new_single_operand_instruction(
+                    SingleOperandOpcode::Lui,
+                    vec![Rc::new(Register::A1)],
+                    Value::Immediate(0xFF),
+                ),
+                Instruction::new_double_operand_instruction(
+                    DoubleOperandOpcode::Andi,
+                    vec![Rc::new(Register::A2)],
+                    Rc::new(Register::A2),
+                    Value::Label(Rc::from("MASK")),
+                ),
+                Instruction::new_read_instruction(
+                    ImmediateOrLabel::Expr(ConstExpr::new_binary(
+                        ConstOp::Add,
+                        ConstExpr::Symbol(Rc::from("arr")),
+                        ConstExpr::Number(1),
+                    )),
+                    vec![Rc::new(Register::A3)],
+                ),
+            ],
+        )]);
+
+        assert_eq!(program.errors.len(), 0);
+        assert_eq!(program.sections, vec![equ, data, text]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn const_expression_display() {
+        for (input, expected) in [
+            ("K", "K"),
+            ("-1", "-1"),
+            ("-(1 + K)", "-(1 + K)"),
+            ("(1 << 4) - 1", "(1 << 4) - 1"),
+            ("1 << (4 - 1)", "1 << 4 - 1"),
+            ("(a + 1) * 2", "(a + 1) * 2"),
+            ("a - (b - c)", "a - (b - c)"),
+            ("(a - b) - c", "a - b - c"),
+            ("0x10 | 0b1 & MASK", "16 | 1 & MASK"),
+        ] {
+            let program = create_program(&format!(".equ X, {}", input));
+            let Some(Sections::DataSection(data)) = program.sections.first() else {
+                panic!("{}", input)
+            };
+            let DataKind::Equ(ref expr) = data[0].kind else {
+                panic!("{}", input)
+            };
+            assert_eq!(expr.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn parse_immediate_out_of_range() {
+        for (input, value) in [
+            ("addi a0 <- a0, 0x100", 256),
+            ("lui a1 <- 2 - 200", -198),
+            ("addi a2 <- a2, -1", -1),
+        ] {
+            let program = create_program(&format!(".text\nmain:\n    {}", input));
+            let err = program.errors[0].downcast_ref::<ParserError>();
+            assert!(
+                matches!(err, Some(ParserError::ValueOutOfRange { value: v, .. }) if *v == value),
+                "{:?}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn parse_data_directives() -> Result<()> {
+        let input = r#"
+.data
+    list: .word 1, SIZE, 3
+          .byte 'a', 2
+    buf: .space 4 * 4
+    .align 8
+    msg: .ascii "hi\n"
+    str: .asciz "hey"
+"#;
+        let program = create_program(input);
+
+        let expected = Sections::new_data_section(vec![
+            Sections::new_data_writed(
+                DataKind::WordList(vec![
+                    ConstExpr::Number(1),
+                    ConstExpr::Symbol(Rc::from("SIZE")),
+                    ConstExpr::Number(3),
+                ]),
+                Rc::from("list"),
+            ),
+            Sections::new_unlabeled_data(DataKind::ByteList(vec![
+                ConstExpr::Number(97),
+                ConstExpr::Number(2),
+            ])),
+            Sections::new_data_writed(
+                DataKind::Space(ConstExpr::new_binary(
+                    ConstOp::Mul,
+                    ConstExpr::Number(4),
+                    ConstExpr::Number(4),
+                )),
+                Rc::from("buf"),
+            ),
+            Sections::new_unlabeled_data(DataKind::Align(ConstExpr::Number(8))),
+            Sections::new_data_writed(DataKind::Ascii(Rc::from("hi\n")), Rc::from("msg")),
+            Sections::new_data_writed(DataKind::Asciz(Rc::from("hey")), Rc::from("str")),
+        ]);
+
+        assert_eq!(program.errors.len(), 0);
+        assert_eq!(program.sections, vec![expected]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_micro() {
+        let input = r"
+.text
+main:
+    .micro 0xFFFFFFFFFFFFFFFF
+    .micro 0x1ff, 42
+    nop
+    .micro 512, 1
+";
+        let program = create_program(input);
+
+        let expected = Sections::new_text_section(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                Instruction::new_micro_instruction(None, u64::MAX),
+                Instruction::new_micro_instruction(Some(0x1ff), 42),
+                Instruction::new_no_operand_instruction(NoOperandOpcode::Nop),
+            ],
+        )]);
+
+        assert_eq!(program.sections, vec![expected]);
+        assert_eq!(program.errors.len(), 1);
+        assert!(matches!(
+            program.errors[0].downcast_ref::<ParserError>(),
+            Some(ParserError::ValueOutOfRange { value: 512, .. })
+        ));
+    }
+
+    #[test]
+    fn parse_indirect_jumps() {
+        let input = r"
+.text
+main:
+    call twice
+    jalr t0, s6
+    ret
+    jalr s6, s6
+";
+        let program = create_program(input);
+
+        let expected = Sections::new_text_section(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                Instruction::new_call_instruction(Rc::from("twice")),
+                Instruction::new_jalr_instruction(Rc::new(Register::T0), Rc::new(Register::S6)),
+                Instruction::new_no_operand_instruction(NoOperandOpcode::Ret),
+            ],
+        )]);
+
+        assert_eq!(program.sections, vec![expected]);
+        assert_eq!(program.errors.len(), 1);
+        assert!(matches!(
+            program.errors[0].downcast_ref::<ParserError>(),
+            Some(ParserError::JalrLinksIntoTarget { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_branches() {
+        let input = r"
+.text
+main:
+    bge t0, t1, main
+    bltu t0, 200, main
+    beqz a0, main
+    bgt pc, t0, main
+    blt pc, t0, main
+    bleu t0, 256, main
+";
+        let program = create_program(input);
+
+        let branch = |op, rs1, rs2| {
+            Instruction::new_branch_instruction(op, Rc::new(rs1), rs2, Rc::from("main"))
+        };
+        let expected = Sections::new_text_section(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                branch(
+                    BranchOp::Bge,
+                    Register::T0,
+                    Value::Reg(Rc::new(Register::T1)),
+                ),
+                branch(BranchOp::Bltu, Register::T0, Value::Immediate(200)),
+                branch(BranchOp::Beq, Register::A0, Value::Immediate(0)),
+                branch(
+                    BranchOp::Bgt,
+                    Register::Pc,
+                    Value::Reg(Rc::new(Register::T0)),
+                ),
+            ],
+        )]);
+
+        assert_eq!(program.sections, vec![expected]);
+        // blt compares with pc on the B bus
+        assert_eq!(program.errors.len(), 2);
+        assert!(matches!(
+            program.errors[0].downcast_ref::<ParserError>(),
+            Some(ParserError::RegisterCannotBeUsedInBBus { .. })
+        ));
+        assert!(matches!(
+            program.errors[1].downcast_ref::<ParserError>(),
+            Some(ParserError::ValueOutOfRange { value: 256, .. })
+        ));
+    }
+
+    #[test]
+    fn parse_stack() {
+        let input = r"
+.text
+main:
+    push ra
+    pop s0, cpp
+    push mar
+";
+        let program = create_program(input);
+
+        let expected = Sections::new_text_section(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                Instruction::new_push_instruction(Rc::new(Register::Ra)),
+                Instruction::new_pop_instruction(vec![
+                    Rc::new(Register::S0),
+                    Rc::new(Register::Cpp),
+                ]),
+            ],
+        )]);
+
+        assert_eq!(program.sections, vec![expected]);
+        assert_eq!(program.errors.len(), 1);
+        assert!(matches!(
+            program.errors[0].downcast_ref::<ParserError>(),
+            Some(ParserError::RegisterCannotBeUsedInABus { .. })
+        ));
+    }
+
+    #[test]
+    fn parse_shifts() {
+        let input = r"
+.text
+main:
+    srl s0 <- a0
+    srai s0, s1 <- a0, 31
+    sllv s0 <- a0, a1
+    slli s0 <- a0, 32
+    srlv s0 <- a0, t0
+";
+        let program = create_program(input);
+
+        let expected = Sections::new_text_section(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                Instruction::new_single_operand_instruction(
+                    SingleOperandOpcode::Srl,
+                    vec![Rc::new(Register::S0)],
+                    Value::Reg(Rc::new(Register::A0)),
+                ),
+                Instruction::new_double_operand_instruction(
+                    DoubleOperandOpcode::Srai,
+                    vec![Rc::new(Register::S0), Rc::new(Register::S1)],
+                    Rc::new(Register::A0),
+                    Value::Immediate(31),
+                ),
+                Instruction::new_double_operand_instruction(
+                    DoubleOperandOpcode::Sllv,
+                    vec![Rc::new(Register::S0)],
+                    Rc::new(Register::A0),
+                    Value::Reg(Rc::new(Register::A1)),
+                ),
+            ],
+        )]);
+
+        assert_eq!(program.sections, vec![expected]);
+        let errors: Vec<_> = program
+            .errors
+            .iter()
+            .map(|e| e.downcast_ref::<ParserError>().unwrap().code())
+            .collect();
+        assert_eq!(errors, vec!["E0009", "E0026"]);
+    }
+
+    #[test]
+    fn parse_indexed_addresses() {
+        let input = r"
+.text
+main:
+    read a0, a1 <- [s0]
+    read a0 <- [s0 + 4 * 2]
+    write [t1 - 8] <- a1
+    write [mdr + SIZE] <- a1
+    read a0 <- [pc - 1]
+    read a0 <- [s0 + 256]
+";
+        let program = create_program(input);
+
+        let indexed = |base: Register, offset: ConstExpr| IndexedAddress {
+            base: Rc::new(base),
+            offset,
+        };
+        let expected = Sections::new_text_section(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                Instruction::new_read_indexed_instruction(
+                    indexed(Register::S0, ConstExpr::Number(0)),
+                    vec![Rc::new(Register::A0), Rc::new(Register::A1)],
+                ),
+                Instruction::new_read_indexed_instruction(
+                    indexed(Register::S0, ConstExpr::Number(8)),
+                    vec![Rc::new(Register::A0)],
+                ),
+                Instruction::new_write_indexed_instruction(
+                    indexed(Register::T1, ConstExpr::Number(-8)),
+                    Rc::new(Register::A1),
+                ),
+                Instruction::new_write_indexed_instruction(
+                    indexed(
+                        Register::Mdr,
+                        ConstExpr::new_binary(
+                            ConstOp::Add,
+                            ConstExpr::Number(0),
+                            ConstExpr::Symbol(Rc::from("SIZE")),
+                        ),
+                    ),
+                    Rc::new(Register::A1),
+                ),
+            ],
+        )]);
+
+        assert_eq!(program.sections, vec![expected]);
+        let errors: Vec<_> = program
+            .errors
+            .iter()
+            .map(|e| e.downcast_ref::<ParserError>().unwrap().code())
+            .collect();
+        assert_eq!(errors, vec!["E0006", "E0009"]);
+    }
+
+    #[test]
+    fn parse_subword_accesses() {
+        let input = r"
+.text
+main:
+    readbu a0, a1 <- [s0 + 1]
+    writeh [s1 - 2] <- a1
+    readb t0 <- [s0]
+    writeb [s0] <- t2
+    readh a0 <- 5
+";
+        let program = create_program(input);
+
+        let expected = Sections::new_text_section(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                Instruction::new_read_subword_instruction(
+                    LoadOp::Readbu,
+                    IndexedAddress {
+                        base: Rc::new(Register::S0),
+                        offset: ConstExpr::Number(1),
+                    },
+                    vec![Rc::new(Register::A0), Rc::new(Register::A1)],
+                ),
+                Instruction::new_write_subword_instruction(
+                    StoreOp::Writeh,
+                    IndexedAddress {
+                        base: Rc::new(Register::S1),
+                        offset: ConstExpr::Number(-2),
+                    },
+                    Rc::new(Register::A1),
+                ),
+            ],
+        )]);
+
+        assert_eq!(program.sections, vec![expected]);
+        let errors: Vec<_> = program
+            .errors
+            .iter()
+            .map(|e| e.downcast_ref::<ParserError>().unwrap().code())
+            .collect();
+        assert_eq!(errors, vec!["E0027", "E0027", "E0002"]);
+    }
+
+    #[test]
+    fn error_recovery() {
+        let input = r"
+.data
+    x: .word 1
+    x: .byte 2
+.text
+main:
+    add t0 <- t1
+    sub t0 <- t1, t2
+    addi t0 <- t1, 300
+    jal main
+main:
+    nop
+";
+        let program = create_program(input);
+
+        let errors: Vec<_> = program
+            .errors
+            .iter()
+            .map(|e| {
+                let e = e.downcast_ref::<ParserError>().unwrap();
+                (e.code(), e.span().line, e.span().column)
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("E0012", 4, 5),
+                ("E0002", 8, 5),
+                ("E0009", 9, 20),
+                ("E0012", 11, 1)
+            ]
+        );
+        assert!(matches!(
+            program.errors[3].downcast_ref::<ParserError>(),
+            Some(ParserError::DuplicateLabel { first_line: 6, .. })
+        ));
+
+        let text = Sections::TextSection(vec![TextSegment::new_labeled_section(
+            Rc::from("main"),
+            vec![
+                Instruction::new_double_operand_instruction(
+                    DoubleOperandOpcode::Sub,
+                    vec![Rc::new(Register::T0)],
+                    Rc::new(Register::T1),
+                    Value::Reg(Rc::new(Register::T2)),
+                ),
+                Instruction::new_jal_instruction(Rc::from("main")),
+            ],
+        )]);
+        assert_eq!(program.sections.len(), 2);
+        assert_eq!(program.sections[1], text);
+        assert_eq!(program.spans.label_refs[0].1, Span::new("<input>", 10, 9));
+    }
+
+    #[test]
+    fn unknown_instruction_recovery() {
+        let input = r"
+.text
+main:
+    foo t0 <- t1
+    addi t0 <- t0, 300
+    mov t0 <-
+    add t0 <- zz, t1
+    halt
+";
+        let program = create_program(input);
+
+        let errors: Vec<_> = program
+            .errors
+            .iter()
+            .map(|e| {
+                let e = e.downcast_ref::<ParserError>().unwrap();
+                (e.code(), e.span().line, e.span().column)
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            vec![
+                ("E0028", 4, 5),
+                ("E0009", 5, 20),
+                ("E0002", 7, 5),
+                ("E0002", 7, 15)
+            ]
+        );
+        assert_eq!(
+            program.sections[0],
+            Sections::TextSection(vec![TextSegment::new_labeled_section(
+                Rc::from("main"),
+                vec![Instruction::new_no_operand_instruction(
+                    NoOperandOpcode::Halt
+                )],
+            )])
+        );
+    }
 }
@@ -1,4 +1,6 @@
-use std::rc::Rc;
+use std::{fmt, rc::Rc};
+
+use thiserror::Error;
 
 use crate::assembler::tokens::Register;
 
@@ -21,6 +23,12 @@ pub enum DoubleOperandOpcode {
     Andi,
     Ori,
     Subi,
+    Slli,
+    Srli,
+    Srai,
+    Sllv,
+    Srlv,
+    Srav,
 }
 
 #[derive(Debug, PartialEq)]
@@ -30,6 +38,8 @@ pub enum SingleOperandOpcode {
     Sll,
     Sra,
     Sla,
+    Srl,
+    Rol,
     Mov,
 }
 
@@ -37,26 +47,206 @@ pub enum SingleOperandOpcode {
 pub enum NoOperandOpcode {
     Halt,
     Nop,
+    Ret,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum BranchOp {
     Beq,
     Bne,
     Blt,
     Bgt,
+    Bge,
+    Ble,
+    Bltu,
+    Bgtu,
+    Bgeu,
+    Bleu,
+}
+
+/// Loads of a byte or a halfword, sign or zero extended.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LoadOp {
+    Readb,
+    Readbu,
+    Readh,
+    Readhu,
+}
+
+/// Stores of the low byte or halfword of a register.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum StoreOp {
+    Writeb,
+    Writeh,
+}
+
+#[derive(Debug, PartialEq, Error)]
+pub enum ExprError {
+    #[error("Undefined symbol '{name}' in constant expression")]
+    UndefinedSymbol { name: String },
+
+    #[error("Division by zero in constant expression")]
+    DivisionByZero,
+
+    #[error("Constant expression overflows")]
+    Overflow,
+}
+
+impl ExprError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ExprError::UndefinedSymbol { .. } => "E0017",
+            ExprError::DivisionByZero => "E0023",
+            ExprError::Overflow => "E0024",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ConstOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Shl,
+    Shr,
+    And,
+    Or,
+}
+
+impl ConstOp {
+    /// How tightly the operator binds, higher binds tighter.
+    pub fn precedence(self) -> u8 {
+        match self {
+            ConstOp::Or => 1,
+            ConstOp::And => 2,
+            ConstOp::Shl | ConstOp::Shr => 3,
+            ConstOp::Add | ConstOp::Sub => 4,
+            ConstOp::Mul | ConstOp::Div => 5,
+        }
+    }
+}
+
+impl fmt::Display for ConstOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            ConstOp::Add => "+",
+            ConstOp::Sub => "-",
+            ConstOp::Mul => "*",
+            ConstOp::Div => "/",
+            ConstOp::Shl => "<<",
+            ConstOp::Shr => ">>",
+            ConstOp::And => "&",
+            ConstOp::Or => "|",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// An expression evaluated at assembly time, over numbers, `.equ` constants and data labels.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ConstExpr {
+    Number(i64),
+    Symbol(Rc<str>),
+    Neg(Box<ConstExpr>),
+    Binary {
+        op: ConstOp,
+        left: Box<ConstExpr>,
+        right: Box<ConstExpr>,
+    },
+}
+
+impl ConstExpr {
+    pub fn new_binary(op: ConstOp, left: ConstExpr, right: ConstExpr) -> Self {
+        ConstExpr::Binary {
+            op,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    /// Evaluates the expression, using `lookup` to resolve symbols.
+    pub fn eval<F>(&self, lookup: &F) -> Result<i64, ExprError>
+    where
+        F: Fn(&str) -> Option<i64>,
+    {
+        match self {
+            ConstExpr::Number(n) => Ok(*n),
+            ConstExpr::Symbol(name) => lookup(name).ok_or_else(|| ExprError::UndefinedSymbol {
+                name: name.to_string(),
+            }),
+            ConstExpr::Neg(e) => e.eval(lookup)?.checked_neg().ok_or(ExprError::Overflow),
+            ConstExpr::Binary { op, left, right } => {
+                let l = left.eval(lookup)?;
+                let r = right.eval(lookup)?;
+                let res = match op {
+                    ConstOp::Add => l.checked_add(r),
+                    ConstOp::Sub => l.checked_sub(r),
+                    ConstOp::Mul => l.checked_mul(r),
+                    ConstOp::Div if r == 0 => return Err(ExprError::DivisionByZero),
+                    ConstOp::Div => l.checked_div(r),
+                    ConstOp::Shl => u32::try_from(r).ok().and_then(|r| l.checked_shl(r)),
+                    ConstOp::Shr => u32::try_from(r).ok().and_then(|r| l.checked_shr(r)),
+                    ConstOp::And => Some(l & r),
+                    ConstOp::Or => Some(l | r),
+                };
+                res.ok_or(ExprError::Overflow)
+            }
+        }
+    }
 }
+
 #[derive(Debug, PartialEq)]
 pub enum Value {
     Immediate(u8),
     Reg(Rc<Register>),
     Label(Rc<str>),
+    Expr(ConstExpr),
+}
+
+/// Writes the expression as it would be written in the sources, with the
+/// parentheses its operators need.
+impl fmt::Display for ConstExpr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstExpr::Number(n) => write!(f, "{}", n),
+            ConstExpr::Symbol(name) => write!(f, "{}", name),
+            ConstExpr::Neg(e) if matches!(**e, ConstExpr::Binary { .. }) => write!(f, "-({})", e),
+            ConstExpr::Neg(e) => write!(f, "-{}", e),
+            ConstExpr::Binary { op, left, right } => {
+                // The operators are left-associative, so a right operand binding as
+                // tight as `op` needs parentheses too
+                let looser = |e: &ConstExpr, min: u8| matches!(e, ConstExpr::Binary { op, .. } if op.precedence() < min);
+                let prec = op.precedence();
+                if looser(left, prec) {
+                    write!(f, "({})", left)?;
+                } else {
+                    write!(f, "{}", left)?;
+                }
+                write!(f, " {} ", op)?;
+                if looser(right, prec + 1) {
+                    write!(f, "({})", right)
+                } else {
+                    write!(f, "{}", right)
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub enum ImmediateOrLabel {
     Immediate(u8),
     Label(Rc<str>),
+    Expr(ConstExpr),
+}
+
+/// An address computed at run time, adding a constant offset to a register.
+#[derive(Debug, PartialEq)]
+pub struct IndexedAddress {
+    pub base: Rc<Register>,
+    /// From -255 to 255.
+    pub offset: ConstExpr,
 }
 
 #[derive(Debug, PartialEq)]
@@ -78,7 +268,8 @@ pub struct SingleOperandInstruction {
 pub struct BranchInstruction {
     pub opcode: BranchOp,
     pub rs1: Rc<Register>,
-    pub rs2: Rc<Register>,
+    /// A register, or an immediate from 0 to 255.
+    pub rs2: Value,
     pub label: Rc<str>,
 }
 
@@ -88,9 +279,28 @@ pub enum Instruction {
     SingleOperand(SingleOperandInstruction),
     Branch(BranchInstruction),
     NoOperand(NoOperandOpcode),
-    WriteInstruction(ImmediateOrLabel, Rc<Register>),
-    ReadInstruction(ImmediateOrLabel, Vec<Rc<Register>>),
+    Write(ImmediateOrLabel, Rc<Register>),
+    Read(ImmediateOrLabel, Vec<Rc<Register>>),
+    /// Stores `rs` at the address held by a register plus an offset.
+    WriteIndexed(IndexedAddress, Rc<Register>),
+    /// Loads the word at the address held by a register plus an offset.
+    ReadIndexed(IndexedAddress, Vec<Rc<Register>>),
+    /// Loads the byte or halfword at a byte address into the registers.
+    ReadSubword(LoadOp, IndexedAddress, Vec<Rc<Register>>),
+    /// Stores the low byte or halfword of `rs` at a byte address.
+    WriteSubword(StoreOp, IndexedAddress, Rc<Register>),
+    /// Stores `rs` on top of the stack pointed by CPP.
+    Push(Rc<Register>),
+    /// Loads the top of the stack pointed by CPP into the registers.
+    Pop(Vec<Rc<Register>>),
     Jal(Rc<str>),
+    /// Links the return address into `rd` and jumps to the address held by `rs`.
+    Jalr(Rc<Register>, Rc<Register>),
+    /// Links the return address into `ra` and jumps to the label.
+    Call(Rc<str>),
+    Li(ConstExpr, Vec<Rc<Register>>),
+    /// A raw microinstruction, at the next address or at the given one.
+    Micro(Option<u16>, u64),
 }
 
 impl Instruction {
@@ -124,24 +334,75 @@ impl Instruction {
         Instruction::Jal(label)
     }
 
+    pub fn new_jalr_instruction(rd: Rc<Register>, rs: Rc<Register>) -> Instruction {
+        Instruction::Jalr(rd, rs)
+    }
+
+    pub fn new_call_instruction(label: Rc<str>) -> Instruction {
+        Instruction::Call(label)
+    }
+
     pub fn new_write_instruction(
         immediate_or_label: ImmediateOrLabel,
         rd: Rc<Register>,
     ) -> Instruction {
-        Instruction::WriteInstruction(immediate_or_label, rd)
+        Instruction::Write(immediate_or_label, rd)
     }
 
     pub fn new_read_instruction(
         immediate_or_label: ImmediateOrLabel,
         rd: Vec<Rc<Register>>,
     ) -> Instruction {
-        Instruction::ReadInstruction(immediate_or_label, rd)
+        Instruction::Read(immediate_or_label, rd)
+    }
+
+    pub fn new_write_indexed_instruction(addr: IndexedAddress, rs: Rc<Register>) -> Instruction {
+        Instruction::WriteIndexed(addr, rs)
+    }
+
+    pub fn new_read_indexed_instruction(
+        addr: IndexedAddress,
+        rd: Vec<Rc<Register>>,
+    ) -> Instruction {
+        Instruction::ReadIndexed(addr, rd)
+    }
+
+    pub fn new_read_subword_instruction(
+        op: LoadOp,
+        addr: IndexedAddress,
+        rd: Vec<Rc<Register>>,
+    ) -> Instruction {
+        Instruction::ReadSubword(op, addr, rd)
+    }
+
+    pub fn new_write_subword_instruction(
+        op: StoreOp,
+        addr: IndexedAddress,
+        rs: Rc<Register>,
+    ) -> Instruction {
+        Instruction::WriteSubword(op, addr, rs)
+    }
+
+    pub fn new_push_instruction(rs: Rc<Register>) -> Instruction {
+        Instruction::Push(rs)
+    }
+
+    pub fn new_pop_instruction(rd: Vec<Rc<Register>>) -> Instruction {
+        Instruction::Pop(rd)
+    }
+
+    pub fn new_li_instruction(value: ConstExpr, rd: Vec<Rc<Register>>) -> Instruction {
+        Instruction::Li(value, rd)
+    }
+
+    pub fn new_micro_instruction(addr: Option<u16>, word: u64) -> Instruction {
+        Instruction::Micro(addr, word)
     }
 
     pub fn new_branch_instruction(
         opcode: BranchOp,
         rs1: Rc<Register>,
-        rs2: Rc<Register>,
+        rs2: Value,
         label: Rc<str>,
     ) -> Instruction {
         Instruction::Branch(BranchInstruction {
@@ -157,12 +418,24 @@ impl Instruction {
 pub enum DataKind {
     Byte(u8),
     Word(i32),
+    ByteExpr(ConstExpr),
+    WordExpr(ConstExpr),
+    ByteList(Vec<ConstExpr>),
+    WordList(Vec<ConstExpr>),
+    /// Reserves the given amount of zeroed bytes.
+    Space(ConstExpr),
+    Ascii(Rc<str>),
+    /// Like `Ascii`, followed by a zero byte.
+    Asciz(Rc<str>),
+    /// Pads with zeroed bytes until the given byte alignment.
+    Align(ConstExpr),
+    Equ(ConstExpr),
 }
 
 #[derive(Debug, PartialEq)]
 pub struct DataWrited {
     pub kind: DataKind,
-    pub label: Rc<str>,
+    pub label: Option<Rc<str>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -205,6 +478,14 @@ impl Sections {
     }
 
     pub fn new_data_writed(kind: DataKind, label: Rc<str>) -> DataWrited {
-        DataWrited { kind, label }
+        DataWrited {
+            kind,
+            label: Some(label),
+        }
+    }
+
+    /// Data that continues the previous one, without a label of its own.
+    pub fn new_unlabeled_data(kind: DataKind) -> DataWrited {
+        DataWrited { kind, label: None }
     }
 }
@@ -0,0 +1,465 @@
+use std::{fmt, path::Path};
+
+use anyhow::{bail, Result};
+use thiserror::Error;
+
+use crate::{
+    assembler::evaluator::AsmEvaluator,
+    uarch::{
+        mem::{CtrlStore, Ram, Register, Registers},
+        Computer,
+    },
+};
+
+/// Names of the general purpose registers, in the order the datapath keeps them.
+const GEN_REGS: [&str; 16] = [
+    "ra", "t0", "t1", "t2", "t3", "s0", "s1", "s2", "s3", "s4", "s5", "s6", "a0", "a1", "a2", "a3",
+];
+const SYS_REGS: [&str; 5] = ["mar", "mdr", "pc", "lv", "cpp"];
+
+#[derive(Debug, PartialEq, Error)]
+pub enum ExpectationError {
+    #[error("Line {line}: expected `<target> = <value>` or `cycles <= <value>`")]
+    MissingOperator { line: usize },
+    #[error("Line {line}: unknown expectation target '{target}'")]
+    UnknownTarget { line: usize, target: String },
+    #[error("Line {line}: invalid value '{value}'")]
+    InvalidValue { line: usize, value: String },
+    #[error("Line {line}: the microarchitecture has no console, so there is no output to check")]
+    NoConsole { line: usize },
+}
+
+/// A word of the RAM, either at a data label plus a word offset or at a fixed address.
+#[derive(Debug, PartialEq, Clone)]
+pub enum RamAddr {
+    Label(String, u32),
+    Word(u32),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Target {
+    Register(&'static str),
+    Ram(RamAddr),
+    /// Upper bound of the cycles the program takes to halt.
+    Cycles,
+}
+
+/// Something a program must leave behind once it halts.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Expectation {
+    pub target: Target,
+    pub value: u32,
+    /// Line it was declared on, in the source or the sidecar file.
+    pub line: usize,
+}
+
+impl Expectation {
+    /// Parses `a0 = 12`, `ram label+1 = 0x2a`, `ram 16 = -1` or `cycles <= 500`.
+    fn parse(text: &str, line: usize) -> Result<Self, ExpectationError> {
+        if text == "output" || text.starts_with("output ") {
+            return Err(ExpectationError::NoConsole { line });
+        }
+        let (target, value) = match text.split_once("<=").map(|(t, v)| (t.trim(), v)) {
+            Some(("cycles", v)) => (Target::Cycles, v),
+            Some((t, _)) => {
+                let target = t.to_string();
+                return Err(ExpectationError::UnknownTarget { line, target });
+            }
+            None => {
+                let (t, v) = text
+                    .split_once('=')
+                    .ok_or(ExpectationError::MissingOperator { line })?;
+                (Self::parse_target(t.trim(), line)?, v)
+            }
+        };
+        let value = value.trim();
+        let value = parse_value(value).ok_or_else(|| ExpectationError::InvalidValue {
+            line,
+            value: value.to_string(),
+        })?;
+
+        Ok(Self {
+            target,
+            value,
+            line,
+        })
+    }
+
+    fn parse_target(t: &str, line: usize) -> Result<Target, ExpectationError> {
+        let unknown = || ExpectationError::UnknownTarget {
+            line,
+            target: t.to_string(),
+        };
+        if let Some(reg) = GEN_REGS.iter().chain(SYS_REGS.iter()).find(|r| **r == t) {
+            return Ok(Target::Register(reg));
+        }
+        let addr = t.strip_prefix("ram ").ok_or_else(unknown)?.trim();
+        if let Some(word) = parse_value(addr) {
+            return Ok(Target::Ram(RamAddr::Word(word)));
+        }
+        let (label, offset) = match addr.split_once('+') {
+            Some((label, offset)) => (
+                label.trim(),
+                parse_value(offset.trim()).ok_or_else(unknown)?,
+            ),
+            None => (addr, 0),
+        };
+        let valid = label.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(unknown());
+        }
+        Ok(Target::Ram(RamAddr::Label(label.to_string(), offset)))
+    }
+}
+
+impl fmt::Display for Expectation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            Target::Cycles => write!(f, "cycles <= {}", self.value),
+            target => write!(f, "{} = {}", TargetName(target), show(self.value)),
+        }
+    }
+}
+
+struct TargetName<'a>(&'a Target);
+
+impl fmt::Display for TargetName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Target::Register(reg) => write!(f, "{}", reg),
+            Target::Ram(RamAddr::Word(word)) => write!(f, "ram {}", word),
+            Target::Ram(RamAddr::Label(label, 0)) => write!(f, "ram {}", label),
+            Target::Ram(RamAddr::Label(label, offset)) => write!(f, "ram {}+{}", label, offset),
+            Target::Cycles => write!(f, "cycles"),
+        }
+    }
+}
+
+fn show(value: u32) -> String {
+    format!("{} ({:#010x})", value, value)
+}
+
+/// Decimal, `0x` hexadecimal or `0b` binary, with negative values taken as two's
+/// complement.
+fn parse_value(text: &str) -> Option<u32> {
+    let (neg, digits) = match text.strip_prefix('-') {
+        Some(d) => (true, d),
+        None => (false, text),
+    };
+    let magnitude = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse::<i64>().ok()?
+    };
+    let value = if neg { -magnitude } else { magnitude };
+    (i32::MIN as i64..=u32::MAX as i64)
+        .contains(&value)
+        .then_some(value as u32)
+}
+
+/// Reads the expectations declared in the comments of a source, as in `; expect a0 = 12`.
+pub fn parse_source(src: &str) -> Result<Vec<Expectation>, ExpectationError> {
+    src.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let comment = &line[line.find([';', '#'])? + 1..];
+            let text = comment.trim().strip_prefix("expect ")?;
+            Some(Expectation::parse(text.trim(), i + 1))
+        })
+        .collect()
+}
+
+/// Reads a sidecar file, which has one expectation per line, with or without the
+/// leading `expect`. Blank lines and comments are skipped.
+pub fn parse_sidecar(src: &str) -> Result<Vec<Expectation>, ExpectationError> {
+    src.lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let text = line.trim();
+            if text.is_empty() || text.starts_with([';', '#']) {
+                return None;
+            }
+            let text = text.strip_prefix("expect ").unwrap_or(text);
+            Some(Expectation::parse(text.trim(), i + 1))
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RunOptions {
+    /// Cycles after which a program that didn't halt is stopped.
+    pub max_cycles: u32,
+    pub optimize: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            max_cycles: 1_000_000,
+            optimize: false,
+        }
+    }
+}
+
+/// An expectation that didn't hold, along with what was found instead.
+#[derive(Debug, PartialEq)]
+pub struct Failure {
+    pub expected: String,
+    pub actual: String,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub halted: bool,
+    pub cycles: u64,
+    pub failures: Vec<Failure>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.halted && self.failures.is_empty()
+    }
+}
+
+/// Renders the failures as a diff of what was expected against what was found.
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.halted {
+            writeln!(f, "did not halt within {} cycles", self.cycles)?;
+        }
+        for failure in self.failures.iter() {
+            writeln!(f, "- {}", failure.expected)?;
+            writeln!(f, "+ {}", failure.actual)?;
+        }
+        Ok(())
+    }
+}
+
+/// Assembles a file and runs it, checking the expectations in its comments and in a
+/// `.expect` file next to it.
+pub fn run_file(path: &Path, opts: RunOptions) -> Result<Report> {
+    let mut expectations = parse_source(&std::fs::read_to_string(path)?)?;
+    let sidecar = path.with_extension("expect");
+    if sidecar.exists() {
+        expectations.extend(parse_sidecar(&std::fs::read_to_string(sidecar)?)?);
+    }
+
+    let mut evaluator = AsmEvaluator::new();
+    evaluator.set_optimize(opts.optimize);
+    let res = evaluator
+        .evaluate_file(path)
+        .map(|(ctrl, ram)| (ctrl, ram.to_vec()));
+    match res {
+        Ok((ctrl, ram)) => run(&evaluator, ctrl, ram, &expectations, opts),
+        Err(e) => bail!("{}", evaluator.diagnostics(Some(&e))),
+    }
+}
+
+/// Like [`run_file`], for a source in memory.
+pub fn run_buffer(src: &str, opts: RunOptions) -> Result<Report> {
+    let expectations = parse_source(src)?;
+    let mut evaluator = AsmEvaluator::new();
+    evaluator.set_optimize(opts.optimize);
+    let (ctrl, ram) = evaluator
+        .evaluate_buffer(src)
+        .map(|(ctrl, ram)| (ctrl, ram.to_vec()))?;
+
+    run(&evaluator, ctrl, ram, &expectations, opts)
+}
+
+fn run(
+    evaluator: &AsmEvaluator,
+    ctrl: CtrlStore,
+    ram: Vec<u32>,
+    expectations: &[Expectation],
+    opts: RunOptions,
+) -> Result<Report> {
+    let mut mem = Ram::new();
+    mem.load(0, ram);
+    let mut computer = Computer::new(mem, ctrl);
+    let halted = computer.exec_for(opts.max_cycles);
+    let cycles = computer.cycles() as u64;
+    if !halted {
+        return Ok(Report {
+            halted,
+            cycles: opts.max_cycles as u64,
+            failures: Vec::new(),
+        });
+    }
+
+    let mut failures = Vec::new();
+    for exp in expectations {
+        let actual = match &exp.target {
+            Target::Cycles => {
+                if cycles > exp.value as u64 {
+                    failures.push(Failure {
+                        expected: exp.to_string(),
+                        actual: format!("cycles = {}", cycles),
+                    });
+                }
+                continue;
+            }
+            Target::Register(reg) => register(computer.regs(), reg),
+            Target::Ram(RamAddr::Word(word)) => computer.ram().get(*word),
+            Target::Ram(RamAddr::Label(label, offset)) => {
+                let Some(addr) = evaluator.data_label(label) else {
+                    bail!("Line {}: undefined data label '{}'", exp.line, label);
+                };
+                computer.ram().get(addr + offset)
+            }
+        };
+        if actual != exp.value {
+            failures.push(Failure {
+                expected: exp.to_string(),
+                actual: format!("{} = {}", TargetName(&exp.target), show(actual)),
+            });
+        }
+    }
+
+    Ok(Report {
+        halted,
+        cycles,
+        failures,
+    })
+}
+
+/// Value of a register by its assembly name, 0 for a name that isn't one.
+pub(crate) fn register(regs: &Registers, name: &str) -> u32 {
+    match name {
+        "mar" => regs.mem.mar(),
+        "mdr" => regs.mem.mdr(),
+        "pc" => regs.mem.pc(),
+        "lv" => regs.sys.lv.get(),
+        "cpp" => regs.sys.cpp.get(),
+        reg => GEN_REGS
+            .iter()
+            .position(|r| *r == reg)
+            .and_then(|i| regs.gen.get(i))
+            .unwrap_or(0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_expectations() {
+        let src = "
+.data
+result: .word 0
+.text
+main:
+    lui a0 <- 12 ; expect a0 = 12
+    # expect ram result+1 = 0x2A
+    ; expect ram 16 = -1
+    ; expect cycles <= 500
+    halt ; not an expectation
+";
+        let exps = parse_source(src).unwrap();
+        let label = RamAddr::Label("result".to_string(), 1);
+        assert_eq!(
+            exps,
+            vec![
+                Expectation {
+                    target: Target::Register("a0"),
+                    value: 12,
+                    line: 6,
+                },
+                Expectation {
+                    target: Target::Ram(label),
+                    value: 42,
+                    line: 7,
+                },
+                Expectation {
+                    target: Target::Ram(RamAddr::Word(16)),
+                    value: u32::MAX,
+                    line: 8,
+                },
+                Expectation {
+                    target: Target::Cycles,
+                    value: 500,
+                    line: 9,
+                },
+            ]
+        );
+        assert_eq!(exps[1].to_string(), "ram result+1 = 42 (0x0000002a)");
+
+        let sidecar = "# comment\n\nexpect ra = 1\nt3 = 0b11\n";
+        let exps = parse_sidecar(sidecar).unwrap();
+        assert_eq!(exps[0].target, Target::Register("ra"));
+        assert_eq!((exps[1].value, exps[1].line), (3, 4));
+
+        let errors = [
+            (
+                "x0 = 1",
+                ExpectationError::UnknownTarget {
+                    line: 1,
+                    target: "x0".to_string(),
+                },
+            ),
+            (
+                "a0 = 1x",
+                ExpectationError::InvalidValue {
+                    line: 1,
+                    value: "1x".to_string(),
+                },
+            ),
+            ("a0 12", ExpectationError::MissingOperator { line: 1 }),
+            (
+                "a0 = 4294967296",
+                ExpectationError::InvalidValue {
+                    line: 1,
+                    value: "4294967296".to_string(),
+                },
+            ),
+            ("output \"hi\"", ExpectationError::NoConsole { line: 1 }),
+        ];
+        for (text, err) in errors {
+            assert_eq!(parse_sidecar(text), Err(err));
+        }
+    }
+
+    #[test]
+    fn run_checks_expectations() {
+        let src = "
+.data
+    result: .word 0, 0
+.text
+main:
+    lui a0 <- 12
+    lui a1 <- 5
+    add a2 <- a0, a1
+    write result <- a2
+    halt
+    ; expect a2 = 17
+    ; expect ram result = 17
+    ; expect cycles <= 100
+";
+        let report = run_buffer(src, RunOptions::default()).unwrap();
+        assert!(report.passed(), "{}", report);
+
+        let failing = format!(
+            "{}\n; expect a0 = 13\n; expect ram result+1 = 1\n; expect cycles <= 1",
+            src
+        );
+        let report = run_buffer(&failing, RunOptions::default()).unwrap();
+        assert!(!report.passed());
+        let diff = report.to_string();
+        assert!(diff.contains("- a0 = 13 (0x0000000d)\n+ a0 = 12 (0x0000000c)\n"));
+        assert!(diff.contains("- ram result+1 = 1 (0x00000001)\n+ ram result+1 = 0 (0x00000000)\n"));
+        assert!(diff.contains("- cycles <= 1\n+ cycles = "));
+
+        let looping = ".text\nloop:\n    jal loop\n";
+        let opts = RunOptions {
+            max_cycles: 50,
+            ..RunOptions::default()
+        };
+        let report = run_buffer(looping, opts).unwrap();
+        assert!(!report.halted);
+        assert_eq!(report.to_string(), "did not halt within 50 cycles\n");
+    }
+}
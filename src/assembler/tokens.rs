@@ -5,18 +5,42 @@ pub struct TokWithCtx {
     pub tok: Rc<AsmToken>,
     pub cur_line: usize,
     pub cur_column: usize,
+    pub file: Rc<str>,
 }
 
 impl TokWithCtx {
-    pub fn new(tok: AsmToken, cur_line: usize, cur_column: usize) -> Self {
+    pub fn new(tok: AsmToken, cur_line: usize, cur_column: usize, file: Rc<str>) -> Self {
         TokWithCtx {
             tok: Rc::new(tok),
             cur_line,
             cur_column,
+            file,
         }
     }
 }
 
+/// Every opcode, as written in the sources.
+pub const OPCODE_NAMES: &[&str] = &[
+    "lui", "li", "addi", "subi", "muli", "andi", "ori", "xori", "add", "sub", "and", "or", "not",
+    "mov", "xor", "sll", "sra", "sla", "srl", "rol", "slli", "srli", "srai", "sllv", "srlv",
+    "srav", "nop", "jal", "jalr", "call", "ret", "beq", "bne", "blt", "bgt", "bge", "ble", "bltu",
+    "bgtu", "bgeu", "bleu", "beqz", "bnez", "bltz", "bgtz", "bgez", "blez", "mul", "mul2", "div",
+    "divi", "mod", "modi", "halt", "read", "write", "readb", "readbu", "readh", "readhu", "writeb",
+    "writeh", "push", "pop",
+];
+
+/// Every pseudo-op, as written in the sources.
+pub const PSEUDO_OP_NAMES: &[&str] = &[
+    ".data", ".word", ".byte", ".text", ".global", ".include", ".equ", ".space", ".ascii",
+    ".asciz", ".align", ".micro",
+];
+
+/// Every register, as written in the sources.
+pub const REGISTER_NAMES: &[&str] = &[
+    "mar", "mdr", "pc", "mbr", "mbru", "mbr2", "mbr2u", "cpp", "lv", "ra", "t0", "t1", "t2", "t3",
+    "s0", "s1", "s2", "s3", "s4", "s5", "s6", "a0", "a1", "a2", "a3",
+];
+
 //RISC-V ABI
 #[derive(Debug, PartialEq, Clone)]
 pub enum Register {
@@ -52,6 +76,8 @@ pub enum Register {
 pub enum Opcode {
     // Integer Register-Immediate Instructions
     Lui,
+    // Load a 32-bit constant (pseudo-instruction)
+    Li,
     Addi,
     Subi,
     Muli,
@@ -70,14 +96,41 @@ pub enum Opcode {
     Sll,
     Sla,
     Sra,
+    Srl,
+    Rol,
+    // Shift by an immediate
+    Slli,
+    Srli,
+    Srai,
+    // Shift by a register
+    Sllv,
+    Srlv,
+    Srav,
     Nop,
     // Unconditional Jumps
     Jal,
+    // Indirect Jumps
+    Jalr,
+    Call,
+    Ret,
     // Conditional Branches
     Beq,
     Bne,
     Blt,
     Bgt,
+    Bge,
+    Ble,
+    Bltu,
+    Bgtu,
+    Bgeu,
+    Bleu,
+    // Compare with zero
+    Beqz,
+    Bnez,
+    Bltz,
+    Bgtz,
+    Bgez,
+    Blez,
     // Multiplication Operations
     Mul,
     Mul2,
@@ -90,6 +143,16 @@ pub enum Opcode {
     // x ,addr ,rd
     Read,
     Write,
+    // Bytes and halfwords, at byte addresses
+    Readb,
+    Readbu,
+    Readh,
+    Readhu,
+    Writeb,
+    Writeh,
+    // Stack, through CPP
+    Push,
+    Pop,
     // Halt
     Halt,
 }
@@ -101,12 +164,20 @@ pub enum PseudoOps {
     Byte,
     Text,
     Global,
+    Include,
+    Equ,
+    Space,
+    Ascii,
+    Asciz,
+    Align,
+    Micro,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum AsmToken {
     Number(Rc<str>),
     Label(Rc<str>),
+    Str(Rc<str>),
     Reg(Rc<Register>),
     Opcode(Rc<Opcode>),
     PseudoOp(Rc<PseudoOps>),
@@ -115,6 +186,22 @@ pub enum AsmToken {
     Illegal,
     Eof,
     Assign,
+    // Constant expression operators
+    Plus,
+    Minus,
+    Asterisk,
+    Slash,
+    Shl,
+    Shr,
+    Ampersand,
+    Pipe,
+    LParen,
+    RParen,
+    // Indexed addresses
+    LBracket,
+    RBracket,
+    /// Only kept by a lexer made `with_trivia`.
+    Comment(Rc<str>),
 }
 
 impl AsmToken {
@@ -127,11 +214,19 @@ impl AsmToken {
                 ".byte" => AsmToken::PseudoOp(Rc::new(PseudoOps::Byte)),
                 ".text" => AsmToken::PseudoOp(Rc::new(PseudoOps::Text)),
                 ".global" => AsmToken::PseudoOp(Rc::new(PseudoOps::Global)),
+                ".include" => AsmToken::PseudoOp(Rc::new(PseudoOps::Include)),
+                ".equ" => AsmToken::PseudoOp(Rc::new(PseudoOps::Equ)),
+                ".space" => AsmToken::PseudoOp(Rc::new(PseudoOps::Space)),
+                ".ascii" => AsmToken::PseudoOp(Rc::new(PseudoOps::Ascii)),
+                ".asciz" => AsmToken::PseudoOp(Rc::new(PseudoOps::Asciz)),
+                ".align" => AsmToken::PseudoOp(Rc::new(PseudoOps::Align)),
+                ".micro" => AsmToken::PseudoOp(Rc::new(PseudoOps::Micro)),
                 _ => AsmToken::Illegal,
             },
 
             //OPCODES
             "lui" => AsmToken::Opcode(Rc::new(Opcode::Lui)),
+            "li" => AsmToken::Opcode(Rc::new(Opcode::Li)),
             "addi" => AsmToken::Opcode(Rc::new(Opcode::Addi)),
             "subi" => AsmToken::Opcode(Rc::new(Opcode::Subi)),
             "muli" => AsmToken::Opcode(Rc::new(Opcode::Muli)),
@@ -148,12 +243,35 @@ impl AsmToken {
             "sll" => AsmToken::Opcode(Rc::new(Opcode::Sll)),
             "sra" => AsmToken::Opcode(Rc::new(Opcode::Sra)),
             "sla" => AsmToken::Opcode(Rc::new(Opcode::Sla)),
+            "srl" => AsmToken::Opcode(Rc::new(Opcode::Srl)),
+            "rol" => AsmToken::Opcode(Rc::new(Opcode::Rol)),
+            "slli" => AsmToken::Opcode(Rc::new(Opcode::Slli)),
+            "srli" => AsmToken::Opcode(Rc::new(Opcode::Srli)),
+            "srai" => AsmToken::Opcode(Rc::new(Opcode::Srai)),
+            "sllv" => AsmToken::Opcode(Rc::new(Opcode::Sllv)),
+            "srlv" => AsmToken::Opcode(Rc::new(Opcode::Srlv)),
+            "srav" => AsmToken::Opcode(Rc::new(Opcode::Srav)),
             "nop" => AsmToken::Opcode(Rc::new(Opcode::Nop)),
             "jal" => AsmToken::Opcode(Rc::new(Opcode::Jal)),
+            "jalr" => AsmToken::Opcode(Rc::new(Opcode::Jalr)),
+            "call" => AsmToken::Opcode(Rc::new(Opcode::Call)),
+            "ret" => AsmToken::Opcode(Rc::new(Opcode::Ret)),
             "beq" => AsmToken::Opcode(Rc::new(Opcode::Beq)),
             "bne" => AsmToken::Opcode(Rc::new(Opcode::Bne)),
             "blt" => AsmToken::Opcode(Rc::new(Opcode::Blt)),
             "bgt" => AsmToken::Opcode(Rc::new(Opcode::Bgt)),
+            "bge" => AsmToken::Opcode(Rc::new(Opcode::Bge)),
+            "ble" => AsmToken::Opcode(Rc::new(Opcode::Ble)),
+            "bltu" => AsmToken::Opcode(Rc::new(Opcode::Bltu)),
+            "bgtu" => AsmToken::Opcode(Rc::new(Opcode::Bgtu)),
+            "bgeu" => AsmToken::Opcode(Rc::new(Opcode::Bgeu)),
+            "bleu" => AsmToken::Opcode(Rc::new(Opcode::Bleu)),
+            "beqz" => AsmToken::Opcode(Rc::new(Opcode::Beqz)),
+            "bnez" => AsmToken::Opcode(Rc::new(Opcode::Bnez)),
+            "bltz" => AsmToken::Opcode(Rc::new(Opcode::Bltz)),
+            "bgtz" => AsmToken::Opcode(Rc::new(Opcode::Bgtz)),
+            "bgez" => AsmToken::Opcode(Rc::new(Opcode::Bgez)),
+            "blez" => AsmToken::Opcode(Rc::new(Opcode::Blez)),
             "mul" => AsmToken::Opcode(Rc::new(Opcode::Mul)),
             "mul2" => AsmToken::Opcode(Rc::new(Opcode::Mul2)),
             "div" => AsmToken::Opcode(Rc::new(Opcode::Div)),
@@ -163,6 +281,14 @@ impl AsmToken {
             "halt" => AsmToken::Opcode(Rc::new(Opcode::Halt)),
             "read" => AsmToken::Opcode(Rc::new(Opcode::Read)),
             "write" => AsmToken::Opcode(Rc::new(Opcode::Write)),
+            "readb" => AsmToken::Opcode(Rc::new(Opcode::Readb)),
+            "readbu" => AsmToken::Opcode(Rc::new(Opcode::Readbu)),
+            "readh" => AsmToken::Opcode(Rc::new(Opcode::Readh)),
+            "readhu" => AsmToken::Opcode(Rc::new(Opcode::Readhu)),
+            "writeb" => AsmToken::Opcode(Rc::new(Opcode::Writeb)),
+            "writeh" => AsmToken::Opcode(Rc::new(Opcode::Writeh)),
+            "push" => AsmToken::Opcode(Rc::new(Opcode::Push)),
+            "pop" => AsmToken::Opcode(Rc::new(Opcode::Pop)),
 
             //REGISTERS
             "mar" => AsmToken::Reg(Rc::new(Register::Mar)),
@@ -1,8 +1,12 @@
-use std::{fs::File, io::Write};
+use std::{fs::File, io::Write, path::Path};
 
 use anyhow::Result;
 use clap::Parser;
-use vondel::assembler::{cli::AssemblerCli, evaluator::AsmEvaluator};
+use vondel::assembler::{
+    cli::{AssemblerCli, MessageFormat},
+    diagnostics::Diagnostics,
+    evaluator::AsmEvaluator,
+};
 
 const GUY: &str = r"
 ⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⢀⡀⠴⠤⠤⠴⠄⡄⡀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
@@ -37,34 +41,29 @@ const GUY: &str = r"
 ⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀⠘⠀⠀⠀⠀⠀⠀⠀⠀⠀⠀
 ";
 
-fn read_from_file(file: &str) -> Result<String> {
-    let buf = match std::fs::read_to_string(file) {
-        Ok(buf) => buf,
-        Err(e) => {
-            eprintln!("Error reading file: {}", e);
-            std::process::exit(1);
-        }
-    };
-    Ok(buf)
-}
-
 fn main() -> Result<()> {
     let cli = AssemblerCli::parse();
     let name_out = match cli.output {
         Some(n) => n,
         None => "a".to_string(),
     };
-    let buf = read_from_file(&cli.input)?;
     let mut evaluator = AsmEvaluator::new();
+    evaluator.set_optimize(cli.optimize);
 
-    let (ctrl, ram) = match evaluator.evaluate_buffer(&buf) {
+    let res = evaluator
+        .evaluate_file(Path::new(&cli.input))
+        .map(|(ctrl, ram)| (ctrl, ram.to_vec()));
+    let diags = evaluator.diagnostics(res.as_ref().err());
+    report(&diags, cli.message_format);
+    let (ctrl, ram) = match res {
         Ok((ctrl, ram)) => (ctrl, ram),
-        Err(e) => {
-            eprintln!("Error evaluating buffer: {}\n", e);
-            std::process::exit(1);
-        }
+        Err(_) => std::process::exit(1),
     };
 
+    if cli.listing {
+        std::fs::write(format!("{name_out}.lst"), evaluator.listing(&ctrl))?;
+    }
+
     let mut ram_dump = File::create(format!("{name_out}.ram"))?;
     for word in ram.iter() {
         let bytes = word.to_le_bytes();
@@ -77,9 +76,26 @@ fn main() -> Result<()> {
         rom_dump.write_all(&bytes)?;
     }
 
+    if cli.message_format == MessageFormat::Json {
+        return Ok(());
+    }
     println!("{}", GUY);
+    if let Some(stats) = evaluator.optimizations() {
+        println!(
+            "Optimizer saved {} words and {} cycles",
+            stats.words, stats.cycles
+        );
+    }
     println!("Congratulations! You have successfully assembled your program!");
     println!("You can now run it with the microarchitecture:");
 
     Ok(())
 }
+
+fn report(diags: &Diagnostics, format: MessageFormat) {
+    match format {
+        MessageFormat::Json => println!("{}", diags.to_json()),
+        MessageFormat::Human if !diags.items.is_empty() => eprintln!("{}", diags),
+        MessageFormat::Human => {}
+    }
+}
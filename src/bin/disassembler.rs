@@ -0,0 +1,41 @@
+use anyhow::Result;
+use clap::Parser;
+use vondel::assembler::{cli::DisassemblerCli, disassembler::Disassembler};
+
+fn read_rom(file: &str) -> Result<Vec<u64>> {
+    let buffer = std::fs::read(file)?;
+    let mut result = Vec::new();
+    for i in (0..buffer.len()).step_by(8) {
+        let mut word = [0u8; 8];
+        word.copy_from_slice(&buffer[i..i + 8]);
+        result.push(u64::from_le_bytes(word));
+    }
+    Ok(result)
+}
+
+fn read_ram(file: &str) -> Result<Vec<u32>> {
+    let buffer = std::fs::read(file)?;
+    let mut result = Vec::new();
+    for i in (0..buffer.len()).step_by(4) {
+        let mut word = [0u8; 4];
+        word.copy_from_slice(&buffer[i..i + 4]);
+        result.push(u32::from_le_bytes(word));
+    }
+    Ok(result)
+}
+
+fn main() -> Result<()> {
+    let cli = DisassemblerCli::parse();
+    let rom = read_rom(&cli.input)?;
+    let ram = match &cli.ram {
+        Some(file) => Some(read_ram(file)?),
+        None => None,
+    };
+
+    let asm = Disassembler::new(&rom).disassemble(ram.as_deref());
+    match cli.output {
+        Some(out) => std::fs::write(out, asm)?,
+        None => print!("{}", asm),
+    }
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use std::fs;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use vondel::assembler::{cli::FormatterCli, formatter};
+
+fn main() -> Result<()> {
+    let cli = FormatterCli::parse();
+
+    let mut unformatted = 0;
+    for input in cli.inputs.iter() {
+        let src = fs::read_to_string(input).with_context(|| format!("Could not read {}", input))?;
+        let formatted = formatter::format_source(&src, input)?;
+        if formatted == src {
+            continue;
+        }
+
+        if cli.check {
+            unformatted += 1;
+            println!("{}", input);
+            // The first line that differs, to tell what is wrong
+            let old = src.lines().chain(std::iter::repeat(""));
+            if let Some((num, (old, new))) = old
+                .zip(formatted.lines())
+                .enumerate()
+                .find(|(_, (old, new))| old != new)
+            {
+                println!("    {}:", num + 1);
+                println!("    -{}", old);
+                println!("    +{}", new);
+            }
+        } else {
+            fs::write(input, formatted).with_context(|| format!("Could not write {}", input))?;
+        }
+    }
+
+    if unformatted > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
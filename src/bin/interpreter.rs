@@ -22,9 +22,15 @@ fn main() -> Result<()> {
     let evaluator: Box<dyn evaluator::Evaluator> = match cli.evaluator {
         EvaluatorChoice::Rust => Box::new(evaluator::rust::RustEvaluator::new()),
         EvaluatorChoice::Custom => Box::new(evaluator::custom::CustomEvaluator::new()),
+        EvaluatorChoice::Vm => Box::new(evaluator::vm::VmEvaluator::new()),
     };
 
     match cli.file {
+        Some(file) if cli.emit_asm.is_some() => {
+            let buf = read_from_file(&file)?;
+            let asm = evaluator::custom::compile_buffer(buf)?;
+            std::fs::write(cli.emit_asm.unwrap(), asm)?;
+        }
         Some(file) => {
             let buf = read_from_file(&file)?;
             evaluate_buffer(evaluator, buf)?;
@@ -0,0 +1,11 @@
+use std::io;
+
+use anyhow::Result;
+use clap::Parser;
+use vondel::assembler::{cli::LspCli, lsp};
+
+fn main() -> Result<()> {
+    LspCli::parse();
+    let code = lsp::run(io::stdin().lock(), io::stdout().lock())?;
+    std::process::exit(code);
+}
@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use anyhow::Result;
+use clap::Parser;
+use vondel::assembler::{
+    cli::TesterCli,
+    tester::{self, RunOptions},
+};
+
+fn main() -> Result<()> {
+    let cli = TesterCli::parse();
+    let opts = RunOptions {
+        max_cycles: cli.max_cycles,
+        optimize: cli.optimize,
+    };
+
+    let mut failed = 0;
+    for input in cli.inputs.iter() {
+        match tester::run_file(Path::new(input), opts) {
+            Ok(report) if report.passed() => {
+                println!("ok   {} ({} cycles)", input, report.cycles);
+            }
+            Ok(report) => {
+                failed += 1;
+                println!("FAIL {}", input);
+                print!("{}", indent(&report.to_string()));
+            }
+            Err(e) => {
+                failed += 1;
+                println!("FAIL {}", input);
+                print!("{}", indent(&format!("{}\n", e)));
+            }
+        }
+    }
+
+    let passed = cli.inputs.len() - failed;
+    println!("\n{} passed, {} failed", passed, failed);
+    if failed > 0 {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
+
+fn indent(text: &str) -> String {
+    text.lines().map(|line| format!("    {}\n", line)).collect()
+}
@@ -1,4 +1,5 @@
 pub mod ast;
+pub mod builtins;
 pub mod cli;
 pub mod environment;
 pub mod evaluator;
@@ -44,24 +44,32 @@ impl std::fmt::Display for ErrorWithCtx {
 #[derive(Debug, PartialEq, PartialOrd)]
 enum Precedence {
     Lowest = 1,
-    Equals = 2,
-    LessGreater = 3,
-    Sum = 4,
-    Product = 5,
-    Prefix = 6,
-    Call = 7,
+    LogicalOr = 2,
+    LogicalAnd = 3,
+    Equals = 4,
+    LessGreater = 5,
+    Sum = 6,
+    Product = 7,
+    Prefix = 8,
+    Call = 9,
+    Index = 10,
 }
 
 impl Precedence {
     /// Returns the precedence of a given token used in Pratt Parsing
     fn precedence_of(t: &TokenType) -> Precedence {
-        // TODO: Add more precedence for LESSEQ AND GREATEREQ
         match t {
+            TokenType::Or => Precedence::LogicalOr,
+            TokenType::And => Precedence::LogicalAnd,
             TokenType::Equal | TokenType::NotEqual => Precedence::Equals,
-            TokenType::LessThan | TokenType::GreaterThan => Precedence::LessGreater,
+            TokenType::LessThan
+            | TokenType::GreaterThan
+            | TokenType::LessEQ
+            | TokenType::GreaterEQ => Precedence::LessGreater,
             TokenType::Plus | TokenType::Minus => Precedence::Sum,
-            TokenType::Slash | TokenType::Asterisk => Precedence::Product,
+            TokenType::Slash | TokenType::Asterisk | TokenType::Percent => Precedence::Product,
             TokenType::LParen => Precedence::Call,
+            TokenType::LBracket => Precedence::Index,
             _ => Precedence::Lowest,
         }
     }
@@ -91,7 +99,7 @@ pub struct Parser<'a> {
 
 impl<'a> Parser<'a> {
     /// Creates a new parser with the given tokens.
-    pub fn new(toks: &'a [TokenType]) -> Parser {
+    pub fn new(toks: &'a [TokenType]) -> Parser<'a> {
         let mut p = Parser {
             cur_token: &TokenType::Eof,
             peek_token: &TokenType::Eof,
@@ -288,22 +296,26 @@ impl<'a> Parser<'a> {
         match self.cur_token {
             TokenType::Ident(v) => Some(self.parse_identifier_expression(v)),
             TokenType::Integer(v) => Some(self.parse_integer_expression(v)),
+            TokenType::String(v) => Some(Ok(Expression::String(v.clone()))),
             TokenType::Bang | TokenType::Minus => Some(self.parse_prefix_expression()),
             TokenType::True | TokenType::False => Some(self.parse_boolean_expression()),
             TokenType::LParen => Some(self.parse_grouped_expression()),
             TokenType::If => Some(self.parse_if_expression()),
             TokenType::Function => Some(self.parse_function_literal()),
+            TokenType::LBracket => Some(self.parse_array_literal()),
+            TokenType::LSquirly => Some(self.parse_hash_literal()),
             _ => None,
         }
     }
 
-    /// Returns the arguments of a function call
-    fn parse_call_arguments(&mut self) -> Result<Vec<Expression>> {
+    /// Returns the comma separated expressions up to the `end` token, like the
+    /// arguments of a function call or the elements of an array
+    fn parse_expression_list(&mut self, end: TokenType) -> Result<Vec<Expression>> {
         let mut args = Vec::new();
 
         self.next_token();
 
-        if self.curr_token_is(TokenType::RParen) {
+        if self.curr_token_is(end.clone()) {
             return Ok(args);
         }
 
@@ -315,17 +327,68 @@ impl<'a> Parser<'a> {
             args.push(self.parse_expression(Precedence::Lowest)?);
         }
 
-        self.expect_peek(TokenType::RParen)?;
+        self.expect_peek(end)?;
 
         Ok(args)
     }
 
     /// Parses a function call
     fn parse_call_expression(&mut self, function: Expression) -> Result<Expression> {
-        let args = self.parse_call_arguments()?;
+        let args = self.parse_expression_list(TokenType::RParen)?;
         Ok(Expression::new_call(function, args))
     }
 
+    /// Parses an array literal
+    fn parse_array_literal(&mut self) -> Result<Expression> {
+        let elements = self.parse_expression_list(TokenType::RBracket)?;
+        Ok(Expression::Array(elements))
+    }
+
+    /// Parses a hash literal, like `{"one": 1, "two": 2}`
+    fn parse_hash_literal(&mut self) -> Result<Expression> {
+        let mut pairs = Vec::new();
+
+        while !self.peek_token_is(TokenType::RSquirly) {
+            self.next_token();
+            let key = self.parse_expression(Precedence::Lowest)?;
+            self.expect_peek(TokenType::Colon)?;
+            self.next_token();
+            let value = self.parse_expression(Precedence::Lowest)?;
+            pairs.push((key, value));
+
+            if !self.peek_token_is(TokenType::RSquirly) {
+                self.expect_peek(TokenType::Comma)?;
+            }
+        }
+        self.expect_peek(TokenType::RSquirly)?;
+
+        Ok(Expression::Hash(pairs))
+    }
+
+    /// Tells if the `{` starting a statement opens a hash literal, empty or with a
+    /// `key:` after it, instead of a block
+    fn is_hash_literal(&mut self) -> bool {
+        if self.peek_token_is(TokenType::RSquirly) {
+            return true;
+        }
+
+        let (cur_token, peek_token, idx) = (self.cur_token, self.peek_token, self.idx);
+        self.next_token();
+        let is_hash = self.parse_expression(Precedence::Lowest).is_ok()
+            && self.peek_token_is(TokenType::Colon);
+        (self.cur_token, self.peek_token, self.idx) = (cur_token, peek_token, idx);
+
+        is_hash
+    }
+
+    /// Parses an index expression, like `arr[1]`
+    fn parse_index_expression(&mut self, left: Expression) -> Result<Expression> {
+        self.next_token();
+        let index = self.parse_expression(Precedence::Lowest)?;
+        self.expect_peek(TokenType::RBracket)?;
+        Ok(Expression::new_index(left, index))
+    }
+
     /// Return which Infix Parse Function to use based on the current token
     /// If there is no infix parse function for the current token, return None
     fn infix_parse_fns(
@@ -336,10 +399,15 @@ impl<'a> Parser<'a> {
             | TokenType::Minus
             | TokenType::Slash
             | TokenType::Asterisk
+            | TokenType::Percent
             | TokenType::Equal
             | TokenType::NotEqual
             | TokenType::LessThan
-            | TokenType::GreaterThan => {
+            | TokenType::GreaterThan
+            | TokenType::LessEQ
+            | TokenType::GreaterEQ
+            | TokenType::And
+            | TokenType::Or => {
                 self.next_token();
                 Some(Box::new(|v| self.parse_infix_expression(v)))
             }
@@ -347,6 +415,10 @@ impl<'a> Parser<'a> {
                 self.next_token();
                 Some(Box::new(|v| self.parse_call_expression(v)))
             }
+            TokenType::LBracket => {
+                self.next_token();
+                Some(Box::new(|v| self.parse_index_expression(v)))
+            }
             _ => None,
         }
     }
@@ -403,11 +475,22 @@ impl<'a> Parser<'a> {
         Ok(StatementType::Expression(res))
     }
 
+    /// Parses a statement starting with `{`, which is a block unless it's a hash
+    fn parse_squirly_statement(&mut self) -> Result<StatementType> {
+        if self.is_hash_literal() {
+            return self.parse_expression_statement();
+        }
+        let block = self.parse_block_statement()?;
+        self.skip_peek_semicolon();
+        Ok(block)
+    }
+
     /// Parses a statement and returns the resulting `StatementType` enum.
     fn parse_statement(&mut self) -> Result<StatementType> {
         let res = match self.cur_token {
             TokenType::Let => self.parse_let_statement()?,
             TokenType::Return => self.parse_return_statement()?,
+            TokenType::LSquirly => self.parse_squirly_statement()?,
             TokenType::Illegal(_) => bail!(ParserError::IllegalToken {
                 tok: self.cur_token.clone()
             }),
@@ -595,6 +678,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_string_expression() {
+        //"hello world";
+        let toks = vec![
+            TokenType::String(String::from("hello world")),
+            TokenType::Semicolon,
+            TokenType::Eof,
+        ];
+        let mut parser = Parser::new(&toks);
+        let program = parser.get_deez_program();
+
+        let stts = vec![StatementType::Expression(Expression::String(String::from(
+            "hello world",
+        )))];
+
+        assert_eq!(program.statements.len(), 1);
+        assert_eq!(program.errors.len(), 0);
+
+        for (i, s) in stts.into_iter().enumerate() {
+            assert_eq!(s, program.statements[i]);
+        }
+    }
+
     #[test]
     fn parse_prefix_operator() {
         /* !5;
@@ -1161,4 +1267,253 @@ mod tests {
             assert_eq!(s, program.statements[i]);
         }
     }
+
+    #[test]
+    fn parse_array_and_index_expressions() {
+        /*
+         * [1, 2 * 2, []];
+         * a * [1, 2][b + 1] * f(x)[0];
+         * --------------------------
+         * RES
+         * --------------------------
+         * [1, (2 * 2), []]
+         * ((a * ([1, 2][(b + 1)])) * (f(x)[0]))
+         * */
+        let toks = vec![
+            TokenType::LBracket,
+            TokenType::Integer(1.to_string()),
+            TokenType::Comma,
+            TokenType::Integer(2.to_string()),
+            TokenType::Asterisk,
+            TokenType::Integer(2.to_string()),
+            TokenType::Comma,
+            TokenType::LBracket,
+            TokenType::RBracket,
+            TokenType::RBracket,
+            TokenType::Semicolon,
+            TokenType::Ident(String::from("a")),
+            TokenType::Asterisk,
+            TokenType::LBracket,
+            TokenType::Integer(1.to_string()),
+            TokenType::Comma,
+            TokenType::Integer(2.to_string()),
+            TokenType::RBracket,
+            TokenType::LBracket,
+            TokenType::Ident(String::from("b")),
+            TokenType::Plus,
+            TokenType::Integer(1.to_string()),
+            TokenType::RBracket,
+            TokenType::Asterisk,
+            TokenType::Ident(String::from("f")),
+            TokenType::LParen,
+            TokenType::Ident(String::from("x")),
+            TokenType::RParen,
+            TokenType::LBracket,
+            TokenType::Integer(0.to_string()),
+            TokenType::RBracket,
+            TokenType::Semicolon,
+            TokenType::Eof,
+        ];
+
+        let stts = vec![
+            StatementType::Expression(Expression::Array(vec![
+                Expression::Integer(1),
+                Expression::Infix {
+                    left: Box::new(Expression::Integer(2)),
+                    op: InfixOp::Asterisk,
+                    right: Box::new(Expression::Integer(2)),
+                },
+                Expression::Array(vec![]),
+            ])),
+            StatementType::Expression(Expression::Infix {
+                left: Box::new(Expression::Infix {
+                    left: Box::new(Expression::Identifier("a".to_string())),
+                    op: InfixOp::Asterisk,
+                    right: Box::new(Expression::Index {
+                        left: Box::new(Expression::Array(vec![
+                            Expression::Integer(1),
+                            Expression::Integer(2),
+                        ])),
+                        index: Box::new(Expression::Infix {
+                            left: Box::new(Expression::Identifier("b".to_string())),
+                            op: InfixOp::Plus,
+                            right: Box::new(Expression::Integer(1)),
+                        }),
+                    }),
+                }),
+                op: InfixOp::Asterisk,
+                right: Box::new(Expression::Index {
+                    left: Box::new(Expression::Call {
+                        function: Box::new(Expression::Identifier("f".to_string())),
+                        arguments: vec![Expression::Identifier("x".to_string())],
+                    }),
+                    index: Box::new(Expression::Integer(0)),
+                }),
+            }),
+        ];
+
+        let mut parser = Parser::new(&toks);
+        let program = parser.get_deez_program();
+
+        assert_eq!(program.errors.len(), 0);
+        assert_eq!(program.statements.len(), 2);
+
+        for (i, s) in stts.into_iter().enumerate() {
+            assert_eq!(s, program.statements[i]);
+        }
+    }
+
+    #[test]
+    fn parse_hash_literals_and_blocks() {
+        /*
+         * {"one": 1, true: 2 + 3};
+         * {};
+         * { x; };
+         * let h = {1: {}};
+         * */
+        let toks = vec![
+            TokenType::LSquirly,
+            TokenType::String(String::from("one")),
+            TokenType::Colon,
+            TokenType::Integer(1.to_string()),
+            TokenType::Comma,
+            TokenType::True,
+            TokenType::Colon,
+            TokenType::Integer(2.to_string()),
+            TokenType::Plus,
+            TokenType::Integer(3.to_string()),
+            TokenType::RSquirly,
+            TokenType::Semicolon,
+            TokenType::LSquirly,
+            TokenType::RSquirly,
+            TokenType::Semicolon,
+            TokenType::LSquirly,
+            TokenType::Ident(String::from("x")),
+            TokenType::Semicolon,
+            TokenType::RSquirly,
+            TokenType::Semicolon,
+            TokenType::Let,
+            TokenType::Ident(String::from("h")),
+            TokenType::Assign,
+            TokenType::LSquirly,
+            TokenType::Integer(1.to_string()),
+            TokenType::Colon,
+            TokenType::LSquirly,
+            TokenType::RSquirly,
+            TokenType::RSquirly,
+            TokenType::Semicolon,
+            TokenType::Eof,
+        ];
+
+        let stts = vec![
+            StatementType::Expression(Expression::Hash(vec![
+                (
+                    Expression::String("one".to_string()),
+                    Expression::Integer(1),
+                ),
+                (
+                    Expression::Boolean(true),
+                    Expression::Infix {
+                        left: Box::new(Expression::Integer(2)),
+                        op: InfixOp::Plus,
+                        right: Box::new(Expression::Integer(3)),
+                    },
+                ),
+            ])),
+            StatementType::Expression(Expression::Hash(vec![])),
+            StatementType::Block(vec![StatementType::Expression(Expression::Identifier(
+                "x".to_string(),
+            ))]),
+            StatementType::Let {
+                name: Expression::Identifier("h".to_string()),
+                value: Expression::Hash(vec![(Expression::Integer(1), Expression::Hash(vec![]))]),
+            },
+        ];
+
+        let mut parser = Parser::new(&toks);
+        let program = parser.get_deez_program();
+
+        assert_eq!(program.errors.len(), 0);
+        assert_eq!(program.statements.len(), 4);
+
+        for (i, s) in stts.into_iter().enumerate() {
+            assert_eq!(s, program.statements[i]);
+        }
+    }
+
+    #[test]
+    fn parse_logical_and_comparison_operators() {
+        /*
+         * a || b && c <= d % 2;
+         * a >= b == c && d;
+         * --------------------------
+         * RES
+         * --------------------------
+         * (a || (b && (c <= (d % 2))))
+         * (((a >= b) == c) && d)
+         * */
+        let ident = |name: &str| TokenType::Ident(String::from(name));
+        let toks = vec![
+            ident("a"),
+            TokenType::Or,
+            ident("b"),
+            TokenType::And,
+            ident("c"),
+            TokenType::LessEQ,
+            ident("d"),
+            TokenType::Percent,
+            TokenType::Integer(2.to_string()),
+            TokenType::Semicolon,
+            ident("a"),
+            TokenType::GreaterEQ,
+            ident("b"),
+            TokenType::Equal,
+            ident("c"),
+            TokenType::And,
+            ident("d"),
+            TokenType::Semicolon,
+            TokenType::Eof,
+        ];
+
+        let infix = |left: Expression, op: InfixOp, right: Expression| Expression::Infix {
+            left: Box::new(left),
+            op,
+            right: Box::new(right),
+        };
+        let ident = |name: &str| Expression::Identifier(String::from(name));
+        let stts = vec![
+            StatementType::Expression(infix(
+                ident("a"),
+                InfixOp::Or,
+                infix(
+                    ident("b"),
+                    InfixOp::And,
+                    infix(
+                        ident("c"),
+                        InfixOp::LessEqual,
+                        infix(ident("d"), InfixOp::Modulo, Expression::Integer(2)),
+                    ),
+                ),
+            )),
+            StatementType::Expression(infix(
+                infix(
+                    infix(ident("a"), InfixOp::GreaterEqual, ident("b")),
+                    InfixOp::Equal,
+                    ident("c"),
+                ),
+                InfixOp::And,
+                ident("d"),
+            )),
+        ];
+
+        let mut parser = Parser::new(&toks);
+        let program = parser.get_deez_program();
+
+        assert_eq!(program.errors.len(), 0);
+        assert_eq!(program.statements.len(), 2);
+
+        for (i, s) in stts.into_iter().enumerate() {
+            assert_eq!(s, program.statements[i]);
+        }
+    }
 }
@@ -17,10 +17,15 @@ pub enum InfixOp {
     Minus,
     Asterisk,
     Slash,
+    Modulo,
     Equal,
     NotEqual,
     LessThan,
     GreaterThan,
+    LessEqual,
+    GreaterEqual,
+    And,
+    Or,
 }
 
 impl InfixOp {
@@ -30,10 +35,15 @@ impl InfixOp {
             InfixOp::Minus => "-",
             InfixOp::Asterisk => "*",
             InfixOp::Slash => "/",
+            InfixOp::Modulo => "%",
             InfixOp::Equal => "==",
             InfixOp::NotEqual => "!=",
             InfixOp::LessThan => "<",
             InfixOp::GreaterThan => ">",
+            InfixOp::LessEqual => "<=",
+            InfixOp::GreaterEqual => ">=",
+            InfixOp::And => "&&",
+            InfixOp::Or => "||",
         }
     }
 }
@@ -45,10 +55,15 @@ impl fmt::Display for InfixOp {
             InfixOp::Minus => "-",
             InfixOp::Asterisk => "*",
             InfixOp::Slash => "/",
+            InfixOp::Modulo => "%",
             InfixOp::Equal => "==",
             InfixOp::NotEqual => "!=",
             InfixOp::LessThan => "<",
             InfixOp::GreaterThan => ">",
+            InfixOp::LessEqual => "<=",
+            InfixOp::GreaterEqual => ">=",
+            InfixOp::And => "&&",
+            InfixOp::Or => "||",
         };
         write!(f, "{}", buf)
     }
@@ -58,6 +73,7 @@ impl fmt::Display for InfixOp {
 pub enum Expression {
     Identifier(String),
     Integer(i64),
+    String(String),
     Prefix {
         op: PrefixOp,
         right: Box<Expression>,
@@ -81,6 +97,12 @@ pub enum Expression {
         function: Box<Expression>,
         arguments: Vec<Expression>,
     },
+    Array(Vec<Expression>),
+    Hash(Vec<(Expression, Expression)>),
+    Index {
+        left: Box<Expression>,
+        index: Box<Expression>,
+    },
 }
 
 impl Expression {
@@ -88,12 +110,16 @@ impl Expression {
         match self {
             Expression::Identifier(_) => "Identifier",
             Expression::Integer(_) => "Integer",
+            Expression::String(_) => "String",
             Expression::Prefix { .. } => "Prefix",
             Expression::Infix { .. } => "Infix",
             Expression::Boolean(_) => "Boolean",
             Expression::If { .. } => "If",
             Expression::FunctionLiteral { .. } => "FunctionLiteral",
             Expression::Call { .. } => "Call",
+            Expression::Array(_) => "Array",
+            Expression::Hash(_) => "Hash",
+            Expression::Index { .. } => "Index",
         }
     }
     pub fn new_ident(ident: &String) -> Self {
@@ -128,10 +154,15 @@ impl Expression {
             TokenType::Minus => InfixOp::Minus,
             TokenType::Asterisk => InfixOp::Asterisk,
             TokenType::Slash => InfixOp::Slash,
+            TokenType::Percent => InfixOp::Modulo,
             TokenType::Equal => InfixOp::Equal,
             TokenType::NotEqual => InfixOp::NotEqual,
             TokenType::LessThan => InfixOp::LessThan,
             TokenType::GreaterThan => InfixOp::GreaterThan,
+            TokenType::LessEQ => InfixOp::LessEqual,
+            TokenType::GreaterEQ => InfixOp::GreaterEqual,
+            TokenType::And => InfixOp::And,
+            TokenType::Or => InfixOp::Or,
             _ => bail!(super::ParserError::NotAllowedInfix { infix: op.clone() }),
         };
 
@@ -171,4 +202,11 @@ impl Expression {
             arguments: args,
         }
     }
+
+    pub fn new_index(left: Expression, index: Expression) -> Self {
+        Expression::Index {
+            left: Box::new(left),
+            index: Box::new(index),
+        }
+    }
 }
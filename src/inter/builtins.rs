@@ -0,0 +1,215 @@
+use std::io::{self, Write};
+
+use anyhow::{bail, Result};
+
+use crate::inter::{evaluator::EvaluationError, object::Object};
+
+/// Native functions, visible from the global environment unless a variable with the
+/// same name shadows them.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Builtin {
+    Puts,
+    Print,
+    Len,
+    Type,
+    First,
+    Last,
+    Rest,
+    Push,
+    Int,
+    Str,
+}
+
+/// The registry of the builtins, looked up by name.
+pub const BUILTINS: [Builtin; 10] = [
+    Builtin::Puts,
+    Builtin::Print,
+    Builtin::Len,
+    Builtin::Type,
+    Builtin::First,
+    Builtin::Last,
+    Builtin::Rest,
+    Builtin::Push,
+    Builtin::Int,
+    Builtin::Str,
+];
+
+impl Builtin {
+    pub fn lookup(name: &str) -> Option<Builtin> {
+        BUILTINS.into_iter().find(|b| b.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Builtin::Puts => "puts",
+            Builtin::Print => "print",
+            Builtin::Len => "len",
+            Builtin::Type => "type",
+            Builtin::First => "first",
+            Builtin::Last => "last",
+            Builtin::Rest => "rest",
+            Builtin::Push => "push",
+            Builtin::Int => "int",
+            Builtin::Str => "str",
+        }
+    }
+
+    /// Number of arguments taken, `None` for any number.
+    pub fn arity(&self) -> Option<usize> {
+        match self {
+            Builtin::Puts | Builtin::Print => None,
+            Builtin::Push => Some(2),
+            _ => Some(1),
+        }
+    }
+
+    pub fn check_arity(&self, found: usize) -> Result<()> {
+        match self.arity() {
+            Some(expected) if expected != found => {
+                bail!(EvaluationError::WrongNumberOfArguments { found, expected })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn call(&self, args: Vec<Object>) -> Result<Object> {
+        self.check_arity(args.len())?;
+
+        match self {
+            Builtin::Puts => {
+                let mut out = io::stdout().lock();
+                for arg in args.iter() {
+                    writeln!(out, "{}", text(arg))?;
+                }
+                Ok(Object::Null)
+            }
+            Builtin::Print => {
+                let args: Vec<String> = args.iter().map(text).collect();
+                let mut out = io::stdout().lock();
+                write!(out, "{}", args.join(" "))?;
+                out.flush()?;
+                Ok(Object::Null)
+            }
+            Builtin::Type => Ok(Object::String(args[0].type_as_string().to_string())),
+            Builtin::Str => Ok(Object::String(text(&args[0]))),
+            _ => self.call_on_value(args),
+        }
+    }
+
+    /// Calls the builtins that only take some types.
+    fn call_on_value(&self, mut args: Vec<Object>) -> Result<Object> {
+        let res = match (self, &args[0]) {
+            (Builtin::Len, Object::String(s)) => Object::Integer(s.chars().count() as i64),
+            (Builtin::Len, Object::Array(elements)) => Object::Integer(elements.len() as i64),
+            (Builtin::Len, Object::Hash(pairs)) => Object::Integer(pairs.len() as i64),
+            (Builtin::First, Object::Array(elements)) => {
+                elements.first().cloned().unwrap_or(Object::Null)
+            }
+            (Builtin::Last, Object::Array(elements)) => {
+                elements.last().cloned().unwrap_or(Object::Null)
+            }
+            (Builtin::Rest, Object::Array(elements)) if elements.is_empty() => Object::Null,
+            (Builtin::Rest, Object::Array(elements)) => Object::Array(elements[1..].to_vec()),
+            (Builtin::Push, Object::Array(_)) => {
+                let value = args.pop().expect("push takes two arguments");
+                let Some(Object::Array(mut elements)) = args.pop() else {
+                    unreachable!("matched an array");
+                };
+                elements.push(value);
+                Object::Array(elements)
+            }
+            // Strings that aren't an integer give null, to be checked by the caller
+            (Builtin::Int, Object::String(s)) => match s.trim().parse::<i64>() {
+                Ok(i) => Object::Integer(i),
+                Err(_) => Object::Null,
+            },
+            (Builtin::Int, Object::Integer(i)) => Object::Integer(*i),
+            (_, arg) => bail!(EvaluationError::UnsupportedArgument {
+                builtin: self.name(),
+                found: arg.type_as_string(),
+            }),
+        };
+        Ok(res)
+    }
+}
+
+/// Text of a value as `puts` prints it: strings without quotes, the rest as inspected.
+pub fn text(obj: &Object) -> String {
+    match obj {
+        Object::String(s) => s.clone(),
+        obj => obj.inspect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(name: &str, args: Vec<Object>) -> Result<Object> {
+        Builtin::lookup(name).unwrap().call(args)
+    }
+
+    fn array(elements: &[i64]) -> Object {
+        Object::Array(elements.iter().map(|i| Object::Integer(*i)).collect())
+    }
+
+    #[test]
+    fn registry() {
+        for builtin in BUILTINS {
+            assert_eq!(Builtin::lookup(builtin.name()), Some(builtin));
+        }
+        assert_eq!(Builtin::lookup("nope"), None);
+    }
+
+    #[test]
+    fn calls() -> Result<()> {
+        let string = |s: &str| Object::String(s.to_string());
+        let calls = [
+            ("len", vec![string("ação")], Object::Integer(4)),
+            ("len", vec![array(&[1, 2, 3])], Object::Integer(3)),
+            ("type", vec![array(&[])], string("Array")),
+            ("first", vec![array(&[1, 2, 3])], Object::Integer(1)),
+            ("first", vec![array(&[])], Object::Null),
+            ("last", vec![array(&[1, 2, 3])], Object::Integer(3)),
+            ("rest", vec![array(&[1, 2, 3])], array(&[2, 3])),
+            ("rest", vec![array(&[])], Object::Null),
+            (
+                "push",
+                vec![array(&[1]), Object::Integer(2)],
+                array(&[1, 2]),
+            ),
+            ("int", vec![string(" -42 ")], Object::Integer(-42)),
+            ("int", vec![string("4x2")], Object::Null),
+            ("str", vec![Object::Integer(42)], string("42")),
+            ("str", vec![string("hi")], string("hi")),
+            ("str", vec![array(&[1, 2])], string("[1, 2]")),
+            ("puts", vec![], Object::Null),
+        ];
+
+        for (name, args, expected) in calls {
+            assert_eq!(call(name, args)?, expected, "{}", name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let err = call("len", vec![Object::Integer(1)]).unwrap_err();
+        assert_eq!(
+            err.downcast::<EvaluationError>().unwrap(),
+            EvaluationError::UnsupportedArgument {
+                builtin: "len",
+                found: "Integer"
+            }
+        );
+
+        let err = call("push", vec![array(&[])]).unwrap_err();
+        assert_eq!(
+            err.downcast::<EvaluationError>().unwrap(),
+            EvaluationError::WrongNumberOfArguments {
+                found: 1,
+                expected: 2
+            }
+        );
+    }
+}
@@ -6,6 +6,8 @@ pub enum EvaluatorChoice {
     Rust,
     /// Use the Custom Evaluator for processing the AST
     Custom,
+    /// Use the bytecode compiler and stack VM for processing the AST
+    Vm,
 }
 
 #[derive(Debug, Parser)]
@@ -25,4 +27,8 @@ pub struct InterpreterCli {
     /// The file to run the interpreter on
     #[arg(short, long)]
     pub file: Option<String>,
+
+    /// Write the assembly the custom evaluator compiles the file to, instead of running it
+    #[arg(long, requires = "file")]
+    pub emit_asm: Option<String>,
 }
@@ -1,7 +1,7 @@
 use anyhow::{bail, Result};
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use crate::inter::{ast::Expression, evaluator::EvaluationError};
+use crate::inter::{ast::Expression, builtins::Builtin, evaluator::EvaluationError};
 
 use super::object::Object;
 
@@ -58,6 +58,7 @@ impl Environment {
 
     /// Retrieves the value of a variable with the given name from the environment.
     /// If the variable is not found in the current environment, it recursively looks for it in the outer environments.
+    /// The global environment, the outermost one, falls back to the builtins.
     /// Returns an error if the variable is not found.
     pub fn get(&self, name: &str) -> Result<Object> {
         let res = match self.store.get(name) {
@@ -67,14 +68,22 @@ impl Environment {
                     let outer_env = outer_env.borrow();
                     return outer_env.get(name);
                 }
-                None => bail!(EvaluationError::IdentifierNotFound {
-                    identifier: name.to_string()
-                }),
+                None => match Builtin::lookup(name) {
+                    Some(builtin) => return Ok(Object::Builtin(builtin)),
+                    None => bail!(EvaluationError::IdentifierNotFound {
+                        identifier: name.to_string()
+                    }),
+                },
             },
         };
         Ok(res.clone())
     }
 
+    /// The variables set in this environment, without the ones of the outer environments.
+    pub fn variables(&self) -> impl Iterator<Item = (&String, &Object)> {
+        self.store.iter()
+    }
+
     /// Sets the value of a variable with the given name in the environment.
     /// Returns an error if the name is not a valid identifier.
     pub fn set(&mut self, name: &Expression, value: Object) -> Result<()> {
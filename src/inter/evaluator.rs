@@ -11,6 +11,7 @@ use thiserror::Error;
 
 pub mod custom;
 pub mod rust;
+pub mod vm;
 
 const TRUE: Object = Object::Boolean(true);
 const FALSE: Object = Object::Boolean(false);
@@ -32,6 +33,9 @@ pub enum EvaluationError {
     #[error("Unexpected boolean operator '{operator}' for 'Boolean' and 'Boolean', must be '==', '!=', '&&' or '||'")]
     UnallowedBooleanComparisonOperator { operator: &'static str },
 
+    #[error("Division by zero")]
+    DivisionByZero,
+
     #[error("Identifier not found: '{identifier}'")]
     IdentifierNotFound { identifier: String },
 
@@ -43,6 +47,21 @@ pub enum EvaluationError {
 
     #[error("Expected function found: '{found}'")]
     NotAFunction { found: &'static str },
+
+    #[error("Argument to '{builtin}' not supported, found '{found}'")]
+    UnsupportedArgument {
+        builtin: &'static str,
+        found: &'static str,
+    },
+
+    #[error("Unusable as hash key: '{found}', must be 'Integer', 'Boolean' or 'String'")]
+    UnhashableKey { found: &'static str },
+
+    #[error("Index operator not supported: '{left}[{index}]'")]
+    IndexNotSupported {
+        left: &'static str,
+        index: &'static str,
+    },
 }
 
 /// Defines the behavior of an evaluator.
@@ -92,6 +111,8 @@ pub fn evaluate_buffer(evaluator: Box<dyn Evaluator>, input: String) -> Result<(
     let mut env = Environment::new();
     let evaluated = evaluator.eval(&program, &mut env);
     match evaluated {
+        // What the program printed is enough when it ends on a `puts` or `print`
+        Ok(Object::Null) if ends_printing(&program) => {}
         Ok(obj) => println!("{}\n", obj.inspect()),
         Err(e) => println!("{}\n", e),
     }
@@ -99,6 +120,19 @@ pub fn evaluate_buffer(evaluator: Box<dyn Evaluator>, input: String) -> Result<(
     Ok(())
 }
 
+/// Whether the last statement of the program is a call to `puts` or `print`.
+fn ends_printing(program: &Program) -> bool {
+    let Some(ast::StatementType::Expression(ast::Expression::Call { function, .. })) =
+        program.statements.last()
+    else {
+        return false;
+    };
+    match &**function {
+        ast::Expression::Identifier(name) => name == "puts" || name == "print",
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::{cell::RefCell, rc::Rc};
@@ -118,6 +152,55 @@ mod test {
         Ok(res)
     }
 
+    /// How the custom evaluator, which only compiles integers, booleans and
+    /// functions to 32-bit microcode, takes part in a test.
+    #[derive(Clone, Copy)]
+    enum Custom {
+        /// Gives the same result or error as the other evaluators.
+        Agrees,
+        /// Rejects the program, which uses a feature it doesn't compile.
+        Unsupported(&'static str),
+        /// Isn't run, as the program needs 64-bit integers.
+        Skipped,
+    }
+
+    /// Evaluates `prog` on every evaluator, checking that they give the same result
+    /// or the same error, and returns what the Rust evaluator gave.
+    fn eval_all(prog: &ast::Program, custom: Custom) -> Result<Object> {
+        let rust_res = test_eval_program(&rust::RustEvaluator::new(), prog);
+        let vm_res = test_eval_program(&vm::VmEvaluator::new(), prog);
+        assert_same(&rust_res, &vm_res, "vm");
+        match custom {
+            Custom::Agrees => {
+                let custom_res = test_eval_program(&custom::CustomEvaluator::new(), prog);
+                assert_same(&rust_res, &custom_res, "custom");
+            }
+            Custom::Unsupported(what) => {
+                let err = test_eval_program(&custom::CustomEvaluator::new(), prog).unwrap_err();
+                assert_eq!(
+                    err.downcast_ref::<custom::CustomEvaluationError>(),
+                    Some(&custom::CustomEvaluationError::Unsupported { what })
+                );
+            }
+            Custom::Skipped => {}
+        }
+        rust_res
+    }
+
+    fn assert_same(expected: &Result<Object>, found: &Result<Object>, evaluator: &str) {
+        match (expected, found) {
+            (Ok(expected), Ok(found)) => assert_eq!(expected, found, "{}", evaluator),
+            (Err(expected), Err(found)) => assert_eq!(
+                expected.downcast_ref::<EvaluationError>(),
+                Some(found.downcast_ref::<EvaluationError>().unwrap()),
+                "{}: {}",
+                evaluator,
+                found
+            ),
+            _ => panic!("{}: expected {:?}, found {:?}", evaluator, expected, found),
+        }
+    }
+
     fn create_program(ast: Vec<ast::StatementType>) -> ast::Program {
         ast::Program {
             statements: ast,
@@ -125,6 +208,28 @@ mod test {
         }
     }
 
+    fn parse_program(input: &str) -> ast::Program {
+        let toks = Lexer::new(input.to_string()).get_deez_toks();
+        let prog = Parser::new(&toks).get_deez_program();
+        assert!(prog.errors.is_empty(), "{:?}", prog.errors);
+        prog
+    }
+
+    #[test]
+    fn ends_printing_only_on_puts_and_print() {
+        let cases = [
+            ("puts(1)", true),
+            ("let x = 1; print(x, 2)", true),
+            ("puts(1); if (false) { 1 }", false),
+            ("let f = fn() { puts(1) }; f()", false),
+            ("first([])", false),
+            ("", false),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(ends_printing(&parse_program(input)), expected, "{}", input);
+        }
+    }
+
     #[test]
     fn eval_integer_expression() -> Result<()> {
         // 5
@@ -218,11 +323,10 @@ mod test {
 
         let results = vec![5, 10, -5, -10, 20, 25, 0, 30, 50];
 
-        let rust_eval = rust::RustEvaluator::new();
-
         for (idx, node) in ast.into_iter().enumerate() {
-            let rust_res = test_eval_program(&rust_eval, &create_program(vec![node]))?;
-            assert_eq!(rust_res.inspect(), results[idx].to_string())
+            let prog = create_program(vec![node]);
+            let res = eval_all(&prog, Custom::Agrees)?;
+            assert_eq!(res.inspect(), results[idx].to_string());
         }
 
         Ok(())
@@ -320,11 +424,10 @@ mod test {
             true, false, true, false, false, false, true, false, false, true, true, true, false,
             true, true,
         ];
-        let rust_eval = rust::RustEvaluator::new();
-
         for (idx, node) in ast.into_iter().enumerate() {
-            let rust_res = test_eval_program(&rust_eval, &create_program(vec![node]))?;
-            assert_eq!(rust_res.inspect(), results[idx].to_string())
+            let prog = create_program(vec![node]);
+            let res = eval_all(&prog, Custom::Agrees)?;
+            assert_eq!(res.inspect(), results[idx].to_string());
         }
 
         Ok(())
@@ -365,12 +468,11 @@ mod test {
             ),
         ];
 
-        let results = vec![false, true, false, true, false, true];
-        let rust_eval = rust::RustEvaluator::new();
-
+        let results = [false, true, false, true, false, true];
         for (idx, node) in ast.into_iter().enumerate() {
-            let rust_res = test_eval_program(&rust_eval, &create_program(vec![node]))?;
-            assert_eq!(rust_res.inspect(), results[idx].to_string())
+            let prog = create_program(vec![node]);
+            let res = eval_all(&prog, Custom::Agrees)?;
+            assert_eq!(res.inspect(), results[idx].to_string());
         }
 
         Ok(())
@@ -459,7 +561,7 @@ mod test {
             }),
         ];
 
-        let results = vec![
+        let results = [
             10.to_string(),
             NULL.to_string(),
             10.to_string(),
@@ -469,11 +571,10 @@ mod test {
             10.to_string(),
         ];
 
-        let rust_eval = rust::RustEvaluator::new();
-
         for (idx, node) in ast.into_iter().enumerate() {
-            let rust_res = test_eval_program(&rust_eval, &create_program(vec![node]))?;
-            assert_eq!(rust_res.inspect(), results[idx].to_string())
+            let prog = create_program(vec![node]);
+            let res = eval_all(&prog, Custom::Agrees)?;
+            assert_eq!(res.inspect(), results[idx].to_string());
         }
 
         Ok(())
@@ -539,12 +640,11 @@ mod test {
                 alternative: None,
             })],
         ];
-        let results = vec![1, 2, 6, 20, 100];
-
-        let rust_eval = rust::RustEvaluator::new();
+        let results = [1, 2, 6, 20, 100];
 
         for (idx, p) in programs.into_iter().enumerate() {
-            let res = test_eval_program(&rust_eval, &create_program(p))?;
+            let prog = create_program(p);
+            let res = eval_all(&prog, Custom::Agrees)?;
             assert_eq!(res.inspect(), results[idx].to_string());
         }
         Ok(())
@@ -645,7 +745,7 @@ mod test {
             ))],
         ];
 
-        let errors = vec![
+        let errors = [
             EvaluationError::MismatchedTypesInfix {
                 left: Object::Integer(5).type_as_string(),
                 right: Object::Boolean(true).type_as_string(),
@@ -676,10 +776,9 @@ mod test {
             },
         ];
 
-        let rust_eval = rust::RustEvaluator::new();
-
         for (idx, ast) in programs.into_iter().enumerate() {
-            let err = test_eval_program(&rust_eval, &create_program(ast)).unwrap_err();
+            let prog = create_program(ast);
+            let err = eval_all(&prog, Custom::Agrees).unwrap_err();
             assert_eq!(err.to_string(), errors[idx].to_string());
         }
 
@@ -748,13 +847,12 @@ mod test {
                 StatementType::Expression(Expression::Identifier("c".to_string())),
             ],
         ];
-        let results = vec![5, 25, 5, 15];
-
-        let rust_eval = rust::RustEvaluator::new();
+        let results = [5, 25, 5, 15];
 
         for (idx, ast) in programs.into_iter().enumerate() {
-            let rust_res = test_eval_program(&rust_eval, &create_program(ast))?;
-            assert_eq!(rust_res.inspect(), results[idx].to_string());
+            let prog = create_program(ast);
+            let res = eval_all(&prog, Custom::Agrees)?;
+            assert_eq!(res.inspect(), results[idx].to_string());
         }
 
         Ok(())
@@ -784,11 +882,9 @@ mod test {
             env: Rc::new(RefCell::new(Environment::new())),
         };
 
-        let rust_eval = rust::RustEvaluator::new();
-
-        let rust_res = test_eval_program(&rust_eval, &create_program(ast))?;
-
-        assert_eq!(rust_res, expect);
+        let prog = create_program(ast);
+        let res = eval_all(&prog, Custom::Agrees)?;
+        assert_eq!(res, expect);
 
         Ok(())
     }
@@ -918,13 +1014,12 @@ mod test {
             })],
         ];
 
-        let results = vec![5, 5, 10, 10, 20, 5];
-
-        let rust_eval = rust::RustEvaluator::new();
+        let results = [5, 5, 10, 10, 20, 5];
 
         for (idx, ast) in programs.into_iter().enumerate() {
-            let rust_res = test_eval_program(&rust_eval, &create_program(ast))?;
-            assert_eq!(rust_res.inspect(), results[idx].to_string());
+            let prog = create_program(ast);
+            let res = eval_all(&prog, Custom::Agrees)?;
+            assert_eq!(res.inspect(), results[idx].to_string());
         }
 
         Ok(())
@@ -983,7 +1078,7 @@ mod test {
             ],
         ];
 
-        let errors = vec![
+        let errors = [
             EvaluationError::WrongNumberOfArguments {
                 expected: 2,
                 found: 0,
@@ -994,10 +1089,9 @@ mod test {
             },
         ];
 
-        let rust_eval = rust::RustEvaluator::new();
-
         for (idx, ast) in programs.into_iter().enumerate() {
-            let err = test_eval_program(&rust_eval, &create_program(ast)).unwrap_err();
+            let prog = create_program(ast);
+            let err = eval_all(&prog, Custom::Agrees).unwrap_err();
             assert_eq!(err.to_string(), errors[idx].to_string());
         }
 
@@ -1050,13 +1144,459 @@ mod test {
                 arguments: vec![Expression::Integer(5)],
             }),
         ]];
-        let results = vec![120];
+        let results = [120];
 
-        let rust_eval = rust::RustEvaluator::new();
+        for (idx, ast) in programs.into_iter().enumerate() {
+            let prog = create_program(ast);
+            let res = eval_all(&prog, Custom::Agrees)?;
+            assert_eq!(res.inspect(), results[idx].to_string());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_closures_keep_the_globals_they_saw() -> Result<()> {
+        let programs = [
+            ("let x = 5; let f = fn() { x }; let x = 10; f()", "5"),
+            ("let x = 5; let f = fn() { x }; let x = 10; f() + x", "15"),
+            (
+                "let x = 1; let f = fn() { fn() { x } }; let x = 2; f()()",
+                "1",
+            ),
+        ];
+        for (input, expected) in programs.into_iter() {
+            let prog = parse_program(input);
+            let res = eval_all(&prog, Custom::Agrees)?;
+            assert_eq!(res.inspect(), expected, "{}", input);
+        }
+
+        // A function doesn't see the `let`s that come after it
+        let prog = parse_program(
+            "let a = fn(n) { if (n == 0) { 0 } else { b(n - 1) } }; let b = fn(n) { a(n) }; a(3)",
+        );
+        let err = eval_all(&prog, Custom::Agrees).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvaluationError>(),
+            Some(&EvaluationError::IdentifierNotFound {
+                identifier: "b".to_string()
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_string_expressions() -> Result<()> {
+        /*
+         * "Hello";
+         * "Hello" + ", " + "World!";
+         * let name = "Alice"; "Hi " + name;
+         * "a\"b\\c\n" == "a\"b\\c\n";
+         * "a" != "a";
+         */
+        let string = |s: &str| Expression::String(s.to_string());
+        let concat = |left, right| Expression::Infix {
+            left: Box::new(left),
+            op: InfixOp::Plus,
+            right: Box::new(right),
+        };
+        let programs = vec![
+            vec![StatementType::Expression(string("Hello"))],
+            vec![StatementType::Expression(concat(
+                concat(string("Hello"), string(", ")),
+                string("World!"),
+            ))],
+            vec![
+                StatementType::Let {
+                    name: Expression::Identifier("name".to_string()),
+                    value: string("Alice"),
+                },
+                StatementType::Expression(concat(
+                    string("Hi "),
+                    Expression::Identifier("name".to_string()),
+                )),
+            ],
+            vec![StatementType::Expression(Expression::Infix {
+                left: Box::new(string("a\"b\\c\n")),
+                op: InfixOp::Equal,
+                right: Box::new(string("a\"b\\c\n")),
+            })],
+            vec![StatementType::Expression(Expression::Infix {
+                left: Box::new(string("a")),
+                op: InfixOp::NotEqual,
+                right: Box::new(string("a")),
+            })],
+        ];
+        let results = [
+            Object::String("Hello".to_string()),
+            Object::String("Hello, World!".to_string()),
+            Object::String("Hi Alice".to_string()),
+            Object::Boolean(true),
+            Object::Boolean(false),
+        ];
+
+        for (idx, ast) in programs.into_iter().enumerate() {
+            let prog = create_program(ast);
+            let res = eval_all(&prog, Custom::Unsupported("Strings"))?;
+            assert_eq!(res, results[idx]);
+        }
+
+        let quoted = Object::String("say \"hi\"\n".to_string());
+        assert_eq!(quoted.inspect(), r#""say \"hi\"\n""#);
+        assert_eq!(quoted.to_string(), quoted.inspect());
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_string_errors() -> Result<()> {
+        /*
+         * "a" - "b";
+         * "a" + 1;
+         * -"a";
+         * true == "true";
+         */
+        let string = |s: &str| Box::new(Expression::String(s.to_string()));
+        let programs = vec![
+            vec![StatementType::Expression(Expression::Infix {
+                left: string("a"),
+                op: InfixOp::Minus,
+                right: string("b"),
+            })],
+            vec![StatementType::Expression(Expression::Infix {
+                left: string("a"),
+                op: InfixOp::Plus,
+                right: Box::new(Expression::Integer(1)),
+            })],
+            vec![StatementType::Expression(Expression::Prefix {
+                op: PrefixOp::Minus,
+                right: string("a"),
+            })],
+            vec![StatementType::Expression(Expression::Infix {
+                left: Box::new(Expression::Boolean(true)),
+                op: InfixOp::Equal,
+                right: string("true"),
+            })],
+        ];
+        let errors = [
+            EvaluationError::MismatchedTypesInfix {
+                left: "String",
+                right: "String",
+                operator: "-",
+            },
+            EvaluationError::MismatchedTypesInfix {
+                left: "String",
+                right: "Integer",
+                operator: "+",
+            },
+            EvaluationError::MissingIntegerToInvert { obj: "String" },
+            EvaluationError::MismatchedTypesInfix {
+                left: "Boolean",
+                right: "String",
+                operator: "==",
+            },
+        ];
 
         for (idx, ast) in programs.into_iter().enumerate() {
-            let rust_res = test_eval_program(&rust_eval, &create_program(ast))?;
-            assert_eq!(rust_res.inspect(), results[idx].to_string());
+            let prog = create_program(ast);
+            let err = eval_all(&prog, Custom::Unsupported("Strings")).unwrap_err();
+            assert_eq!(err.to_string(), errors[idx].to_string());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_array_expressions() -> Result<()> {
+        let programs = [
+            ("[1, 2 * 2, \"three\", [true]]", "[1, 4, \"three\", [true]]"),
+            ("[]", "[]"),
+            ("[1, 2, 3][0] + [1, 2, 3][2]", "4"),
+            ("let i = 1; [10, 20][i]", "20"),
+            ("[[1, 2], [3, 4]][1][0]", "3"),
+            ("[1, 2, 3][3]", "null"),
+            ("[1, 2, 3][-1]", "null"),
+            (
+                "let second = fn(arr) { arr[1] }; let make = fn(x) { [x, x * 2] }; second(make(21))",
+                "42",
+            ),
+            (
+                "let apply = fn(fs, x) { fs[1](x) }; apply([fn(x) { x }, fn(x) { x + 1 }], 1)",
+                "2",
+            ),
+        ];
+
+        for (input, expected) in programs.into_iter() {
+            let prog = parse_program(input);
+            let res = eval_all(&prog, Custom::Unsupported("Arrays"))?;
+            assert_eq!(res.inspect(), expected, "{}", input);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_index_errors() -> Result<()> {
+        let programs = [
+            ("1[0]", ("Integer", "Integer")),
+            ("[1][true]", ("Array", "Boolean")),
+            ("\"abc\"[0]", ("String", "Integer")),
+        ];
+
+        for (input, (left, index)) in programs.into_iter() {
+            let prog = parse_program(input);
+            let expected = EvaluationError::IndexNotSupported { left, index }.to_string();
+            let err = eval_all(&prog, Custom::Unsupported("Arrays")).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_hash_expressions() -> Result<()> {
+        // The custom evaluator rejects the first unsupported expression it compiles
+        let programs = [
+            (
+                "let two = \"two\"; {\"one\": 10 - 9, two: 1 + 1, \"thr\" + \"ee\": 6 / 2, 4: 4, true: 5}",
+                "{4: 4, true: 5, \"one\": 1, \"three\": 3, \"two\": 2}",
+                "Strings",
+            ),
+            ("{}", "{}", "Hashes"),
+            ("{\"a\": 1, \"a\": 2}", "{\"a\": 2}", "Hashes"),
+            ("{\"foo\": 5}[\"foo\"]", "5", "Arrays"),
+            ("{\"foo\": 5}[\"bar\"]", "null", "Arrays"),
+            ("let key = \"foo\"; {\"foo\": 5}[key]", "5", "Strings"),
+            (
+                "{5: 5}[5] + {true: 1}[true] + {false: 2}[false]",
+                "8",
+                "Arrays",
+            ),
+            ("{1: [1, {\"x\": 42}]}[1][1][\"x\"]", "42", "Arrays"),
+            (
+                "let get = fn(h, k) { h[k] }; get({\"f\": fn(x) { x * 2 }}, \"f\")(21)",
+                "42",
+                "Hashes",
+            ),
+        ];
+
+        for (input, expected, unsupported) in programs.into_iter() {
+            let prog = parse_program(input);
+            let res = eval_all(&prog, Custom::Unsupported(unsupported))?;
+            assert_eq!(res.inspect(), expected, "{}", input);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_unhashable_keys() -> Result<()> {
+        let programs = [
+            ("{fn(x) { x }: 1}", "Function", "Hashes"),
+            ("{[1]: 1}", "Array", "Hashes"),
+            ("{\"a\": 1}[{}]", "Hash", "Arrays"),
+            ("{1: 1}[fn() { 1 }]", "Function", "Arrays"),
+        ];
+
+        for (input, found, unsupported) in programs.into_iter() {
+            let prog = parse_program(input);
+            let expected = EvaluationError::UnhashableKey { found }.to_string();
+            let err = eval_all(&prog, Custom::Unsupported(unsupported)).unwrap_err();
+            assert_eq!(err.to_string(), expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_builtin_functions() -> Result<()> {
+        // The custom evaluator rejects the first unsupported expression it compiles
+        let programs = [
+            ("len(\"four\") + len([1, 2]) + len({1: 1})", "7", "Builtins"),
+            (
+                "type(1) + type(\"\") + type(len)",
+                "\"IntegerStringBuiltin\"",
+                "Builtins",
+            ),
+            (
+                "let a = push([1], 2); [first(a), last(a), rest(a), a]",
+                "[1, 2, [2], [1, 2]]",
+                "Builtins",
+            ),
+            ("[first([]), last([]), rest([])]", "[null, null, null]", "Arrays"),
+            ("int(\"12\") + int(str(30))", "42", "Builtins"),
+            ("int(\"twelve\")", "null", "Builtins"),
+            ("str(\"a\") + str(1) + str(true)", "\"a1true\"", "Builtins"),
+            ("let len = fn(x) { 0 }; len(\"abc\")", "0", "Strings"),
+            ("let f = fn(g) { g([3, 4]) }; f(last)", "4", "Builtins"),
+            ("first(push([], fn(x) { fn() { x } }(7)))()", "7", "Builtins"),
+            (
+                "let map = fn(arr, f) { let iter = fn(arr, acc) { if (len(arr) == 0) { acc } else { iter(rest(arr), push(acc, f(first(arr)))) } }; iter(arr, []) }; map([1, 2, 3], fn(x) { x * 2 })",
+                "[2, 4, 6]",
+                "Arrays",
+            ),
+            ("puts(\"from puts\")", "null", "Builtins"),
+        ];
+
+        for (input, expected, unsupported) in programs.into_iter() {
+            let prog = parse_program(input);
+            let res = eval_all(&prog, Custom::Unsupported(unsupported))?;
+            assert_eq!(res.inspect(), expected, "{}", input);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_builtin_errors() -> Result<()> {
+        let programs = [
+            (
+                "len(1)",
+                EvaluationError::UnsupportedArgument {
+                    builtin: "len",
+                    found: "Integer",
+                },
+            ),
+            (
+                "first(\"abc\")",
+                EvaluationError::UnsupportedArgument {
+                    builtin: "first",
+                    found: "String",
+                },
+            ),
+            (
+                "push(1, 2)",
+                EvaluationError::UnsupportedArgument {
+                    builtin: "push",
+                    found: "Integer",
+                },
+            ),
+            (
+                "len(\"a\", \"b\")",
+                EvaluationError::WrongNumberOfArguments {
+                    found: 2,
+                    expected: 1,
+                },
+            ),
+            (
+                "rest()",
+                EvaluationError::WrongNumberOfArguments {
+                    found: 0,
+                    expected: 1,
+                },
+            ),
+        ];
+
+        for (input, expected) in programs.into_iter() {
+            let prog = parse_program(input);
+            let err = eval_all(&prog, Custom::Unsupported("Builtins")).unwrap_err();
+            assert_eq!(err.to_string(), expected.to_string());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_modulo_comparison_and_logical_operators() -> Result<()> {
+        let programs = [
+            ("7 % 3", Object::Integer(1)),
+            ("-7 % 3", Object::Integer(-1)),
+            ("2 + 10 % 4 * 3", Object::Integer(8)),
+            ("1 <= 1", Object::Boolean(true)),
+            ("2 <= 1", Object::Boolean(false)),
+            ("1 >= 2", Object::Boolean(false)),
+            ("2 >= 2", Object::Boolean(true)),
+            ("true && false", Object::Boolean(false)),
+            ("true || false", Object::Boolean(true)),
+            ("false || false", Object::Boolean(false)),
+            ("1 && 0", Object::Boolean(true)),
+            ("1 < 2 && 2 < 3 == true", Object::Boolean(true)),
+            ("let x = 5; x > 1 && x % 2 == 1", Object::Boolean(true)),
+            (
+                "if (1 > 2 || 3 >= 3) { 10 } else { 20 }",
+                Object::Integer(10),
+            ),
+            (
+                "let digit = fn(n) { n >= 0 && n <= 9 }; digit(10) || digit(3)",
+                Object::Boolean(true),
+            ),
+            // The right side isn't evaluated when the left one decides
+            (
+                "let boom = fn() { 1 / 0 }; false && boom()",
+                Object::Boolean(false),
+            ),
+            (
+                "let boom = fn() { 1 / 0 }; true || boom()",
+                Object::Boolean(true),
+            ),
+        ];
+
+        for (input, expected) in programs.into_iter() {
+            let prog = parse_program(input);
+            assert_eq!(eval_all(&prog, Custom::Agrees)?, expected, "{}", input);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_operator_errors() -> Result<()> {
+        let programs = [
+            (
+                "true <= false",
+                EvaluationError::UnallowedBooleanComparisonOperator { operator: "<=" },
+            ),
+            (
+                "true % false",
+                EvaluationError::UnallowedBooleanComparisonOperator { operator: "%" },
+            ),
+            (
+                "5 >= true",
+                EvaluationError::MismatchedTypesInfix {
+                    left: "Integer",
+                    right: "Boolean",
+                    operator: ">=",
+                },
+            ),
+            ("let x = 0; 10 / x", EvaluationError::DivisionByZero),
+            ("let x = 0; 10 % x", EvaluationError::DivisionByZero),
+        ];
+
+        for (input, expected) in programs.into_iter() {
+            let prog = parse_program(input);
+            let err = eval_all(&prog, Custom::Agrees).unwrap_err();
+            assert_eq!(err.downcast::<EvaluationError>()?, expected);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn eval_integer_overflow() -> Result<()> {
+        // Wraps around instead of panicking. The custom evaluator has 32-bit
+        // integers, so it checks its own limits
+        let programs = [
+            ("9223372036854775807 + 1", Object::Integer(i64::MIN)),
+            ("-9223372036854775807 - 2", Object::Integer(i64::MAX)),
+            ("9223372036854775807 * 2", Object::Integer(-2)),
+            (
+                "let x = -9223372036854775807 - 1; -x",
+                Object::Integer(i64::MIN),
+            ),
+            (
+                "let m = -9223372036854775807 - 1; m % -1",
+                Object::Integer(0),
+            ),
+            (
+                "let m = -9223372036854775807 - 1; m / -1",
+                Object::Integer(i64::MIN),
+            ),
+        ];
+
+        for (input, expected) in programs.into_iter() {
+            let prog = parse_program(input);
+            assert_eq!(eval_all(&prog, Custom::Skipped)?, expected, "{}", input);
         }
 
         Ok(())
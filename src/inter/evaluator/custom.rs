@@ -1,6 +1,1061 @@
-use crate::inter::{ast::Program, environment::Environment, evaluator::Evaluator};
-use anyhow::Result;
+use std::{cell::RefCell, collections::HashMap, fmt::Write, rc::Rc};
 
+use crate::{
+    assembler::evaluator::AsmEvaluator,
+    inter::{
+        ast::{
+            expression::{InfixOp, PrefixOp},
+            Expression, Parser, Program, StatementType,
+        },
+        environment::Environment,
+        evaluator::{EvaluationError, Evaluator},
+        lexer::Lexer,
+        object::Object,
+    },
+    uarch::{mem::Ram, Computer},
+};
+use anyhow::{bail, Result};
+use thiserror::Error;
+
+/// Cycles after which a compiled program that didn't halt is given up on.
+const MAX_CYCLES: u32 = 10_000_000;
+
+/// Indexes of the general registers the compiled programs leave their results on:
+/// the value in `a0`, its type in `a3`, when it's only known at runtime, and the
+/// error that stopped the program in `s6`.
+const A0: usize = 12;
+const A3: usize = 15;
+const S6: usize = 11;
+
+/// Error codes set on `s6` by the runtime checks of the compiled programs.
+const DIVISION_BY_ZERO: u32 = 1;
+
+/// Represents errors of programs that can't be compiled or that fail while running
+/// on the microarchitecture.
+#[derive(Debug, Error, PartialEq)]
+pub enum CustomEvaluationError {
+    #[error("Integer '{int}' doesn't fit in the 32 bits of the microarchitecture")]
+    IntegerOutOfRange { int: i64 },
+
+    #[error("Closures are not supported: '{identifier}' belongs to an enclosing function")]
+    CapturedVariable { identifier: String },
+
+    #[error("The type of the value in {context} depends on the branch taken, only the result of a function or of the program can")]
+    AmbiguousType { context: &'static str },
+
+    #[error("Unable to infer the type returned by '{function}'")]
+    UninferableReturn { function: String },
+
+    #[error("{what} are not supported on the microarchitecture")]
+    Unsupported { what: &'static str },
+
+    #[error("The program didn't halt within {cycles} cycles")]
+    DidNotHalt { cycles: u32 },
+}
+
+/// Type of a value, known while compiling since the machine has no room for tags.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Null,
+    Int,
+    Boolean,
+    Function(usize),
+    /// Different types depending on the branch taken, with the type tag in `a3`.
+    Tagged,
+    /// Returned by a recursive call before the type of the function is inferred.
+    Unknown,
+    /// The value of code that always returns, like a block ending with `return`.
+    Never,
+}
+
+impl Type {
+    fn name(&self) -> &'static str {
+        match self {
+            Type::Null => "Null",
+            Type::Int => "Integer",
+            Type::Boolean => "Boolean",
+            Type::Function(_) => "Function",
+            Type::Tagged | Type::Unknown | Type::Never => "Unknown",
+        }
+    }
+
+    fn tag(&self) -> Option<u32> {
+        match self {
+            Type::Null => Some(0),
+            Type::Int => Some(1),
+            Type::Boolean => Some(2),
+            Type::Function(_) => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Whether the type stands for whatever the other side of an operation is.
+    fn is_wildcard(&self) -> bool {
+        matches!(self, Type::Unknown | Type::Never)
+    }
+
+    /// Type of a value that can come from either of two branches.
+    fn join(self, other: Type) -> Type {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (a, b) if a.is_wildcard() => b,
+            (a, b) if b.is_wildcard() => a,
+            _ => Type::Tagged,
+        }
+    }
+}
+
+/// A line of the generated assembly.
+enum Line {
+    Label(String),
+    Asm(String),
+    Comment(String),
+    /// Where a branch sets the type tag of its value, if the branches turn out to
+    /// have different types.
+    Tag(usize),
+}
+
+/// Where a variable lives.
+#[derive(Clone)]
+enum Slot {
+    /// Word above the frame pointer, for the arguments.
+    Arg(usize),
+    /// Word below the frame pointer, for the `let`s of a function.
+    Local(usize),
+    /// Data label, for the `let`s of the program.
+    Global(String),
+    /// A function bound to the name it was called by, which takes no room.
+    Function,
+    /// A value left by a previous program in the environment.
+    Const(i64),
+}
+
+struct FnDef {
+    params: Vec<Expression>,
+    body: StatementType,
+    /// Variables of the functions it was written in, which it can't see.
+    outer: Vec<String>,
+    /// Globals of the program when the function was created, which it sees with the
+    /// values they had then.
+    globals: Vec<(String, String, Type)>,
+}
+
+/// A function compiled for the types of its arguments.
+struct Spec {
+    def: usize,
+    args: Vec<Type>,
+    name: Option<String>,
+    label: String,
+    /// Type returned, `None` while the function is still being compiled.
+    ret: Option<Type>,
+    code: Vec<Line>,
+}
+
+struct Frame {
+    vars: HashMap<String, (Slot, Type)>,
+    outer: Vec<String>,
+    globals: Vec<(String, String, Type)>,
+    locals: usize,
+    ret_label: String,
+    ret: Type,
+    /// The `return`s and the end of the body, tagged if the types they return differ.
+    ret_sites: Vec<(usize, Type)>,
+}
+
+impl Frame {
+    fn new(ret_label: String) -> Self {
+        Self {
+            vars: HashMap::new(),
+            outer: Vec::new(),
+            globals: Vec::new(),
+            locals: 0,
+            ret_label,
+            ret: Type::Never,
+            ret_sites: Vec::new(),
+        }
+    }
+}
+
+/// Whether the condition of an `if` is known while compiling.
+enum Cond {
+    Runtime,
+    Always(bool),
+}
+
+/// Compiles the AST to Vondel assembly, with the program in the `main` label and
+/// each function specialized for the types of its arguments.
+struct Compiler<'e> {
+    env: &'e Environment,
+    defs: Vec<Rc<FnDef>>,
+    /// Functions by the literal they come from, or by their name in the environment.
+    literals: HashMap<*const Expression, usize>,
+    env_defs: HashMap<String, usize>,
+    specs: Vec<Spec>,
+    /// Specs whose type was needed while they were still being compiled.
+    unknowns: Vec<usize>,
+    /// Data label and type of each `let` of the program, a name bound again getting
+    /// a new label so the functions created before keep seeing the old value.
+    globals: Vec<(String, String, Type)>,
+    frames: Vec<Frame>,
+    code: Vec<Line>,
+    tags: Vec<Option<u32>>,
+    labels: usize,
+    uses_div: bool,
+}
+
+/// Result of compiling a program.
+struct Compiled {
+    asm: String,
+    ret: Type,
+    defs: Vec<Rc<FnDef>>,
+    globals: Vec<(String, String, Type)>,
+}
+
+impl<'e> Compiler<'e> {
+    fn new(env: &'e Environment) -> Self {
+        Self {
+            env,
+            defs: Vec::new(),
+            literals: HashMap::new(),
+            env_defs: HashMap::new(),
+            specs: Vec::new(),
+            unknowns: Vec::new(),
+            globals: Vec::new(),
+            frames: vec![Frame::new("__end".to_string())],
+            code: Vec::new(),
+            tags: Vec::new(),
+            labels: 0,
+            uses_div: false,
+        }
+    }
+
+    fn compile(mut self, prog: &Program) -> Result<Compiled> {
+        let mut ty = Type::Null;
+        self.emit_label("main");
+        for stmt in prog.statements.iter() {
+            ty = self.compile_statement(stmt)?;
+            if ty == Type::Never {
+                break;
+            }
+        }
+        if ty != Type::Never {
+            self.ret_site(ty);
+        }
+        let frame = self.frames.pop().expect("the program has a frame");
+        let ret = frame.ret;
+        self.tag_sites(&frame.ret_sites, ret);
+        self.emit_label("__end");
+        self.emit("halt");
+
+        if self.uses_div {
+            self.emit_div();
+        }
+
+        let mut asm = String::new();
+        if !self.globals.is_empty() {
+            asm.push_str(".data\n");
+            for (name, label, _) in self.globals.iter() {
+                let _ = writeln!(asm, "    {}: .word 0 ; {}", label, name);
+            }
+            asm.push('\n');
+        }
+        asm.push_str(".text\n");
+        self.render(&self.code, &mut asm);
+        for spec in self.specs.iter() {
+            asm.push('\n');
+            self.render(&spec.code, &mut asm);
+        }
+
+        Ok(Compiled {
+            asm,
+            ret,
+            defs: self.defs,
+            globals: self.globals,
+        })
+    }
+
+    fn render(&self, code: &[Line], asm: &mut String) {
+        for line in code {
+            let _ = match line {
+                Line::Label(label) => writeln!(asm, "{}:", label),
+                Line::Asm(instr) => writeln!(asm, "    {}", instr),
+                Line::Comment(text) => writeln!(asm, "; {}", text),
+                Line::Tag(site) => match self.tags[*site] {
+                    Some(tag) => writeln!(asm, "    lui a3 <- {}", tag),
+                    None => Ok(()),
+                },
+            };
+        }
+    }
+
+    fn emit(&mut self, instr: impl Into<String>) {
+        self.code.push(Line::Asm(instr.into()));
+    }
+
+    fn emit_label(&mut self, label: impl Into<String>) {
+        self.code.push(Line::Label(label.into()));
+    }
+
+    fn new_label(&mut self) -> String {
+        self.labels += 1;
+        format!("L{}", self.labels)
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("there is always a frame")
+    }
+
+    fn is_top_level(&self) -> bool {
+        self.frames.len() == 1
+    }
+
+    /// The globals the code being compiled sees, the ones of the program or, in a
+    /// function, the ones there were when it was created.
+    fn visible_globals(&self) -> &[(String, String, Type)] {
+        match self.frames.as_slice() {
+            [_] => &self.globals,
+            [.., frame] => &frame.globals,
+            [] => unreachable!("there is always a frame"),
+        }
+    }
+
+    /// Marks where a branch with a value of type `ty` ends.
+    fn tag_site(&mut self, ty: Type) -> (usize, Type) {
+        self.tags.push(None);
+        self.code.push(Line::Tag(self.tags.len() - 1));
+        (self.tags.len() - 1, ty)
+    }
+
+    /// Sets the type tags of branches whose value has type `ty` once joined.
+    fn tag_sites(&mut self, sites: &[(usize, Type)], ty: Type) {
+        if ty == Type::Tagged {
+            for (site, ty) in sites {
+                self.tags[*site] = ty.tag();
+            }
+        }
+    }
+
+    fn ret_site(&mut self, ty: Type) {
+        let site = self.tag_site(ty);
+        let frame = self.frame();
+        frame.ret = frame.ret.join(ty);
+        frame.ret_sites.push(site);
+    }
+
+    fn load_int(&mut self, reg: &str, int: i64) -> Result<()> {
+        match int {
+            0..=255 => self.emit(format!("lui {} <- {}", reg, int)),
+            int if i32::try_from(int).is_ok() => self.emit(format!("li {} <- {}", reg, int)),
+            int => bail!(CustomEvaluationError::IntegerOutOfRange { int }),
+        }
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, stmt: &StatementType) -> Result<Type> {
+        match stmt {
+            StatementType::Expression(expr) => self.compile_expression(expr),
+            StatementType::Block(stmts) => {
+                let mut ty = Type::Null;
+                if stmts.is_empty() {
+                    self.emit("lui a0 <- 0");
+                }
+                for stmt in stmts.iter() {
+                    ty = self.compile_statement(stmt)?;
+                    // Whatever comes after a return is never run
+                    if ty == Type::Never {
+                        break;
+                    }
+                }
+                Ok(ty)
+            }
+            StatementType::Return(expr) => {
+                let ty = self.compile_expression(expr)?;
+                self.ret_site(ty);
+                let label = self.frame().ret_label.clone();
+                self.emit(format!("jal {}", label));
+                Ok(Type::Never)
+            }
+            StatementType::Let { name, value } => {
+                let Expression::Identifier(name) = name else {
+                    bail!(EvaluationError::ExpectedIdentifier {
+                        found: name.type_as_string()
+                    });
+                };
+                let ty = self.compile_expression(value)?;
+                if ty == Type::Tagged {
+                    bail!(CustomEvaluationError::AmbiguousType { context: "a let" });
+                }
+                let slot = self.define(name, ty);
+                self.store(&slot);
+                Ok(ty)
+            }
+        }
+    }
+
+    /// Finds or makes room for a variable, global in the program and local in a
+    /// function, like the environments of the interpreter.
+    fn define(&mut self, name: &str, ty: Type) -> Slot {
+        if self.is_top_level() {
+            let label = match self.globals.iter().filter(|(n, ..)| n == name).count() {
+                0 => format!("v_{}", name),
+                n => format!("v{}_{}", n, name),
+            };
+            self.globals.push((name.to_string(), label.clone(), ty));
+            return Slot::Global(label);
+        }
+
+        let frame = self.frame();
+        let slot = match frame.vars.get(name) {
+            Some((slot @ (Slot::Arg(_) | Slot::Local(_)), _)) => slot.clone(),
+            _ => {
+                frame.locals += 1;
+                Slot::Local(frame.locals)
+            }
+        };
+        frame.vars.insert(name.to_string(), (slot.clone(), ty));
+        slot
+    }
+
+    fn store(&mut self, slot: &Slot) {
+        match slot {
+            Slot::Arg(off) => self.emit(format!("write [lv + {}] <- a0", off)),
+            Slot::Local(off) => self.emit(format!("write [lv - {}] <- a0", off)),
+            Slot::Global(label) => self.emit(format!("write {} <- a0", label)),
+            Slot::Function | Slot::Const(_) => {}
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Result<(Slot, Type)> {
+        if !self.is_top_level() {
+            if let Some(var) = self.frame().vars.get(name) {
+                return Ok(var.clone());
+            }
+            // Closures would need their environment on the heap
+            if self.frame().outer.iter().any(|n| n == name) {
+                bail!(CustomEvaluationError::CapturedVariable {
+                    identifier: name.to_string()
+                });
+            }
+        }
+        if let Some((_, label, ty)) = self.visible_globals().iter().rfind(|(n, ..)| n == name) {
+            return Ok((Slot::Global(label.clone()), *ty));
+        }
+        if let Ok(obj) = self.env.get(name) {
+            return match obj {
+                Object::Integer(i) => Ok((Slot::Const(i), Type::Int)),
+                Object::Boolean(b) => Ok((Slot::Const(b as i64), Type::Boolean)),
+                Object::Function { params, body, .. } => {
+                    let def = match self.env_defs.get(name) {
+                        Some(def) => *def,
+                        None => {
+                            self.defs.push(Rc::new(FnDef {
+                                params,
+                                body,
+                                outer: Vec::new(),
+                                globals: Vec::new(),
+                            }));
+                            self.env_defs.insert(name.to_string(), self.defs.len() - 1);
+                            self.defs.len() - 1
+                        }
+                    };
+                    Ok((Slot::Const(def as i64), Type::Function(def)))
+                }
+                Object::String(_) => bail!(CustomEvaluationError::Unsupported { what: "Strings" }),
+                Object::Array(_) => bail!(CustomEvaluationError::Unsupported { what: "Arrays" }),
+                Object::Hash(_) => bail!(CustomEvaluationError::Unsupported { what: "Hashes" }),
+                Object::Builtin(_) => {
+                    bail!(CustomEvaluationError::Unsupported { what: "Builtins" })
+                }
+                _ => Ok((Slot::Const(0), Type::Null)),
+            };
+        }
+        bail!(EvaluationError::IdentifierNotFound {
+            identifier: name.to_string()
+        })
+    }
+
+    /// Loads a literal or a variable straight into `reg`, returning `None` for the
+    /// expressions that have to go through `a0`.
+    fn load_simple(&mut self, expr: &Expression, reg: &str) -> Result<Option<Type>> {
+        match expr {
+            Expression::Integer(i) => {
+                self.load_int(reg, *i)?;
+                Ok(Some(Type::Int))
+            }
+            Expression::Boolean(b) => {
+                self.emit(format!("lui {} <- {}", reg, *b as u8));
+                Ok(Some(Type::Boolean))
+            }
+            Expression::Identifier(name) => {
+                let (slot, ty) = self.lookup(name)?;
+                match slot {
+                    Slot::Arg(off) => self.emit(format!("read {} <- [lv + {}]", reg, off)),
+                    Slot::Local(off) => self.emit(format!("read {} <- [lv - {}]", reg, off)),
+                    Slot::Global(label) => self.emit(format!("read {} <- {}", reg, label)),
+                    Slot::Function => match ty {
+                        Type::Function(def) => self.load_int(reg, def as i64)?,
+                        _ => unreachable!("only functions are bound to their name"),
+                    },
+                    Slot::Const(value) => self.load_int(reg, value)?,
+                }
+                Ok(Some(ty))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn compile_expression(&mut self, expr: &Expression) -> Result<Type> {
+        if let Some(ty) = self.load_simple(expr, "a0")? {
+            return Ok(ty);
+        }
+
+        match expr {
+            Expression::Prefix { op, right } => {
+                let ty = self.compile_expression(right)?;
+                self.compile_prefix(op, ty)
+            }
+            Expression::Infix { left, op, right } => self.compile_infix(left, op, right),
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => self.compile_if(condition, consequence, alternative.as_deref()),
+            Expression::FunctionLiteral { parameters, block } => {
+                let key = expr as *const Expression;
+                let def = match self.literals.get(&key) {
+                    Some(def) => *def,
+                    None => {
+                        let outer = match self.is_top_level() {
+                            true => Vec::new(),
+                            false => {
+                                let frame = self.frames.last().expect("there is always a frame");
+                                frame
+                                    .vars
+                                    .keys()
+                                    .chain(frame.outer.iter())
+                                    .cloned()
+                                    .collect()
+                            }
+                        };
+                        self.defs.push(Rc::new(FnDef {
+                            params: parameters.clone(),
+                            body: *block.clone(),
+                            outer,
+                            globals: self.visible_globals().to_vec(),
+                        }));
+                        self.literals.insert(key, self.defs.len() - 1);
+                        self.defs.len() - 1
+                    }
+                };
+                self.load_int("a0", def as i64)?;
+                Ok(Type::Function(def))
+            }
+            Expression::Call {
+                function,
+                arguments,
+            } => self.compile_call(function, arguments),
+            Expression::String(_) => bail!(CustomEvaluationError::Unsupported { what: "Strings" }),
+            Expression::Array(_) | Expression::Index { .. } => {
+                bail!(CustomEvaluationError::Unsupported { what: "Arrays" })
+            }
+            Expression::Hash(_) => bail!(CustomEvaluationError::Unsupported { what: "Hashes" }),
+            Expression::Integer(_) | Expression::Boolean(_) | Expression::Identifier(_) => {
+                unreachable!("loaded by load_simple")
+            }
+        }
+    }
+
+    fn compile_prefix(&mut self, op: &PrefixOp, ty: Type) -> Result<Type> {
+        match (op, ty) {
+            (_, Type::Tagged) => bail!(CustomEvaluationError::AmbiguousType {
+                context: "a prefix expression"
+            }),
+            (PrefixOp::Minus, Type::Int | Type::Unknown | Type::Never) => {
+                self.emit("not a0 <- a0");
+                self.emit("addi a0 <- a0, 1");
+                Ok(Type::Int)
+            }
+            (PrefixOp::Minus, ty) => {
+                bail!(EvaluationError::MissingIntegerToInvert { obj: ty.name() })
+            }
+            (PrefixOp::Bang, Type::Boolean | Type::Unknown | Type::Never) => {
+                self.emit("xori a0 <- a0, 1");
+                Ok(Type::Boolean)
+            }
+            (PrefixOp::Bang, Type::Null) => {
+                self.emit("lui a0 <- 1");
+                Ok(Type::Boolean)
+            }
+            (PrefixOp::Bang, _) => {
+                self.emit("lui a0 <- 0");
+                Ok(Type::Boolean)
+            }
+        }
+    }
+
+    /// Leaves the left operand in `a0` and the right one in `a1`, through the
+    /// stack when the right one needs `a0` too.
+    fn compile_operands(&mut self, left: &Expression, right: &Expression) -> Result<(Type, Type)> {
+        let lt = self.compile_expression(left)?;
+        if let Some(rt) = self.load_simple(right, "a1")? {
+            return Ok((lt, rt));
+        }
+        self.emit("push a0");
+        let rt = self.compile_expression(right)?;
+        self.emit("mov a1 <- a0");
+        self.emit("pop a0");
+        Ok((lt, rt))
+    }
+
+    /// Type of an infix expression, with the same rules as the interpreter.
+    fn infix_type(&self, lt: Type, op: &InfixOp, rt: Type) -> Result<Type> {
+        let (lt, rt) = match (lt, rt) {
+            (Type::Tagged, _) | (_, Type::Tagged) => {
+                bail!(CustomEvaluationError::AmbiguousType {
+                    context: "an infix expression"
+                })
+            }
+            (l, r) if l.is_wildcard() && r.is_wildcard() => (Type::Int, Type::Int),
+            (l, r) if l.is_wildcard() => (r, r),
+            (l, r) if r.is_wildcard() => (l, l),
+            types => types,
+        };
+        let comparison = matches!(
+            op,
+            InfixOp::Equal
+                | InfixOp::NotEqual
+                | InfixOp::LessThan
+                | InfixOp::GreaterThan
+                | InfixOp::LessEqual
+                | InfixOp::GreaterEqual
+        );
+        match (lt, rt) {
+            (Type::Int, Type::Int) if comparison => Ok(Type::Boolean),
+            (Type::Int, Type::Int) => Ok(Type::Int),
+            (Type::Boolean, Type::Boolean) => match op {
+                InfixOp::Equal | InfixOp::NotEqual => Ok(Type::Boolean),
+                _ => bail!(EvaluationError::UnallowedBooleanComparisonOperator {
+                    operator: op.type_as_string(),
+                }),
+            },
+            _ => bail!(EvaluationError::MismatchedTypesInfix {
+                left: lt.name(),
+                right: rt.name(),
+                operator: op.type_as_string(),
+            }),
+        }
+    }
+
+    fn compile_infix(
+        &mut self,
+        left: &Expression,
+        op: &InfixOp,
+        right: &Expression,
+    ) -> Result<Type> {
+        if let InfixOp::And | InfixOp::Or = op {
+            return self.compile_logical(left, op, right);
+        }
+
+        // Small constants go as the immediate of the instruction
+        if let (Expression::Integer(imm @ 0..=255), Some(instr)) = (right, immediate_instr(op)) {
+            let lt = self.compile_expression(left)?;
+            let ty = self.infix_type(lt, op, Type::Int)?;
+            self.emit(format!("{} a0 <- a0, {}", instr, imm));
+            return Ok(ty);
+        }
+
+        let (lt, rt) = self.compile_operands(left, right)?;
+        let ty = self.infix_type(lt, op, rt)?;
+        match op {
+            InfixOp::Plus => self.emit("add a0 <- a0, a1"),
+            InfixOp::Minus => self.emit("sub a0 <- a0, a1"),
+            InfixOp::Asterisk => self.emit("mul2 a0 <- a0, a1"),
+            InfixOp::Slash => {
+                self.uses_div = true;
+                self.emit("call __div");
+            }
+            InfixOp::Modulo => {
+                self.uses_div = true;
+                self.emit("call __mod");
+            }
+            op => {
+                let (yes, end) = (self.new_label(), self.new_label());
+                self.emit(format!("{} a0, a1, {}", branch(op, false), yes));
+                self.emit("lui a0 <- 0");
+                self.emit(format!("jal {}", end));
+                self.emit_label(yes);
+                self.emit("lui a0 <- 1");
+                self.emit_label(end);
+            }
+        }
+        Ok(ty)
+    }
+
+    /// Compiles `&&` and `||` to a boolean, only running the right side when the
+    /// left one doesn't decide it, like the interpreter.
+    fn compile_logical(
+        &mut self,
+        left: &Expression,
+        op: &InfixOp,
+        right: &Expression,
+    ) -> Result<Type> {
+        let (next, falsy, end) = (self.new_label(), self.new_label(), self.new_label());
+        let first = match op {
+            InfixOp::And => self.compile_condition(left, &falsy)?,
+            _ => self.compile_condition(left, &next)?,
+        };
+        match (op, first) {
+            (InfixOp::And, Cond::Always(false)) => {
+                self.emit("lui a0 <- 0");
+                return Ok(Type::Boolean);
+            }
+            (InfixOp::Or, Cond::Always(true)) => {
+                self.emit("lui a0 <- 1");
+                return Ok(Type::Boolean);
+            }
+            (InfixOp::Or, Cond::Runtime) => {
+                self.emit("lui a0 <- 1");
+                self.emit(format!("jal {}", end));
+            }
+            _ => {}
+        }
+
+        self.emit_label(next);
+        if !matches!(self.compile_condition(right, &falsy)?, Cond::Always(false)) {
+            self.emit("lui a0 <- 1");
+            self.emit(format!("jal {}", end));
+        }
+        self.emit_label(falsy);
+        self.emit("lui a0 <- 0");
+        self.emit_label(end);
+        Ok(Type::Boolean)
+    }
+
+    /// Compiles the condition of an `if`, jumping to `otherwise` when it's falsy.
+    fn compile_condition(&mut self, cond: &Expression, otherwise: &str) -> Result<Cond> {
+        // Comparisons branch on their own, without making a boolean first
+        if let Expression::Infix { left, op, right } = cond {
+            if !matches!(
+                op,
+                InfixOp::Plus
+                    | InfixOp::Minus
+                    | InfixOp::Asterisk
+                    | InfixOp::Slash
+                    | InfixOp::Modulo
+                    | InfixOp::And
+                    | InfixOp::Or
+            ) {
+                let (lt, rt) = self.compile_operands(left, right)?;
+                self.infix_type(lt, op, rt)?;
+                self.emit(format!("{} a0, a1, {}", branch(op, true), otherwise));
+                return Ok(Cond::Runtime);
+            }
+        }
+
+        match self.compile_expression(cond)? {
+            Type::Boolean | Type::Unknown => {
+                self.emit(format!("beqz a0, {}", otherwise));
+                Ok(Cond::Runtime)
+            }
+            Type::Tagged => bail!(CustomEvaluationError::AmbiguousType {
+                context: "a condition"
+            }),
+            Type::Null => Ok(Cond::Always(false)),
+            Type::Int | Type::Function(_) | Type::Never => Ok(Cond::Always(true)),
+        }
+    }
+
+    fn compile_if(
+        &mut self,
+        cond: &Expression,
+        cons: &StatementType,
+        alt: Option<&StatementType>,
+    ) -> Result<Type> {
+        let (otherwise, end) = (self.new_label(), self.new_label());
+        match self.compile_condition(cond, &otherwise)? {
+            Cond::Always(true) => self.compile_statement(cons),
+            Cond::Always(false) => match alt {
+                Some(alt) => self.compile_statement(alt),
+                None => {
+                    self.emit("lui a0 <- 0");
+                    Ok(Type::Null)
+                }
+            },
+            Cond::Runtime => {
+                let ct = self.compile_statement(cons)?;
+                let cons_site = self.tag_site(ct);
+                if ct != Type::Never {
+                    self.emit(format!("jal {}", end));
+                }
+                self.emit_label(otherwise);
+                let at = match alt {
+                    Some(alt) => self.compile_statement(alt)?,
+                    None => {
+                        self.emit("lui a0 <- 0");
+                        Type::Null
+                    }
+                };
+                let alt_site = self.tag_site(at);
+                self.emit_label(end);
+
+                let ty = ct.join(at);
+                self.tag_sites(&[cons_site, alt_site], ty);
+                Ok(ty)
+            }
+        }
+    }
+
+    fn compile_call(&mut self, function: &Expression, arguments: &[Expression]) -> Result<Type> {
+        let ty = match function {
+            Expression::Identifier(name) => self.lookup(name)?.1,
+            function => self.compile_expression(function)?,
+        };
+        let def = match ty {
+            Type::Function(def) => def,
+            Type::Tagged => bail!(CustomEvaluationError::AmbiguousType { context: "a call" }),
+            Type::Unknown | Type::Never => bail!(CustomEvaluationError::UninferableReturn {
+                function: function.type_as_string().to_string()
+            }),
+            ty => bail!(EvaluationError::NotAFunction { found: ty.name() }),
+        };
+
+        let params = self.defs[def].params.len();
+        if arguments.len() != params {
+            bail!(EvaluationError::WrongNumberOfArguments {
+                found: arguments.len(),
+                expected: params,
+            });
+        }
+
+        let mut args = Vec::with_capacity(arguments.len());
+        for arg in arguments {
+            let ty = self.compile_expression(arg)?;
+            if ty == Type::Tagged {
+                bail!(CustomEvaluationError::AmbiguousType {
+                    context: "an argument"
+                });
+            }
+            args.push(ty);
+            self.emit("push a0");
+        }
+
+        let name = match function {
+            Expression::Identifier(name) => Some(name.clone()),
+            _ => None,
+        };
+        let spec = self.specialize(def, args, name)?;
+        self.emit(format!("call {}", self.specs[spec].label));
+        if !arguments.is_empty() {
+            self.emit(format!("addi cpp <- cpp, {}", arguments.len()));
+        }
+
+        match self.specs[spec].ret {
+            Some(ty) => Ok(ty),
+            None => {
+                self.unknowns.push(spec);
+                Ok(Type::Unknown)
+            }
+        }
+    }
+
+    /// Finds the function compiled for these arguments, compiling it if needed.
+    /// Recursive functions are compiled again once the type they return is known,
+    /// along with what was compiled while it wasn't.
+    fn specialize(&mut self, def: usize, args: Vec<Type>, name: Option<String>) -> Result<usize> {
+        if let Some(spec) = self
+            .specs
+            .iter()
+            .position(|s| s.def == def && s.args == args && s.name == name)
+        {
+            return Ok(spec);
+        }
+
+        let idx = self.specs.len();
+        let label = match &name {
+            Some(name) => format!("f_{}_{}", name, idx),
+            None => format!("f_{}", idx),
+        };
+        self.specs.push(Spec {
+            def,
+            args,
+            name,
+            label,
+            ret: None,
+            code: Vec::new(),
+        });
+
+        for _ in 0..3 {
+            self.specs.truncate(idx + 1);
+            self.unknowns.retain(|s| *s <= idx);
+            let (code, ret) = self.compile_spec(idx)?;
+
+            let needed_itself = self.unknowns.contains(&idx);
+            self.unknowns.retain(|s| *s != idx);
+            let spec = &mut self.specs[idx];
+            let converged = spec.ret == Some(ret) || !needed_itself;
+            spec.ret = Some(ret);
+            if converged {
+                spec.code = code;
+                // A function that never returns gives back nothing
+                if ret.is_wildcard() {
+                    spec.ret = Some(Type::Null);
+                }
+                return Ok(idx);
+            }
+        }
+
+        let spec = &self.specs[idx];
+        bail!(CustomEvaluationError::UninferableReturn {
+            function: spec.name.clone().unwrap_or_else(|| spec.label.clone())
+        })
+    }
+
+    fn compile_spec(&mut self, idx: usize) -> Result<(Vec<Line>, Type)> {
+        let spec = &self.specs[idx];
+        let def = Rc::clone(&self.defs[spec.def]);
+        let label = spec.label.clone();
+        let mut frame = Frame::new(format!("{}_ret", label));
+        frame.outer = def.outer.clone();
+        frame.globals = def.globals.clone();
+
+        // The arguments were pushed in order, so the last one is the closest to the
+        // return address and the saved frame pointer
+        let n = def.params.len();
+        for (i, (param, ty)) in def.params.iter().zip(spec.args.iter()).enumerate() {
+            let Expression::Identifier(param) = param else {
+                bail!(EvaluationError::ExpectedIdentifier {
+                    found: param.type_as_string()
+                });
+            };
+            frame
+                .vars
+                .insert(param.clone(), (Slot::Arg(n - i + 1), *ty));
+        }
+        if let Some(name) = &spec.name {
+            frame
+                .vars
+                .insert(name.clone(), (Slot::Function, Type::Function(spec.def)));
+        }
+        let signature = spec
+            .args
+            .iter()
+            .map(Type::name)
+            .collect::<Vec<_>>()
+            .join(", ");
+        let comment = format!("{}({})", spec.name.as_deref().unwrap_or("fn"), signature);
+
+        let outer = std::mem::take(&mut self.code);
+        self.frames.push(frame);
+        let res = self.compile_statement(&def.body);
+        if let Ok(ty) = res {
+            if ty != Type::Never {
+                self.ret_site(ty);
+            }
+        }
+        let frame = self.frames.pop().expect("the function has a frame");
+        let body = std::mem::replace(&mut self.code, outer);
+        res?;
+        self.tag_sites(&frame.ret_sites, frame.ret);
+
+        let mut code = vec![Line::Comment(comment), Line::Label(label)];
+        code.push(Line::Asm("push ra".to_string()));
+        code.push(Line::Asm("push lv".to_string()));
+        code.push(Line::Asm("mov lv <- cpp".to_string()));
+        if frame.locals > 0 {
+            code.push(Line::Asm(format!("subi cpp <- cpp, {}", frame.locals)));
+        }
+        code.extend(body);
+        code.push(Line::Label(frame.ret_label));
+        code.push(Line::Asm("mov cpp <- lv".to_string()));
+        code.push(Line::Asm("pop lv".to_string()));
+        code.push(Line::Asm("pop ra".to_string()));
+        code.push(Line::Asm("ret".to_string()));
+        Ok((code, frame.ret))
+    }
+
+    /// Signed division and remainder of `a0` by `a1`, rounding towards zero like the
+    /// interpreter, since the ALU divides unsigned words.
+    fn emit_div(&mut self) {
+        self.code.push(Line::Comment(
+            "a0 <- a0 / a1, signed, stopping on a division by zero".to_string(),
+        ));
+        self.emit_label("__div");
+        self.emit("beqz a1, __div_by_zero");
+        self.emit("lui a2 <- 0");
+        self.emit("bgez a0, __div_left");
+        self.emit("not a0 <- a0");
+        self.emit("addi a0 <- a0, 1");
+        self.emit("xori a2 <- a2, 1");
+        self.emit_label("__div_left");
+        self.emit("bgez a1, __div_right");
+        self.emit("not a1 <- a1");
+        self.emit("addi a1 <- a1, 1");
+        self.emit("xori a2 <- a2, 1");
+        self.emit_label("__div_right");
+        self.emit("div a0 <- a0, a1");
+        self.emit("beqz a2, __div_end");
+        self.emit("not a0 <- a0");
+        self.emit("addi a0 <- a0, 1");
+        self.emit_label("__div_end");
+        self.emit("ret");
+        self.code.push(Line::Comment(
+            "a0 <- a0 % a1, signed like the dividend, stopping on a division by zero".to_string(),
+        ));
+        self.emit_label("__mod");
+        self.emit("beqz a1, __div_by_zero");
+        self.emit("lui a2 <- 0");
+        self.emit("bgez a0, __mod_left");
+        self.emit("not a0 <- a0");
+        self.emit("addi a0 <- a0, 1");
+        self.emit("lui a2 <- 1");
+        self.emit_label("__mod_left");
+        self.emit("bgez a1, __mod_right");
+        self.emit("not a1 <- a1");
+        self.emit("addi a1 <- a1, 1");
+        self.emit_label("__mod_right");
+        self.emit("mod a0 <- a0, a1");
+        self.emit("beqz a2, __mod_end");
+        self.emit("not a0 <- a0");
+        self.emit("addi a0 <- a0, 1");
+        self.emit_label("__mod_end");
+        self.emit("ret");
+        self.emit_label("__div_by_zero");
+        self.emit(format!("lui s6 <- {}", DIVISION_BY_ZERO));
+        self.emit("halt");
+    }
+}
+
+/// Instruction taking the right operand as an immediate, for the operators that
+/// have one.
+fn immediate_instr(op: &InfixOp) -> Option<&'static str> {
+    match op {
+        InfixOp::Plus => Some("addi"),
+        InfixOp::Minus => Some("subi"),
+        InfixOp::Asterisk => Some("muli"),
+        _ => None,
+    }
+}
+
+/// Branch taken when a comparison holds, or when it doesn't if `negated`. The
+/// assembler's ordered branches are exact for any pair of words, even when their
+/// difference overflows.
+fn branch(op: &InfixOp, negated: bool) -> &'static str {
+    match (op, negated) {
+        (InfixOp::Equal, false) | (InfixOp::NotEqual, true) => "beq",
+        (InfixOp::NotEqual, false) | (InfixOp::Equal, true) => "bne",
+        (InfixOp::LessThan, false) => "blt",
+        (InfixOp::LessThan, true) => "bge",
+        (InfixOp::GreaterThan, false) => "bgt",
+        (InfixOp::GreaterThan, true) => "ble",
+        (InfixOp::LessEqual, false) => "ble",
+        (InfixOp::LessEqual, true) => "bgt",
+        (InfixOp::GreaterEqual, false) => "bge",
+        (InfixOp::GreaterEqual, true) => "blt",
+        _ => unreachable!("only comparisons branch"),
+    }
+}
+
+/// Compiles programs to Vondel assembly and runs them on the microarchitecture.
 #[derive(Default)]
 pub struct CustomEvaluator {}
 
@@ -8,10 +1063,256 @@ impl CustomEvaluator {
     pub fn new() -> Self {
         Self {}
     }
+
+    /// Compiles a program to Vondel assembly, which leaves its result on `a0`.
+    pub fn compile(&self, prog: &Program, env: &Environment) -> Result<String> {
+        Ok(Compiler::new(env).compile(prog)?.asm)
+    }
+
+    /// Maps a word left by a compiled program back to an `Object`.
+    fn object(&self, compiled: &Compiled, word: u32, ty: Type) -> Object {
+        match ty {
+            Type::Int => Object::Integer(word as i32 as i64),
+            Type::Boolean => Object::Boolean(word != 0),
+            Type::Function(def) => {
+                let def = &compiled.defs[def];
+                Object::Function {
+                    params: def.params.clone(),
+                    body: def.body.clone(),
+                    env: Rc::new(RefCell::new(Environment::new())),
+                }
+            }
+            Type::Null | Type::Tagged | Type::Unknown | Type::Never => Object::Null,
+        }
+    }
 }
 
 impl Evaluator for CustomEvaluator {
-    fn eval(&self, _prog: &Program, _e: &mut Environment) -> Result<super::Object> {
-        todo!();
+    fn eval(&self, prog: &Program, e: &mut Environment) -> Result<Object> {
+        let compiled = Compiler::new(e).compile(prog)?;
+
+        let mut asm = AsmEvaluator::new();
+        let (ctrl, ram) = match asm.evaluate_buffer(&compiled.asm) {
+            Ok((ctrl, ram)) => (ctrl, ram.to_vec()),
+            Err(err) => bail!("{}", asm.diagnostics(Some(&err))),
+        };
+        let mut mem = Ram::new();
+        mem.load(0, ram);
+        let mut computer = Computer::new(mem, ctrl);
+        if !computer.exec_for(MAX_CYCLES) {
+            bail!(CustomEvaluationError::DidNotHalt { cycles: MAX_CYCLES });
+        }
+
+        let regs = &computer.regs().gen;
+        let reg = |id| regs.get(id).expect("general registers go up to a3");
+        if reg(S6) == DIVISION_BY_ZERO {
+            bail!(EvaluationError::DivisionByZero);
+        }
+
+        // The globals stay in the environment for the next programs, like in the REPL
+        for (name, label, ty) in compiled.globals.iter() {
+            let addr = asm.data_label(label).expect("globals have a data label");
+            let obj = self.object(&compiled, computer.ram().get(addr), *ty);
+            e.set(&Expression::Identifier(name.clone()), obj)?;
+        }
+
+        let ty = match compiled.ret {
+            Type::Tagged => match reg(A3) {
+                0 => Type::Null,
+                1 => Type::Int,
+                2 => Type::Boolean,
+                _ => Type::Function(reg(A0) as usize),
+            },
+            ty => ty,
+        };
+        Ok(self.object(&compiled, reg(A0), ty))
+    }
+}
+
+/// Compiles a source to Vondel assembly with the custom evaluator.
+pub fn compile_buffer(input: String) -> Result<String> {
+    let toks = Lexer::new(input).get_deez_toks();
+    let mut program = Parser::new(&toks).get_deez_program();
+    if !program.errors.is_empty() {
+        return Err(program.errors.remove(0));
+    }
+    CustomEvaluator::new().compile(&program, &Environment::new())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_in(src: &str, env: &mut Environment) -> Result<Object> {
+        let toks = Lexer::new(src.to_string()).get_deez_toks();
+        let program = Parser::new(&toks).get_deez_program();
+        assert!(program.errors.is_empty(), "{:?}", program.errors);
+        CustomEvaluator::new().eval(&program, env)
+    }
+
+    fn eval(src: &str) -> Result<Object> {
+        eval_in(src, &mut Environment::new())
+    }
+
+    #[test]
+    fn arithmetic_on_32_bits() -> Result<()> {
+        let cases = [
+            ("7 / 2", 3),
+            ("-7 / 2", -3),
+            ("7 / -2", -3),
+            ("-7 / -2", 3),
+            ("100000 * -3", -300000),
+            ("2147483647 + 1", -2147483648),
+            ("let x = 300; x - 1000 * 2", -1700),
+            ("-7 % 3", -1),
+            ("(-2147483647 - 1) / -1", -2147483648),
+            ("(-2147483647 - 1) % -1", 0),
+            ("7 % -3", 1),
+            ("let x = 10; x % 4", 2),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(eval(src)?, Object::Integer(expected), "{}", src);
+        }
+
+        let err = eval("let x = 0; 1 / x").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvaluationError>(),
+            Some(&EvaluationError::DivisionByZero)
+        );
+        let err = eval("let x = 0; 1 % x").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvaluationError>(),
+            Some(&EvaluationError::DivisionByZero)
+        );
+        let err = eval("4294967296").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CustomEvaluationError>(),
+            Some(&CustomEvaluationError::IntegerOutOfRange { int: 4294967296 })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn comparisons_that_overflow_a_subtraction() -> Result<()> {
+        let pairs = [
+            ("2000000000", "-2000000000"),
+            ("(-2147483647 - 1)", "1"),
+            ("2147483647", "-1"),
+            ("-2000000000", "2000000000"),
+        ];
+        let rust_eval = crate::inter::evaluator::rust::RustEvaluator::new();
+        for (x, y) in pairs {
+            for op in ["<", ">", "<=", ">=", "==", "!="] {
+                // As a value and as the condition of an `if`, which branches on it
+                for src in [
+                    format!("{} {} {}", x, op, y),
+                    format!("if ({} {} {}) {{ 1 }} else {{ 0 }}", x, op, y),
+                ] {
+                    let toks = Lexer::new(src.clone()).get_deez_toks();
+                    let program = Parser::new(&toks).get_deez_program();
+                    let expected = rust_eval.eval(&program, &mut Environment::new())?;
+                    assert_eq!(eval(&src)?, expected, "{}", src);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn functions() -> Result<()> {
+        let cases = [
+            (
+                "let fib = fn(x) { if (x < 2) { return x; } fib(x - 1) + fib(x - 2) }; fib(10)",
+                Object::Integer(55),
+            ),
+            (
+                "let twice = fn(f, x) { f(f(x)) }; twice(fn(x) { x * 3 }, 2)",
+                Object::Integer(18),
+            ),
+            (
+                "let count = fn(n) { let half = n / 2; let g = fn(m) { if (m > 0) { g(m - 1) } else { 0 } }; g(half) + n }; count(8)",
+                Object::Integer(8),
+            ),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(eval(src)?, expected, "{}", src);
+        }
+
+        // Functions only see the globals there were when they were created
+        assert_eq!(
+            eval("let x = 5; let f = fn() { x }; let x = 10; f() + x")?,
+            Object::Integer(15)
+        );
+        let err = eval(
+            "let even = fn(n) { if (n == 0) { true } else { odd(n - 1) } };
+             let odd = fn(n) { if (n == 0) { false } else { even(n - 1) } };
+             even(7)",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<EvaluationError>(),
+            Some(&EvaluationError::IdentifierNotFound {
+                identifier: "odd".to_string()
+            })
+        );
+
+        let err = eval("let f = fn(x) { fn(y) { x + y } }; f(1)(2)").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CustomEvaluationError>(),
+            Some(&CustomEvaluationError::CapturedVariable {
+                identifier: "x".to_string()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn types_known_at_runtime() -> Result<()> {
+        let cases = [
+            (
+                "let f = fn(x) { if (x > 1) { 10 } }; f(2)",
+                Object::Integer(10),
+            ),
+            ("let f = fn(x) { if (x > 1) { 10 } }; f(1)", Object::Null),
+            (
+                "let x = 3; if (x == 3) { true } else { 3 }",
+                Object::Boolean(true),
+            ),
+        ];
+        for (src, expected) in cases {
+            assert_eq!(eval(src)?, expected, "{}", src);
+        }
+
+        let err = eval("let f = fn(x) { if (x > 1) { 10 } }; f(1) + 1").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<CustomEvaluationError>(),
+            Some(&CustomEvaluationError::AmbiguousType {
+                context: "an infix expression"
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn globals_stay_in_the_environment() -> Result<()> {
+        let mut env = Environment::new();
+        eval_in(
+            "let x = 40; let inc = fn(n) { n + 1 }; let yes = true",
+            &mut env,
+        )?;
+        assert_eq!(env.get("x")?, Object::Integer(40));
+        assert_eq!(
+            eval_in("if (yes) { inc(x) + 1 }", &mut env)?,
+            Object::Integer(42)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compiles_to_assembly() -> Result<()> {
+        let asm = compile_buffer("let sq = fn(x) { x * x }; sq(3)".to_string())?;
+        assert!(asm.starts_with(".data\n    v_sq: .word 0 ; sq\n\n.text\nmain:\n"));
+        assert!(asm.contains("; sq(Integer)\nf_sq_0:\n    push ra\n"));
+        Ok(())
     }
 }
@@ -1,9 +1,9 @@
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
 use crate::inter::{
     ast::expression::{InfixOp, PrefixOp},
     environment::Environment,
-    object::Object,
+    object::{HashKey, Object},
 };
 
 use super::ast::*;
@@ -41,7 +41,7 @@ impl RustEvaluator {
     /// Evaluates the arithmetic minus (`-`) operator on the right operand.
     fn eval_minus_operator(&self, right: Object) -> Result<Object> {
         match right {
-            Object::Integer(i) => Ok(Object::Integer(-i)),
+            Object::Integer(i) => Ok(Object::Integer(i.wrapping_neg())),
             _ => bail!(super::EvaluationError::MissingIntegerToInvert {
                 obj: right.type_as_string()
             }),
@@ -62,7 +62,8 @@ impl RustEvaluator {
         }
     }
 
-    /// Evaluates an infix expression (e.g., +, -, *, /, <, >, ==, !=).
+    /// Evaluates an infix expression (e.g., +, -, *, /, <, >, ==, !=), strings taking
+    /// only `+`, `==` and `!=`.
     fn eval_infix_expression(
         &self,
         left: &Expression,
@@ -71,18 +72,47 @@ impl RustEvaluator {
         e: &mut Environment,
     ) -> Result<Object> {
         let left = self.eval_expression(left, e)?;
+
+        // `&&` and `||` only evaluate the right side when the left one doesn't decide
+        match (op, self.is_truthy(&left)) {
+            (InfixOp::And, false) => return Ok(super::FALSE),
+            (InfixOp::Or, true) => return Ok(super::TRUE),
+            (InfixOp::And | InfixOp::Or, _) => {
+                let right = self.eval_expression(right, e)?;
+                return Ok(self.map_boolean(self.is_truthy(&right)));
+            }
+            _ => {}
+        }
+
         let right = self.eval_expression(right, e)?;
 
         match (&left, &right, &op) {
+            (Object::Integer(_), Object::Integer(0), InfixOp::Slash | InfixOp::Modulo) => {
+                bail!(super::EvaluationError::DivisionByZero)
+            }
             (Object::Integer(l), Object::Integer(r), o) => match o {
-                InfixOp::Plus => Ok(Object::Integer(l + r)),
-                InfixOp::Minus => Ok(Object::Integer(l - r)),
-                InfixOp::Asterisk => Ok(Object::Integer(l * r)),
-                InfixOp::Slash => Ok(Object::Integer(l / r)),
+                InfixOp::Plus => Ok(Object::Integer(l.wrapping_add(*r))),
+                InfixOp::Minus => Ok(Object::Integer(l.wrapping_sub(*r))),
+                InfixOp::Asterisk => Ok(Object::Integer(l.wrapping_mul(*r))),
+                InfixOp::Slash => Ok(Object::Integer(l.wrapping_div(*r))),
+                InfixOp::Modulo => Ok(Object::Integer(l.wrapping_rem(*r))),
                 InfixOp::LessThan => Ok(self.map_boolean(l < r)),
                 InfixOp::GreaterThan => Ok(self.map_boolean(l > r)),
+                InfixOp::LessEqual => Ok(self.map_boolean(l <= r)),
+                InfixOp::GreaterEqual => Ok(self.map_boolean(l >= r)),
+                InfixOp::Equal => Ok(self.map_boolean(l == r)),
+                InfixOp::NotEqual => Ok(self.map_boolean(l != r)),
+                InfixOp::And | InfixOp::Or => unreachable!("short-circuited above"),
+            },
+            (Object::String(l), Object::String(r), o) => match o {
+                InfixOp::Plus => Ok(Object::String(format!("{}{}", l, r))),
                 InfixOp::Equal => Ok(self.map_boolean(l == r)),
                 InfixOp::NotEqual => Ok(self.map_boolean(l != r)),
+                _ => bail!(super::EvaluationError::MismatchedTypesInfix {
+                    left: left.type_as_string(),
+                    right: right.type_as_string(),
+                    operator: op.type_as_string(),
+                }),
             },
             (Object::Boolean(l), Object::Boolean(r), o) => match o {
                 InfixOp::Equal => Ok(self.map_boolean(l == r)),
@@ -192,6 +222,7 @@ impl RustEvaluator {
                     Ok(evaluated)
                 }
             }
+            Object::Builtin(builtin) => builtin.call(self.eval_expressions(arguments, e)?),
             _ => bail!(super::EvaluationError::NotAFunction {
                 found: func.type_as_string(),
             }),
@@ -202,6 +233,7 @@ impl RustEvaluator {
     fn eval_expression(&self, expr: &Expression, e: &mut Environment) -> Result<Object> {
         match *expr {
             Expression::Integer(i) => Ok(Object::Integer(i)),
+            Expression::String(ref s) => Ok(Object::String(s.clone())),
             Expression::Boolean(b) => Ok(self.map_boolean(b)),
             Expression::Prefix { ref op, ref right } => {
                 Ok(self.eval_prefix_expression(op, right, e)?)
@@ -229,6 +261,58 @@ impl RustEvaluator {
                 ref function,
                 ref arguments,
             } => self.eval_fn_call(function, arguments, e),
+            Expression::Array(ref elements) => {
+                Ok(Object::Array(self.eval_expressions(elements, e)?))
+            }
+            Expression::Hash(ref pairs) => self.eval_hash_literal(pairs, e),
+            Expression::Index {
+                ref left,
+                ref index,
+            } => {
+                let left = self.eval_expression(left, e)?;
+                let index = self.eval_expression(index, e)?;
+                self.eval_index_expression(left, index)
+            }
+        }
+    }
+
+    /// Evaluates a hash literal, the last of repeated keys taking its place.
+    fn eval_hash_literal(
+        &self,
+        pairs: &[(Expression, Expression)],
+        e: &mut Environment,
+    ) -> Result<Object> {
+        let mut hash = BTreeMap::new();
+        for (key, value) in pairs.iter() {
+            let key = self.eval_expression(key, e)?;
+            let Some(key) = HashKey::from_object(&key) else {
+                bail!(super::EvaluationError::UnhashableKey {
+                    found: key.type_as_string()
+                });
+            };
+            hash.insert(key, self.eval_expression(value, e)?);
+        }
+        Ok(Object::Hash(hash))
+    }
+
+    /// Evaluates an index expression, which is `null` out of the bounds of the array
+    /// or for a key missing from the hash.
+    fn eval_index_expression(&self, left: Object, index: Object) -> Result<Object> {
+        match (left, index) {
+            (Object::Array(elements), Object::Integer(i)) => Ok(usize::try_from(i)
+                .ok()
+                .and_then(|i| elements.get(i).cloned())
+                .unwrap_or(super::NULL)),
+            (Object::Hash(pairs), key) => match HashKey::from_object(&key) {
+                Some(key) => Ok(pairs.get(&key).cloned().unwrap_or(super::NULL)),
+                None => bail!(super::EvaluationError::UnhashableKey {
+                    found: key.type_as_string()
+                }),
+            },
+            (left, index) => bail!(super::EvaluationError::IndexNotSupported {
+                left: left.type_as_string(),
+                index: index.type_as_string(),
+            }),
         }
     }
 
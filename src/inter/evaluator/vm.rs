@@ -0,0 +1,639 @@
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
+
+use anyhow::{bail, Result};
+use thiserror::Error;
+
+use self::{
+    code::{read_u16, CompiledFunction, Constant, Op},
+    compiler::{Bytecode, Compiler, Seed},
+};
+use crate::inter::{
+    ast::{Expression, Program, StatementType},
+    builtins::Builtin,
+    environment::Environment,
+    evaluator::{EvaluationError, Evaluator},
+    object::{HashKey, Object},
+};
+
+pub mod code;
+pub mod compiler;
+
+/// Calls deep after which a program is stopped, instead of taking all the memory.
+const MAX_FRAMES: usize = 1 << 16;
+
+/// Represents errors of the VM that the tree-walking evaluator doesn't have.
+#[derive(Debug, Error, PartialEq)]
+pub enum VmError {
+    #[error("Stack overflow: more than {MAX_FRAMES} nested calls")]
+    StackOverflow,
+
+    #[error("Too many {what} for the bytecode, at most {max}")]
+    TooMany { what: &'static str, max: usize },
+}
+
+/// A function with the values it captured.
+#[derive(Debug)]
+pub struct Closure {
+    func: Rc<CompiledFunction>,
+    free: Vec<Value>,
+}
+
+/// Values on the stack of the VM, cheap to clone unlike `Object`.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Integer(i64),
+    Boolean(bool),
+    String(Rc<str>),
+    Array(Rc<Vec<Value>>),
+    Hash(Rc<BTreeMap<HashKey, Value>>),
+    Null,
+    Closure(Rc<Closure>),
+    Builtin(Builtin),
+}
+
+impl Value {
+    fn type_as_string(&self) -> &'static str {
+        match self {
+            Value::Integer(_) => "Integer",
+            Value::Boolean(_) => "Boolean",
+            Value::String(_) => "String",
+            Value::Array(_) => "Array",
+            Value::Hash(_) => "Hash",
+            Value::Null => "Null",
+            Value::Closure(_) => "Function",
+            Value::Builtin(_) => "Builtin",
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Boolean(b) => *b,
+            _ => true,
+        }
+    }
+
+    fn hash_key(&self) -> Result<HashKey> {
+        match self {
+            Value::Integer(i) => Ok(HashKey::Integer(*i)),
+            Value::Boolean(b) => Ok(HashKey::Boolean(*b)),
+            Value::String(s) => Ok(HashKey::String(s.to_string())),
+            _ => bail!(EvaluationError::UnhashableKey {
+                found: self.type_as_string()
+            }),
+        }
+    }
+
+    /// Values the environment holds, but functions, which are compiled instead.
+    fn from_object(obj: &Object) -> Value {
+        match obj {
+            Object::Integer(i) => Value::Integer(*i),
+            Object::Boolean(b) => Value::Boolean(*b),
+            Object::String(s) => Value::String(Rc::from(s.as_str())),
+            Object::Array(elements) => {
+                Value::Array(Rc::new(elements.iter().map(Value::from_object).collect()))
+            }
+            Object::Hash(pairs) => Value::Hash(Rc::new(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.clone(), Value::from_object(v)))
+                    .collect(),
+            )),
+            Object::Builtin(builtin) => Value::Builtin(*builtin),
+            _ => Value::Null,
+        }
+    }
+
+    /// Closures come back with the values they captured as their environment.
+    fn into_object(self) -> Object {
+        match self {
+            Value::Integer(i) => Object::Integer(i),
+            Value::Boolean(b) => Object::Boolean(b),
+            Value::String(s) => Object::String(s.to_string()),
+            Value::Array(elements) => {
+                Object::Array(elements.iter().map(|e| e.clone().into_object()).collect())
+            }
+            Value::Hash(pairs) => Object::Hash(
+                pairs
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone().into_object()))
+                    .collect(),
+            ),
+            Value::Null => Object::Null,
+            Value::Builtin(builtin) => Object::Builtin(builtin),
+            Value::Closure(closure) => {
+                let mut env = Environment::new();
+                for (name, value) in closure.func.free.iter().zip(closure.free.iter()) {
+                    env.set(
+                        &Expression::Identifier(name.clone()),
+                        value.clone().into_object(),
+                    )
+                    .expect("captured variables have a name");
+                }
+                Object::Function {
+                    params: closure.func.params.clone(),
+                    body: closure.func.body.clone(),
+                    env: Rc::new(RefCell::new(env)),
+                }
+            }
+        }
+    }
+}
+
+/// A call in progress, saved while it calls another function.
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    /// Where its locals start on the stack, after the callee.
+    bp: usize,
+}
+
+/// Runs the bytecode of a program.
+pub struct Vm<'b> {
+    bytecode: &'b Bytecode,
+    stack: Vec<Value>,
+    globals: Vec<Option<Value>>,
+    frames: Vec<Frame>,
+    last_popped: Value,
+}
+
+impl<'b> Vm<'b> {
+    pub fn new(bytecode: &'b Bytecode) -> Self {
+        let mut globals = vec![None; bytecode.globals.len()];
+        for (idx, seed) in bytecode.seeds.iter() {
+            globals[*idx] = Some(Self::seed_value(bytecode, seed));
+        }
+
+        Self {
+            bytecode,
+            stack: Vec::new(),
+            globals,
+            frames: Vec::new(),
+            last_popped: Value::Null,
+        }
+    }
+
+    fn seed_value(bytecode: &Bytecode, seed: &Seed) -> Value {
+        match seed {
+            Seed::Object(obj) => Value::from_object(obj),
+            Seed::Function { idx, free } => Value::Closure(Rc::new(Closure {
+                func: Self::function(bytecode, *idx),
+                free: free.iter().map(|s| Self::seed_value(bytecode, s)).collect(),
+            })),
+        }
+    }
+
+    fn function(bytecode: &Bytecode, idx: usize) -> Rc<CompiledFunction> {
+        match &bytecode.constants[idx] {
+            Constant::Function(func) => Rc::clone(func),
+            _ => unreachable!("closures are made of functions"),
+        }
+    }
+
+    /// Value of a global, if it was set.
+    pub fn global(&self, idx: usize) -> Option<&Value> {
+        self.globals[idx].as_ref()
+    }
+
+    fn push(&mut self, value: Value) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the compiler balances the stack")
+    }
+
+    /// Runs the program, returning the value of its last statement.
+    pub fn run(&mut self) -> Result<Value> {
+        let main = CompiledFunction {
+            instructions: self.bytecode.instructions.clone(),
+            locals: 0,
+            free: Vec::new(),
+            params: Vec::new(),
+            body: StatementType::Block(Vec::new()),
+        };
+        let mut closure = Rc::new(Closure {
+            func: Rc::new(main),
+            free: Vec::new(),
+        });
+        let mut ip = 0;
+        let mut bp = 0;
+
+        while ip < closure.func.instructions.len() {
+            let code = &closure.func.instructions;
+            let op = Op::from_byte(code[ip]);
+            ip += 1;
+
+            match op {
+                Op::Constant => {
+                    let idx = read_u16(code, ip);
+                    ip += 2;
+                    match &self.bytecode.constants[idx] {
+                        Constant::Integer(i) => self.push(Value::Integer(*i)),
+                        Constant::String(s) => self.push(Value::String(Rc::clone(s))),
+                        Constant::Function(_) => unreachable!("functions become closures"),
+                    }
+                }
+                Op::Pop => self.last_popped = self.pop(),
+                Op::Add
+                | Op::Sub
+                | Op::Mul
+                | Op::Div
+                | Op::Equal
+                | Op::NotEqual
+                | Op::GreaterThan
+                | Op::LessThan
+                | Op::Mod
+                | Op::GreaterEqual
+                | Op::LessEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+                    let value = self.infix(op, left, right)?;
+                    self.push(value);
+                }
+                Op::Minus => match self.pop() {
+                    Value::Integer(i) => self.push(Value::Integer(i.wrapping_neg())),
+                    value => bail!(EvaluationError::MissingIntegerToInvert {
+                        obj: value.type_as_string()
+                    }),
+                },
+                Op::Bang => {
+                    let value = match self.pop() {
+                        Value::Boolean(b) => !b,
+                        Value::Null => true,
+                        _ => false,
+                    };
+                    self.push(Value::Boolean(value));
+                }
+                Op::True => self.push(Value::Boolean(true)),
+                Op::False => self.push(Value::Boolean(false)),
+                Op::Null => self.push(Value::Null),
+                Op::JumpNotTruthy => {
+                    let target = read_u16(code, ip);
+                    ip += 2;
+                    if !self.pop().is_truthy() {
+                        ip = target;
+                    }
+                }
+                Op::Jump => ip = read_u16(code, ip),
+                Op::GetGlobal => {
+                    let idx = read_u16(code, ip);
+                    ip += 2;
+                    let Some(value) = self.globals[idx].clone() else {
+                        // Used by a function called before the global was defined
+                        bail!(EvaluationError::IdentifierNotFound {
+                            identifier: self.bytecode.globals[idx].clone()
+                        });
+                    };
+                    self.push(value);
+                }
+                Op::SetGlobal => {
+                    let idx = read_u16(code, ip);
+                    ip += 2;
+                    self.globals[idx] = self.stack.last().cloned();
+                }
+                Op::GetLocal => {
+                    let idx = code[ip] as usize;
+                    ip += 1;
+                    self.push(self.stack[bp + idx].clone());
+                }
+                Op::SetLocal => {
+                    let idx = code[ip] as usize;
+                    ip += 1;
+                    self.stack[bp + idx] = self.stack.last().expect("a value to set").clone();
+                }
+                Op::GetFree => {
+                    let idx = code[ip] as usize;
+                    ip += 1;
+                    self.push(closure.free[idx].clone());
+                }
+                Op::CurrentClosure => self.push(Value::Closure(Rc::clone(&closure))),
+                Op::Closure => {
+                    let idx = read_u16(code, ip);
+                    let captured = code[ip + 2] as usize;
+                    ip += 3;
+                    let free = self.stack.split_off(self.stack.len() - captured);
+                    self.push(Value::Closure(Rc::new(Closure {
+                        func: Self::function(self.bytecode, idx),
+                        free,
+                    })));
+                }
+                Op::Call => {
+                    let args = code[ip] as usize;
+                    ip += 1;
+                    let callee = match &self.stack[self.stack.len() - 1 - args] {
+                        Value::Closure(callee) => Rc::clone(callee),
+                        Value::Builtin(builtin) => {
+                            let builtin = *builtin;
+                            let args = self.stack.split_off(self.stack.len() - args);
+                            self.pop();
+                            let res = self.call_builtin(builtin, args)?;
+                            self.push(res);
+                            continue;
+                        }
+                        value => bail!(EvaluationError::NotAFunction {
+                            found: value.type_as_string()
+                        }),
+                    };
+                    if args != callee.func.params.len() {
+                        bail!(EvaluationError::WrongNumberOfArguments {
+                            found: args,
+                            expected: callee.func.params.len(),
+                        });
+                    }
+                    if self.frames.len() >= MAX_FRAMES {
+                        bail!(VmError::StackOverflow);
+                    }
+
+                    let caller = std::mem::replace(&mut closure, callee);
+                    self.frames.push(Frame {
+                        closure: caller,
+                        ip,
+                        bp,
+                    });
+                    bp = self.stack.len() - args;
+                    ip = 0;
+                    self.stack.resize(bp + closure.func.locals, Value::Null);
+                }
+                Op::Array => {
+                    let len = read_u16(code, ip);
+                    ip += 2;
+                    let elements = self.stack.split_off(self.stack.len() - len);
+                    self.push(Value::Array(Rc::new(elements)));
+                }
+                Op::Hash => {
+                    let len = read_u16(code, ip);
+                    ip += 2;
+                    let mut hash = BTreeMap::new();
+                    let pairs = self.stack.split_off(self.stack.len() - 2 * len);
+                    for pair in pairs.chunks_exact(2) {
+                        hash.insert(pair[0].hash_key()?, pair[1].clone());
+                    }
+                    self.push(Value::Hash(Rc::new(hash)));
+                }
+                Op::Index => {
+                    let index = self.pop();
+                    let value = match (self.pop(), index) {
+                        // Out of the bounds of the array it's null
+                        (Value::Array(elements), Value::Integer(i)) => usize::try_from(i)
+                            .ok()
+                            .and_then(|i| elements.get(i).cloned())
+                            .unwrap_or(Value::Null),
+                        (Value::Hash(pairs), key) => {
+                            pairs.get(&key.hash_key()?).cloned().unwrap_or(Value::Null)
+                        }
+                        (left, index) => bail!(EvaluationError::IndexNotSupported {
+                            left: left.type_as_string(),
+                            index: index.type_as_string(),
+                        }),
+                    };
+                    self.push(value);
+                }
+                Op::ReturnValue => {
+                    let value = self.pop();
+                    // A return outside of functions ends the program
+                    let Some(frame) = self.frames.pop() else {
+                        return Ok(value);
+                    };
+                    self.stack.truncate(bp - 1);
+                    self.push(value);
+                    closure = frame.closure;
+                    ip = frame.ip;
+                    bp = frame.bp;
+                }
+            }
+        }
+
+        Ok(self.last_popped.clone())
+    }
+
+    /// Calls a builtin, on the values themselves for the ones that move the elements
+    /// of arrays, which keeps the closures in them whole.
+    fn call_builtin(&self, builtin: Builtin, mut args: Vec<Value>) -> Result<Value> {
+        builtin.check_arity(args.len())?;
+        let value = match (builtin, args.first()) {
+            (Builtin::First, Some(Value::Array(elements))) => {
+                elements.first().cloned().unwrap_or(Value::Null)
+            }
+            (Builtin::Last, Some(Value::Array(elements))) => {
+                elements.last().cloned().unwrap_or(Value::Null)
+            }
+            (Builtin::Rest, Some(Value::Array(elements))) if elements.is_empty() => Value::Null,
+            (Builtin::Rest, Some(Value::Array(elements))) => {
+                Value::Array(Rc::new(elements[1..].to_vec()))
+            }
+            (Builtin::Push, Some(Value::Array(_))) => {
+                let value = args.pop().expect("push takes two arguments");
+                let Some(Value::Array(elements)) = args.pop() else {
+                    unreachable!("matched an array");
+                };
+                let mut elements = Rc::unwrap_or_clone(elements);
+                elements.push(value);
+                Value::Array(Rc::new(elements))
+            }
+            _ => {
+                let args = args.into_iter().map(Value::into_object).collect();
+                Value::from_object(&builtin.call(args)?)
+            }
+        };
+        Ok(value)
+    }
+
+    fn infix(&self, op: Op, left: Value, right: Value) -> Result<Value> {
+        let value = match (&left, &right) {
+            (Value::Integer(l), Value::Integer(r)) => match op {
+                Op::Add => Value::Integer(l.wrapping_add(*r)),
+                Op::Sub => Value::Integer(l.wrapping_sub(*r)),
+                Op::Mul => Value::Integer(l.wrapping_mul(*r)),
+                Op::Div if *r == 0 => bail!(EvaluationError::DivisionByZero),
+                Op::Div => Value::Integer(l.wrapping_div(*r)),
+                Op::Mod if *r == 0 => bail!(EvaluationError::DivisionByZero),
+                Op::Mod => Value::Integer(l.wrapping_rem(*r)),
+                Op::Equal => Value::Boolean(l == r),
+                Op::NotEqual => Value::Boolean(l != r),
+                Op::GreaterThan => Value::Boolean(l > r),
+                Op::LessThan => Value::Boolean(l < r),
+                Op::GreaterEqual => Value::Boolean(l >= r),
+                Op::LessEqual => Value::Boolean(l <= r),
+                _ => unreachable!("only infix operators"),
+            },
+            (Value::String(l), Value::String(r)) => match op {
+                Op::Add => Value::String(Rc::from(format!("{}{}", l, r))),
+                Op::Equal => Value::Boolean(l == r),
+                Op::NotEqual => Value::Boolean(l != r),
+                _ => bail!(EvaluationError::MismatchedTypesInfix {
+                    left: left.type_as_string(),
+                    right: right.type_as_string(),
+                    operator: operator(op),
+                }),
+            },
+            (Value::Boolean(l), Value::Boolean(r)) => match op {
+                Op::Equal => Value::Boolean(l == r),
+                Op::NotEqual => Value::Boolean(l != r),
+                _ => bail!(EvaluationError::UnallowedBooleanComparisonOperator {
+                    operator: operator(op)
+                }),
+            },
+            _ => bail!(EvaluationError::MismatchedTypesInfix {
+                left: left.type_as_string(),
+                right: right.type_as_string(),
+                operator: operator(op),
+            }),
+        };
+        Ok(value)
+    }
+}
+
+/// The operator an infix instruction was compiled from, for the errors.
+fn operator(op: Op) -> &'static str {
+    match op {
+        Op::Add => "+",
+        Op::Sub => "-",
+        Op::Mul => "*",
+        Op::Div => "/",
+        Op::Equal => "==",
+        Op::NotEqual => "!=",
+        Op::GreaterThan => ">",
+        Op::LessThan => "<",
+        Op::Mod => "%",
+        Op::GreaterEqual => ">=",
+        Op::LessEqual => "<=",
+        _ => unreachable!("only infix operators"),
+    }
+}
+
+/// Compiles programs to bytecode and runs them on a stack VM.
+#[derive(Default)]
+pub struct VmEvaluator {}
+
+impl VmEvaluator {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Evaluator for VmEvaluator {
+    fn eval(&self, prog: &Program, e: &mut Environment) -> Result<Object> {
+        let bytecode = Compiler::new(e).compile(prog)?;
+        let mut vm = Vm::new(&bytecode);
+        let res = vm.run();
+
+        // The globals stay in the environment for the next programs, like in the REPL
+        for (idx, name) in bytecode.globals.iter().enumerate() {
+            if let Some(value) = vm.global(idx) {
+                e.set(
+                    &Expression::Identifier(name.clone()),
+                    value.clone().into_object(),
+                )?;
+            }
+        }
+
+        Ok(res?.into_object())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inter::{ast::Parser, lexer::Lexer};
+
+    fn eval(src: &str, env: &mut Environment) -> Result<Object> {
+        let toks = Lexer::new(src.to_string()).get_deez_toks();
+        let program = Parser::new(&toks).get_deez_program();
+        VmEvaluator::new().eval(&program, env)
+    }
+
+    #[test]
+    fn closures() -> Result<()> {
+        let src = "
+            let adder = fn(a) { fn(b) { fn(c) { a + b + c } } };
+            let counter = fn(n) { if (n > 0) { counter(n - 1) } else { adder(1)(2)(3) } };
+            counter(50)";
+        assert_eq!(eval(src, &mut Environment::new())?, Object::Integer(6));
+
+        let src = "
+            let wrap = fn() {
+                let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } };
+                fact
+            };
+            wrap()(10)";
+        assert_eq!(
+            eval(src, &mut Environment::new())?,
+            Object::Integer(3628800)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn closures_across_programs() -> Result<()> {
+        let mut env = Environment::new();
+        eval("let adder = fn(x) { fn(y) { x + y } };", &mut env)?;
+        eval("let addtwo = adder(2);", &mut env)?;
+        assert_eq!(eval("addtwo(3)", &mut env)?, Object::Integer(5));
+
+        // Captured closures come back with what they captured too
+        eval(
+            "let compose = fn(f, g) { fn(x) { f(g(x)) } }; let h = compose(addtwo, adder(10));",
+            &mut env,
+        )?;
+        assert_eq!(eval("h(1)", &mut env)?, Object::Integer(13));
+        assert_eq!(eval("let k = h; k(h(0))", &mut env)?, Object::Integer(24));
+        Ok(())
+    }
+
+    #[test]
+    fn fib() -> Result<()> {
+        let src = "
+            let fib = fn(n) { if (n < 2) { return n; } fib(n - 1) + fib(n - 2) };
+            fib(20)";
+        assert_eq!(eval(src, &mut Environment::new())?, Object::Integer(6765));
+        Ok(())
+    }
+
+    #[test]
+    fn globals_stay_in_the_environment() -> Result<()> {
+        let mut env = Environment::new();
+        eval("let x = 5; let double = fn(n) { n * 2 };", &mut env)?;
+        assert_eq!(env.get("x")?, Object::Integer(5));
+        assert_eq!(eval("double(x) + 1", &mut env)?, Object::Integer(11));
+        eval("let x = x + 1;", &mut env)?;
+        assert_eq!(env.get("x")?, Object::Integer(6));
+        // A global from a previous program is captured like the ones of the program
+        assert_eq!(
+            eval("let f = fn() { x }; let x = 10; f()", &mut env)?,
+            Object::Integer(6)
+        );
+        assert_eq!(eval("f() + x", &mut env)?, Object::Integer(16));
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let mut env = Environment::new();
+        let err = eval("1 / 0", &mut env).unwrap_err();
+        assert_eq!(
+            err.downcast::<EvaluationError>().unwrap(),
+            EvaluationError::DivisionByZero
+        );
+
+        let err = eval("let f = fn() { f() }; f()", &mut env).unwrap_err();
+        assert_eq!(err.downcast::<VmError>().unwrap(), VmError::StackOverflow);
+
+        let err = eval("let g = fn() { h }; g()", &mut env).unwrap_err();
+        assert_eq!(
+            err.downcast::<EvaluationError>().unwrap(),
+            EvaluationError::IdentifierNotFound {
+                identifier: "h".to_string()
+            }
+        );
+
+        let err = eval("fn(a) { a }(1, 2)", &mut env).unwrap_err();
+        assert_eq!(
+            err.downcast::<EvaluationError>().unwrap(),
+            EvaluationError::WrongNumberOfArguments {
+                found: 2,
+                expected: 1
+            }
+        );
+    }
+}
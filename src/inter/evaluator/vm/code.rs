@@ -0,0 +1,194 @@
+use std::{fmt::Write, rc::Rc};
+
+use crate::inter::ast::{Expression, StatementType};
+
+/// Bytecode: each instruction is an opcode byte followed by its operands, in big
+/// endian.
+pub type Instructions = Vec<u8>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Op {
+    /// Pushes the constant at the index of the operand.
+    Constant,
+    /// Pops the value of a statement, which is the result if it's the last one.
+    Pop,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    Minus,
+    Bang,
+    True,
+    False,
+    Null,
+    /// Jumps to the operand when the value on top, which is popped, is falsy.
+    JumpNotTruthy,
+    Jump,
+    GetGlobal,
+    /// Stores the value on top in a global, leaving it on the stack.
+    SetGlobal,
+    GetLocal,
+    /// Stores the value on top in a local, leaving it on the stack.
+    SetLocal,
+    /// Pushes a variable captured by the running closure.
+    GetFree,
+    /// Pushes the running closure, for the functions that call themselves.
+    CurrentClosure,
+    /// Makes a closure of the function constant of the first operand, capturing
+    /// as many values from the stack as the second operand.
+    Closure,
+    /// Calls the closure below the arguments, as many as the operand.
+    Call,
+    ReturnValue,
+    /// Makes an array of as many values from the stack as the operand.
+    Array,
+    /// Pushes the element of the array, or the value of the hash, below at the
+    /// index on top.
+    Index,
+    /// Makes a hash of as many key and value pairs from the stack as the operand.
+    Hash,
+    Mod,
+    GreaterEqual,
+    LessEqual,
+}
+
+const OPS: [Op; 32] = [
+    Op::Constant,
+    Op::Pop,
+    Op::Add,
+    Op::Sub,
+    Op::Mul,
+    Op::Div,
+    Op::Equal,
+    Op::NotEqual,
+    Op::GreaterThan,
+    Op::LessThan,
+    Op::Minus,
+    Op::Bang,
+    Op::True,
+    Op::False,
+    Op::Null,
+    Op::JumpNotTruthy,
+    Op::Jump,
+    Op::GetGlobal,
+    Op::SetGlobal,
+    Op::GetLocal,
+    Op::SetLocal,
+    Op::GetFree,
+    Op::CurrentClosure,
+    Op::Closure,
+    Op::Call,
+    Op::ReturnValue,
+    Op::Array,
+    Op::Index,
+    Op::Hash,
+    Op::Mod,
+    Op::GreaterEqual,
+    Op::LessEqual,
+];
+
+impl Op {
+    pub fn from_byte(byte: u8) -> Op {
+        OPS[byte as usize]
+    }
+
+    /// Size in bytes of each operand.
+    pub fn operand_widths(&self) -> &'static [usize] {
+        match self {
+            Op::Constant
+            | Op::JumpNotTruthy
+            | Op::Jump
+            | Op::GetGlobal
+            | Op::SetGlobal
+            | Op::Array
+            | Op::Hash => &[2],
+            Op::GetLocal | Op::SetLocal | Op::GetFree | Op::Call => &[1],
+            Op::Closure => &[2, 1],
+            _ => &[],
+        }
+    }
+}
+
+/// Encodes an instruction.
+pub fn make(op: Op, operands: &[usize]) -> Instructions {
+    let mut ins = vec![op as u8];
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        match width {
+            2 => ins.extend_from_slice(&(*operand as u16).to_be_bytes()),
+            _ => ins.push(*operand as u8),
+        }
+    }
+    ins
+}
+
+pub fn read_u16(ins: &[u8], at: usize) -> usize {
+    u16::from_be_bytes([ins[at], ins[at + 1]]) as usize
+}
+
+/// One instruction per line, after its offset, like `0003 GetLocal 1`.
+pub fn disassemble(ins: &[u8]) -> String {
+    let mut out = String::new();
+    let mut ip = 0;
+    while ip < ins.len() {
+        let op = Op::from_byte(ins[ip]);
+        let _ = write!(out, "{:04} {:?}", ip, op);
+        ip += 1;
+        for width in op.operand_widths() {
+            let operand = match width {
+                2 => read_u16(ins, ip),
+                _ => ins[ip] as usize,
+            };
+            let _ = write!(out, " {}", operand);
+            ip += width;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// A function compiled to bytecode, keeping its source to give it back as an
+/// `Object`.
+#[derive(Debug, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    pub locals: usize,
+    /// Names of the variables captured by its closures, in the order of `GetFree`.
+    pub free: Vec<String>,
+    pub params: Vec<Expression>,
+    pub body: StatementType,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Integer(i64),
+    String(Rc<str>),
+    Function(Rc<CompiledFunction>),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn make_and_disassemble() {
+        let ins = [
+            make(Op::Constant, &[65534]),
+            make(Op::GetLocal, &[255]),
+            make(Op::Closure, &[258, 3]),
+            make(Op::Add, &[]),
+        ]
+        .concat();
+
+        assert_eq!(ins, vec![0, 255, 254, 19, 255, 23, 1, 2, 3, 2]);
+        assert_eq!(
+            disassemble(&ins),
+            "0000 Constant 65534\n0003 GetLocal 255\n0005 Closure 258 3\n0009 Add\n"
+        );
+        assert!(OPS.iter().enumerate().all(|(i, op)| *op as usize == i));
+    }
+}
@@ -0,0 +1,615 @@
+use std::{collections::HashMap, rc::Rc};
+
+use anyhow::{bail, Result};
+
+use super::{
+    code::{make, CompiledFunction, Constant, Instructions, Op},
+    VmError,
+};
+use crate::inter::{
+    ast::{
+        expression::{InfixOp, PrefixOp},
+        Expression, Program, StatementType,
+    },
+    environment::Environment,
+    evaluator::EvaluationError,
+    object::Object,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scope {
+    Global,
+    Local,
+    /// Captured from an enclosing function by the closure.
+    Free,
+    /// The function itself, under the name it was bound to.
+    Function,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Symbol {
+    scope: Scope,
+    index: usize,
+}
+
+#[derive(Default)]
+struct SymbolTable {
+    store: HashMap<String, Symbol>,
+    /// The symbols of the enclosing functions captured, in the order of `GetFree`.
+    free: Vec<Symbol>,
+    definitions: usize,
+}
+
+/// A function, or the program, being compiled.
+#[derive(Default)]
+struct CompilationScope {
+    symbols: SymbolTable,
+    instructions: Instructions,
+    /// Holds the variables of a function taken from the environment, see `seed_value`.
+    seeded: bool,
+}
+
+/// Value of a global taken from the environment, given to the VM before it starts.
+#[derive(Debug)]
+pub enum Seed {
+    Object(Object),
+    /// A closure of the function constant at `idx`, capturing the values of `free`.
+    Function {
+        idx: usize,
+        free: Vec<Seed>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Constant>,
+    /// Name of each global, by index.
+    pub globals: Vec<String>,
+    pub seeds: Vec<(usize, Seed)>,
+}
+
+/// Compiles the AST to bytecode, resolving the variables to globals, locals of the
+/// function or values captured by closures.
+pub struct Compiler<'e> {
+    env: &'e Environment,
+    scopes: Vec<CompilationScope>,
+    constants: Vec<Constant>,
+    integers: HashMap<i64, usize>,
+    globals: Vec<String>,
+    seeds: Vec<(usize, Seed)>,
+}
+
+impl<'e> Compiler<'e> {
+    /// Creates a compiler that looks up in `env` the variables the program doesn't
+    /// define.
+    pub fn new(env: &'e Environment) -> Self {
+        Self {
+            env,
+            scopes: vec![CompilationScope::default()],
+            constants: Vec::new(),
+            integers: HashMap::new(),
+            globals: Vec::new(),
+            seeds: Vec::new(),
+        }
+    }
+
+    pub fn compile(mut self, prog: &Program) -> Result<Bytecode> {
+        for stmt in prog.statements.iter() {
+            self.compile_statement(stmt)?;
+            self.emit(Op::Pop, &[]);
+        }
+
+        let scope = self.scopes.pop().expect("the program has a scope");
+        Ok(Bytecode {
+            instructions: scope.instructions,
+            constants: self.constants,
+            globals: self.globals,
+            seeds: self.seeds,
+        })
+    }
+
+    fn scope(&mut self) -> &mut CompilationScope {
+        self.scopes.last_mut().expect("there is always a scope")
+    }
+
+    /// Appends an instruction, returning where it starts.
+    fn emit(&mut self, op: Op, operands: &[usize]) -> usize {
+        let ins = &mut self.scope().instructions;
+        let pos = ins.len();
+        ins.extend(make(op, operands));
+        pos
+    }
+
+    /// Points the jump at `pos` to the next instruction.
+    fn patch_jump(&mut self, pos: usize) -> Result<()> {
+        let target = self.scope().instructions.len();
+        let target = limit(target, u16::MAX as usize, "instructions in a function")?;
+        let ins = &mut self.scope().instructions;
+        ins[pos + 1..pos + 3].copy_from_slice(&(target as u16).to_be_bytes());
+        Ok(())
+    }
+
+    fn add_constant(&mut self, constant: Constant) -> Result<usize> {
+        self.constants.push(constant);
+        limit(self.constants.len() - 1, u16::MAX as usize, "constants")
+    }
+
+    /// Compiles a statement leaving its value on the stack, a `let` included.
+    fn compile_statement(&mut self, stmt: &StatementType) -> Result<()> {
+        match stmt {
+            StatementType::Expression(expr) => self.compile_expression(expr),
+            StatementType::Block(stmts) => {
+                if stmts.is_empty() {
+                    self.emit(Op::Null, &[]);
+                }
+                for (i, stmt) in stmts.iter().enumerate() {
+                    self.compile_statement(stmt)?;
+                    if i + 1 < stmts.len() {
+                        self.emit(Op::Pop, &[]);
+                    }
+                }
+                Ok(())
+            }
+            StatementType::Return(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(Op::ReturnValue, &[]);
+                Ok(())
+            }
+            StatementType::Let { name, value } => {
+                let Expression::Identifier(name) = name else {
+                    bail!(EvaluationError::ExpectedIdentifier {
+                        found: name.type_as_string()
+                    });
+                };
+                // Functions see the name they're bound to, to call themselves
+                let symbol = match value {
+                    Expression::FunctionLiteral { parameters, block } => {
+                        let symbol = self.define(name)?;
+                        self.compile_function(parameters, block, Some(name))?;
+                        symbol
+                    }
+                    value => {
+                        self.compile_expression(value)?;
+                        self.define(name)?
+                    }
+                };
+                match symbol.scope {
+                    Scope::Global => self.emit(Op::SetGlobal, &[symbol.index]),
+                    _ => self.emit(Op::SetLocal, &[symbol.index]),
+                };
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_expression(&mut self, expr: &Expression) -> Result<()> {
+        match expr {
+            Expression::Integer(i) => {
+                let idx = match self.integers.get(i) {
+                    Some(idx) => *idx,
+                    None => {
+                        let idx = self.add_constant(Constant::Integer(*i))?;
+                        self.integers.insert(*i, idx);
+                        idx
+                    }
+                };
+                self.emit(Op::Constant, &[idx]);
+            }
+            Expression::String(s) => {
+                let idx = self.add_constant(Constant::String(Rc::from(s.as_str())))?;
+                self.emit(Op::Constant, &[idx]);
+            }
+            Expression::Boolean(true) => {
+                self.emit(Op::True, &[]);
+            }
+            Expression::Boolean(false) => {
+                self.emit(Op::False, &[]);
+            }
+            Expression::Prefix { op, right } => {
+                self.compile_expression(right)?;
+                match op {
+                    PrefixOp::Minus => self.emit(Op::Minus, &[]),
+                    PrefixOp::Bang => self.emit(Op::Bang, &[]),
+                };
+            }
+            Expression::Infix {
+                left,
+                op: op @ (InfixOp::And | InfixOp::Or),
+                right,
+            } => self.compile_logical(left, op, right)?,
+            Expression::Infix { left, op, right } => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                let op = match op {
+                    InfixOp::Plus => Op::Add,
+                    InfixOp::Minus => Op::Sub,
+                    InfixOp::Asterisk => Op::Mul,
+                    InfixOp::Slash => Op::Div,
+                    InfixOp::Modulo => Op::Mod,
+                    InfixOp::Equal => Op::Equal,
+                    InfixOp::NotEqual => Op::NotEqual,
+                    InfixOp::LessThan => Op::LessThan,
+                    InfixOp::GreaterThan => Op::GreaterThan,
+                    InfixOp::LessEqual => Op::LessEqual,
+                    InfixOp::GreaterEqual => Op::GreaterEqual,
+                    InfixOp::And | InfixOp::Or => unreachable!("compiled with jumps"),
+                };
+                self.emit(op, &[]);
+            }
+            Expression::If {
+                condition,
+                consequence,
+                alternative,
+            } => {
+                self.compile_expression(condition)?;
+                let otherwise = self.emit(Op::JumpNotTruthy, &[0]);
+                self.compile_statement(consequence)?;
+                let end = self.emit(Op::Jump, &[0]);
+                self.patch_jump(otherwise)?;
+                match alternative {
+                    Some(alt) => self.compile_statement(alt)?,
+                    None => {
+                        self.emit(Op::Null, &[]);
+                    }
+                }
+                self.patch_jump(end)?;
+            }
+            Expression::Identifier(name) => {
+                let symbol = self.resolve(name)?;
+                self.load(symbol);
+            }
+            Expression::FunctionLiteral { parameters, block } => {
+                self.compile_function(parameters, block, None)?;
+            }
+            Expression::Call {
+                function,
+                arguments,
+            } => {
+                self.compile_expression(function)?;
+                for arg in arguments.iter() {
+                    self.compile_expression(arg)?;
+                }
+                let args = limit(arguments.len(), u8::MAX as usize, "arguments")?;
+                self.emit(Op::Call, &[args]);
+            }
+            Expression::Array(elements) => {
+                for element in elements.iter() {
+                    self.compile_expression(element)?;
+                }
+                let len = limit(elements.len(), u16::MAX as usize, "elements in an array")?;
+                self.emit(Op::Array, &[len]);
+            }
+            Expression::Hash(pairs) => {
+                for (key, value) in pairs.iter() {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+                let len = limit(pairs.len(), u16::MAX as usize, "pairs in a hash")?;
+                self.emit(Op::Hash, &[len]);
+            }
+            Expression::Index { left, index } => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit(Op::Index, &[]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles `&&` and `||` with jumps, so the right side only runs when the left
+    /// one doesn't decide the result. The side that does is turned into a boolean by
+    /// negating it twice.
+    fn compile_logical(
+        &mut self,
+        left: &Expression,
+        op: &InfixOp,
+        right: &Expression,
+    ) -> Result<()> {
+        self.compile_expression(left)?;
+        let otherwise = self.emit(Op::JumpNotTruthy, &[0]);
+        match op {
+            InfixOp::And => {
+                self.compile_expression(right)?;
+                self.emit(Op::Bang, &[]);
+                self.emit(Op::Bang, &[]);
+                let end = self.emit(Op::Jump, &[0]);
+                self.patch_jump(otherwise)?;
+                self.emit(Op::False, &[]);
+                self.patch_jump(end)
+            }
+            _ => {
+                self.emit(Op::True, &[]);
+                let end = self.emit(Op::Jump, &[0]);
+                self.patch_jump(otherwise)?;
+                self.compile_expression(right)?;
+                self.emit(Op::Bang, &[]);
+                self.emit(Op::Bang, &[]);
+                self.patch_jump(end)
+            }
+        }
+    }
+
+    /// Compiles a function to a constant, leaving a closure of it on the stack.
+    fn compile_function(
+        &mut self,
+        params: &[Expression],
+        body: &StatementType,
+        name: Option<&str>,
+    ) -> Result<()> {
+        let (idx, free) = self.compile_function_constant(params, body, name)?;
+        for symbol in free.iter() {
+            self.load(*symbol);
+        }
+        self.emit(Op::Closure, &[idx, free.len()]);
+        Ok(())
+    }
+
+    /// Compiles a function, returning its constant and the symbols it captures.
+    fn compile_function_constant(
+        &mut self,
+        params: &[Expression],
+        body: &StatementType,
+        name: Option<&str>,
+    ) -> Result<(usize, Vec<Symbol>)> {
+        self.scopes.push(CompilationScope::default());
+        if let Some(name) = name {
+            let symbol = Symbol {
+                scope: Scope::Function,
+                index: 0,
+            };
+            self.scope().symbols.store.insert(name.to_string(), symbol);
+        }
+        for param in params.iter() {
+            let Expression::Identifier(param) = param else {
+                self.scopes.pop();
+                bail!(EvaluationError::ExpectedIdentifier {
+                    found: param.type_as_string()
+                });
+            };
+            self.define(param)?;
+        }
+
+        let res = self.compile_statement(body);
+        self.emit(Op::ReturnValue, &[]);
+        let scope = self.scopes.pop().expect("the function has a scope");
+        res?;
+
+        let mut free: Vec<(usize, String)> = scope
+            .symbols
+            .store
+            .iter()
+            .filter(|(_, symbol)| symbol.scope == Scope::Free)
+            .map(|(name, symbol)| (symbol.index, name.clone()))
+            .collect();
+        free.sort();
+        let func = CompiledFunction {
+            instructions: scope.instructions,
+            locals: scope.symbols.definitions,
+            free: free.into_iter().map(|(_, name)| name).collect(),
+            params: params.to_vec(),
+            body: body.clone(),
+        };
+        let idx = self.add_constant(Constant::Function(Rc::new(func)))?;
+        Ok((idx, scope.symbols.free))
+    }
+
+    /// Gives a variable a place, reusing the one it has in the same function.
+    fn define(&mut self, name: &str) -> Result<Symbol> {
+        let global = self.scopes.len() == 1;
+        let symbols = &mut self.scope().symbols;
+        if let Some(symbol) = symbols.store.get(name) {
+            if matches!(symbol.scope, Scope::Global | Scope::Local) {
+                return Ok(*symbol);
+            }
+        }
+
+        let symbol = Symbol {
+            scope: if global { Scope::Global } else { Scope::Local },
+            index: symbols.definitions,
+        };
+        symbols.definitions += 1;
+        symbols.store.insert(name.to_string(), symbol);
+        if global {
+            self.globals.push(name.to_string());
+            limit(symbol.index, u16::MAX as usize, "globals")?;
+        } else {
+            limit(symbol.index, u8::MAX as usize, "locals in a function")?;
+        }
+        Ok(symbol)
+    }
+
+    fn resolve(&mut self, name: &str) -> Result<Symbol> {
+        if let Some(symbol) = self.resolve_in(self.scopes.len() - 1, name) {
+            return Ok(symbol);
+        }
+        let Ok(obj) = self.env.get(name) else {
+            bail!(EvaluationError::IdentifierNotFound {
+                identifier: name.to_string()
+            });
+        };
+
+        // Left by a previous program, it becomes a global set before the VM starts
+        let outer = self.scopes.split_off(1);
+        let res = self.seed(name, obj);
+        self.scopes.extend(outer);
+        res?;
+        Ok(self
+            .resolve_in(self.scopes.len() - 1, name)
+            .expect("the global was just seeded"))
+    }
+
+    fn seed(&mut self, name: &str, obj: Object) -> Result<Symbol> {
+        let symbol = self.define(name)?;
+        let seed = self.seed_value(obj, Some(name))?;
+        self.seeds.push((symbol.index, seed));
+        Ok(symbol)
+    }
+
+    /// A function is compiled inside a scope with the variables of its environment
+    /// as locals, so that it captures again the ones it uses.
+    fn seed_value(&mut self, obj: Object, name: Option<&str>) -> Result<Seed> {
+        let Object::Function { params, body, env } = obj else {
+            return Ok(Seed::Object(obj));
+        };
+        let captured: Vec<(String, Object)> = env
+            .borrow()
+            .variables()
+            .map(|(name, obj)| (name.clone(), obj.clone()))
+            .collect();
+
+        self.scopes.push(CompilationScope {
+            seeded: true,
+            ..Default::default()
+        });
+        let res = captured
+            .iter()
+            .try_for_each(|(name, _)| self.define(name).map(|_| ()))
+            .and_then(|_| self.compile_function_constant(&params, &body, name));
+        self.scopes.pop();
+        let (idx, free) = res?;
+
+        let free = free
+            .into_iter()
+            .map(|symbol| self.seed_value(captured[symbol.index].1.clone(), None))
+            .collect::<Result<_>>()?;
+        Ok(Seed::Function { idx, free })
+    }
+
+    /// Looks a name up from the function at `level` outwards, capturing it if it
+    /// belongs to an enclosing function or is a global. Like the environment the
+    /// tree-walking evaluator gives a closure, a global keeps the value it had when
+    /// the closure was created. The functions taken from the environment bring the
+    /// values they captured, and read the other globals as they are.
+    fn resolve_in(&mut self, level: usize, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.scopes[level].symbols.store.get(name) {
+            return Some(*symbol);
+        }
+        if level == 0 {
+            return None;
+        }
+
+        let outer = self.resolve_in(level - 1, name)?;
+        if outer.scope == Scope::Global && self.scopes[1..=level].iter().any(|s| s.seeded) {
+            return Some(outer);
+        }
+        let symbols = &mut self.scopes[level].symbols;
+        symbols.free.push(outer);
+        let symbol = Symbol {
+            scope: Scope::Free,
+            index: symbols.free.len() - 1,
+        };
+        symbols.store.insert(name.to_string(), symbol);
+        Some(symbol)
+    }
+
+    fn load(&mut self, symbol: Symbol) {
+        match symbol.scope {
+            Scope::Global => self.emit(Op::GetGlobal, &[symbol.index]),
+            Scope::Local => self.emit(Op::GetLocal, &[symbol.index]),
+            Scope::Free => self.emit(Op::GetFree, &[symbol.index]),
+            Scope::Function => self.emit(Op::CurrentClosure, &[]),
+        };
+    }
+}
+
+fn limit(n: usize, max: usize, what: &'static str) -> Result<usize> {
+    if n > max {
+        bail!(VmError::TooMany { what, max });
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inter::{ast::Parser, evaluator::vm::code::disassemble, lexer::Lexer};
+
+    fn compile(src: &str) -> Bytecode {
+        let toks = Lexer::new(src.to_string()).get_deez_toks();
+        let program = Parser::new(&toks).get_deez_program();
+        Compiler::new(&Environment::new())
+            .compile(&program)
+            .unwrap()
+    }
+
+    fn function(bytecode: &Bytecode, idx: usize) -> &CompiledFunction {
+        match &bytecode.constants[idx] {
+            Constant::Function(func) => func,
+            c => panic!("expected a function, found {:?}", c),
+        }
+    }
+
+    #[test]
+    fn conditionals_and_globals() {
+        let bytecode = compile("let x = 2; if (x > 1) { x * 2 } else { 0 };");
+        assert_eq!(
+            disassemble(&bytecode.instructions),
+            "\
+0000 Constant 0
+0003 SetGlobal 0
+0006 Pop
+0007 GetGlobal 0
+0010 Constant 1
+0013 GreaterThan
+0014 JumpNotTruthy 27
+0017 GetGlobal 0
+0020 Constant 0
+0023 Mul
+0024 Jump 30
+0027 Constant 2
+0030 Pop
+"
+        );
+        assert_eq!(bytecode.globals, vec!["x".to_string()]);
+        assert_eq!(
+            bytecode.constants[..3],
+            [
+                Constant::Integer(2),
+                Constant::Integer(1),
+                Constant::Integer(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn closures_capture_their_free_variables() {
+        let bytecode = compile("fn(a) { fn(b) { let c = a + b; fn() { a + c } } }");
+        let innermost = function(&bytecode, 0);
+        assert_eq!(
+            disassemble(&innermost.instructions),
+            "0000 GetFree 0\n0002 GetFree 1\n0004 Add\n0005 ReturnValue\n"
+        );
+        let middle = function(&bytecode, 1);
+        assert_eq!(middle.locals, 2);
+        assert_eq!(
+            disassemble(&middle.instructions),
+            "\
+0000 GetFree 0
+0002 GetLocal 0
+0004 Add
+0005 SetLocal 1
+0007 Pop
+0008 GetFree 0
+0010 GetLocal 1
+0012 Closure 0 2
+0016 ReturnValue
+"
+        );
+        let outer = function(&bytecode, 2);
+        assert_eq!(
+            disassemble(&outer.instructions),
+            "0000 GetLocal 0\n0002 Closure 1 1\n0006 ReturnValue\n"
+        );
+    }
+
+    #[test]
+    fn functions_see_their_name() {
+        let bytecode = compile("let f = fn() { let g = fn(n) { g(n) }; g(1) };");
+        let g = function(&bytecode, 0);
+        assert_eq!(
+            disassemble(&g.instructions),
+            "0000 CurrentClosure\n0001 GetLocal 0\n0003 Call 1\n0005 ReturnValue\n"
+        );
+    }
+}
@@ -58,7 +58,11 @@ impl Lexer {
     /// Parses an operator token based on the current and next characters in the input.
     fn parse_operator(&mut self) -> TokenType {
         let curr = self.ch;
-        let doubled = self.peek_char() == '=';
+        // `&&` and `||` repeat their character, the rest are followed by `=`
+        let doubled = match curr {
+            b'&' | b'|' => self.peek_char() == curr as char,
+            _ => self.peek_char() == '=',
+        };
         if doubled {
             self.read_char();
         }
@@ -75,6 +79,9 @@ impl Lexer {
             (b'-', false) => TokenType::Minus,
             (b'*', false) => TokenType::Asterisk,
             (b'/', false) => TokenType::Slash,
+            (b'%', false) => TokenType::Percent,
+            (b'&', true) => TokenType::And,
+            (b'|', true) => TokenType::Or,
             v => TokenType::Illegal(String::from_utf8(vec![v.0]).unwrap()),
         }
     }
@@ -84,12 +91,18 @@ impl Lexer {
         match self.ch {
             b'\0' => TokenType::Eof,
             b',' => TokenType::Comma,
+            b':' => TokenType::Colon,
             b';' => TokenType::Semicolon,
             b'(' => TokenType::LParen,
             b')' => TokenType::RParen,
             b'{' => TokenType::LSquirly,
             b'}' => TokenType::RSquirly,
-            b'!' | b'=' | b'<' | b'>' | b'+' | b'-' | b'*' | b'/' => self.parse_operator(),
+            b'[' => TokenType::LBracket,
+            b']' => TokenType::RBracket,
+            b'"' => self.read_string(),
+            b'!' | b'=' | b'<' | b'>' | b'+' | b'-' | b'*' | b'/' | b'%' | b'&' | b'|' => {
+                self.parse_operator()
+            }
             c if c.is_ascii_alphabetic() || c == b'_' => self.read_name(),
             c if c.is_ascii_digit() => self.read_number(),
             rest => TokenType::Illegal(String::from_utf8(vec![rest]).unwrap()),
@@ -117,6 +130,37 @@ impl Lexer {
         }
     }
 
+    /// Reads a string literal, replacing the escape sequences `\"`, `\\`, `\n`, `\t`,
+    /// `\r` and `\0`. Strings with unknown escapes or without the closing quote are
+    /// illegal as a whole.
+    fn read_string(&mut self) -> TokenType {
+        let start = self.position;
+        let mut bytes = Vec::new();
+        let mut illegal = false;
+        while self.read_position < self.input.len() {
+            self.read_char();
+            match self.ch {
+                b'"' if illegal => break,
+                b'"' => return TokenType::String(String::from_utf8_lossy(&bytes).into_owned()),
+                b'\\' if self.read_position < self.input.len() => {
+                    self.read_char();
+                    match self.ch {
+                        b'"' => bytes.push(b'"'),
+                        b'\\' => bytes.push(b'\\'),
+                        b'n' => bytes.push(b'\n'),
+                        b't' => bytes.push(b'\t'),
+                        b'r' => bytes.push(b'\r'),
+                        b'0' => bytes.push(b'\0'),
+                        _ => illegal = true,
+                    }
+                }
+                c => bytes.push(c),
+            }
+        }
+        let raw = &self.input[start..self.read_position];
+        TokenType::Illegal(String::from_utf8_lossy(raw).into_owned())
+    }
+
     /// Reads an integer number token from the input.
     fn read_number(&mut self) -> TokenType {
         let start = self.position;
@@ -173,6 +217,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn read_string() {
+        let mut l = Lexer::new(String::from(
+            r#"let name = "Alice"; "tab\tquote\"back\\slash\nnul\0" "" "ção" "bad\q" "open"#,
+        ));
+        let v = vec![
+            TokenType::Let,
+            TokenType::Ident(String::from("name")),
+            TokenType::Assign,
+            TokenType::String(String::from("Alice")),
+            TokenType::Semicolon,
+            TokenType::String(String::from("tab\tquote\"back\\slash\nnul\0")),
+            TokenType::String(String::new()),
+            TokenType::String(String::from("ção")),
+            TokenType::Illegal(String::from(r#""bad\q""#)),
+            TokenType::Illegal(String::from(r#""open"#)),
+            TokenType::Eof,
+        ];
+
+        for i in v.into_iter() {
+            assert_eq!(i, l.next_token());
+        }
+    }
+
+    #[test]
+    fn brackets() {
+        let mut l = Lexer::new(String::from("[1, a][0]{:}"));
+        let v = vec![
+            TokenType::LBracket,
+            TokenType::Integer(String::from("1")),
+            TokenType::Comma,
+            TokenType::Ident(String::from("a")),
+            TokenType::RBracket,
+            TokenType::LBracket,
+            TokenType::Integer(String::from("0")),
+            TokenType::RBracket,
+            TokenType::LSquirly,
+            TokenType::Colon,
+            TokenType::RSquirly,
+            TokenType::Eof,
+        ];
+
+        for i in v.into_iter() {
+            assert_eq!(i, l.next_token());
+        }
+    }
+
     #[test]
     fn no_whitespace_idents() {
         let mut l = Lexer::new(String::from("five=5;"));
@@ -195,7 +286,10 @@ mod tests {
             r#"10 == 10;
             10 != 9;
             10 >= 9;
-            9 <= 10;"#,
+            9 <= 10;
+            10 % 3;
+            a && b || c;
+            a & b;"#,
         ));
         let v = vec![
             TokenType::Integer(String::from("10")),
@@ -214,6 +308,20 @@ mod tests {
             TokenType::LessEQ,
             TokenType::Integer(String::from("10")),
             TokenType::Semicolon,
+            TokenType::Integer(String::from("10")),
+            TokenType::Percent,
+            TokenType::Integer(String::from("3")),
+            TokenType::Semicolon,
+            TokenType::Ident(String::from("a")),
+            TokenType::And,
+            TokenType::Ident(String::from("b")),
+            TokenType::Or,
+            TokenType::Ident(String::from("c")),
+            TokenType::Semicolon,
+            TokenType::Ident(String::from("a")),
+            TokenType::Illegal(String::from("&")),
+            TokenType::Ident(String::from("b")),
+            TokenType::Semicolon,
             TokenType::Eof,
         ];
 
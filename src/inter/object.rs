@@ -1,7 +1,8 @@
-use std::{cell::RefCell, fmt, rc::Rc};
+use std::{cell::RefCell, collections::BTreeMap, fmt, rc::Rc};
 
 use crate::inter::{
     ast::{Expression, StatementType},
+    builtins::Builtin,
     environment::Environment,
 };
 
@@ -10,6 +11,9 @@ use crate::inter::{
 pub enum Object {
     Integer(i64),
     Boolean(bool),
+    String(String),
+    Array(Vec<Object>),
+    Hash(BTreeMap<HashKey, Object>),
     ReturnValue(Box<Object>),
     Null,
     Function {
@@ -17,6 +21,7 @@ pub enum Object {
         body: StatementType,
         env: Rc<RefCell<Environment>>,
     },
+    Builtin(Builtin),
 }
 
 impl Object {
@@ -25,9 +30,22 @@ impl Object {
         match self {
             Object::Integer(i) => i.to_string(),
             Object::Boolean(b) => b.to_string(),
+            Object::String(s) => quote(s),
+            Object::Array(elements) => {
+                let elements: Vec<String> = elements.iter().map(|e| e.inspect()).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Object::Hash(pairs) => {
+                let pairs: Vec<String> = pairs
+                    .iter()
+                    .map(|(k, v)| format!("{}: {}", k.to_object().inspect(), v.inspect()))
+                    .collect();
+                format!("{{{}}}", pairs.join(", "))
+            }
             Object::Null => String::from("null"),
             Object::ReturnValue(v) => v.inspect(),
             Object::Function { .. } => "".to_string(),
+            Object::Builtin(b) => format!("builtin {}", b.name()),
         }
     }
 
@@ -36,9 +54,13 @@ impl Object {
         match self {
             Object::Integer(_) => "Integer",
             Object::Boolean(_) => "Boolean",
+            Object::String(_) => "String",
+            Object::Array(_) => "Array",
+            Object::Hash(_) => "Hash",
             Object::Null => "Null",
             Object::ReturnValue(_) => "ReturnValue",
             Object::Function { .. } => "Function",
+            Object::Builtin(_) => "Builtin",
         }
     }
 }
@@ -49,10 +71,62 @@ impl fmt::Display for Object {
         let buf = match self {
             Object::Integer(v) => v.to_string(),
             Object::Boolean(v) => v.to_string(),
+            Object::String(v) => quote(v),
+            Object::Array(_) | Object::Hash(_) => self.inspect(),
             Object::Null => String::from("null"),
             Object::ReturnValue(v) => v.to_string(),
             Object::Function { .. } => String::from("fn"),
+            Object::Builtin(_) => self.inspect(),
         };
         write!(f, "{}", buf)
     }
 }
+
+/// The values that can be keys of a hash, ordered to show the hash the same way
+/// every time.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+impl HashKey {
+    /// Returns the key for an object, or `None` when it can't be a key.
+    pub fn from_object(obj: &Object) -> Option<HashKey> {
+        match obj {
+            Object::Integer(i) => Some(HashKey::Integer(*i)),
+            Object::Boolean(b) => Some(HashKey::Boolean(*b)),
+            Object::String(s) => Some(HashKey::String(s.clone())),
+            _ => None,
+        }
+    }
+
+    pub fn to_object(&self) -> Object {
+        match self {
+            HashKey::Integer(i) => Object::Integer(*i),
+            HashKey::Boolean(b) => Object::Boolean(*b),
+            HashKey::String(s) => Object::String(s.clone()),
+        }
+    }
+}
+
+/// Writes a string the way it's written in the source, between quotes and with the
+/// characters the lexer unescapes escaped back.
+pub fn quote(s: &str) -> String {
+    let mut buf = String::with_capacity(s.len() + 2);
+    buf.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\t' => buf.push_str("\\t"),
+            '\r' => buf.push_str("\\r"),
+            '\0' => buf.push_str("\\0"),
+            c => buf.push(c),
+        }
+    }
+    buf.push('"');
+    buf
+}
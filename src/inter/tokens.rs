@@ -1,20 +1,27 @@
 use core::fmt;
 
+use super::object::quote;
+
 pub type TokenType = Token;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
     Ident(String),
     Integer(String),
+    /// String literal, with its escape sequences already replaced.
+    String(String),
     Illegal(String),
     Eof,
     //Punctuation
     Comma,
+    Colon,
     Semicolon,
     LParen,
     RParen,
     LSquirly,
     RSquirly,
+    LBracket,
+    RBracket,
     //Keywords
     True,
     False,
@@ -30,27 +37,34 @@ pub enum Token {
     Bang,
     Asterisk,
     Slash,
+    Percent,
     LessThan,
     GreaterThan,
     LessEQ,
     GreaterEQ,
     Equal,
     NotEqual,
+    And,
+    Or,
 }
 
 impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let buf = match self {
+            Token::String(v) => return write!(f, "{}", quote(v)),
             Token::Ident(v) => v,
             Token::Integer(v) => v,
             Token::Illegal(v) => v,
             Token::Eof => "",
             Token::Comma => ",",
+            Token::Colon => ":",
             Token::Semicolon => ";",
             Token::LParen => "(",
             Token::RParen => ")",
             Token::LSquirly => "{",
             Token::RSquirly => "}",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
             Token::True => "true",
             Token::False => "false",
             Token::Return => "return",
@@ -64,12 +78,15 @@ impl fmt::Display for Token {
             Token::Bang => "!",
             Token::Asterisk => "*",
             Token::Slash => "/",
+            Token::Percent => "%",
             Token::LessThan => "<",
             Token::GreaterThan => ">",
             Token::LessEQ => "<=",
             Token::GreaterEQ => ">=",
             Token::Equal => "==",
             Token::NotEqual => "!=",
+            Token::And => "&&",
+            Token::Or => "||",
         };
         write!(f, "{}", buf)
     }
@@ -50,17 +50,19 @@ impl Alu {
     pub fn op(&self) -> u32 {
         let c = match &self.f {
             Func::Add { inc } => {
-                let mut sum = self.a as i32 + self.b as i32;
+                // Two's complement, overflowing like the hardware does
+                let sum = self.a.wrapping_add(self.b);
                 if *inc {
-                    sum += 1;
+                    sum.wrapping_add(1)
+                } else {
+                    sum
                 }
-                sum as u32
             }
             Func::And => self.a & self.b,
             Func::Or => self.a | self.b,
             Func::Not => !self.b,
             Func::Xor => self.a ^ self.b,
-            Func::Mul => self.a * self.b,
+            Func::Mul => self.a.wrapping_mul(self.b),
             Func::Div => self.a / self.b,
             Func::Mod => self.a % self.b,
         };
@@ -78,24 +80,21 @@ impl Alu {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 enum Func {
+    #[default]
     And,
     Or,
     Xor,
     Not,
-    Add { inc: bool },
+    Add {
+        inc: bool,
+    },
     Mul,
     Div,
     Mod,
 }
 
-impl Default for Func {
-    fn default() -> Self {
-        Self::And
-    }
-}
-
 #[derive(Debug, Default)]
 struct Shifter {
     entry: u8,
@@ -104,11 +103,15 @@ struct Shifter {
 impl Shifter {
     fn shift(&self, input: u32) -> u32 {
         match self.entry {
-            0b00 => input,
-            0b01 => input >> 1,
-            0b10 => input << 8,
-            0b11 => input << 1,
-            _ => unreachable!("The shifter entry must have only 2 bits"),
+            0b000 => input,
+            0b001 => ((input as i32) >> 1) as u32,
+            0b010 => input << 8,
+            0b011 => input << 1,
+            0b100 => input >> 1,
+            0b101 => ((input as i32) >> 8) as u32,
+            0b110 => input >> 8,
+            0b111 => input.rotate_left(1),
+            _ => unreachable!("The shifter entry must have only 3 bits"),
         }
     }
 }
@@ -160,6 +163,15 @@ pub mod test {
         assert_eq!(A * B, alu.op());
     }
 
+    #[test]
+    fn wrap_around() {
+        let mut alu = Alu::default();
+        alu.entry(0b001011100, 2, -10i32 as u32);
+        assert_eq!(-20, alu.op() as i32);
+        alu.entry(0b000111100, i32::MAX as u32, 1);
+        assert_eq!(i32::MIN, alu.op() as i32);
+    }
+
     #[test]
     fn div() {
         let mut alu = Alu::default();
@@ -231,6 +243,29 @@ pub mod test {
         let mut alu = Alu::default();
         alu.entry(0b010111101, A, B);
         assert_eq!((A + B + 1) >> 1, alu.op());
+
+        alu.entry(0b0010011000, -8i32 as u32, B);
+        assert_eq!(-4, alu.op() as i32);
+
+        alu.entry(0b1010011000, 0x80001234, B);
+        assert_eq!(0xFF800012, alu.op());
+    }
+
+    #[test]
+    fn srl() {
+        let mut alu = Alu::default();
+        alu.entry(0b1000011000, -8i32 as u32, B);
+        assert_eq!(0x7FFFFFFC, alu.op());
+
+        alu.entry(0b1100011000, 0x80001234, B);
+        assert_eq!(0x00800012, alu.op());
+    }
+
+    #[test]
+    fn rol() {
+        let mut alu = Alu::default();
+        alu.entry(0b1110011000, 0x80000001, B);
+        assert_eq!(0x00000003, alu.op());
     }
 
     #[test]
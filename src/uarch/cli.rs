@@ -8,7 +8,6 @@ use clap::Parser;
 #[command(
     help_template = "{author-with-newline} {about-section}Version: {version} \n\n {usage-heading} {usage} \n {all-args} {tab}"
 )]
-
 pub struct UArchCli {
     /// The name of the file that contains the ram dump
     #[arg(long)]
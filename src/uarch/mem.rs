@@ -6,7 +6,7 @@ use std::{
 };
 
 // 32-bit word * 20-bit addr = about 4 MB
-const RAM_ADDRS: usize = 2usize.pow(20);
+pub const RAM_ADDRS: usize = 2usize.pow(20);
 // 9-bit addr
 const CS_ADDRS: usize = 2usize.pow(9);
 
@@ -88,6 +88,11 @@ impl CtrlStoreBuilder {
         }
     }
 
+    /// The words loaded so far
+    pub fn firmware(&self) -> &[u64; CS_ADDRS] {
+        &self.firmware
+    }
+
     pub fn set_mpc(mut self, byte: u16) -> Self {
         self.mpc = byte & 0b0000000111111111;
         self
@@ -145,7 +150,17 @@ impl CtrlStore {
     ///
     /// where `JMPC`, `JAMN` and `JAMZ` are 1-bit wide and `NEXT_ADDR` is
     /// 9-bit wide. The 4 bits represented by `...` are ignored.
-    pub fn update_mpc(&self, mut opcode: u16, z: bool, n: bool, mem_regs: &mut MemRegs) {
+    ///
+    /// Setting the three bits at once is `JMPR`: the 9 LSBs of the C bus are
+    /// ORed into `NEXT_ADDR`, so the MPC can be loaded from a register.
+    pub fn update_mpc(
+        &self,
+        mut opcode: u16,
+        z: bool,
+        n: bool,
+        c_bus: u32,
+        mem_regs: &mut MemRegs,
+    ) {
         // ignored 4 MSBs
         opcode &= 0b0000111111111111;
 
@@ -158,6 +173,11 @@ impl CtrlStore {
 
         let mut next_addr = opcode;
 
+        if jmpc && jamn && jamz {
+            self.mpc.set(next_addr | (c_bus & 0x1FF) as u16);
+            return;
+        }
+
         if jamn && n {
             next_addr |= 0b0000000100000000;
         }
@@ -520,6 +540,16 @@ mod tests {
         assert_eq!(builder.mpc, 0);
     }
 
+    #[test]
+    fn test_ctrl_store_update_mpc_jmpr() {
+        let ctrl_store = CtrlStoreBuilder::default().build();
+        let mut mem_regs = MemRegs::new();
+        ctrl_store.update_mpc(0b0000_1000_0111, false, false, 0xFFFF_0021, &mut mem_regs);
+        assert_eq!(ctrl_store.mpc.get(), 0x31);
+        ctrl_store.update_mpc(0b0000_1000_0011, true, true, 0x21, &mut mem_regs);
+        assert_eq!(ctrl_store.mpc.get(), 0x110);
+    }
+
     #[test]
     fn test_shared_reg_new() {
         let shared_reg = SharedReg::new(42);
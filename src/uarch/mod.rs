@@ -27,6 +27,15 @@ impl Computer {
     }
 
     pub fn exec(&mut self) {
+        self.run(None);
+    }
+
+    /// Runs for at most `cycles` cycles, returning whether the program halted.
+    pub fn exec_for(&mut self, cycles: u32) -> bool {
+        self.run(Some(cycles))
+    }
+
+    fn run(&mut self, limit: Option<u32>) -> bool {
         self.cpu.thr_sync();
         self.clock.lock().expect("Cannot get the clock lock.").alt();
 
@@ -40,7 +49,7 @@ impl Computer {
                 Err(_) => break,
             }
         });
-        self.cpu.run(&mut self.mem, rx);
+        self.cpu.run(&mut self.mem, rx, limit)
     }
 
     pub fn cycles(&self) -> f64 {
@@ -51,6 +60,10 @@ impl Computer {
     pub fn regs(&self) -> &Registers {
         self.cpu.thr.regs()
     }
+
+    pub fn ram(&self) -> &Ram {
+        &self.mem
+    }
 }
 
 #[derive(Debug)]
@@ -71,13 +84,25 @@ impl Cpu {
         self.thr.sync(&self.firmware);
     }
 
-    pub fn run(&mut self, mem: &mut Ram, recver: mpsc::Receiver<ClkLevel>) {
-        for trigger in recver {
+    /// Steps until the firmware halts or, when given, the limit of cycles runs out.
+    pub fn run(
+        &mut self,
+        mem: &mut Ram,
+        recver: mpsc::Receiver<ClkLevel>,
+        limit: Option<u32>,
+    ) -> bool {
+        // Each trigger is half a cycle
+        let alts = limit.map(|cycles| cycles as u64 * 2);
+        for (i, trigger) in recver.into_iter().enumerate() {
             if self.firmware.get_mi() == CtrlStore::HALT {
-                break;
+                return true;
+            }
+            if alts.is_some_and(|alts| i as u64 >= alts) {
+                return false;
             }
             self.thr.step(&trigger, mem, &self.firmware);
         }
+        false
     }
 }
 
@@ -204,6 +229,14 @@ impl DataPath {
 
         // NEXT_ADDR | JAM
         self.state.cs_opcode = mi as u16;
+        mi >>= 12;
+
+        // CPP is written from the spare bit above NEXT_ADDR
+        self.state.enable_cpp_in = (mi & 1) == 1;
+        mi >>= 1;
+
+        // The last bit is the most significant one of the shifter
+        self.state.alu_entry |= ((mi & 1) as u16) << 9;
     }
 
     pub fn end_cycle(&mut self, mem: &mut Ram, cs: &CtrlStore) {
@@ -243,10 +276,15 @@ impl DataPath {
             self.regs.mem.update_mdr(c_bus);
         }
 
+        if self.state.enable_cpp_in {
+            self.regs.sys.cpp.set(c_bus);
+        }
+
         cs.update_mpc(
             self.state.cs_opcode,
             self.alu.z(),
             self.alu.n(),
+            c_bus,
             &mut self.regs.mem,
         );
 
@@ -276,18 +314,13 @@ impl Clock {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
 pub enum ClkLevel {
+    #[default]
     Falling,
     Rising,
 }
 
-impl Default for ClkLevel {
-    fn default() -> Self {
-        Self::Falling
-    }
-}
-
 impl ClkLevel {
     pub fn inv(&self) -> Self {
         match self {
@@ -302,9 +335,31 @@ struct DPState {
     cs_opcode: u16,
     alu_entry: u16,
     enable_in: u32,
+    enable_cpp_in: bool,
     a: u32,
     b: u32,
     write: bool,
     read: bool,
     fetch: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exec_for_stops_at_the_limit() {
+        // A single word that jumps to itself
+        let firmware = CtrlStore::builder().set(0, 0).build();
+        let mut comp = Computer::new(Ram::new(), firmware);
+        assert!(!comp.exec_for(100));
+        assert!(comp.cycles() <= 101.0);
+
+        let firmware = CtrlStore::builder()
+            .set(0, 1 << 53)
+            .set(1, CtrlStore::HALT)
+            .build();
+        let mut comp = Computer::new(Ram::new(), firmware);
+        assert!(comp.exec_for(100));
+    }
+}